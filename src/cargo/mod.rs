@@ -0,0 +1,127 @@
+#[cfg(test)]
+pub mod tests;
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::Path,
+};
+use tokio::fs;
+use url::Url;
+
+/// The error type for writing a source replacement into a Cargo configuration file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WriteConfigurationError {
+    /// The configuration already defines a source that the source replacement defines.
+    AlreadyConfigured(String),
+    /// The existing configuration is not valid TOML.
+    Parse(toml::de::Error),
+    Io(io::Error),
+}
+
+impl From<io::Error> for WriteConfigurationError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for WriteConfigurationError {
+    fn from(error: toml::de::Error) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl Display for WriteConfigurationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyConfigured(name) => {
+                write!(f, "the configuration already defines the source {name}")
+            }
+            Self::Parse(error) => write!(f, "failed to parse the configuration: {error}"),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for WriteConfigurationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::AlreadyConfigured(_) => None,
+            Self::Parse(error) => error.source(),
+            Self::Io(error) => error.source(),
+        }
+    }
+}
+
+/// Quotes a string as a TOML basic string.
+fn quote(value: &str) -> String {
+    // JSON strings are valid TOML basic strings.
+    serde_json::to_string(value).expect("failed to quote string")
+}
+
+/// A Cargo source replacement that directs Cargo to use a mirror instead of a registry.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SourceReplacement {
+    /// The name of the source that is defined for the mirror.
+    pub name: String,
+    /// The name of the source that is replaced by the mirror.
+    pub replaces: String,
+    /// The URL of the mirror's index.
+    pub index: Url,
+}
+
+impl SourceReplacement {
+    /// Returns the header of the table that defines the mirror.
+    fn header(&self) -> String {
+        format!("[source.{}]", quote(&self.name))
+    }
+
+    /// Appends the source replacement to the Cargo configuration file at `path`. The file and its
+    /// parent directories are created if they do not exist.
+    ///
+    /// The configuration is not changed if it already defines either of the sources.
+    pub async fn write(&self, path: &Path) -> Result<(), WriteConfigurationError> {
+        let mut contents = match fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        let configuration = contents.parse::<toml::Table>()?;
+        if let Some(sources) = configuration.get("source").and_then(toml::Value::as_table) {
+            if let Some(name) = [&self.replaces, &self.name]
+                .into_iter()
+                .find(|name| sources.contains_key(name.as_str()))
+            {
+                return Err(WriteConfigurationError::AlreadyConfigured(name.clone()));
+            }
+        }
+
+        if !contents.is_empty() {
+            if !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+            contents.push('\n');
+        }
+        contents.push_str(&self.to_string());
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::write(path, contents).await?;
+        Ok(())
+    }
+}
+
+impl Display for SourceReplacement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[source.{}]", quote(&self.replaces))?;
+        writeln!(f, "replace-with = {}", quote(&self.name))?;
+        writeln!(f)?;
+        writeln!(f, "{}", self.header())?;
+        writeln!(f, "registry = {}", quote(self.index.as_str()))
+    }
+}
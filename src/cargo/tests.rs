@@ -0,0 +1,107 @@
+use super::*;
+use tempfile::TempDir;
+
+fn replacement() -> SourceReplacement {
+    SourceReplacement {
+        name: String::from("crateful"),
+        replaces: String::from("crates-io"),
+        index: Url::parse("https://mirror.example/index").expect("failed to parse url"),
+    }
+}
+
+#[test]
+fn test_display_source_replacement() {
+    assert_eq!(
+        replacement().to_string(),
+        r#"[source."crates-io"]
+replace-with = "crateful"
+
+[source."crateful"]
+registry = "https://mirror.example/index"
+"#
+    );
+}
+
+#[tokio::test]
+async fn test_write_source_replacement() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join(".cargo/config.toml");
+    fs::create_dir_all(path.parent().expect("path must have a parent"))
+        .await
+        .expect("failed to create configuration directory");
+    fs::write(&path, "[build]\njobs = 4")
+        .await
+        .expect("failed to write configuration");
+
+    replacement()
+        .write(&path)
+        .await
+        .expect("failed to write source replacement");
+    assert_eq!(
+        fs::read_to_string(&path)
+            .await
+            .expect("failed to read configuration"),
+        format!("[build]\njobs = 4\n\n{}", replacement())
+    );
+
+    assert!(matches!(
+        replacement().write(&path).await,
+        Err(WriteConfigurationError::AlreadyConfigured(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_write_source_replacement_with_replaced_source() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join("config.toml");
+    let configuration = "[source.crates-io]\nreplace-with = \"vendored\"\n";
+    fs::write(&path, configuration)
+        .await
+        .expect("failed to write configuration");
+
+    assert!(matches!(
+        replacement().write(&path).await,
+        Err(WriteConfigurationError::AlreadyConfigured(name)) if name == "crates-io"
+    ));
+    assert_eq!(
+        fs::read_to_string(&path)
+            .await
+            .expect("failed to read configuration"),
+        configuration
+    );
+}
+
+#[tokio::test]
+async fn test_write_source_replacement_with_quoted_source() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join("config.toml");
+    let configuration = "[source.\"crateful\"]\nregistry = \"https://other.example/index\"\n";
+    fs::write(&path, configuration)
+        .await
+        .expect("failed to write configuration");
+
+    assert!(matches!(
+        replacement().write(&path).await,
+        Err(WriteConfigurationError::AlreadyConfigured(name)) if name == "crateful"
+    ));
+    assert_eq!(
+        fs::read_to_string(&path)
+            .await
+            .expect("failed to read configuration"),
+        configuration
+    );
+}
+
+#[tokio::test]
+async fn test_write_source_replacement_with_invalid_configuration() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join("config.toml");
+    fs::write(&path, "[build")
+        .await
+        .expect("failed to write configuration");
+
+    assert!(matches!(
+        replacement().write(&path).await,
+        Err(WriteConfigurationError::Parse(_))
+    ));
+}
@@ -0,0 +1,181 @@
+#[cfg(test)]
+pub mod tests;
+
+use crate::registry::{
+    cache::{
+        filter::{Constraint, Date, Preset, YankPolicy},
+        layout::Layout,
+    },
+    index::authentication::Authentication,
+};
+use semver::VersionReq;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+use url::Url;
+
+/// The error type for loading a configuration file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LoadConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// More than one registry has the same name.
+    DuplicateRegistry(String),
+}
+
+impl From<io::Error> for LoadConfigError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for LoadConfigError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
+
+impl Display for LoadConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Json(error) => error.fmt(f),
+            Self::DuplicateRegistry(name) => write!(f, "registry {name} is defined more than once"),
+        }
+    }
+}
+
+impl Error for LoadConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::Json(error) => error.source(),
+            Self::DuplicateRegistry(_) => None,
+        }
+    }
+}
+
+/// A registry that is mirrored.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Registry {
+    /// The name of the registry. The cache for the registry is held in a directory with this name.
+    pub name: String,
+    /// The URL of the registry index.
+    pub index: Url,
+    /// The private key that the index is cloned and fetched over SSH with. The keys of the SSH
+    /// agent are used if it is not given.
+    pub ssh_identity: Option<PathBuf>,
+    /// The user name that the index is cloned and fetched over HTTPS with.
+    pub git_username: Option<String>,
+    /// The environment variable that holds the password or token that the index is cloned and
+    /// fetched over HTTPS with. The Git credential helpers are asked if it is not given.
+    pub git_token_env: Option<String>,
+    /// How crates are laid out in the crates directory when the cache is created.
+    pub layout: Option<Layout>,
+    /// The token that is sent with requests to download crates.
+    pub token: Option<String>,
+    /// The environment variable that holds the token that is sent with requests to download
+    /// crates.
+    pub token_env: Option<String>,
+    /// Only mirror crate versions that were published on or after this date.
+    pub since: Option<Date>,
+    /// How crates that have been yanked from the registry are handled.
+    pub yanked: Option<YankPolicy>,
+    /// Only mirror this many of the most downloaded crates and the crates that they depend on.
+    pub top: Option<NonZeroUsize>,
+    /// Only mirror the crates that this preset selects and the crates that they depend on.
+    pub preset: Option<Preset>,
+    /// Only mirror the versions of each crate that match its semver requirement.
+    #[serde(default)]
+    pub versions: BTreeMap<String, VersionReq>,
+    /// Only mirror these crates, every crate that depends on them, and the crates that they
+    /// depend on.
+    #[serde(default)]
+    pub dependents_of: Vec<String>,
+    /// Only mirror this many of the latest versions of each crate.
+    pub keep_latest: Option<NonZeroUsize>,
+    /// Whether pre-release versions are not mirrored.
+    pub exclude_prerelease: Option<bool>,
+    /// The mirrors that crates are downloaded from before the registry.
+    #[serde(default)]
+    pub parents: Vec<Url>,
+    /// The program that verifies the signatures of crates before they are stored.
+    pub signature_verifier: Option<PathBuf>,
+    /// The crates that are tracked when the index is a sparse index.
+    #[serde(default)]
+    pub crates: Vec<String>,
+}
+
+impl Registry {
+    /// Returns the constraints on the versions of crates that are mirrored.
+    #[must_use]
+    pub fn constraints(&self) -> Vec<Constraint> {
+        self.versions
+            .iter()
+            .map(|(name, requirement)| Constraint {
+                name: name.clone(),
+                requirement: requirement.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns how the remote of the index is authenticated.
+    #[must_use]
+    pub fn authentication(&self) -> Authentication {
+        Authentication {
+            identity: self.ssh_identity.clone(),
+            username: self.git_username.clone(),
+            token_env: self.git_token_env.clone(),
+        }
+    }
+
+    /// Returns the token that is sent with requests to download crates.
+    #[must_use]
+    pub fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| {
+            self.token_env
+                .as_ref()
+                .and_then(|variable| env::var(variable).ok())
+        })
+    }
+}
+
+/// A configuration file describes the registries that are mirrored.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The registries that are mirrored.
+    pub registries: Vec<Registry>,
+}
+
+impl Config {
+    /// Deserialises a configuration from a slice.
+    pub fn from_slice(slice: &[u8]) -> Result<Self, LoadConfigError> {
+        let config = serde_json::from_slice::<Self>(slice)?;
+        for (index, registry) in config.registries.iter().enumerate() {
+            if config.registries[..index]
+                .iter()
+                .any(|other| other.name == registry.name)
+            {
+                return Err(LoadConfigError::DuplicateRegistry(registry.name.clone()));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Loads a configuration file.
+    pub async fn load(path: &Path) -> Result<Self, LoadConfigError> {
+        Self::from_slice(&fs::read(path).await?)
+    }
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+#[test]
+fn test_deserialise_config() {
+    let data = r#"{
+        "registries": [
+            {"name": "crates-io", "index": "https://github.com/rust-lang/crates.io-index", "since": "2021-01-01", "yanked": "skip", "layout": "content-addressed"},
+            {"name": "internal", "index": "ssh://git@git.example/index.git", "ssh-identity": "/keys/index", "token": "secret"},
+            {"name": "hosted", "index": "https://git.example/index.git", "git-username": "mirror", "git-token-env": "INDEX_TOKEN", "signature-verifier": "/usr/local/bin/verify"}
+        ]
+    }"#;
+
+    let config = Config::from_slice(data.as_bytes()).expect("failed to deserialise config");
+    assert_eq!(config.registries.len(), 3);
+    assert_eq!(
+        config.registries[0].since,
+        Some(Date::try_from(String::from("2021-01-01")).expect("failed to parse date"))
+    );
+    assert_eq!(config.registries[0].yanked, Some(YankPolicy::Skip));
+    assert_eq!(config.registries[0].layout, Some(Layout::ContentAddressed));
+    assert_eq!(config.registries[1].layout, None);
+    assert_eq!(
+        config.registries[2].signature_verifier,
+        Some(PathBuf::from("/usr/local/bin/verify"))
+    );
+    assert_eq!(config.registries[1].token(), Some(String::from("secret")));
+    assert_eq!(
+        config.registries[1].ssh_identity,
+        Some(PathBuf::from("/keys/index"))
+    );
+    assert_eq!(
+        config.registries[2].authentication(),
+        Authentication {
+            identity: None,
+            username: Some(String::from("mirror")),
+            token_env: Some(String::from("INDEX_TOKEN")),
+        }
+    );
+}
+
+#[test]
+fn test_deserialise_config_with_duplicate_registry() {
+    let data = r#"{
+        "registries": [
+            {"name": "a", "index": "https://git.example/a"},
+            {"name": "a", "index": "https://git.example/b"}
+        ]
+    }"#;
+
+    assert!(matches!(
+        Config::from_slice(data.as_bytes()),
+        Err(LoadConfigError::DuplicateRegistry(_))
+    ));
+}
+
+#[test]
+fn test_deserialise_config_with_invalid_date() {
+    let data = r#"{"registries": [{"name": "a", "index": "https://git.example/a", "since": "yesterday"}]}"#;
+    assert!(matches!(
+        Config::from_slice(data.as_bytes()),
+        Err(LoadConfigError::Json(_))
+    ));
+}
+
+#[test]
+fn test_deserialise_config_with_versions() {
+    let data = r#"{"registries": [{"name": "a", "index": "https://git.example/a", "versions": {"serde": ">=1.0, <2"}}]}"#;
+
+    let config = Config::from_slice(data.as_bytes()).expect("failed to deserialise config");
+    let constraints = config.registries[0].constraints();
+    assert_eq!(constraints.len(), 1);
+    assert_eq!(constraints[0].to_string(), "serde >=1.0, <2");
+}
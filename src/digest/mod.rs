@@ -0,0 +1,246 @@
+#[cfg(test)]
+pub mod tests;
+
+use serde::{de, Deserialize, Deserializer};
+use sha2::Digest as _;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::{sync::Semaphore, task};
+
+/// A hash function that digests are computed with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Sha512 => write!(f, "sha512"),
+            Self::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseDigestError {
+    Hex(hex::FromHexError),
+    /// The digest does not have the length of a digest computed with the algorithm.
+    InvalidLength(Algorithm),
+    UnknownAlgorithm(String),
+}
+
+impl From<hex::FromHexError> for ParseDigestError {
+    fn from(error: hex::FromHexError) -> Self {
+        Self::Hex(error)
+    }
+}
+
+impl Display for ParseDigestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hex(error) => error.fmt(f),
+            Self::InvalidLength(algorithm) => write!(f, "invalid length for a {algorithm} digest"),
+            Self::UnknownAlgorithm(algorithm) => write!(f, "unknown digest algorithm {algorithm}"),
+        }
+    }
+}
+
+impl Error for ParseDigestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Hex(error) => error.source(),
+            Self::InvalidLength(_) | Self::UnknownAlgorithm(_) => None,
+        }
+    }
+}
+
+/// A digest of an artefact.
+///
+/// A digest is written as its algorithm and hexadecimal encoding separated by a colon (eg.
+/// `blake3:af13...`). A hexadecimal encoding without an algorithm is a SHA-256 digest because that
+/// is how checksums are published in the index.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Digest {
+    Sha256([u8; 32]),
+    Sha512([u8; 64]),
+    Blake3([u8; 32]),
+}
+
+impl Digest {
+    /// Computes the digest of `bytes` with `algorithm`.
+    #[must_use]
+    pub fn compute(algorithm: Algorithm, bytes: &[u8]) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(sha2::Sha256::digest(bytes).into()),
+            Algorithm::Sha512 => Self::Sha512(
+                sha2::Sha512::digest(bytes)
+                    .as_slice()
+                    .try_into()
+                    .expect("a sha-512 digest is 64 bytes"),
+            ),
+            Algorithm::Blake3 => Self::Blake3(*blake3::hash(bytes).as_bytes()),
+        }
+    }
+
+    /// Returns the algorithm that the digest was computed with.
+    #[must_use]
+    pub const fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::Sha256(_) => Algorithm::Sha256,
+            Self::Sha512(_) => Algorithm::Sha512,
+            Self::Blake3(_) => Algorithm::Blake3,
+        }
+    }
+
+    /// Returns the bytes of the digest.
+    #[must_use]
+    pub const fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Sha256(bytes) | Self::Blake3(bytes) => bytes,
+            Self::Sha512(bytes) => bytes,
+        }
+    }
+}
+
+/// Computes a digest incrementally as the bytes of an artefact are received.
+#[derive(Clone, Debug)]
+pub enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    /// Returns a hasher that computes a digest with `algorithm`.
+    #[must_use]
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            Algorithm::Sha512 => Self::Sha512(sha2::Sha512::new()),
+            Algorithm::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    /// Adds `bytes` to the digest.
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(bytes),
+            Self::Sha512(hasher) => hasher.update(bytes),
+            Self::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    /// Returns the digest of every byte that was added.
+    #[must_use]
+    pub fn finalize(self) -> Digest {
+        match self {
+            Self::Sha256(hasher) => Digest::Sha256(hasher.finalize().into()),
+            Self::Sha512(hasher) => Digest::Sha512(
+                hasher
+                    .finalize()
+                    .as_slice()
+                    .try_into()
+                    .expect("a sha-512 digest is 64 bytes"),
+            ),
+            Self::Blake3(hasher) => Digest::Blake3(*hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm(), hex::encode(self.as_bytes()))
+    }
+}
+
+impl FromStr for Digest {
+    type Err = ParseDigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, encoded) = match s.split_once(':') {
+            Some(("sha256", encoded)) => (Algorithm::Sha256, encoded),
+            Some(("sha512", encoded)) => (Algorithm::Sha512, encoded),
+            Some(("blake3", encoded)) => (Algorithm::Blake3, encoded),
+            Some((algorithm, _)) => {
+                return Err(ParseDigestError::UnknownAlgorithm(algorithm.to_owned()))
+            }
+            None => (Algorithm::Sha256, s),
+        };
+
+        let bytes = hex::decode(encoded)?;
+        let invalid = |_| ParseDigestError::InvalidLength(algorithm);
+        Ok(match algorithm {
+            Algorithm::Sha256 => Self::Sha256(bytes.try_into().map_err(invalid)?),
+            Algorithm::Sha512 => Self::Sha512(bytes.try_into().map_err(invalid)?),
+            Algorithm::Blake3 => Self::Blake3(bytes.try_into().map_err(invalid)?),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A pool that computes digests on threads where blocking is permitted.
+///
+/// The number of digests that are computed at once is bounded independently of the number of
+/// crates that are acted on concurrently so that hashing can occupy every processor without
+/// starving the runtime of the threads that it uses for input and output.
+#[derive(Clone, Debug)]
+pub struct Pool {
+    permits: Arc<Semaphore>,
+    threads: NonZeroUsize,
+}
+
+impl Pool {
+    /// Returns a pool that computes up to `threads` digests at once.
+    pub fn new(threads: NonZeroUsize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(threads.get())),
+            threads,
+        }
+    }
+
+    /// Returns the number of digests that are computed at once.
+    pub const fn threads(&self) -> NonZeroUsize {
+        self.threads
+    }
+
+    /// Computes the digest of `bytes` with `algorithm`. The bytes are returned with their digest.
+    pub async fn digest<B>(&self, algorithm: Algorithm, bytes: B) -> (B, Digest)
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("hashing semaphore is never closed");
+
+        task::spawn_blocking(move || {
+            let digest = Digest::compute(algorithm, bytes.as_ref());
+            (bytes, digest)
+        })
+        .await
+        .expect("panicked while hashing")
+    }
+}
@@ -0,0 +1,70 @@
+use super::*;
+
+#[test]
+fn test_compute_digests() {
+    assert_eq!(
+        Digest::compute(Algorithm::Sha256, b"abc").to_string(),
+        "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        Digest::compute(Algorithm::Sha512, b"abc").to_string(),
+        "sha512:ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+    );
+    assert_eq!(
+        Digest::compute(Algorithm::Blake3, b"abc").to_string(),
+        "blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+}
+
+#[test]
+fn test_hash_incrementally() {
+    for algorithm in [Algorithm::Sha256, Algorithm::Sha512, Algorithm::Blake3] {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(b"a");
+        hasher.update(b"");
+        hasher.update(b"bc");
+        assert_eq!(
+            hasher.finalize(),
+            Digest::compute(algorithm, b"abc"),
+            "{algorithm} digests differ"
+        );
+    }
+}
+
+#[test]
+fn test_parse_digest_without_algorithm() {
+    let digest: Digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        .parse()
+        .expect("failed to parse digest");
+    assert_eq!(digest, Digest::compute(Algorithm::Sha256, b"abc"));
+}
+
+#[test]
+fn test_parse_digest_with_algorithm() {
+    for algorithm in [Algorithm::Sha256, Algorithm::Sha512, Algorithm::Blake3] {
+        let digest = Digest::compute(algorithm, b"abc");
+        assert_eq!(
+            digest
+                .to_string()
+                .parse::<Digest>()
+                .expect("failed to parse digest"),
+            digest
+        );
+    }
+}
+
+#[test]
+fn test_parse_invalid_digest() {
+    assert!(matches!(
+        "md5:900150983cd24fb0d6963f7d28e17f72".parse::<Digest>(),
+        Err(ParseDigestError::UnknownAlgorithm(_))
+    ));
+    assert!(matches!(
+        "sha512:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".parse::<Digest>(),
+        Err(ParseDigestError::InvalidLength(Algorithm::Sha512))
+    ));
+    assert!(matches!(
+        "not hex".parse::<Digest>(),
+        Err(ParseDigestError::Hex(_))
+    ));
+}
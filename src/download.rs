@@ -1,12 +1,28 @@
-use crate::digest;
-use sha2::{Digest, Sha256};
+use crate::{
+    digest::{Algorithm, Digest, Hasher, Pool},
+    file::{self, Durability, Permissions},
+    logging::{self, HTTP},
+    signature::{Signature, VerifySignatureError},
+    storage::{self, Form, Storage},
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Method, RequestBuilder, Response, StatusCode, Version,
+};
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display, Formatter},
     io,
+    num::{NonZeroU64, NonZeroUsize},
     path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-use tokio::{fs, io::AsyncReadExt};
-use tracing::{debug, info};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, info, trace, warn, Level, Span};
 use url::Url;
 
 #[derive(Debug)]
@@ -31,6 +47,14 @@ pub enum Error {
     },
 
     Reqwest(reqwest::Error),
+
+    /// A downloaded file was rejected by the signature verifier.
+    SignatureRejected {
+        /// The URL of the downloaded file.
+        url: Url,
+        /// What the verifier reported.
+        reason: String,
+    },
 }
 
 impl From<reqwest::Error> for Error {
@@ -54,10 +78,18 @@ impl Display for Error {
             }
 
             Self::Http { status, url } => {
-                write!(f, "a http response had a {} status for {}", status, url)
+                write!(f, "a http response had a {status} status for {url}")
             }
 
             Self::Reqwest(error) => error.fmt(f),
+
+            Self::SignatureRejected { url, reason } if reason.is_empty() => {
+                write!(f, "the signature verifier rejected {url}")
+            }
+
+            Self::SignatureRejected { url, reason } => {
+                write!(f, "the signature verifier rejected {url}: {reason}")
+            }
         }
     }
 }
@@ -73,26 +105,232 @@ impl std::error::Error for Error {
 }
 
 /// Specifies how existing download artefacts should be handled.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PreservationStrategy {
     /// Always preserve an existing download.
     Always,
     /// Preserve an existing download when the checksum matches.
     Checksum,
+    /// Preserve an existing download when it has the expected size. The checksum is used when the
+    /// expected size is not known.
+    Size,
+    /// Preserve an existing download when it has the size that was recorded when it was stored.
+    /// Nothing is requested to find the expected size, so the checksum is used when no size was
+    /// recorded.
+    RecordedSize,
+    /// Never preserve an existing download so that it is downloaded again.
+    Never,
+}
+
+/// Specifies how large artefacts are downloaded in segments.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Segmentation {
+    /// Artefacts that are larger than this number of bytes are downloaded in segments.
+    pub threshold: u64,
+    /// The number of segments that are downloaded concurrently.
+    pub segments: NonZeroUsize,
 }
 
 // Specifies download options.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct Options {
     pub preserve: PreservationStrategy,
+    pub storage: Storage,
+    /// Large artefacts are downloaded in concurrent ranged requests when this is set.
+    pub segmentation: Option<Segmentation>,
+    /// Artefacts are downloaded over HTTP/3 when this is set and the server supports it.
+    pub http3: bool,
+    /// How durably artefacts are written.
+    pub durability: Durability,
+    /// The permissions that artefacts and their directories are written with.
+    pub permissions: Permissions,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             preserve: PreservationStrategy::Always,
+            storage: Storage::Plain,
+            segmentation: None,
+            http3: false,
+            durability: Durability::default(),
+            permissions: Permissions::default(),
+        }
+    }
+}
+
+/// The number of bytes that are reserved for a response that does not have a length.
+const UNKNOWN_LENGTH: u64 = 1024 * 1024;
+
+/// The most kibibytes that can be reserved for responses.
+const MAX_RESERVATION: u32 = u32::MAX >> 3;
+
+/// A limit on the number of downloads that are in flight at once and on the bytes of their
+/// responses that are held in memory.
+///
+/// Artefacts that are already downloaded are checked without a connection so that checking their
+/// integrity can occupy every processor without opening more connections than the registry
+/// tolerates.
+#[derive(Clone, Debug)]
+pub struct Connections {
+    permits: Arc<Semaphore>,
+    /// The kibibytes of responses that can be held in memory.
+    memory: Arc<Semaphore>,
+    capacity: u32,
+}
+
+impl Connections {
+    /// Returns a limit of `connections` downloads at once that hold up to `bytes` of their
+    /// responses in memory.
+    pub fn new(connections: NonZeroUsize, bytes: NonZeroU64) -> Self {
+        let capacity = u32::try_from(bytes.get().div_ceil(1024))
+            .unwrap_or(u32::MAX)
+            .min(MAX_RESERVATION);
+        Self {
+            permits: Arc::new(Semaphore::new(connections.get())),
+            memory: Arc::new(Semaphore::new(capacity as usize)),
+            capacity,
+        }
+    }
+
+    /// Waits until a response of `length` bytes can be held in memory. A response without a
+    /// length is assumed to be a megabyte and a response that is larger than the limit waits until
+    /// no other responses are held. The memory is released when the reservation is dropped.
+    pub async fn reserve(&self, length: Option<u64>) -> SemaphorePermit<'_> {
+        let kibibytes = u32::try_from(length.unwrap_or(UNKNOWN_LENGTH).div_ceil(1024))
+            .unwrap_or(u32::MAX)
+            .clamp(1, self.capacity);
+        self.memory
+            .acquire_many(kibibytes)
+            .await
+            .expect("memory semaphore is never closed")
+    }
+
+    /// Waits until a download can start. The download ends when the permit is dropped.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.permits
+            .acquire()
+            .await
+            .expect("connection semaphore is never closed")
+    }
+}
+
+/// Set once a request could not be sent over HTTP/3. Later requests are sent over HTTP/1.1 or
+/// HTTP/2 so that an unreachable QUIC endpoint does not delay every download.
+static HTTP3_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Sends `request` and traces it and its response when HTTP tracing is enabled.
+async fn execute(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    if !tracing::enabled!(target: HTTP, Level::TRACE) {
+        return request.send().await;
+    }
+
+    let (client, request) = request.build_split();
+    let request = request?;
+    trace!(
+        target: HTTP,
+        "> {} {} {:?}",
+        request.method(),
+        request.url(),
+        request.version()
+    );
+
+    for (name, value) in request.headers() {
+        trace!(target: HTTP, "> {}: {}", name, logging::redact(name, value));
+    }
+
+    let response = client.execute(request).await;
+    match &response {
+        Ok(response) => {
+            trace!(
+                target: HTTP,
+                "< {:?} {} from {}",
+                response.version(),
+                response.status(),
+                response.url()
+            );
+
+            for (name, value) in response.headers() {
+                trace!(target: HTTP, "< {}: {}", name, logging::redact(name, value));
+            }
+        }
+        Err(error) => trace!(target: HTTP, "request failed: {}", error),
+    }
+
+    response
+}
+
+/// Sends a request that downloads an artefact from `url`. The request is sent over HTTP/3 first
+/// when `http3` is set and is sent again over HTTP/1.1 or HTTP/2 if that fails.
+async fn send(request: RequestBuilder, url: &Url, http3: bool) -> Result<Response, reqwest::Error> {
+    // HTTP/3 is only defined for secure connections.
+    if http3 && url.scheme() == "https" && !HTTP3_UNAVAILABLE.load(Ordering::Relaxed) {
+        if let Some(attempt) = request.try_clone() {
+            match execute(attempt.version(Version::HTTP_3)).await {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    HTTP3_UNAVAILABLE.store(true, Ordering::Relaxed);
+                    warn!("falling back from http/3: {}", error);
+                    trace!(target: HTTP, "retrying without http/3");
+                }
+            }
         }
     }
+
+    execute(request).await
+}
+
+/// The validators that a server responded with when an artefact was downloaded. They identify the
+/// version of the artefact so that the server can be asked whether it has changed since.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Validators {
+    /// The entity tag of the artefact.
+    pub etag: Option<String>,
+    /// The time that the artefact was last modified as it was formatted by the server.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Returns the validators in the headers of a response.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    /// Returns true if there are no validators.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Describes how a download was satisfied.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Transfer {
+    /// An existing artefact was preserved without checking its integrity.
+    Preserved,
+    /// An existing artefact was preserved after checking its integrity.
+    Verified,
+    /// The artefact was downloaded.
+    Downloaded {
+        /// The validators that the server responded with.
+        validators: Validators,
+        /// The number of bytes that were downloaded.
+        size: u64,
+        /// The digest of the stored artefact that was computed as it was written or nothing if
+        /// the download did not ask for one or the artefact was not stored as it was downloaded.
+        digest: Option<Digest>,
+    },
 }
 
 /// Represents a downloadable artefact.
@@ -100,56 +338,70 @@ impl Default for Options {
 pub struct Download {
     pub url: Url,
     pub destination: PathBuf,
-    pub checksum: digest::Sha256,
+    pub checksum: Digest,
+    /// The algorithm that the stored artefact is digested with as it is written so that it does
+    /// not need to be read again to record its digest.
+    pub digest: Option<Algorithm>,
+    /// The credentials that are sent with every request for the remote artefact. They are only
+    /// given for the registry itself so that they are never sent to mirrors.
+    pub authorization: Option<HeaderValue>,
 }
 
 impl Download {
-    /// Runs a download.
-    pub async fn run(&self, client: &reqwest::Client, options: Options) -> Result<(), Error> {
-        match fs::metadata(&self.destination).await {
-            Ok(_) => match options.preserve {
-                PreservationStrategy::Always => {
-                    debug!("skipped integrity checking");
-                    info!("already downloaded");
-                    return Ok(());
-                }
+    /// Returns a request for the remote artefact that carries the credentials of the download.
+    fn request(&self, client: &reqwest::Client, method: Method) -> RequestBuilder {
+        let request = client.request(method, self.url.clone());
+        match &self.authorization {
+            Some(authorization) => request.header(header::AUTHORIZATION, authorization),
+            None => request,
+        }
+    }
 
-                PreservationStrategy::Checksum => {
-                    let mut bytes = Vec::new();
-                    let mut file =
-                        fs::File::open(&self.destination)
-                            .await
-                            .map_err(|error| Error::Io {
-                                source: error,
-                                path: self.destination.clone(),
-                            })?;
-
-                    file.read_to_end(&mut bytes)
-                        .await
-                        .map_err(|error| Error::Io {
-                            source: error,
-                            path: self.destination.clone(),
-                        })?;
-
-                    if Sha256::digest(bytes).as_ref() == self.checksum.0 {
-                        info!("already downloaded");
-                        return Ok(());
-                    }
-                }
-            },
+    /// Returns the length of the remote artefact in bytes without downloading it. `None` is
+    /// returned if the server does not report a length.
+    pub async fn length(&self, client: &reqwest::Client) -> Result<Option<u64>, Error> {
+        let response = execute(self.request(client, Method::HEAD)).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Http {
+                status,
+                url: self.url.clone(),
+            });
+        }
 
-            Err(error) => {
-                if error.kind() != io::ErrorKind::NotFound {
-                    return Err(Error::Io {
-                        source: error,
-                        path: self.destination.clone(),
-                    });
-                }
-            }
+        Ok(response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok()))
+    }
+
+    /// Returns true if the server reports that the remote artefact has not changed since it was
+    /// downloaded with `validators`. Nothing is downloaded.
+    pub async fn is_not_modified(
+        &self,
+        client: &reqwest::Client,
+        validators: &Validators,
+    ) -> Result<bool, Error> {
+        if validators.is_empty() {
+            return Ok(false);
+        }
+
+        let mut request = self.request(client, Method::HEAD);
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
         }
 
-        let response = client.get(self.url.clone()).send().await?;
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = execute(request).await?;
         let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(true);
+        }
+
         if !status.is_success() {
             return Err(Error::Http {
                 status,
@@ -157,32 +409,358 @@ impl Download {
             });
         }
 
-        let bytes = response.bytes().await?;
-        if Sha256::digest(&bytes).as_ref() != self.checksum.0 {
+        Ok(false)
+    }
+
+    /// Downloads the remote artefact in concurrent ranged requests if it is larger than the
+    /// threshold of `segmentation`. Nothing is returned if the artefact is not large enough or the
+    /// server does not support ranged requests.
+    async fn download_segmented<'c>(
+        &self,
+        client: &reqwest::Client,
+        segmentation: Segmentation,
+        http3: bool,
+        connections: &'c Connections,
+    ) -> Result<Option<(Validators, Vec<u8>, SemaphorePermit<'c>)>, Error> {
+        let response = execute(self.request(client, Method::HEAD)).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Http {
+                status,
+                url: self.url.clone(),
+            });
+        }
+
+        let headers = response.headers();
+        let ranged = headers
+            .get(header::ACCEPT_RANGES)
+            .is_some_and(|value| value.as_bytes() == b"bytes");
+        let Some(length) = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|length| ranged && *length > segmentation.threshold)
+        else {
+            return Ok(None);
+        };
+
+        let validators = Validators::from_headers(headers);
+        let reservation = connections.reserve(Some(length)).await;
+
+        // The segments must be ranges of the same artefact. Only a strong entity tag can be used
+        // to ensure that the artefact has not changed between requests.
+        let condition = validators
+            .etag
+            .as_ref()
+            .filter(|etag| !etag.starts_with("W/"));
+
+        let count = u64::try_from(segmentation.segments.get()).unwrap_or(u64::MAX);
+        let size = length.div_ceil(count.min(length));
+        let segments =
+            stream::iter((0..length).step_by(usize::try_from(size).unwrap_or(usize::MAX)))
+                .map(|start| async move {
+                    let end = (start + size).min(length) - 1;
+                    let mut request = self
+                        .request(client, Method::GET)
+                        .header(header::RANGE, format!("bytes={start}-{end}"));
+                    if let Some(etag) = condition {
+                        request = request.header(header::IF_RANGE, etag);
+                    }
+
+                    let response = send(request, &self.url, http3).await?;
+                    if response.status() != StatusCode::PARTIAL_CONTENT {
+                        return Ok(None);
+                    }
+
+                    let bytes = response.bytes().await?;
+                    Ok::<_, Error>(
+                        (u64::try_from(bytes.len()).ok() == Some(end - start + 1)).then_some(bytes),
+                    )
+                })
+                .buffered(segmentation.segments.get())
+                .try_collect::<Vec<_>>()
+                .await?;
+
+        let mut bytes = Vec::with_capacity(usize::try_from(length).unwrap_or_default());
+        for segment in &segments {
+            let Some(segment) = segment else {
+                debug!("the server did not satisfy a ranged request");
+                return Ok(None);
+            };
+
+            bytes.extend_from_slice(segment);
+        }
+
+        debug!("downloaded in {} segments", segments.len());
+        Ok(Some((validators, bytes, reservation)))
+    }
+
+    /// Returns true if an existing artefact in `form` has the expected checksum. A compressed
+    /// artefact is checked against the checksum that was recorded when it was compressed. Nothing
+    /// is changed.
+    pub async fn is_intact(&self, form: Form, pool: &Pool) -> Result<bool, io::Error> {
+        match form {
+            Form::Plain => {
+                let (_, digest) = pool
+                    .digest(
+                        self.checksum.algorithm(),
+                        file::read(&self.destination).await?,
+                    )
+                    .await;
+                Ok(digest == self.checksum)
+            }
+            Form::Compressed => storage::verify_compressed(&self.destination, pool).await,
+        }
+    }
+
+    /// Returns true if an existing artefact can be preserved. Artefacts that are preserved while
+    /// their integrity is checked are converted to the storage of `options`.
+    async fn preserve(&self, form: Form, options: Options, pool: &Pool) -> Result<bool, io::Error> {
+        match options.preserve {
+            PreservationStrategy::Always => {
+                debug!("skipped integrity checking");
+                return Ok(true);
+            }
+            PreservationStrategy::Never => return Ok(false),
+            _ => (),
+        }
+
+        let bytes = match form {
+            Form::Plain => file::read(&self.destination).await?,
+            Form::Compressed => {
+                if !storage::verify_compressed(&self.destination, pool).await? {
+                    return Ok(false);
+                }
+
+                if options.storage == Storage::Zstd {
+                    return Ok(true);
+                }
+
+                storage::load(&self.destination)
+                    .await?
+                    .expect("stored artefact should exist")
+            }
+        };
+
+        let (bytes, digest) = pool.digest(self.checksum.algorithm(), bytes).await;
+        if digest != self.checksum {
+            return Ok(false);
+        }
+
+        if form == Form::Compressed || options.storage == Storage::Zstd {
+            let form = storage::store(
+                &self.destination,
+                bytes,
+                options.storage,
+                options.durability,
+                options.permissions,
+                pool,
+            )
+            .await?;
+            debug!("stored as {:?}", form);
+        }
+
+        Ok(true)
+    }
+
+    /// Runs a download. Digests are computed in `pool`. If there is a `signature`, the signature
+    /// of a downloaded artefact is verified before it is stored. Existing artefacts are not
+    /// verified again.
+    pub async fn run(
+        &self,
+        client: &reqwest::Client,
+        options: Options,
+        pool: &Pool,
+        connections: &Connections,
+        signature: Option<&Signature>,
+    ) -> Result<Transfer, Error> {
+        let io = |error: io::Error| Error::Io {
+            source: error,
+            path: self.destination.clone(),
+        };
+
+        if let Some(form) = storage::form(&self.destination).await.map_err(io)? {
+            if self.preserve(form, options, pool).await.map_err(io)? {
+                info!("already downloaded");
+                return Ok(match options.preserve {
+                    PreservationStrategy::Always | PreservationStrategy::Never => {
+                        Transfer::Preserved
+                    }
+                    PreservationStrategy::Checksum
+                    | PreservationStrategy::Size
+                    | PreservationStrategy::RecordedSize => Transfer::Verified,
+                });
+            }
+        }
+
+        let connection = connections.acquire().await;
+        let segmented = match options.segmentation {
+            Some(segmentation) => {
+                self.download_segmented(client, segmentation, options.http3, connections)
+                    .await?
+            }
+            None => None,
+        };
+
+        let (validators, bytes, reservation) = if let Some(segmented) = segmented {
+            segmented
+        } else {
+            let response =
+                send(self.request(client, Method::GET), &self.url, options.http3).await?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(Error::Http {
+                    status,
+                    url: self.url.clone(),
+                });
+            }
+
+            // Artefacts that are stored as they were downloaded are written as they are received.
+            // Otherwise, the response is not read until it can be held in memory.
+            if options.storage == Storage::Plain {
+                let validators = Validators::from_headers(response.headers());
+                let (size, digest) = self.stream(response, &options, signature).await?;
+                drop(connection);
+
+                info!("downloaded");
+                return Ok(Transfer::Downloaded {
+                    validators,
+                    size,
+                    digest,
+                });
+            }
+
+            let reservation = connections.reserve(response.content_length()).await;
+            (
+                Validators::from_headers(response.headers()),
+                response.bytes().await?.to_vec(),
+                reservation,
+            )
+        };
+        drop(connection);
+
+        let size = bytes.len() as u64;
+        Span::current().record("bytes", size);
+
+        let (bytes, digest) = pool.digest(self.checksum.algorithm(), bytes).await;
+        if digest != self.checksum {
             return Err(Error::ChecksumMismatch {
                 url: self.url.clone(),
             });
         }
 
-        fs::create_dir_all(
+        file::create_dir_all(
             self.destination
                 .parent()
                 .expect("destination should have a parent"),
+            options.permissions,
+        )
+        .await
+        .map_err(io)?;
+
+        if let Some(signature) = signature {
+            signature
+                .verify(&self.url, &bytes, &self.destination)
+                .await
+                .map_err(|error| self.rejected(signature, error))?;
+            debug!("verified signature");
+        }
+
+        storage::store(
+            &self.destination,
+            bytes,
+            options.storage,
+            options.durability,
+            options.permissions,
+            pool,
         )
         .await
-        .map_err(|error| Error::Io {
+        .map_err(io)?;
+        drop(reservation);
+
+        info!("downloaded");
+        Ok(Transfer::Downloaded {
+            validators,
+            size,
+            digest: None,
+        })
+    }
+
+    /// Writes the body of `response` beside the destination as it is received and moves it into
+    /// place once it has the expected checksum. The checksum and the requested digest are computed
+    /// as the body is written so that the artefact is never held in memory or read again.
+    /// Returns the size of the artefact and its digest.
+    async fn stream(
+        &self,
+        mut response: Response,
+        options: &Options,
+        signature: Option<&Signature>,
+    ) -> Result<(u64, Option<Digest>), Error> {
+        let io = |error: io::Error| Error::Io {
             source: error,
             path: self.destination.clone(),
-        })?;
+        };
 
-        fs::write(&self.destination, bytes)
-            .await
-            .map_err(|error| Error::Io {
-                source: error,
-                path: self.destination.clone(),
-            })?;
+        file::create_dir_all(
+            self.destination
+                .parent()
+                .expect("destination should have a parent"),
+            options.permissions,
+        )
+        .await
+        .map_err(io)?;
 
-        info!("downloaded");
-        Ok(())
+        let mut writer = file::Writer::create(&self.destination).await.map_err(io)?;
+        let mut checksum = Hasher::new(self.checksum.algorithm());
+        let mut digest = self.digest.map(Hasher::new);
+        let mut size = 0_u64;
+        while let Some(chunk) = response.chunk().await? {
+            checksum.update(&chunk);
+            if let Some(digest) = digest.as_mut() {
+                digest.update(&chunk);
+            }
+
+            writer.write(&chunk).await.map_err(io)?;
+            size += chunk.len() as u64;
+        }
+
+        Span::current().record("bytes", size);
+        if checksum.finalize() != self.checksum {
+            return Err(Error::ChecksumMismatch {
+                url: self.url.clone(),
+            });
+        }
+
+        if let Some(signature) = signature {
+            signature
+                .verify_file(&self.url, writer.path())
+                .await
+                .map_err(|error| self.rejected(signature, error))?;
+            debug!("verified signature");
+        }
+
+        storage::store_written(
+            &self.destination,
+            writer,
+            options.durability,
+            options.permissions,
+        )
+        .await
+        .map_err(io)?;
+        Ok((size, digest.map(Hasher::finalize)))
+    }
+
+    /// Returns the error for a failure to verify the signature of the artefact with `signature`.
+    fn rejected(&self, signature: &Signature, error: VerifySignatureError) -> Error {
+        match error {
+            VerifySignatureError::Rejected(reason) => Error::SignatureRejected {
+                url: self.url.clone(),
+                reason,
+            },
+            VerifySignatureError::Io(source) => Error::Io {
+                source,
+                path: signature.verifier.program().to_path_buf(),
+            },
+        }
     }
 }
@@ -0,0 +1,246 @@
+mod permissions;
+#[cfg(test)]
+mod tests;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+pub use permissions::{create_dir_all, Mode, Permissions};
+
+use clap::ArgEnum;
+#[cfg(windows)]
+use std::{
+    ffi::OsString,
+    path::{Component, Prefix},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Specifies how durably files are written before they are moved into place.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Durability {
+    /// Files are moved into place without being flushed to storage. A power loss can leave an
+    /// empty or truncated file in place.
+    None,
+    /// Files are flushed to storage before they are moved into place so that a file in place is
+    /// always complete.
+    #[default]
+    Rename,
+    /// The directory is also flushed to storage after a file is moved into place so that the file
+    /// remains in place after a power loss. Directories are only flushed on Unix.
+    FsyncDir,
+}
+
+/// Returns the extended-length form of `path` (eg. `\\?\C:\cache`) so that the paths that are
+/// joined to it are not limited to `MAX_PATH` characters. Relative paths are made absolute first
+/// because extended-length paths are not normalised.
+#[cfg(windows)]
+pub fn extended(path: &Path) -> Result<PathBuf, io::Error> {
+    let path = std::path::absolute(path)?;
+    let mut components = path.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(disk) => OsString::from(format!(r"\\?\{}:\", char::from(disk))),
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                extended.push(r"\");
+                extended
+            }
+
+            // Verbatim and device paths are not limited.
+            _ => return Ok(path),
+        },
+        _ => return Ok(path),
+    };
+
+    // The root is part of the prefix and the path is already normalised.
+    let mut separate = false;
+    for component in components {
+        if let Component::Normal(component) = component {
+            if separate {
+                extended.push(r"\");
+            }
+
+            extended.push(component);
+            separate = true;
+        }
+    }
+
+    Ok(PathBuf::from(extended))
+}
+
+/// Returns `path` because paths are only limited to `MAX_PATH` characters on Windows.
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+pub fn extended(path: &Path) -> Result<PathBuf, io::Error> {
+    Ok(path.to_path_buf())
+}
+
+/// Reads the entire contents of the file at `path`.
+///
+/// Files are read with `io_uring` when the `io-uring` feature is enabled and the kernel supports it.
+pub async fn read(path: &Path) -> Result<Vec<u8>, io::Error> {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if let Some(uring) = uring::Uring::get() {
+        return uring.read(path.to_path_buf()).await;
+    }
+
+    fs::read(path).await
+}
+
+/// A file that is being written beside its destination. The file is removed if it is dropped
+/// before it is moved to its destination so that a cancelled write does not leave it behind.
+pub struct Partial {
+    path: Option<PathBuf>,
+}
+
+impl Partial {
+    /// Returns the partial file for the destination `path`.
+    pub fn new(path: &Path) -> Self {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        Self {
+            path: Some(PathBuf::from(partial)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path
+            .as_deref()
+            .expect("partial file has been persisted")
+    }
+
+    /// Moves the partial file to `destination`.
+    pub async fn persist(mut self, destination: &Path) -> Result<(), io::Error> {
+        fs::rename(self.path(), destination).await?;
+        self.path = None;
+        Ok(())
+    }
+}
+
+impl Drop for Partial {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            drop(std::fs::remove_file(path));
+        }
+    }
+}
+
+/// Writes `bytes` as the entire contents of the file at `path`. The file is created if it does not
+/// exist and replaced if it does.
+///
+/// The file is replaced atomically so that a write that fails or is cancelled never leaves a
+/// truncated file at `path`, and it is flushed to storage as described by `durability`. The file
+/// is given `permissions` before it is moved into place. Files are written with
+/// `io_uring` when the `io-uring` feature is enabled and the kernel supports it.
+pub async fn write(
+    path: &Path,
+    bytes: Vec<u8>,
+    durability: Durability,
+    permissions: Permissions,
+) -> Result<(), io::Error> {
+    let partial = Partial::new(path);
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if let Some(uring) = uring::Uring::get() {
+        uring.write(partial.path().to_path_buf(), bytes).await?;
+        if durability >= Durability::Rename {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(partial.path())
+                .await?
+                .sync_all()
+                .await?;
+        }
+
+        return persist(partial, path, durability, permissions).await;
+    }
+
+    let mut file = fs::File::create(partial.path()).await?;
+    file.write_all(&bytes).await?;
+    if durability >= Durability::Rename {
+        file.sync_all().await?;
+    }
+
+    drop(file);
+    persist(partial, path, durability, permissions).await
+}
+
+/// A file that is written in chunks beside its destination as its contents are received. The
+/// file is removed if it is dropped before it is moved into place.
+pub struct Writer {
+    partial: Partial,
+    file: fs::File,
+}
+
+impl Writer {
+    /// Creates a file that is moved to `path` once it is complete.
+    pub async fn create(path: &Path) -> Result<Self, io::Error> {
+        let partial = Partial::new(path);
+        let file = fs::File::create(partial.path()).await?;
+        Ok(Self { partial, file })
+    }
+
+    /// Returns the path that the file is written to before it is moved into place.
+    pub fn path(&self) -> &Path {
+        self.partial.path()
+    }
+
+    /// Appends `bytes` to the file.
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        self.file.write_all(bytes).await
+    }
+
+    /// Flushes the file as described by `durability`, gives it `permissions` and moves it to
+    /// `path`.
+    pub async fn persist(
+        mut self,
+        path: &Path,
+        durability: Durability,
+        permissions: Permissions,
+    ) -> Result<(), io::Error> {
+        self.file.flush().await?;
+        if durability >= Durability::Rename {
+            self.file.sync_all().await?;
+        }
+
+        drop(self.file);
+        persist(self.partial, path, durability, permissions).await
+    }
+}
+
+/// Moves a partial file that was written with `durability` to `path` once it has `permissions`.
+async fn persist(
+    partial: Partial,
+    path: &Path,
+    durability: Durability,
+    permissions: Permissions,
+) -> Result<(), io::Error> {
+    permissions.apply_to_file(partial.path()).await?;
+    partial.persist(path).await?;
+    if durability == Durability::FsyncDir {
+        if let Some(parent) = path.parent() {
+            sync_directory(parent).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Flushes the entries of the directory at `path` to storage.
+#[cfg(unix)]
+async fn sync_directory(path: &Path) -> Result<(), io::Error> {
+    fs::File::open(path).await?.sync_all().await
+}
+
+/// Does nothing because directories can only be flushed on Unix.
+#[cfg(not(unix))]
+#[allow(clippy::unused_async)]
+async fn sync_directory(_: &Path) -> Result<(), io::Error> {
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::Path,
+    str::FromStr,
+};
+use tokio::fs;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseModeError;
+
+impl Display for ParseModeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "mode must be written in octal (eg. 0644)")
+    }
+}
+
+impl Error for ParseModeError {}
+
+/// The permission bits of a file or directory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Mode(pub(super) u32);
+
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0o").unwrap_or(s);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(Self(mode)),
+            _ => Err(ParseModeError),
+        }
+    }
+}
+
+/// Describes the permissions and ownership that files and directories are written with. Anything
+/// that is not given is left as the process creates it. Permissions are only applied on Unix.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Permissions {
+    pub file_mode: Option<Mode>,
+    pub directory_mode: Option<Mode>,
+    /// The user that owns files and directories. Only a privileged process can change it.
+    pub owner: Option<u32>,
+    /// The group that owns files and directories.
+    pub group: Option<u32>,
+}
+
+impl Permissions {
+    /// Returns true if files and directories are written as the process creates them.
+    fn is_default(self) -> bool {
+        self == Self::default()
+    }
+
+    /// Applies the permissions to the file at `path`.
+    pub async fn apply_to_file(self, path: &Path) -> Result<(), io::Error> {
+        self.apply(path, self.file_mode).await
+    }
+
+    /// Applies the permissions to the directory at `path`.
+    pub async fn apply_to_directory(self, path: &Path) -> Result<(), io::Error> {
+        self.apply(path, self.directory_mode).await
+    }
+
+    #[cfg(unix)]
+    async fn apply(self, path: &Path, mode: Option<Mode>) -> Result<(), io::Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if self.owner.is_some() || self.group.is_some() {
+            let (path, owner, group) = (path.to_path_buf(), self.owner, self.group);
+            tokio::task::spawn_blocking(move || std::os::unix::fs::chown(path, owner, group))
+                .await
+                .expect("panicked while changing ownership")?;
+        }
+
+        // The mode is set after the owner because changing the owner can clear the setuid and
+        // setgid bits.
+        if let Some(Mode(mode)) = mode {
+            fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    #[allow(clippy::unused_async)]
+    async fn apply(self, _: &Path, _: Option<Mode>) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// The number of times that directories are created before an ancestor that keeps being removed is
+/// reported as missing.
+const ATTEMPTS: usize = 3;
+
+/// Creates the directory at `path` and any of its parents that do not exist. Directories that are
+/// created are given `permissions`.
+///
+/// Empty directories are pruned as crates are removed so an ancestor can be removed by another
+/// task while its children are being created. The directories are created again when that happens.
+pub async fn create_dir_all(path: &Path, permissions: Permissions) -> Result<(), io::Error> {
+    let mut attempt = 1;
+    loop {
+        match create(path, permissions).await {
+            Err(error) if error.kind() == io::ErrorKind::NotFound && attempt < ATTEMPTS => {
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn create(path: &Path, permissions: Permissions) -> Result<(), io::Error> {
+    if permissions.is_default() {
+        return fs::create_dir_all(path).await;
+    }
+
+    // The directories that do not exist are found first so that only they are changed.
+    let mut missing = Vec::new();
+    let mut ancestor = Some(path);
+    while let Some(each) = ancestor {
+        if fs::metadata(each).await.is_ok() {
+            break;
+        }
+
+        missing.push(each);
+        ancestor = each.parent();
+    }
+
+    for directory in missing.into_iter().rev() {
+        match fs::create_dir(directory).await {
+            Ok(()) => permissions.apply_to_directory(directory).await?,
+
+            // Another task created the directory first.
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
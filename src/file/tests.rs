@@ -0,0 +1,95 @@
+use super::{extended, write, Durability, Mode, Partial, Permissions};
+use std::path::Path;
+use tempfile::TempDir;
+use tokio::fs;
+
+#[tokio::test]
+async fn test_write() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    for durability in [Durability::None, Durability::Rename, Durability::FsyncDir] {
+        let path = directory.path().join("download");
+        write(&path, b"crate".to_vec(), durability, Permissions::default())
+            .await
+            .expect("failed to write file");
+
+        assert_eq!(
+            fs::read(&path).await.expect("failed to read file"),
+            b"crate"
+        );
+        assert!(
+            fs::metadata(Partial::new(&path).path()).await.is_err(),
+            "partial file was left behind"
+        );
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_write_with_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    for mode in [0o600, 0o640] {
+        let path = directory.path().join("download");
+        let permissions = Permissions {
+            file_mode: Some(Mode(mode)),
+            ..Permissions::default()
+        };
+        write(&path, b"crate".to_vec(), Durability::Rename, permissions)
+            .await
+            .expect("failed to write file");
+
+        let metadata = fs::metadata(&path).await.expect("failed to read metadata");
+        assert_eq!(metadata.permissions().mode() & 0o7777, mode);
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn test_extended_disk() {
+    assert_eq!(
+        extended(Path::new(r"C:\cache\crates")).expect("failed to extend path"),
+        Path::new(r"\\?\C:\cache\crates")
+    );
+    assert_eq!(
+        extended(Path::new(r"C:\cache\..\mirror\.\crates")).expect("failed to extend path"),
+        Path::new(r"\\?\C:\mirror\crates")
+    );
+    assert_eq!(
+        extended(Path::new(r"C:\")).expect("failed to extend path"),
+        Path::new(r"\\?\C:\")
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn test_extended_unc() {
+    assert_eq!(
+        extended(Path::new(r"\\server\share\cache")).expect("failed to extend path"),
+        Path::new(r"\\?\UNC\server\share\cache")
+    );
+    assert_eq!(
+        extended(Path::new(r"\\?\C:\cache")).expect("failed to extend path"),
+        Path::new(r"\\?\C:\cache")
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_extended_is_unchanged() {
+    assert_eq!(
+        extended(Path::new("relative/cache")).expect("failed to extend path"),
+        Path::new("relative/cache")
+    );
+}
+
+#[test]
+fn test_parse_mode() {
+    assert_eq!("0644".parse::<Mode>(), Ok(Mode(0o644)));
+    assert_eq!("755".parse::<Mode>(), Ok(Mode(0o755)));
+    assert_eq!("0o2775".parse::<Mode>(), Ok(Mode(0o2775)));
+    assert!("".parse::<Mode>().is_err());
+    assert!("0648".parse::<Mode>().is_err());
+    assert!("17777".parse::<Mode>().is_err());
+    assert!("rw-r--r--".parse::<Mode>().is_err());
+}
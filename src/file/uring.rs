@@ -0,0 +1,165 @@
+// Files that are opened with `io_uring` are bound to the thread that opened them.
+#![allow(clippy::future_not_send)]
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{mpsc as std_mpsc, OnceLock},
+    thread,
+};
+use tokio::sync::{mpsc, oneshot};
+use tokio_uring::{buf::IoBuf, fs::File};
+use tracing::{debug, warn};
+
+/// The number of bytes that are requested by each read.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file operation that is performed by the `io_uring` thread.
+enum Request {
+    Read {
+        path: PathBuf,
+        reply: oneshot::Sender<io::Result<Vec<u8>>>,
+    },
+    Write {
+        path: PathBuf,
+        bytes: Vec<u8>,
+        reply: oneshot::Sender<io::Result<()>>,
+    },
+}
+
+/// A handle to a thread that performs file operations with `io_uring`.
+///
+/// An `io_uring` runtime can not share threads with the runtime that drives the rest of the program
+/// so file operations are sent to a dedicated thread.
+pub struct Uring {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+/// The `io_uring` thread. It is started on first use and nothing is stored if `io_uring` is not
+/// supported.
+static URING: OnceLock<Option<Uring>> = OnceLock::new();
+
+impl Uring {
+    /// Returns a handle to the `io_uring` thread or nothing if `io_uring` is not supported.
+    pub fn get() -> Option<&'static Self> {
+        URING
+            .get_or_init(|| match Self::start() {
+                Ok(uring) => {
+                    debug!("started io_uring thread");
+                    Some(uring)
+                }
+                Err(error) => {
+                    warn!("failed to start io_uring; falling back to tokio: {}", error);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Starts the `io_uring` thread.
+    fn start() -> Result<Self, io::Error> {
+        let (requests, mut receiver) = mpsc::unbounded_channel();
+        let (started, startup) = std_mpsc::channel();
+
+        thread::Builder::new()
+            .name(String::from("io-uring"))
+            .spawn(move || {
+                let runtime = match tokio_uring::Runtime::new(&tokio_uring::builder()) {
+                    Ok(runtime) => {
+                        drop(started.send(Ok(())));
+                        runtime
+                    }
+                    Err(error) => {
+                        drop(started.send(Err(error)));
+                        return;
+                    }
+                };
+
+                runtime.block_on(async move {
+                    while let Some(request) = receiver.recv().await {
+                        tokio_uring::spawn(handle(request));
+                    }
+                });
+            })?;
+
+        startup
+            .recv()
+            .expect("io_uring thread exited before starting")?;
+        Ok(Self { requests })
+    }
+
+    /// Sends a request to the `io_uring` thread and waits for the reply.
+    async fn send<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<io::Result<T>>) -> Request,
+    ) -> io::Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send(request(reply))
+            .unwrap_or_else(|_| panic!("io_uring thread has stopped"));
+        response.await.expect("io_uring thread dropped a request")
+    }
+
+    /// Reads the entire contents of the file at `path`.
+    pub async fn read(&self, path: PathBuf) -> io::Result<Vec<u8>> {
+        self.send(|reply| Request::Read { path, reply }).await
+    }
+
+    /// Writes `bytes` as the entire contents of the file at `path`.
+    pub async fn write(&self, path: PathBuf, bytes: Vec<u8>) -> io::Result<()> {
+        self.send(|reply| Request::Write { path, bytes, reply })
+            .await
+    }
+}
+
+/// Performs a request and sends the result to its reply channel.
+async fn handle(request: Request) {
+    match request {
+        Request::Read { path, reply } => drop(reply.send(read(path).await)),
+        Request::Write { path, bytes, reply } => drop(reply.send(write(path, bytes).await)),
+    }
+}
+
+async fn read(path: PathBuf) -> io::Result<Vec<u8>> {
+    let file = File::open(path).await?;
+    let mut contents = Vec::new();
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+
+    let result = loop {
+        chunk.clear();
+        let (result, returned) = file.read_at(chunk, contents.len() as u64).await;
+        chunk = returned;
+
+        match result {
+            Ok(0) => break Ok(()),
+            Ok(read) => contents.extend_from_slice(&chunk[..read]),
+            Err(error) => break Err(error),
+        }
+    };
+
+    file.close().await?;
+    result.map(|()| contents)
+}
+
+async fn write(path: PathBuf, mut bytes: Vec<u8>) -> io::Result<()> {
+    let file = File::create(path).await?;
+    let mut written = 0;
+
+    let result = loop {
+        if written == bytes.len() {
+            break Ok(());
+        }
+
+        let (result, slice) = file.write_at(bytes.slice(written..), written as u64).await;
+        bytes = slice.into_inner();
+
+        match result {
+            Ok(0) => break Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(each) => written += each,
+            Err(error) => break Err(error),
+        }
+    };
+
+    file.close().await?;
+    result
+}
@@ -0,0 +1,319 @@
+#[cfg(test)]
+pub mod tests;
+
+use clap::ArgEnum;
+use reqwest::header::{self, HeaderName, HeaderValue};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic, RollingFrequency};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::{Display, Formatter},
+    fs,
+    io::{self, Write},
+    path::Path,
+    process,
+    sync::Mutex,
+};
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::{
+    filter::Targets,
+    fmt::{self, MakeWriter},
+    registry::LookupSpan,
+    Layer,
+};
+
+/// The target of events that summarise an operation. They are logged in quiet mode.
+pub const SUMMARY: &str = "crateful::summary";
+
+/// The target of events that trace HTTP requests, responses, redirects, and retries. They are only
+/// recorded when HTTP tracing is enabled.
+pub const HTTP: &str = "crateful::http";
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TargetError {
+    /// The journal can not be written to on this platform.
+    #[cfg(not(target_os = "linux"))]
+    Unsupported,
+    Journald(io::Error),
+    Syslog(String),
+}
+
+impl Display for TargetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(not(target_os = "linux"))]
+            Self::Unsupported => write!(f, "journald is only available on Linux"),
+            Self::Journald(error) => write!(f, "failed to connect to journald: {error}"),
+            Self::Syslog(error) => write!(f, "failed to connect to syslog: {error}"),
+        }
+    }
+}
+
+impl Error for TargetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(not(target_os = "linux"))]
+            Self::Unsupported => None,
+            Self::Syslog(_) => None,
+            Self::Journald(error) => Some(error),
+        }
+    }
+}
+
+/// Specifies where logs are written.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Target {
+    /// Events are written to standard error.
+    #[default]
+    Stderr,
+    /// Events are written to the systemd journal with their fields.
+    Journald,
+    /// Events are written to the local syslog daemon.
+    Syslog,
+}
+
+/// Specifies the format that logs are written in.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Format {
+    /// Events are written as lines of human-readable text.
+    #[default]
+    Text,
+    /// Events are written as lines of JSON objects.
+    Json,
+}
+
+/// Specifies how often a log file is rotated regardless of its size.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Rotation {
+    /// The log file is only rotated when it reaches its maximum size.
+    #[default]
+    Never,
+    /// The log file is rotated at the start of every hour.
+    Hourly,
+    /// The log file is rotated at the start of every day.
+    Daily,
+}
+
+/// Returns the filter of events that are recorded at all. Events are recorded at `level` and HTTP
+/// events are recorded as well when `http` is set.
+pub fn levels(level: Level, http: bool) -> Targets {
+    let levels = Targets::new().with_default(level);
+    if http {
+        levels.with_target(HTTP, Level::TRACE)
+    } else {
+        levels
+    }
+}
+
+/// Returns the filter of the layer that writes events to the log target. Only errors, HTTP events,
+/// and the summary of the operation pass when `quiet` is set. Otherwise, the log level alone
+/// decides.
+pub fn filter(quiet: bool) -> Targets {
+    if quiet {
+        Targets::new()
+            .with_default(Level::ERROR)
+            .with_target(SUMMARY, Level::INFO)
+            .with_target(HTTP, Level::TRACE)
+    } else {
+        Targets::new().with_default(Level::TRACE)
+    }
+}
+
+/// Returns the value of a header as it is logged. Credentials are redacted.
+#[must_use]
+pub fn redact<'a>(name: &HeaderName, value: &'a HeaderValue) -> Cow<'a, str> {
+    let credential = [
+        header::AUTHORIZATION,
+        header::PROXY_AUTHORIZATION,
+        header::COOKIE,
+        header::SET_COOKIE,
+    ]
+    .contains(name);
+
+    if credential || value.is_sensitive() {
+        Cow::Borrowed("<redacted>")
+    } else {
+        String::from_utf8_lossy(value.as_bytes())
+    }
+}
+
+/// Returns a layer that writes events to `writer` in `format`. Text is coloured when `ansi` is
+/// set.
+pub fn layer<S, W>(format: Format, ansi: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_ansi(ansi).with_writer(writer);
+    match format {
+        Format::Text => layer.boxed(),
+        Format::Json => layer.json().boxed(),
+    }
+}
+
+/// Returns a layer that writes events to `target`. Events written to standard error or syslog are
+/// written in `format`.
+pub fn target<S>(
+    target: Target,
+    format: Format,
+) -> Result<Box<dyn Layer<S> + Send + Sync>, TargetError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    match target {
+        Target::Stderr => Ok(layer(format, true, io::stderr)),
+        Target::Journald => journald(),
+        Target::Syslog => {
+            let logger = syslog::unix(Formatter3164 {
+                facility: Facility::LOG_DAEMON,
+                hostname: None,
+                process: String::from(env!("CARGO_PKG_NAME")),
+                pid: process::id(),
+            })
+            .map_err(|error| {
+                TargetError::Syslog(
+                    error
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(": "),
+                )
+            })?;
+
+            Ok(syslog(logger, format))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn journald<S>() -> Result<Box<dyn Layer<S> + Send + Sync>, TargetError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_journald::layer()
+        .map(Layer::boxed)
+        .map_err(TargetError::Journald)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn journald<S>() -> Result<Box<dyn Layer<S> + Send + Sync>, TargetError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    Err(TargetError::Unsupported)
+}
+
+/// Returns a layer that sends events to `logger` in `format`. The daemon records the time that
+/// each event is received so events do not include it.
+fn syslog<S>(
+    logger: Logger<LoggerBackend, Formatter3164>,
+    format: Format,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let layer = fmt::layer()
+        .with_ansi(false)
+        .without_time()
+        .with_writer(Syslog(Mutex::new(logger)));
+
+    match format {
+        Format::Text => layer.boxed(),
+        Format::Json => layer.json().boxed(),
+    }
+}
+
+/// Makes writers that send each event to syslog with the severity of its level.
+struct Syslog(Mutex<Logger<LoggerBackend, Formatter3164>>);
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = Message<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Message {
+            logger: &self.0,
+            level: Level::INFO,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        Message {
+            logger: &self.0,
+            level: *meta.level(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// An event that is sent to syslog when it is dropped.
+struct Message<'a> {
+    logger: &'a Mutex<Logger<LoggerBackend, Formatter3164>>,
+    level: Level,
+    buffer: Vec<u8>,
+}
+
+impl Write for Message<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Message<'_> {
+    fn drop(&mut self) {
+        let message = String::from_utf8_lossy(&self.buffer);
+        let message = message.trim_end();
+        if message.is_empty() {
+            return;
+        }
+
+        let mut logger = self.logger.lock().expect("lock is poisoned");
+        let result = match self.level {
+            Level::ERROR => logger.err(message),
+            Level::WARN => logger.warning(message),
+            Level::INFO => logger.info(message),
+            _ => logger.debug(message),
+        };
+
+        // Errors can not be logged without writing to syslog again.
+        if let Err(error) = result {
+            eprintln!("failed to write to syslog: {error}");
+        }
+    }
+}
+
+/// Opens the log file at `path`. The file is rotated as described by `rotation` and when it is
+/// larger than `max_size` bytes. Rotated files are renamed with a numeric suffix and only
+/// `max_files` of them are kept.
+pub fn file(
+    path: &Path,
+    rotation: Rotation,
+    max_size: Option<u64>,
+    max_files: usize,
+) -> Result<Mutex<BasicRollingFileAppender>, io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut condition = RollingConditionBasic::new();
+    condition = match rotation {
+        Rotation::Never => condition,
+        Rotation::Hourly => condition.frequency(RollingFrequency::EveryHour),
+        Rotation::Daily => condition.frequency(RollingFrequency::EveryDay),
+    };
+
+    if let Some(max_size) = max_size {
+        condition = condition.max_size(max_size);
+    }
+
+    // Events are not buffered so that the log file can be followed while the program runs.
+    BasicRollingFileAppender::new_with_buffer_capacity(path, condition, max_files, 0)
+        .map(Mutex::new)
+}
@@ -0,0 +1,85 @@
+use super::*;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use tempfile::TempDir;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[cfg(unix)]
+#[test]
+fn test_syslog_severity() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join("log");
+    let daemon = UnixDatagram::bind(&path).expect("failed to bind syslog socket");
+
+    let logger = syslog::unix_custom(
+        Formatter3164 {
+            facility: Facility::LOG_DAEMON,
+            hostname: None,
+            process: String::from("crateful"),
+            pid: 1,
+        },
+        &path,
+    )
+    .expect("failed to connect to syslog socket");
+
+    let subscriber = tracing_subscriber::registry().with(syslog(logger, Format::Text));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(name = "a", "failed to download");
+        tracing::info!("downloaded");
+    });
+
+    let mut buffer = [0; 1024];
+    let mut receive = || {
+        let length = daemon.recv(&mut buffer).expect("failed to receive message");
+        String::from_utf8_lossy(&buffer[..length]).into_owned()
+    };
+
+    // The priority is the facility multiplied by 8 plus the severity.
+    let warning = receive();
+    assert!(warning.starts_with("<28>"), "{warning}");
+    assert!(
+        warning.ends_with("failed to download name=\"a\""),
+        "{warning}"
+    );
+
+    let information = receive();
+    assert!(information.starts_with("<30>"), "{information}");
+    assert!(information.ends_with("downloaded"), "{information}");
+}
+
+#[test]
+fn test_quiet_filter() {
+    let quiet = filter(true);
+    assert!(quiet.would_enable(SUMMARY, &Level::INFO));
+    assert!(quiet.would_enable("crateful::registry::cache", &Level::ERROR));
+    assert!(!quiet.would_enable("crateful::registry::cache", &Level::WARN));
+    assert!(!quiet.would_enable(SUMMARY, &Level::DEBUG));
+
+    let verbose = filter(false);
+    assert!(verbose.would_enable("crateful::registry::cache", &Level::TRACE));
+}
+
+#[test]
+fn test_http_levels() {
+    let traced = levels(Level::INFO, true);
+    assert!(traced.would_enable(HTTP, &Level::TRACE));
+    assert!(!traced.would_enable("crateful::registry::cache", &Level::DEBUG));
+
+    let untraced = levels(Level::INFO, false);
+    assert!(!untraced.would_enable(HTTP, &Level::TRACE));
+}
+
+#[test]
+fn test_redact() {
+    let value = HeaderValue::from_static("secret");
+    assert_eq!(redact(&header::AUTHORIZATION, &value), "<redacted>");
+    assert_eq!(redact(&header::COOKIE, &value), "<redacted>");
+    assert_eq!(redact(&header::ACCEPT, &value), "secret");
+
+    let mut sensitive = HeaderValue::from_static("secret");
+    sensitive.set_sensitive(true);
+    assert_eq!(
+        redact(&HeaderName::from_static("x-token"), &sensitive),
+        "<redacted>"
+    );
+}
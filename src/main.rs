@@ -1,71 +1,1711 @@
 #![warn(clippy::all, clippy::cargo, clippy::nursery, clippy::pedantic)]
-#![allow(clippy::multiple_crate_versions)]
+#![allow(
+    clippy::literal_string_with_formatting_args,
+    clippy::multiple_crate_versions,
+    clippy::significant_drop_tightening
+)]
 
+mod cargo;
+mod config;
 mod digest;
 mod download;
+mod file;
+mod logging;
+mod minisign;
+mod notify;
 mod registry;
+mod resolve;
+mod signature;
+mod storage;
+#[cfg(feature = "otlp")]
+mod telemetry;
 
-use clap::{Parser, Subcommand};
-use eyre::Result;
-use registry::cache::Cache;
-use reqwest::{Client, ClientBuilder};
-use std::{num::NonZeroUsize, path::PathBuf};
-use tracing::info;
+use cargo::SourceReplacement;
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use config::Config;
+use eyre::{eyre, Result};
+use file::{Durability, Mode, Permissions};
+use git2::Oid;
+use logging::{HTTP, SUMMARY};
+use minisign::SecretKey;
+use registry::{
+    cache::{
+        archive,
+        doctor::{self, Severity},
+        feed::Feed,
+        filter::{Constraint, Date, Filter, Preset, YankPolicy},
+        ipfs::Ipfs,
+        layout::Layout,
+        lock::LockError,
+        oci::{self, Oci},
+        plan::{Estimate, Plan},
+        selector::Selector,
+        ApplyBundleError, Cache, CreateCacheError, FailureMode, LoadCacheError, Order, Outcome,
+        RefreshCacheError, RemovalStrategy, RepairIndexError, Settings, SynchroniseError,
+        UpdateError,
+    },
+    index::{
+        authentication::Authentication,
+        package::CrateKey,
+        scope::{Scope, Shard},
+        CheckIntegrityError, GetConfigurationError,
+    },
+    politeness,
+    sparse::SparseIndex,
+};
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION},
+    redirect::Policy,
+    Client, ClientBuilder,
+};
+use signature::Verifier;
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    future,
+    io::{self, Write},
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use storage::Storage;
+use tokio::{fs, signal};
+use tracing::{error, info, info_span, trace, warn, Level};
+use tracing_futures::Instrument;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
 use url::Url;
 
 const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
-async fn new(path: PathBuf, url: Url) -> Result<()> {
-    drop(Cache::new(path, url).await?);
-    info!("created cache");
+/// Creates a cache for the index at `url`. The cache tracks `crates` and every crate that they
+/// depend on if the index is a sparse index.
+async fn create(
+    path: PathBuf,
+    url: &Url,
+    authentication: Authentication,
+    layout: Layout,
+    crates: &[String],
+    client: &Client,
+    jobs: Option<NonZeroUsize>,
+) -> Result<Cache> {
+    let mut cache = if SparseIndex::is_sparse(url) {
+        let jobs = politeness::jobs(jobs, url.host_str());
+        Cache::from_sparse(path, url, crates, client, jobs).await?
+    } else {
+        Cache::new(path, url.clone(), authentication).await?
+    };
+
+    // A new cache holds no crates so recording the layout moves nothing.
+    if layout != cache.layout() {
+        cache.migrate_layout(layout, Permissions::default()).await?;
+    }
+
+    info!(target: SUMMARY, "created cache with the {} layout", layout);
+    Ok(cache)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn new(
+    path: PathBuf,
+    url: Url,
+    authentication: Authentication,
+    layout: Layout,
+    rewrite: Option<String>,
+    crates: Option<PathBuf>,
+    client: &Client,
+    jobs: Option<NonZeroUsize>,
+) -> Result<Outcome> {
+    let crates = match crates {
+        Some(crates) => fs::read_to_string(crates)
+            .await?
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let cache = create(path, &url, authentication, layout, &crates, client, jobs).await?;
+
+    if let Some(template) = rewrite {
+        cache.rewrite(template).await?;
+        info!(target: SUMMARY, "rewrote the download template");
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn configure_cargo(path: PathBuf, arguments: ConfigureCargoArguments) -> Result<Outcome> {
+    let cache = Cache::from_path(path).await?;
+    if cache.rewritten_template().await?.is_none() {
+        warn!("the download template was not rewritten so clients will use the registry");
+    }
+
+    let index = if let Some(index) = arguments.index_url {
+        index
+    } else {
+        let path = fs::canonicalize(cache.index_path()).await?;
+        Url::from_directory_path(&path)
+            .map_err(|()| eyre!("{} can not be used as a URL", path.to_string_lossy()))?
+    };
+
+    let replacement = SourceReplacement {
+        name: arguments.name,
+        replaces: arguments.replaces,
+        index,
+    };
+
+    match arguments.write {
+        Some(configuration) => {
+            replacement.write(&configuration).await?;
+            info!(
+                target: SUMMARY,
+                "wrote source replacement to {}",
+                configuration.to_string_lossy()
+            );
+        }
+        None => print!("{replacement}"),
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn rewrite(path: PathBuf, template: String) -> Result<Outcome> {
+    Cache::from_path(path).await?.rewrite(template).await?;
+    info!(target: SUMMARY, "rewrote the download template");
+
+    Ok(Outcome::default())
+}
+
+async fn export_sparse(path: PathBuf, lenient: bool) -> Result<Outcome> {
+    let cache = open(path, lenient).await?;
+    cache.export_sparse().await?;
+    report_skipped(&cache);
+    info!(
+        target: SUMMARY,
+        "exported the sparse index to {}",
+        cache.sparse_path().display()
+    );
+
+    Ok(Outcome::default())
+}
+
+async fn export_cargo(
+    path: PathBuf,
+    jobs: Option<NonZeroUsize>,
+    filter: &Filter,
+    output: &Path,
+    source: Option<&Path>,
+    lenient: bool,
+) -> Result<Outcome> {
+    let cache = open(path, lenient).await?;
+    let mut filter = filter.clone();
+    filter.deny.extend_from_slice(cache.denied());
+    let exported = cache
+        .export_cargo(politeness::local(jobs), &filter, output, source)
+        .await?;
+    report_skipped(&cache);
+    info!(
+        target: SUMMARY,
+        "exported {} crates and unpacked {} crates", exported.copied, exported.unpacked
+    );
+
+    Ok(Outcome::default())
+}
+
+async fn read(
+    path: PathBuf,
+    name: &str,
+    version: &str,
+    check: bool,
+    permissions: Permissions,
+    lenient: bool,
+) -> Result<Outcome> {
+    let bytes = open(path, lenient)
+        .await?
+        .read(name, version, check, permissions)
+        .await?
+        .ok_or_else(|| eyre!("{} {} is not in the cache", name, version))?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()?;
+
+    Ok(Outcome::default())
+}
+
+async fn dependencies(path: PathBuf, name: &str, version: &str, lenient: bool) -> Result<Outcome> {
+    let dependencies = open(path, lenient)
+        .await?
+        .dependencies(name, version)
+        .await?
+        .ok_or_else(|| eyre!("{} {} is not in the index", name, version))?;
+
+    let mut stdout = io::stdout().lock();
+    for dependency in &dependencies {
+        writeln!(stdout, "{dependency}")?;
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn dependents(
+    path: PathBuf,
+    name: String,
+    transitive: bool,
+    lenient: bool,
+) -> Result<Outcome> {
+    let mut dependents = open(path, lenient)
+        .await?
+        .dependents(name, transitive)
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    dependents.sort_unstable();
+
+    let mut stdout = io::stdout().lock();
+    for dependent in dependents {
+        writeln!(stdout, "{dependent}")?;
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn changes(
+    path: PathBuf,
+    client: &Client,
+    jobs: Option<NonZeroUsize>,
+    json: bool,
+    lenient: bool,
+) -> Result<Outcome> {
+    let cache = open(path, lenient).await?;
+    let jobs = politeness::jobs(jobs, cache.download_host().await.as_deref());
+    let changes = cache.pending_changes(client, jobs).await?;
+    report_skipped(&cache);
+
+    let mut stdout = io::stdout().lock();
+    for change in changes {
+        if json {
+            writeln!(
+                stdout,
+                "{}",
+                serde_json::to_string(&change).expect("failed to serialise change")
+            )?;
+        } else {
+            writeln!(stdout, "{change}")?;
+        }
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn sbom(
+    path: PathBuf,
+    jobs: Option<NonZeroUsize>,
+    output: Option<PathBuf>,
+    signing_key: Option<PathBuf>,
+    lenient: bool,
+) -> Result<Outcome> {
+    // The key is loaded first so that a bill of materials is not written without its signature.
+    let key = match signing_key {
+        Some(path) => Some(SecretKey::load(&path).await?),
+        None => None,
+    };
+
+    let time = archive::timestamp(SystemTime::now());
+    let cache = open(path, lenient).await?;
+    let bom = cache
+        .bill_of_materials(politeness::local(jobs), time)
+        .await?;
+    report_skipped(&cache);
+    let json = serde_json::to_string_pretty(&bom).expect("failed to serialise bill of materials");
+
+    let Some(output) = output else {
+        println!("{json}");
+        return Ok(Outcome::default());
+    };
+
+    file::write(
+        &output,
+        format!("{json}\n").into_bytes(),
+        Durability::Rename,
+        Permissions::default(),
+    )
+    .await?;
+    if let Some(key) = key {
+        let signature = key.sign(&output, time).await?;
+        info!("signed the bill of materials in {}", signature.display());
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn metalink(
+    path: PathBuf,
+    jobs: Option<NonZeroUsize>,
+    mirrors: &[Url],
+    lenient: bool,
+) -> Result<Outcome> {
+    let cache = open(path, lenient).await?;
+    let metalink = cache
+        .metalink(
+            politeness::local(jobs),
+            mirrors,
+            archive::timestamp(SystemTime::now()),
+        )
+        .await?;
+    report_skipped(&cache);
+
+    print!("{metalink}");
+    Ok(Outcome::default())
+}
+
+async fn bundle(
+    path: PathBuf,
+    from: Oid,
+    to: Option<Oid>,
+    output: PathBuf,
+    signing_key: Option<PathBuf>,
+    lenient: bool,
+) -> Result<Outcome> {
+    // The key is loaded first so that a bundle is not written without its signature.
+    let key = match signing_key {
+        Some(path) => Some(SecretKey::load(&path).await?),
+        None => None,
+    };
+
+    let cache = open(path, lenient).await?;
+    let count = cache.bundle(from, to, &output).await?;
+    report_skipped(&cache);
+
+    if let Some(key) = key {
+        let signature = key
+            .sign(&output, archive::timestamp(SystemTime::now()))
+            .await?;
+        info!("signed the bundle in {}", signature.display());
+    }
+
+    info!(target: SUMMARY, "bundled {} crates", count);
+
+    Ok(Outcome::default())
+}
+
+async fn status(path: PathBuf) -> Result<Outcome> {
+    println!("{}", Cache::from_path(path).await?.status().await?);
+    Ok(Outcome::default())
+}
+
+async fn statistics(path: PathBuf, largest: usize) -> Result<Outcome> {
+    println!(
+        "{}",
+        Cache::from_path(path).await?.statistics(largest).await?
+    );
+    Ok(Outcome::default())
+}
+
+async fn usage(path: PathBuf, top: Option<usize>) -> Result<Outcome> {
+    for usage in Cache::from_path(path).await?.usage(top).await? {
+        println!("{usage}");
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn doctor(path: PathBuf, client: &Client) -> Result<Outcome> {
+    let findings = doctor::diagnose(&path, client).await;
+    for finding in &findings {
+        println!("{finding}");
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(eyre!("{} checks failed", errors));
+    }
+
+    info!(target: SUMMARY, "every check passed");
+    Ok(Outcome::default())
+}
+
+async fn list(path: PathBuf) -> Result<Outcome> {
+    for entry in Cache::from_path(path).await?.list().await? {
+        println!("{entry}");
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn pins(path: PathBuf) -> Result<Outcome> {
+    for pin in Cache::from_path(path).await?.pins().await? {
+        println!("{pin}");
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn ingest_dump(path: PathBuf, dump: PathBuf) -> Result<Outcome> {
+    let count = Cache::from_path(path).await?.ingest_dump(dump).await?;
+    info!(target: SUMMARY, "recorded the download counts of {} crates", count);
+
+    Ok(Outcome::default())
+}
+
+async fn migrate_layout(
+    path: PathBuf,
+    layout: Layout,
+    permissions: Permissions,
+    lenient: bool,
+) -> Result<Outcome> {
+    let mut cache = open(path, lenient).await?;
+    let previous = cache.layout();
+
+    let moved = cache.migrate_layout(layout, permissions).await?;
+    report_skipped(&cache);
+    info!(
+        target: SUMMARY,
+        "moved {} crates from the {} layout to the {} layout",
+        moved, previous, layout
+    );
+
+    Ok(Outcome::default())
+}
+
+async fn prune_archive(path: PathBuf, older_than: u64) -> Result<Outcome> {
+    let cache = Cache::from_path(path).await?;
+    let before = SystemTime::now() - Duration::from_secs(older_than.saturating_mul(86_400));
+
+    let removed = cache.prune_archive(before).await?;
+    info!(target: SUMMARY, "removed {} crates from the archive", removed);
+
+    Ok(Outcome::default())
+}
+
+/// Specifies what is done before an operation changes the cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum Preparation {
+    /// The operation is planned and reported but not carried out.
+    DryRun(Estimate),
+    /// The operation is planned and only carried out if there is enough space available.
+    CheckSpace(Estimate),
+    /// The operation is carried out immediately.
+    None,
+}
+
+/// Specifies which crates have their integrity checked when a cache is verified or synchronised.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum Check {
+    /// Crates that have changed since their integrity was last checked. Synchronisations do not
+    /// check stored crates.
+    Changed,
+    /// Every crate.
+    Every,
+    /// Crates that do not have the expected size.
+    Size,
+    /// Crates that do not have the size that was recorded when they were stored.
+    RecordedSize,
+}
+
+/// The options of an operation that refreshes a cache.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct Invocation {
+    dry_run: bool,
+    scope: Scope,
+    order: Order,
+    check: Check,
+    report: bool,
+    /// Every crate is refreshed by a synchronisation or verified by a verification.
+    full: bool,
+    /// Check the integrity of the index before crates are verified.
+    fsck: bool,
+    /// Check that crates are well-formed archives once they are verified.
+    archives: bool,
+    /// The index is synchronised to its state at the start of this date instead of its latest
+    /// state.
+    snapshot: Option<Date>,
+}
+
+impl Default for Invocation {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            scope: Scope::default(),
+            order: Order::Index,
+            check: Check::Changed,
+            report: false,
+            full: false,
+            fsck: false,
+            archives: false,
+            snapshot: None,
+        }
+    }
+}
+
+/// The settings shared by operations that act on an existing cache.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct Context {
+    client: Client,
+    mode: FailureMode,
+    /// The number of jobs that were requested or nothing if they are chosen for each registry.
+    jobs: Option<NonZeroUsize>,
+    /// The most bytes of downloaded crates that are held in memory at once.
+    in_flight_bytes: NonZeroU64,
+    estimate: Estimate,
+    check_space: bool,
+    filter: Filter,
+    /// The crates that are downloaded again regardless of the preservation strategy.
+    force: Vec<Selector>,
+    removal: RemovalStrategy,
+    parents: Vec<Url>,
+    feed: Option<Feed>,
+    ipfs: Option<Ipfs>,
+    oci: Option<Oci>,
+    verifier: Option<Verifier>,
+    /// The download options. The preservation strategy is chosen by each operation.
+    download: download::Options,
+    hashing: digest::Pool,
+    check: Check,
+    report: bool,
+    /// Every crate is refreshed by a synchronisation or verified by a verification.
+    full: bool,
+    /// Check the integrity of the index before crates are verified.
+    fsck: bool,
+    /// Check that crates are well-formed archives once they are verified.
+    archives: bool,
+    /// The token that is sent with requests to download crates from the registry.
+    authorization: Option<HeaderValue>,
+    /// Corrupt lines in the packages of the index are skipped.
+    lenient: bool,
+    /// The index is synchronised to its state at the start of this date instead of its latest
+    /// state.
+    snapshot: Option<Date>,
+}
+
+impl Context {
+    /// Opens the cache at `path` with the options that apply to every operation.
+    async fn open(&self, path: PathBuf) -> Result<Cache> {
+        let mut cache = Cache::from_path(path).await?;
+        self.configure(&mut cache);
+        Ok(cache)
+    }
+
+    /// Applies the options that apply to every operation to `cache`.
+    fn configure(&self, cache: &mut Cache) {
+        cache.set_lenient(self.lenient);
+        cache.set_authorization(self.authorization.clone());
+    }
+
+    /// Returns the settings for an operation that downloads crates to `cache`.
+    async fn settings(&self, cache: &Cache, preserve: download::PreservationStrategy) -> Settings {
+        let jobs = politeness::jobs(self.jobs, cache.download_host().await.as_deref());
+        let mut filter = self.filter.clone();
+        filter.deny.extend_from_slice(cache.denied());
+        Settings {
+            download: download::Options {
+                preserve,
+                ..self.download
+            },
+            mode: self.mode,
+            jobs,
+            connections: download::Connections::new(jobs, self.in_flight_bytes),
+            filter,
+            force: self.force.clone(),
+            snapshot: self.snapshot,
+            removal: self.removal,
+            parents: self.parents.clone(),
+            feed: self.feed.clone(),
+            ipfs: self.ipfs.clone(),
+            oci: self.oci.clone(),
+            verifier: self.verifier.clone(),
+            deep: self.check == Check::Every,
+            hashing: self.hashing.clone(),
+            authenticated: self.authorization.is_some(),
+        }
+    }
+
+    /// Returns what is done before an operation changes the cache.
+    const fn preparation(&self, dry_run: bool) -> Preparation {
+        if dry_run {
+            Preparation::DryRun(self.estimate)
+        } else if self.check_space {
+            Preparation::CheckSpace(self.estimate)
+        } else {
+            Preparation::None
+        }
+    }
+}
+
+async fn verify(
+    path: PathBuf,
+    context: &Context,
+    dry_run: bool,
+    scope: &Scope,
+    order: Order,
+) -> Result<Outcome> {
+    let start = Instant::now();
+    let cache = context.open(path).await?;
+    if context.fsck {
+        if let Err(error) = cache.check_index().await {
+            if let CheckIntegrityError::Damaged(faults) = &error {
+                for fault in faults {
+                    error!("{}", fault);
+                }
+
+                warn!("clone the index again with `repair-index`");
+            }
+
+            return Err(error.into());
+        }
+
+        info!("the index is intact");
+    }
+
+    let client = &context.client;
+    let settings = context
+        .settings(
+            &cache,
+            if context.check == Check::Size {
+                download::PreservationStrategy::Size
+            } else {
+                download::PreservationStrategy::Checksum
+            },
+        )
+        .await;
+
+    if context.report {
+        let mut defects = cache.inspect(client, &settings, scope).await?;
+        if context.archives {
+            defects.extend(cache.inspect_archives(&settings, scope).await?);
+            defects.sort_by(|a, b| {
+                (a.name.as_str(), a.version.as_str()).cmp(&(b.name.as_str(), b.version.as_str()))
+            });
+        }
+
+        let mut stdout = io::stdout().lock();
+        for defect in defects {
+            writeln!(
+                stdout,
+                "{}",
+                serde_json::to_string(&defect).expect("failed to serialise defect")
+            )?;
+        }
+
+        report_skipped(&cache);
+        info!(target: SUMMARY, "inspected cache");
+        return Ok(Outcome::default());
+    }
+
+    match context.preparation(dry_run) {
+        Preparation::DryRun(estimate) => {
+            println!(
+                "{}",
+                cache
+                    .plan_refresh(client, &settings, scope, estimate)
+                    .await?
+            );
+            return Ok(Outcome::default());
+        }
+
+        Preparation::CheckSpace(estimate) => {
+            let plan = cache
+                .plan_refresh(client, &settings, scope, estimate)
+                .await?;
+            plan.check_space(cache.path().to_path_buf()).await?;
+        }
+
+        Preparation::None => (),
+    }
+
+    let outcome = cache
+        .verify(client, &settings, scope, order, context.full)
+        .await?;
+    report_skipped(&cache);
+
+    if context.archives {
+        let suspicious = cache.inspect_archives(&settings, scope).await?.len();
+        if suspicious > 0 {
+            return Err(eyre!("{} crates are suspicious archives", suspicious));
+        }
+    }
+
+    report_transfers(outcome, start.elapsed());
+    info!(target: SUMMARY, "verified cache");
+
+    Ok(outcome)
+}
+
+async fn verify_crates(
+    path: PathBuf,
+    context: &Context,
+    selectors: &[Selector],
+) -> Result<Outcome> {
+    let start = Instant::now();
+    let cache = context.open(path).await?;
+    let settings = context
+        .settings(
+            &cache,
+            if context.check == Check::Size {
+                download::PreservationStrategy::Size
+            } else {
+                download::PreservationStrategy::Checksum
+            },
+        )
+        .await;
+
+    let outcome = cache
+        .verify_crates(&context.client, &settings, selectors)
+        .await?;
+    report_skipped(&cache);
+    if outcome.checked == 0 {
+        let selectors = selectors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        return Err(eyre!(
+            "no crate in the index matches {}",
+            selectors.join(", ")
+        ));
+    }
+
+    report_transfers(outcome, start.elapsed());
+    info!(target: SUMMARY, "verified {} crates", outcome.checked);
+
+    Ok(outcome)
+}
+
+async fn synchronise(
+    path: PathBuf,
+    context: &Context,
+    dry_run: bool,
+    scope: &Scope,
+    order: Order,
+) -> Result<Outcome> {
+    let start = Instant::now();
+    let cache = context.open(path).await?;
+    let client = &context.client;
+    let settings = context
+        .settings(
+            &cache,
+            if context.check == Check::RecordedSize {
+                download::PreservationStrategy::RecordedSize
+            } else {
+                download::PreservationStrategy::Always
+            },
+        )
+        .await;
+
+    // Updates always apply to the entire index. A scoped synchronisation only refreshes the
+    // packages in scope and leaves the index for an unscoped synchronisation to update.
+    let update = scope.is_everything();
+
+    // The space that is needed is checked by the synchronisation once it has fetched the update.
+    let preflight = match context.preparation(dry_run) {
+        Preparation::DryRun(estimate) => {
+            // A cache that is consistent with the index is not refreshed.
+            let mut plan = if update && !context.full && cache.is_consistent(&settings).await? {
+                Plan::default()
+            } else {
+                cache
+                    .plan_refresh(client, &settings, scope, estimate)
+                    .await?
+            };
+
+            if update {
+                plan = plan.merge(cache.plan_update(client, &settings, estimate).await?);
+            }
+
+            println!("{plan}");
+            return Ok(Outcome::default());
+        }
+
+        Preparation::CheckSpace(estimate) => Some(estimate),
+        Preparation::None => None,
+    };
+
+    let outcome = cache
+        .synchronise(client, &settings, scope, order, context.full, preflight)
+        .await?;
+    report_skipped(&cache);
+    report_transfers(outcome, start.elapsed());
+
+    if update {
+        info!(target: SUMMARY, "cache is synchronised");
+    } else {
+        info!(target: SUMMARY, "refreshed {}; the index was not updated", scope);
+    }
+
+    Ok(outcome)
+}
+
+async fn apply_bundle(path: PathBuf, context: &Context, bundle: &Path) -> Result<Outcome> {
+    let cache = context.open(path).await?;
+    let settings = context
+        .settings(&cache, download::PreservationStrategy::Always)
+        .await;
+
+    let outcome = cache
+        .apply_bundle(bundle.to_path_buf(), &context.client, &settings)
+        .await?;
+    report_skipped(&cache);
+    info!(target: SUMMARY, "applied the bundle");
+
+    Ok(outcome)
+}
+
+async fn repair_index(
+    path: PathBuf,
+    context: &Context,
+    url: Option<Url>,
+    order: Order,
+) -> Result<Outcome> {
+    let mut cache = Cache::repair_index(path, url).await?;
+    context.configure(&mut cache);
+    info!(target: SUMMARY, "cloned the index again");
+
+    // The crates that were downloaded for the previous index are preserved and every crate that
+    // the index now holds is refreshed.
+    let settings = context
+        .settings(&cache, download::PreservationStrategy::Always)
+        .await;
+    let outcome = cache
+        .synchronise(
+            &context.client,
+            &settings,
+            &Scope::default(),
+            order,
+            true,
+            None,
+        )
+        .await?;
+    report_skipped(&cache);
+    info!(target: SUMMARY, "cache is synchronised");
+
+    Ok(outcome)
+}
+
+async fn prune(path: PathBuf, context: &Context, dry_run: bool) -> Result<Outcome> {
+    let cache = context.open(path).await?;
+    let settings = context
+        .settings(&cache, download::PreservationStrategy::Always)
+        .await;
 
-    Ok(())
+    let removed = cache.prune(&settings, dry_run).await?;
+    report_skipped(&cache);
+    if dry_run {
+        info!(target: SUMMARY, "would prune {} crates", removed);
+    } else {
+        info!(target: SUMMARY, "pruned {} crates", removed);
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn pin(path: PathBuf, context: &Context, revision: String) -> Result<Outcome> {
+    let id = context.open(path.clone()).await?.pin(revision).await?;
+    info!(target: SUMMARY, "pinned the index to {}", id);
+
+    synchronise(path, context, false, &Scope::default(), Order::Index).await
+}
+
+async fn rollback(path: PathBuf, context: &Context) -> Result<Outcome> {
+    let id = context
+        .open(path.clone())
+        .await?
+        .rollback()
+        .await?
+        .ok_or_else(|| eyre!("no update has been applied to the index"))?;
+    info!(target: SUMMARY, "pinned the index to {}", id);
+
+    synchronise(path, context, false, &Scope::default(), Order::Index).await
+}
+
+async fn create_snapshot(path: PathBuf, name: String) -> Result<Outcome> {
+    let snapshot = Cache::from_path(path).await?.create_snapshot(&name).await?;
+    info!(
+        target: SUMMARY,
+        "recorded {} files at {} in the snapshot {}", snapshot.files, snapshot.commit, snapshot.name
+    );
+
+    Ok(Outcome::default())
+}
+
+async fn snapshots(path: PathBuf) -> Result<Outcome> {
+    let snapshots = Cache::from_path(path).await?.snapshots().await?;
+
+    let mut stdout = io::stdout().lock();
+    for snapshot in snapshots {
+        writeln!(stdout, "{snapshot}")?;
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn diff_snapshots(
+    path: PathBuf,
+    from: String,
+    to: Option<String>,
+    json: bool,
+    lenient: bool,
+) -> Result<Outcome> {
+    let cache = open(path, lenient).await?;
+    let differences = cache.diff_snapshots(&from, to.as_deref()).await?;
+    report_skipped(&cache);
+
+    let mut stdout = io::stdout().lock();
+    for difference in differences {
+        if json {
+            writeln!(
+                stdout,
+                "{}",
+                serde_json::to_string(&difference).expect("failed to serialise difference")
+            )?;
+        } else {
+            writeln!(stdout, "{difference}")?;
+        }
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn restore_snapshot(path: PathBuf, context: &Context, name: String) -> Result<Outcome> {
+    let (snapshot, linked) = context
+        .open(path.clone())
+        .await?
+        .restore_snapshot(&name)
+        .await?;
+    info!(
+        target: SUMMARY,
+        "restored {} files from the snapshot {} and pinned the index to {}",
+        linked.linked + linked.copied,
+        snapshot.name,
+        snapshot.commit
+    );
+
+    synchronise(path, context, false, &Scope::default(), Order::Index).await
+}
+
+async fn refetch(path: PathBuf, context: &Context, key: &CrateKey) -> Result<Outcome> {
+    let cache = context.open(path).await?;
+    let settings = context
+        .settings(&cache, download::PreservationStrategy::Always)
+        .await;
+
+    let refetched = cache
+        .refetch(&context.client, &settings, &key.name, &key.version)
+        .await?
+        .ok_or_else(|| eyre!("{} {} is not in the index", key.name, key.version))?;
+    let old = refetched
+        .old
+        .map_or_else(|| "not stored".to_owned(), |digest| digest.to_string());
+
+    let Some(new) = refetched.new else {
+        return Err(eyre!(
+            "{} {} could not be downloaded again and is no longer stored; the stored crate was {}",
+            key.name,
+            key.version,
+            old
+        ));
+    };
+
+    if refetched.old == Some(new) {
+        info!(
+            target: SUMMARY,
+            "downloaded {} {} again; it was intact with checksum {}", key.name, key.version, new
+        );
+    } else {
+        info!(
+            target: SUMMARY,
+            "downloaded {} {} again; the stored crate was {} and is now {}",
+            key.name,
+            key.version,
+            old,
+            new
+        );
+    }
+
+    Ok(Outcome::default())
+}
+
+async fn remove(
+    path: PathBuf,
+    context: &Context,
+    selector: &Selector,
+    deny: bool,
+) -> Result<Outcome> {
+    let mut cache = context.open(path).await?;
+    let evicted = cache.evict(selector.clone(), deny).await?;
+    report_skipped(&cache);
+
+    if deny {
+        info!(
+            target: SUMMARY,
+            "removed {} crates and denied {}",
+            evicted.len(),
+            selector
+        );
+    } else {
+        info!(target: SUMMARY, "removed {} crates", evicted.len());
+    }
+
+    Ok(Outcome {
+        removed: evicted.len(),
+        ..Outcome::default()
+    })
+}
+
+async fn unpin(path: PathBuf) -> Result<Outcome> {
+    if Cache::from_path(path).await?.unpin().await? {
+        info!(target: SUMMARY, "released the pin on the index");
+    } else {
+        info!(target: SUMMARY, "the index is not pinned");
+    }
+
+    Ok(Outcome::default())
+}
+
+/// Reports the crates that an operation checked, downloaded, and removed in `elapsed` with the
+/// average rate that crates were downloaded at.
+fn report_transfers(outcome: Outcome, elapsed: Duration) {
+    let rate = u128::from(outcome.bytes) * 1000 / elapsed.as_millis().max(1);
+    info!(
+        target: SUMMARY,
+        checked = outcome.checked,
+        downloaded = outcome.downloaded,
+        failed = outcome.failed,
+        removed = outcome.removed,
+        bytes = outcome.bytes,
+        "checked {} crates, downloaded {} ({} bytes), {} failed, and removed {} in {:.1?} ({} bytes/s)",
+        outcome.checked,
+        outcome.downloaded,
+        outcome.bytes,
+        outcome.failed,
+        outcome.removed,
+        elapsed,
+        rate
+    );
+}
+
+/// Reports the number of corrupt lines in the index that were skipped.
+/// Opens the cache at `path`, skipping corrupt lines in the index if `lenient` is true.
+async fn open(path: PathBuf, lenient: bool) -> Result<Cache> {
+    let mut cache = Cache::from_path(path).await?;
+    cache.set_lenient(lenient);
+    Ok(cache)
+}
+
+fn report_skipped(cache: &Cache) {
+    let skipped = cache.skipped_index_lines();
+    if skipped > 0 {
+        warn!(target: SUMMARY, "skipped {} corrupt lines in the index", skipped);
+    }
 }
 
-async fn verify(path: PathBuf, jobs: NonZeroUsize, client: &Client) -> Result<()> {
-    let cache = Cache::from_path(path).await?;
-    let options = download::Options {
-        preserve: download::PreservationStrategy::Checksum,
-    };
+/// An operation that refreshes a cache.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+enum Operation {
+    Verify,
+    /// Verifies only the crates that the selectors select.
+    VerifyCrates(Vec<Selector>),
+    Synchronise,
+    /// Applies the bundle at the path.
+    ApplyBundle(PathBuf),
+    /// Clones the index again from the URL or, if there is none, from the remote of the index.
+    RepairIndex(Option<Url>),
+    Prune,
+    /// Pins the index to the commit that the revision names.
+    Pin(String),
+    /// Pins the index to the commit that it held before the last update.
+    Rollback,
+    /// Restores the crates of the named snapshot and pins the index to its commit.
+    RestoreSnapshot(String),
+    /// Deletes the crate and downloads it again.
+    Refetch(CrateKey),
+    /// Deletes the crates that the selector selects and, if the flag is set, denies them.
+    Remove(Selector, bool),
+}
+
+impl Operation {
+    /// Performs the operation on the cache at `path`.
+    async fn perform(
+        &self,
+        path: PathBuf,
+        context: &Context,
+        dry_run: bool,
+        scope: &Scope,
+        order: Order,
+    ) -> Result<Outcome> {
+        notify::begin(format!("{self} {}", path.display()));
+        match self {
+            Self::Verify => verify(path, context, dry_run, scope, order).await,
+            Self::VerifyCrates(selectors) => verify_crates(path, context, selectors).await,
+            Self::Synchronise => synchronise(path, context, dry_run, scope, order).await,
+            Self::ApplyBundle(bundle) => apply_bundle(path, context, bundle).await,
+            Self::RepairIndex(url) => repair_index(path, context, url.clone(), order).await,
+            Self::Prune => prune(path, context, dry_run).await,
+            Self::Pin(revision) => pin(path, context, revision.clone()).await,
+            Self::Rollback => rollback(path, context).await,
+            Self::RestoreSnapshot(name) => restore_snapshot(path, context, name.clone()).await,
+            Self::Refetch(key) => refetch(path, context, key).await,
+            Self::Remove(selector, deny) => remove(path, context, selector, *deny).await,
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Verify => write!(f, "verify"),
+            Self::VerifyCrates(selectors) => {
+                let selectors = selectors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                write!(f, "verify {} in", selectors.join(", "))
+            }
+            Self::Synchronise => write!(f, "synchronise"),
+            Self::ApplyBundle(_) => write!(f, "apply a bundle to"),
+            Self::RepairIndex(_) => write!(f, "repair the index of"),
+            Self::Prune => write!(f, "prune"),
+            Self::Pin(_) => write!(f, "pin the index of"),
+            Self::Rollback => write!(f, "roll back the index of"),
+            Self::RestoreSnapshot(_) => write!(f, "restore a snapshot of"),
+            Self::Refetch(key) => write!(f, "download {key} again in"),
+            Self::Remove(selector, _) => write!(f, "remove {selector} from"),
+        }
+    }
+}
+
+/// Returns the value of the `Authorization` header that a token is sent as. The token is only sent
+/// with requests to download crates from the registry that it was given for.
+fn authorization(token: Option<String>) -> Result<Option<HeaderValue>> {
+    let Some(token) = token else {
+        return Ok(None);
+    };
+
+    let mut value = HeaderValue::from_str(&token)?;
+    value.set_sensitive(true);
+    trace!(
+        target: HTTP,
+        "sending {}: {} with requests to download crates from the registry",
+        AUTHORIZATION,
+        logging::redact(&AUTHORIZATION, &value)
+    );
+
+    Ok(Some(value))
+}
+
+/// The number of redirects that are followed before a request fails. This is the default of the
+/// HTTP client.
+const MAX_REDIRECTS: usize = 10;
+
+/// Returns a client for downloading crates.
+fn client(contact: Option<&str>, connection: &ConnectionArguments) -> Result<Client> {
+    let mut builder = ClientBuilder::new()
+        .tcp_nodelay(connection.tcp_nodelay)
+        .pool_idle_timeout(Duration::from_secs(connection.pool_idle_timeout.get()));
+
+    if let Some(size) = connection.pool_size {
+        builder = builder.pool_max_idle_per_host(size);
+    }
+
+    builder = match connection.http {
+        HttpVersion::Negotiate => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+
+    for each in &connection.resolve {
+        builder = builder.resolve(&each.host, each.address);
+    }
+
+    builder = match contact {
+        Some(contact) => builder.user_agent(format!("{USER_AGENT} ({contact})")),
+        None => builder.user_agent(USER_AGENT),
+    };
+
+    // Redirects are followed as they are by default but each one is traced.
+    if tracing::enabled!(target: HTTP, Level::TRACE) {
+        builder = builder.redirect(Policy::custom(|attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                trace!(target: HTTP, "not following more than {} redirects", MAX_REDIRECTS);
+                return attempt.error("too many redirects");
+            }
+
+            let from = attempt.previous().last().map_or("", Url::as_str);
+            trace!(
+                target: HTTP,
+                "following {} redirect from {} to {}",
+                attempt.status(),
+                from,
+                attempt.url()
+            );
+
+            attempt.follow()
+        }));
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Describes why the program was unsuccessful. The discriminant is used as the exit code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum Failure {
+    /// An error that does not belong to a more specific category.
+    Other = 1,
+    /// The index could not be read, cloned, or updated.
+    Index = 3,
+    /// A crate could not be downloaded and the operation was aborted.
+    Download = 4,
+    /// The operation completed but some crates could not be downloaded.
+    Partial = 5,
+    /// Another process has locked the cache.
+    Locked = 6,
+    /// The operation was interrupted and cancelled. This is the conventional status for a program
+    /// that is stopped by `SIGINT`.
+    Interrupted = 130,
+}
+
+impl Failure {
+    /// Categorises an error.
+    fn categorise(report: &eyre::Report) -> Self {
+        if let Some(error) = report.downcast_ref::<RefreshCacheError>() {
+            return Self::categorise_refresh(error);
+        }
+
+        if let Some(error) = report.downcast_ref::<UpdateError>() {
+            return Self::categorise_update(error);
+        }
+
+        if let Some(ApplyBundleError::Update(error)) = report.downcast_ref::<ApplyBundleError>() {
+            return Self::categorise_update(error);
+        }
+
+        if let Some(error) = report.downcast_ref::<SynchroniseError>() {
+            return match error {
+                SynchroniseError::Refresh(error) => Self::categorise_refresh(error),
+                SynchroniseError::Update(error) => Self::categorise_update(error),
+                SynchroniseError::Preflight(_) => Self::Other,
+            };
+        }
+
+        if let Some(LockError::Held(_)) = report.downcast_ref::<LockError>() {
+            return Self::Locked;
+        }
+
+        if report.is::<CreateCacheError>()
+            || report.is::<LoadCacheError>()
+            || report.is::<RepairIndexError>()
+            || report.is::<CheckIntegrityError>()
+            || report.is::<GetConfigurationError>()
+        {
+            return Self::Index;
+        }
+
+        Self::Other
+    }
+
+    /// Categorises an error from refreshing a cache.
+    const fn categorise_refresh(error: &RefreshCacheError) -> Self {
+        match error {
+            RefreshCacheError::CrateDownload(_) => Self::Download,
+            RefreshCacheError::Database(_)
+            | RefreshCacheError::Io(_)
+            | RefreshCacheError::ResolveFilter(_)
+            | RefreshCacheError::Unauthenticated => Self::Other,
+            _ => Self::Index,
+        }
+    }
+
+    /// Categorises an error from updating a cache.
+    const fn categorise_update(error: &UpdateError) -> Self {
+        match error {
+            UpdateError::CrateDownload(_) => Self::Download,
+            UpdateError::Io(_)
+            | UpdateError::PruneDirectories(_)
+            | UpdateError::ExportSparse(_)
+            | UpdateError::ResolveFilter(_) => Self::Other,
+            _ => Self::Index,
+        }
+    }
+}
+
+impl From<Failure> for ExitCode {
+    fn from(failure: Failure) -> Self {
+        Self::from(failure as u8)
+    }
+}
+
+/// Specifies how the size of a crate that has not been downloaded is estimated.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum EstimateStrategy {
+    /// Assume every crate has the average size.
+    Average,
+    /// Ask the registry for the size of every crate.
+    Head,
+}
+
+/// Specifies the HTTP versions that crates are downloaded with.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum HttpVersion {
+    /// HTTP/1.1 or HTTP/2 as negotiated with the server.
+    Negotiate,
+    /// Only HTTP/1.1.
+    Http1,
+    /// Only HTTP/2 without negotiating with the server.
+    Http2,
+}
+
+/// Specifies how connections to registries are made.
+#[derive(Args, Debug)]
+struct ConnectionArguments {
+    /// The maximum number of idle connections that are kept open to each host
+    ///
+    /// By default, the number of idle connections is not limited.
+    #[clap(long)]
+    pool_size: Option<usize>,
+
+    /// The number of seconds that idle connections are kept open for
+    #[clap(long, default_value_t = NonZeroU64::new(90).unwrap())]
+    pool_idle_timeout: NonZeroU64,
+
+    /// The HTTP versions that crates are downloaded with
+    #[clap(long, arg_enum, default_value_t = HttpVersion::Negotiate)]
+    http: HttpVersion,
+
+    /// Whether Nagle's algorithm is disabled for connections
+    #[clap(long, parse(try_from_str), default_value_t = true)]
+    tcp_nodelay: bool,
+
+    /// Download crates over HTTP/3 when the registry supports it (experimental)
+    ///
+    /// Crates are downloaded over HTTP/1.1 or HTTP/2 if a connection can not be made over HTTP/3.
+    #[cfg_attr(feature = "http3", clap(long, conflicts_with = "http"))]
+    #[cfg_attr(not(feature = "http3"), clap(skip))]
+    http3: bool,
+
+    /// Connect to an address instead of resolving a host name (eg. `static.crates.io:443:10.0.0.1`)
+    ///
+    /// Overrides apply to crate downloads and to indices that are fetched over HTTP or HTTPS. The
+    /// port of the address is used when a URL does not have a port. This can be given several
+    /// times.
+    #[clap(long, value_name = "HOST:PORT:ADDRESS")]
+    resolve: Vec<resolve::Override>,
+}
+
+/// Specifies how the cache is locked.
+#[derive(Args, Debug)]
+struct LockArguments {
+    /// Wait for another process to unlock the cache
+    ///
+    /// Operations that change the cache lock it so that several processes do not change it at
+    /// once. By default, an operation fails when another process has locked the cache.
+    #[clap(long, overrides_with = "no-wait")]
+    wait: bool,
+
+    /// Fail when another process has locked the cache
+    #[clap(long, overrides_with = "wait")]
+    no_wait: bool,
+
+    /// Remove the lock of the cache before locking it
+    ///
+    /// A lock is released when the process that holds it exits. This is only needed when that
+    /// process has hung or the file system keeps stale locks.
+    #[clap(long)]
+    force_unlock: bool,
+}
+
+/// Specifies where and how logs are written.
+#[derive(Args, Debug)]
+struct LogArguments {
+    /// Only log errors and the summary of the operation
+    ///
+    /// The log file is still written at the log level.
+    #[clap(short, long, conflicts_with = "log-level")]
+    quiet: bool,
+
+    /// Trace the requests and responses of the download client
+    ///
+    /// Request and response lines, headers, redirects, and retries are logged whatever the log
+    /// level is. Credentials are redacted.
+    #[clap(long)]
+    trace_http: bool,
+
+    /// Where logs are written
+    ///
+    /// Events that are written to journald include their fields and the fields of their spans.
+    #[clap(long = "log-target", arg_enum, default_value_t = logging::Target::Stderr)]
+    target: logging::Target,
+
+    /// The format of logs that are written to standard error or syslog
+    #[clap(long = "log-format", arg_enum, default_value_t = logging::Format::Text)]
+    format: logging::Format,
+
+    /// A file that logs are written to as well as standard error
+    #[clap(long = "log-file")]
+    file: Option<PathBuf>,
+
+    /// The format of logs that are written to the log file
+    #[clap(long = "log-file-format", arg_enum, default_value_t = logging::Format::Text)]
+    file_format: logging::Format,
+
+    /// How often the log file is rotated
+    #[clap(long = "log-rotation", arg_enum, default_value_t = logging::Rotation::Never)]
+    rotation: logging::Rotation,
+
+    /// The size in bytes that the log file is rotated at
+    #[clap(long = "log-max-size")]
+    max_size: Option<u64>,
+
+    /// The number of rotated log files that are kept
+    #[clap(long = "log-max-files", default_value_t = 5)]
+    max_files: usize,
+}
+
+/// Collects the program arguments
+#[derive(Parser, Debug)]
+#[clap(version, about)]
+#[allow(clippy::struct_excessive_bools)]
+struct Arguments {
+    #[clap(subcommand)]
+    action: Action,
+
+    /// The path of the registry cache
+    ///
+    /// By default, the path is read from `CRATEFUL_PATH` or is the directory of the registry in the
+    /// `crateful` directory of the XDG data directory (eg. `~/.local/share/crateful/crates-io`).
+    #[clap(short, long)]
+    path: Option<PathBuf>,
+
+    /// The name of the registry whose cache is in the XDG data directory when no path is given
+    #[clap(long, default_value = "crates-io")]
+    registry: String,
+
+    /// The number of jobs that can run in parallel
+    ///
+    /// By default, two jobs are run for each available processor up to the most that the registry
+    /// tolerates. A warning is logged when more jobs are requested than the registry tolerates.
+    #[clap(short, long)]
+    jobs: Option<NonZeroUsize>,
+
+    /// The number of digests that can be computed in parallel
+    ///
+    /// Digests are computed on dedicated threads so that checking the integrity of large crates
+    /// does not delay downloads. By default, one digest is computed for each available processor.
+    #[clap(long)]
+    hash_jobs: Option<NonZeroUsize>,
+
+    /// The log level to use
+    #[clap(short, long, default_value_t = tracing::Level::INFO)]
+    log_level: tracing::Level,
+
+    /// Abort an operation when any crate can not be downloaded
+    ///
+    /// By default, crates that can not be downloaded because of known registry inconsistencies
+    /// are reported and skipped, and the program exits with a distinct status when it finishes.
+    #[clap(long)]
+    strict: bool,
+
+    /// Skip corrupt lines in the packages of the index instead of failing
+    ///
+    /// Each skipped line is reported with a warning and the number of skipped lines is reported
+    /// when the operation finishes. A crate that was modified on a corrupt line is not removed.
+    #[clap(long)]
+    lenient: bool,
+
+    /// How the size of crates that have not been downloaded is estimated
+    #[clap(long, arg_enum, default_value_t = EstimateStrategy::Average)]
+    estimate: EstimateStrategy,
+
+    /// The assumed average size of a crate in bytes
+    #[clap(long, default_value_t = 100_000)]
+    average_crate_size: u64,
+
+    /// Do not check that there is enough space available before changing the cache
+    #[clap(long)]
+    skip_space_check: bool,
+
+    /// Only mirror crate versions that were published on or after this date (eg. `2020-01-01`)
+    ///
+    /// The publication date of a version is the date of the index commit that added it. Versions
+    /// that were published before the history of the index begins are attributed to its first
+    /// commit.
+    #[clap(long)]
+    since: Option<Date>,
+
+    /// How crates that have been yanked from the registry are handled
+    #[clap(long, arg_enum, default_value_t = YankPolicy::Mirror)]
+    yanked: YankPolicy,
+
+    /// Only mirror this many of the most downloaded crates and the crates that they depend on
+    ///
+    /// Download counts are read from a crates.io database dump that was ingested with
+    /// `ingest-dump`. Development dependencies are not mirrored.
+    #[clap(long)]
+    top: Option<NonZeroUsize>,
+
+    /// Only mirror the crates that a built-in preset selects and the crates that they depend on
+    ///
+    /// The cache is refreshed the next time it is synchronised when the preset changes.
+    #[clap(long, arg_enum)]
+    preset: Option<Preset>,
+
+    /// Only mirror a crate, every crate that depends on it, and the crates that they depend on
+    ///
+    /// Crates that depend on the crate through other crates are included. Development
+    /// dependencies are ignored. This can be given more than once.
+    #[clap(long, value_name = "NAME")]
+    dependents_of: Vec<String>,
+
+    /// Only mirror this many of the latest versions of each crate that the other filters select
+    ///
+    /// Versions are ordered by semver. Crates that are no longer retained when an update adds a
+    /// newer version are removed. Use `prune` to remove the crates that were already in the cache.
+    #[clap(long, value_name = "COUNT")]
+    keep_latest: Option<NonZeroUsize>,
+
+    /// Do not mirror pre-release versions
+    #[clap(long)]
+    exclude_prerelease: bool,
+
+    /// Only mirror the versions of a crate that match a semver requirement (eg. `serde=>=1.0, <2`)
+    ///
+    /// Versions that are already in the cache are not removed.
+    #[clap(long)]
+    constraint: Vec<Constraint>,
+
+    /// Download the crates that match a selector again regardless of whether they are stored (eg.
+    /// `serde`, `serde@1.0.0`, or `serde_*`)
+    ///
+    /// Names and versions may contain `*` and `?` wildcards. Forced crates are downloaded again by
+    /// `sync` and `verify` before the rest of the cache is acted on, even if the cache is
+    /// consistent.
+    #[clap(long, value_name = "SELECTOR")]
+    force: Vec<Selector>,
+
+    /// Move crates that are removed or replaced by an update into the archive instead of deleting
+    /// them
+    #[clap(long)]
+    archive: bool,
+
+    /// A mirror to download crates from before the registry
+    ///
+    /// Mirrors are tried in the order that they are given and the registry is only used when a
+    /// crate can not be downloaded from any mirror. A mirror must serve crates at
+    /// `<url>/<name>/<version>/download` like the crates directory of a cache.
+    #[clap(long)]
+    parent: Vec<Url>,
+
+    /// Append the changes to the index that updates pick up to an Atom feed
+    ///
+    /// The feed is written to `feed.xml` in the cache so that it is published with the cache. The
+    /// URL is where the cache is published (eg. `https://mirror.example/`). When several
+    /// registries are mirrored, each cache has its own feed.
+    #[clap(long)]
+    feed: Option<Url>,
+
+    /// The number of the most recent changes that the feed holds
+    #[clap(long, default_value_t = NonZeroUsize::new(100).unwrap())]
+    feed_entries: NonZeroUsize,
+
+    /// Pin downloaded crates to the IPFS node whose RPC API is at this URL (eg.
+    /// `http://127.0.0.1:5001`)
+    ///
+    /// The content identifier of each crate is recorded in the metadata database and listed by
+    /// `pins`.
+    #[clap(long)]
+    ipfs: Option<Url>,
+
+    /// Push downloaded crates as artefacts to the repository of an OCI registry at this URL (eg.
+    /// `https://harbor.example/library/crates`)
+    ///
+    /// Each crate is pushed to a repository that is named after it within this repository and is
+    /// tagged with its version. Crates are still stored in the cache.
+    #[clap(long)]
+    oci: Option<Url>,
+
+    /// The username that is sent to the OCI registry
+    #[clap(long, requires = "oci")]
+    oci_username: Option<String>,
+
+    /// The environment variable that holds the password that is sent to the OCI registry
+    #[clap(long, requires = "oci-username")]
+    oci_password_env: Option<String>,
+
+    /// A program that verifies the signatures that the registry publishes for crates before they
+    /// are stored
+    ///
+    /// The program is run with the path of a downloaded crate as its only argument and with the
+    /// name, version, checksum, and URL of the crate in `CRATEFUL_NAME`, `CRATEFUL_VERSION`,
+    /// `CRATEFUL_CHECKSUM`, and `CRATEFUL_URL`. A crate is only stored if the program exits
+    /// successfully. Crates that are already in the cache are not verified again.
+    #[clap(long)]
+    signature_verifier: Option<PathBuf>,
 
-    cache.refresh(client, options, jobs).await?;
-    info!("verified cache");
+    /// How downloaded crates are stored
+    ///
+    /// Crates that are compressed with zstd are decompressed when they are read. Existing crates
+    /// are converted when every crate in the cache is verified.
+    #[clap(long, arg_enum, default_value_t = Storage::Plain)]
+    storage: Storage,
 
-    Ok(())
-}
+    /// How durably downloaded crates are written
+    ///
+    /// Crates are always written beside their destination and moved into place. `rename` flushes
+    /// each crate to storage before it is moved and `fsync-dir` also flushes its directory after.
+    #[clap(long, arg_enum, default_value_t = Durability::Rename)]
+    durability: Durability,
 
-async fn synchronise(path: PathBuf, jobs: NonZeroUsize, client: &Client) -> Result<()> {
-    let cache = Cache::from_path(path).await?;
-    let options = download::Options::default();
+    /// The mode that crates and other files in the cache are written with in octal (eg. `0644`)
+    #[clap(long)]
+    file_mode: Option<Mode>,
 
-    cache.refresh(client, options, jobs).await?;
-    info!("refreshed cache");
+    /// The mode that directories of crates are created with in octal (eg. `0755`)
+    #[clap(long)]
+    directory_mode: Option<Mode>,
 
-    cache.update(client, options, jobs).await?;
-    info!("updated cache");
-    info!("cache is synchronised");
+    /// The user ID that owns crates and the directories that are created for them
+    ///
+    /// Only a process that runs as root can give files to another user.
+    #[clap(long)]
+    owner: Option<u32>,
 
-    Ok(())
-}
+    /// The group ID that owns crates and the directories that are created for them
+    #[clap(long)]
+    group: Option<u32>,
 
-/// Collects the program arguments
-#[derive(Parser, Debug)]
-#[clap(version, about)]
-struct Arguments {
-    #[clap(subcommand)]
-    action: Action,
+    /// Download crates that are larger than this number of bytes in several concurrent segments
+    ///
+    /// Each segment is requested with a ranged request and the segments are reassembled before
+    /// the checksum is verified. Crates are downloaded in one request when the registry does not
+    /// support ranged requests.
+    #[clap(long)]
+    segment_threshold: Option<u64>,
 
-    /// The path of the registry cache
-    #[clap(short, long)]
-    path: PathBuf,
+    /// The number of segments that large crates are downloaded in
+    #[clap(long, default_value_t = NonZeroUsize::new(4).unwrap())]
+    segments: NonZeroUsize,
 
-    /// The number of jobs that can run in parallel
-    #[clap(short, long, default_value_t = NonZeroUsize::new(1).unwrap())]
-    jobs: NonZeroUsize,
+    /// The most bytes of downloaded crates that are held in memory at once
+    ///
+    /// Crates are only held in memory when they are compressed or downloaded in segments.
+    /// Downloads wait to read their responses until the crates that are held have been stored. A
+    /// crate that is larger than this is downloaded while no other crates are held.
+    #[clap(long, default_value_t = NonZeroU64::new(256 * 1024 * 1024).unwrap())]
+    in_flight_bytes: NonZeroU64,
 
-    /// The log level to use
-    #[clap(short, long, default_value_t = tracing::Level::INFO)]
-    log_level: tracing::Level,
+    /// A configuration file that describes several registries to mirror
+    ///
+    /// Each registry is mirrored by `sync` and `verify` in a cache that is held in a directory of
+    /// the cache path with the name of the registry. Caches are created by `sync` when they do not
+    /// exist.
+    #[clap(long)]
+    config: Option<PathBuf>,
 
     /// Contact information for the user
     ///
@@ -73,6 +1713,106 @@ struct Arguments {
     /// information is transmitted in the user agent of HTTP requests.
     #[clap(short, long)]
     contact: Option<String>,
+
+    /// The endpoint of an OpenTelemetry collector that traces are exported to over OTLP/HTTP
+    ///
+    /// Spans are exported for each registry, crate download, and index change with attributes
+    /// that include the name and version of crates, the number of bytes downloaded, and the
+    /// number of sources that were attempted (eg. `http://localhost:4318/v1/traces`).
+    #[cfg_attr(feature = "otlp", clap(long))]
+    #[cfg_attr(not(feature = "otlp"), clap(skip), allow(dead_code))]
+    otlp_endpoint: Option<Url>,
+
+    #[clap(flatten)]
+    connection: ConnectionArguments,
+
+    #[clap(flatten)]
+    lock: LockArguments,
+
+    #[clap(flatten)]
+    log: LogArguments,
+}
+
+/// Specifies how the remote of an index is authenticated.
+#[derive(Args, Debug)]
+struct AuthenticationArguments {
+    /// The private key that an index is cloned and fetched over SSH with
+    ///
+    /// The keys of the SSH agent are used when it is not given (eg. for
+    /// `ssh://git@git.example/index.git`).
+    #[clap(long)]
+    ssh_identity: Option<PathBuf>,
+
+    /// The user name that an index is cloned and fetched over HTTPS with
+    ///
+    /// The user name in the URL of the index is used when it is not given.
+    #[clap(long)]
+    git_username: Option<String>,
+
+    /// The environment variable that holds the password or token that an index is cloned and
+    /// fetched over HTTPS with
+    ///
+    /// The Git credential helpers are asked for credentials when it is not given. The name of the
+    /// variable is recorded in the index but the token is not.
+    #[clap(long)]
+    git_token_env: Option<String>,
+}
+
+impl From<AuthenticationArguments> for Authentication {
+    fn from(arguments: AuthenticationArguments) -> Self {
+        Self {
+            identity: arguments.ssh_identity,
+            username: arguments.git_username,
+            token_env: arguments.git_token_env,
+        }
+    }
+}
+
+/// Specifies how a refresh is carried out.
+#[derive(Args, Debug)]
+struct RefreshArguments {
+    /// Only act on packages whose path in the index starts with this prefix (eg. `ab` or `3/a`)
+    #[clap(long)]
+    prefix: Vec<String>,
+
+    /// Only act on one of a number of deterministic slices of the index (eg. `3/16`)
+    #[clap(long)]
+    shard: Option<Shard>,
+
+    /// The order that crates are downloaded in
+    #[clap(long, arg_enum, default_value_t = Order::Index)]
+    order: Order,
+}
+
+impl RefreshArguments {
+    /// Returns the packages in the index that the refresh acts on.
+    fn scope(&self) -> Scope {
+        Scope {
+            prefixes: self.prefix.clone(),
+            shard: self.shard,
+        }
+    }
+}
+
+/// Describes the source replacement that Cargo is configured with.
+#[derive(Args, Debug)]
+struct ConfigureCargoArguments {
+    /// The URL that clients use to reach the index (defaults to the index in the cache)
+    #[clap(long)]
+    index_url: Option<Url>,
+
+    /// The name of the source that is defined for the mirror
+    #[clap(long, default_value = "crateful")]
+    name: String,
+
+    /// The name of the source that is replaced by the mirror
+    #[clap(long, default_value = "crates-io")]
+    replaces: String,
+
+    /// Append the configuration to this Cargo configuration file instead of printing it (eg.
+    /// `.cargo/config.toml`)
+    #[clap(long)]
+    write: Option<PathBuf>,
 }
 
 /// Represents an action that a user requests.
@@ -84,42 +1824,1161 @@ enum Action {
         /// The URL of the index.
         #[clap(short, long)]
         url: Url,
+
+        #[clap(flatten)]
+        authentication: AuthenticationArguments,
+
+        /// How crates are laid out in the crates directory of the cache
+        ///
+        /// The layout is recorded in the cache and can be changed later with `migrate-layout`.
+        #[clap(long, arg_enum, default_value_t = Layout::Plain)]
+        layout: Layout,
+
+        /// Rewrite the download template of the index to point at this URL.
+        #[clap(long)]
+        rewrite_dl: Option<String>,
+
+        /// A file that lists the crates to track, one per line, when the index is a sparse index
+        ///
+        /// A sparse index (eg. `sparse+https://index.example/`) can not be enumerated so it is
+        /// mirrored in a Git index that tracks these crates and every crate that they depend on.
+        #[clap(long)]
+        crates: Option<PathBuf>,
+    },
+
+    /// Rewrites the download template of the index so that clients download crates from a mirror.
+    ///
+    /// The download template of the remote index continues to be used to fetch crates.
+    #[clap(name = "rewrite")]
+    Rewrite {
+        /// The download template that clients of the index use (eg. `https://mirror.example`).
+        #[clap(long)]
+        dl: String,
     },
 
     /// Verifies the integrity of the cache and (re)downloads any corrupt or missing crates.
     #[clap(name = "verify")]
-    Verify,
+    Verify {
+        /// Report the crates that are missing without downloading them.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Check the integrity of every crate
+        ///
+        /// By default, crates whose size and modification time have not changed since their
+        /// integrity was last checked are assumed to be intact.
+        #[clap(long)]
+        deep: bool,
+
+        /// Only check that crates have the expected size
+        ///
+        /// The expected size is the size that was recorded when the crate was downloaded or
+        /// verified, or otherwise the size that the registry reports. The integrity of crates whose
+        /// expected size is not known is checked.
+        #[clap(long, conflicts_with = "deep")]
+        size_only: bool,
+
+        /// Verify every crate
+        ///
+        /// By default, only the crates in the packages that changed since every crate was last
+        /// verified are verified. Every crate is verified with `--deep` and `--size-only`.
+        #[clap(long)]
+        all: bool,
+
+        /// Report the crates that are missing or corrupt without downloading them
+        ///
+        /// Each crate is written to standard output as a line of JSON with its name, version, and
+        /// problem (`missing`, `corrupt`, or `suspicious`).
+        #[clap(long, conflicts_with = "dry-run")]
+        report: bool,
+
+        /// Check that every object of the index repository can be read before crates are checked
+        ///
+        /// The entire history of the index is read so that missing or corrupt objects are found
+        /// before they cause a refresh or an update to fail.
+        #[clap(long)]
+        fsck: bool,
+
+        /// Check that every stored crate is a well-formed gzipped tarball whose files are all
+        /// within `<name>-<version>`
+        ///
+        /// Archives with absolute paths, paths that leave that directory, or links that point
+        /// outside of it are reported as suspicious and the verification fails. Suspicious crates
+        /// are kept.
+        #[clap(long)]
+        archives: bool,
+
+        /// Only verify the crates that match this selector (eg. `serde` or `serde@1.0.0`)
+        ///
+        /// The named crates are looked up in the index instead of walking the entire index, and
+        /// their integrity is always checked.
+        #[clap(
+            long = "crate",
+            value_name = "NAME[@VERSION]",
+            conflicts_with_all = &["dry-run", "all", "report", "fsck", "archives", "prefix", "shard"]
+        )]
+        crates: Vec<Selector>,
+
+        #[clap(flatten)]
+        refresh: RefreshArguments,
+    },
 
     /// Synchronises a cache.
     #[clap(name = "sync")]
-    Synchronise,
+    Synchronise {
+        /// Report the crates that would be downloaded or removed without changing the cache.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Refresh every crate even if the cache is consistent with the index
+        ///
+        /// By default, a cache that was fully synchronised by the last synchronisation is only
+        /// updated with the changes to the index since.
+        #[clap(long)]
+        full: bool,
+
+        /// Mirror the index as it was at the start of a date (eg. `2024-01-01`) instead of its
+        /// latest state
+        ///
+        /// The index is moved to the last commit that was made at or before the date, even if it
+        /// holds a later commit, and the cache mirrors exactly that commit. The next
+        /// synchronisation without a date moves it to the latest commit again.
+        #[clap(long, value_name = "DATE")]
+        at: Option<Date>,
+
+        /// Check that stored crates have the size that was recorded when they were stored
+        ///
+        /// By default, stored crates are preserved without being checked. Crates whose size does
+        /// not match, or whose size was not recorded, have their integrity checked and are
+        /// downloaded again if they are corrupt. Every crate is refreshed.
+        #[clap(long)]
+        check_size: bool,
+
+        #[clap(flatten)]
+        refresh: RefreshArguments,
+    },
+
+    /// Removes the crates in a cache that the filters do not retain.
+    ///
+    /// Filters only limit what is downloaded, so crates that were downloaded before a filter was
+    /// narrowed (eg. with `--keep-latest`) remain in the cache until they are pruned. Pruned crates
+    /// are archived if `--archive` is given.
+    #[clap(name = "prune")]
+    Prune {
+        /// Report the crates that would be removed without changing the cache.
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Clones the index of a cache again and synchronises the cache.
+    ///
+    /// An index that was corrupted (eg. by an interrupted clone or a failing disk) is replaced by
+    /// a new clone of the registry index. The crates directory is preserved and every crate that
+    /// the new index holds is refreshed.
+    #[clap(name = "repair-index")]
+    RepairIndex {
+        /// The URL of the index (defaults to the remote that the index was cloned from).
+        #[clap(short, long)]
+        url: Option<Url>,
+
+        /// The order that crates are downloaded in.
+        #[clap(long, arg_enum, default_value_t = Order::Index)]
+        order: Order,
+    },
+
+    /// Pins the index of a cache to a commit and synchronises the cache with it.
+    ///
+    /// Crates are downloaded and removed as they are by an update, so the cache mirrors exactly
+    /// the commit. Later synchronisations keep the index at the commit until it is unpinned.
+    #[clap(name = "pin")]
+    Pin {
+        /// The commit (eg. an object ID or a prefix of one) that the index is pinned to. It must
+        /// have been fetched from the remote index.
+        commit: String,
+    },
+
+    /// Pins the index of a cache to the commit that it held before the last update and
+    /// synchronises the cache with it.
+    ///
+    /// This undoes synchronising a problematic state of the remote index. Later synchronisations
+    /// keep the index at the commit until it is unpinned.
+    #[clap(name = "rollback")]
+    Rollback,
+
+    /// Releases the pin on the index of a cache so that the next synchronisation moves it to the
+    /// latest commit again.
+    #[clap(name = "unpin")]
+    Unpin,
+
+    /// Manages named snapshots of a cache.
+    ///
+    /// A snapshot records the index commit and hard links every stored crate so that a
+    /// point-in-time state of the cache can be kept without duplicating the crates.
+    #[clap(name = "snapshot")]
+    Snapshot {
+        #[clap(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Configures Cargo to use the cache as a mirror.
+    #[clap(name = "configure-cargo")]
+    ConfigureCargo {
+        #[clap(flatten)]
+        arguments: ConfigureCargoArguments,
+    },
+
+    /// Exports the index using the layout of a sparse index so that it can be served by a static
+    /// file server.
+    ///
+    /// Once exported, the sparse index is kept up to date when the cache is synchronised.
+    #[clap(name = "export-sparse")]
+    ExportSparse,
+
+    /// Copies every crate that is stored in the cache to a directory with the file names that the
+    /// registry cache of Cargo uses (eg. `serde-1.0.0.crate`)
+    ///
+    /// The directory can be copied into `~/.cargo/registry/cache/<registry>` to seed a Cargo home
+    /// without downloading crates. Crates that are already in the directory are skipped. Only the
+    /// crates that the filters select are exported.
+    #[clap(name = "export-cargo")]
+    ExportCargo {
+        /// The directory that crates are copied to.
+        output: PathBuf,
+
+        /// Also unpack crates into this directory like Cargo does in `~/.cargo/registry/src`
+        ///
+        /// Each crate is unpacked into `<name>-<version>` so that its source can be searched or
+        /// scanned. Crates that were already unpacked are skipped.
+        #[clap(long)]
+        source: Option<PathBuf>,
+    },
+
+    /// Deletes a crate from the cache and downloads it again.
+    ///
+    /// The crate is checked against the checksum in the index once it is downloaded, and the
+    /// checksums of the stored and downloaded crates are logged. A crate that can not be downloaded
+    /// again is downloaded by the next synchronisation.
+    #[clap(name = "refetch")]
+    Refetch {
+        /// The crate to download again (eg. `serde@1.0.0`).
+        #[clap(value_name = "NAME@VERSION")]
+        key: CrateKey,
+    },
+
+    /// Deletes every version of a crate, or a single version, from the cache.
+    ///
+    /// Directories that are left empty are removed. Removed crates are deleted rather than
+    /// archived. Unless they are denied, they are downloaded again by the next synchronisation.
+    #[clap(name = "remove")]
+    Remove {
+        /// The crates to remove (eg. `serde` or `serde@1.0.0`).
+        #[clap(value_name = "NAME[@VERSION]")]
+        selector: Selector,
+
+        /// Also add the crates to the deny list of the cache so that they are never mirrored again
+        #[clap(long)]
+        deny: bool,
+    },
+
+    /// Writes a crate from the cache to standard output, decompressing it if necessary.
+    #[clap(name = "read")]
+    Read {
+        /// The name of the crate.
+        #[clap(long)]
+        name: String,
+
+        /// The version of the crate.
+        #[clap(long)]
+        version: String,
+
+        /// Hash the crate if it changed since its integrity was last checked. A crate that does not
+        /// match its checksum is quarantined and downloaded again by the next synchronisation.
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Writes the dependencies of a crate in the index to standard output.
+    ///
+    /// Each line holds the name of the crate that is depended on, the version requirement, and the
+    /// kind of the dependency (`normal`, `build`, or `dev`), followed by `optional` if the
+    /// dependency is only enabled by a feature and by the name that the crate refers to a renamed
+    /// dependency by.
+    #[clap(name = "dependencies")]
+    Dependencies {
+        /// The name of the crate.
+        #[clap(long)]
+        name: String,
+
+        /// The version of the crate.
+        #[clap(long)]
+        version: String,
+    },
+
+    /// Writes the names of the crates in the index that depend on a crate to standard output.
+    ///
+    /// Crates that depend on the crate through other crates are included unless `--direct` is
+    /// given. A crate is listed if any of its versions depends on the crate. Development
+    /// dependencies are ignored. Each line holds the name of a crate in alphabetical order.
+    #[clap(name = "dependents")]
+    Dependents {
+        /// The name of the crate.
+        #[clap(long)]
+        name: String,
+
+        /// Only list the crates that depend on the crate directly.
+        #[clap(long)]
+        direct: bool,
+    },
+
+    /// Lists the changes to the index that the next synchronisation will apply without downloading
+    /// any crates or applying the changes.
+    ///
+    /// Each line holds the change (`added`, `removed`, `modified`, `yanked`, or `unyanked`)
+    /// followed by the name and version of the crate.
+    #[clap(name = "changes")]
+    Changes {
+        /// Write each change as a line of JSON with the name, version, and change of the crate.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Writes a CycloneDX software bill of materials to standard output that describes every crate
+    /// stored in the cache.
+    ///
+    /// Each crate is identified by its package URL and listed with the checksum published in the
+    /// index and the URL that it was downloaded from.
+    #[clap(name = "sbom")]
+    Sbom {
+        /// The file that the bill of materials is written to instead of standard output.
+        #[clap(long)]
+        output: Option<PathBuf>,
+
+        /// Sign the bill of materials with this minisign secret key
+        ///
+        /// The signature is written beside the output with `.minisig` appended and can be checked
+        /// with `minisign -V`. The key must not be encrypted with a password.
+        #[clap(long, requires = "output")]
+        signing_key: Option<PathBuf>,
+    },
+
+    /// Writes a Metalink document to standard output that describes every crate stored in the cache
+    ///
+    /// Each crate is listed with the checksum published in the index and the URLs that it can be
+    /// downloaded from so that download managers can fetch crates from several mirrors at once.
+    #[clap(name = "metalink")]
+    Metalink {
+        /// A mirror that serves the crates directory of a cache (eg. `https://mirror.example/crates`)
+        ///
+        /// Mirrors are listed before the registry in the order that they are given.
+        #[clap(long)]
+        mirror: Vec<Url>,
+    },
+
+    /// Writes a bundle that holds the index commits between two commits and the crates that they
+    /// published or modified
+    ///
+    /// The bundle is a tar archive that holds a Git bundle of the commits and the crates that are
+    /// stored in the cache. It can be carried to an air-gapped mirror and applied with
+    /// `apply-bundle`.
+    #[clap(name = "bundle")]
+    Bundle {
+        /// The index commit that the mirror that the bundle is applied to already holds.
+        #[clap(long)]
+        from: Oid,
+
+        /// The index commit that the bundle brings the mirror up to (defaults to the latest commit
+        /// of the index).
+        #[clap(long)]
+        to: Option<Oid>,
+
+        /// The file that the bundle is written to.
+        output: PathBuf,
+
+        /// Sign the bundle with this minisign secret key
+        ///
+        /// The signature is written beside the bundle with `.minisig` appended and can be checked
+        /// with `minisign -V` before the bundle is applied. The key must not be encrypted with a
+        /// password.
+        #[clap(long)]
+        signing_key: Option<PathBuf>,
+    },
+
+    /// Applies a bundle that was written by `bundle` without contacting the registry
+    ///
+    /// The index commits of the bundle are imported and the changes that they make are applied
+    /// with the crates from the bundle.
+    #[clap(name = "apply-bundle")]
+    ApplyBundle {
+        /// The bundle to apply.
+        bundle: PathBuf,
+    },
+
+    /// Summarises the crates that are recorded in the metadata database of the cache.
+    #[clap(name = "status")]
+    Status,
+
+    /// Reports statistics about the crates that are recorded in the metadata database of the cache.
+    ///
+    /// The sizes of crates, the crates that occupy the most space, and what was downloaded and
+    /// failed since the last synchronisation started are reported without walking the crates
+    /// directory.
+    #[clap(name = "stats")]
+    Stats {
+        /// The number of largest crates to report.
+        #[clap(long, default_value_t = 10)]
+        largest: usize,
+    },
+
+    /// Reports the disk usage of each crate that is recorded in the metadata database of the cache.
+    ///
+    /// Each line holds the name of a crate, its number of versions, and the number of bytes that
+    /// they occupy, from the largest crate to the smallest.
+    #[clap(name = "du")]
+    Du {
+        /// Only report this many of the largest crates.
+        #[clap(long)]
+        top: Option<usize>,
+    },
+
+    /// Checks the cache for the problems that most often prevent it from being synchronised.
+    ///
+    /// The index repository and its tracking branch, the configuration of the index, whether the
+    /// cache can be written to, whether the download host can be reached, the clock, and the lock
+    /// are checked. Each problem is reported with what can be done about it.
+    #[clap(name = "doctor")]
+    Doctor,
+
+    /// Lists the crates that are recorded in the metadata database of the cache.
+    ///
+    /// Each line holds the name, version, and size in bytes of a crate followed by the times that
+    /// it was downloaded and last verified as seconds since the Unix epoch.
+    #[clap(name = "list")]
+    List,
+
+    /// Lists the crates that were pinned to an IPFS node.
+    ///
+    /// Each line holds the name and version of a crate followed by its content identifier.
+    #[clap(name = "pins")]
+    Pins,
+
+    /// Records the number of times that each crate has been downloaded from a crates.io database
+    /// dump so that `--top` can select the most popular crates.
+    #[clap(name = "ingest-dump")]
+    IngestDump {
+        /// The database dump (`db-dump.tar.gz`), the directory that it was extracted to, or its
+        /// `crates.csv` table.
+        dump: PathBuf,
+    },
+
+    /// Moves every stored crate to another layout of the crates directory.
+    #[clap(name = "migrate-layout")]
+    MigrateLayout {
+        /// The layout that crates are moved to.
+        #[clap(arg_enum)]
+        layout: Layout,
+    },
+
+    /// Removes crates from the archive.
+    #[clap(name = "prune-archive")]
+    PruneArchive {
+        /// Only remove crates that were archived more than this many days ago.
+        #[clap(long, default_value_t = 0)]
+        older_than: u64,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let arguments = Arguments::parse();
+/// An action on the snapshots of a cache.
+#[derive(Debug, Subcommand)]
+enum SnapshotAction {
+    /// Records the index commit and hard links every stored crate into a new snapshot.
+    ///
+    /// Crates are copied if they can not be linked (eg. because the snapshots directory is on
+    /// another file system).
+    #[clap(name = "create")]
+    Create {
+        /// The name of the snapshot.
+        name: String,
+    },
+
+    /// Lists the snapshots with the index commit, the time that they were taken, and the number
+    /// of files that they hold.
+    #[clap(name = "list")]
+    List,
+
+    /// Lists the crates that were added, removed, or changed between two snapshots, or between a
+    /// snapshot and the cache.
+    ///
+    /// Each line holds the change (`added`, `removed`, or `modified`) followed by the name and
+    /// version of the crate. A crate is modified if its checksum is different.
+    #[clap(name = "diff")]
+    Diff {
+        /// The name of the earlier snapshot.
+        from: String,
+
+        /// The name of the later snapshot. The cache is compared if there is none.
+        to: Option<String>,
+
+        /// Write each difference as a line of JSON with the name, version, and change of the
+        /// crate.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Restores the crates of a snapshot, pins the index to its commit, and synchronises the
+    /// cache with it.
+    ///
+    /// Later synchronisations keep the index at the commit until it is unpinned.
+    #[clap(name = "restore")]
+    Restore {
+        /// The name of the snapshot.
+        name: String,
+    },
+}
+
+impl Action {
+    /// Returns true if the action can change the cache.
+    const fn changes_cache(&self) -> bool {
+        !matches!(
+            self,
+            Self::ConfigureCargo { .. }
+                | Self::Read { check: false, .. }
+                | Self::Dependencies { .. }
+                | Self::Dependents { .. }
+                | Self::Sbom { .. }
+                | Self::Metalink { .. }
+                | Self::Bundle { .. }
+                | Self::Status
+                | Self::Stats { .. }
+                | Self::Du { .. }
+                | Self::Doctor
+                | Self::List
+                | Self::Pins
+                | Self::Snapshot {
+                    action: SnapshotAction::List | SnapshotAction::Diff { .. }
+                }
+        )
+    }
+}
+
+/// Returns the path of the cache when none is given. The path is read from `CRATEFUL_PATH` or is
+/// the directory of `registry` in the XDG data directory. The directory that holds every
+/// registry is returned instead when several registries are configured.
+fn discover_path(registry: &str, several: bool) -> Result<PathBuf> {
+    if let Some(path) = env::var_os("CRATEFUL_PATH").filter(|path| !path.is_empty()) {
+        let path = PathBuf::from(path);
+        info!("using the cache path {} from CRATEFUL_PATH", path.display());
+        return Ok(path);
+    }
+
+    // Relative data directories are ignored as the specification requires.
+    let data = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok_or_else(|| eyre!("no cache path was given and there is no home directory"))?;
+
+    let mut path = data.join(env!("CARGO_PKG_NAME"));
+    if !several {
+        path.push(registry);
+    }
+
+    info!(
+        "using the cache path {} in the data directory",
+        path.display()
+    );
+    Ok(path)
+}
+
+#[allow(clippy::too_many_lines)]
+async fn run(mut arguments: Arguments) -> Result<Outcome> {
+    let path = match arguments.path.take() {
+        Some(path) => path,
+        None => discover_path(&arguments.registry, arguments.config.is_some())?,
+    };
+
+    if !arguments.connection.resolve.is_empty() {
+        resolve::register_git_transport(&arguments.connection.resolve)?;
+    }
+
+    let mode = if arguments.strict {
+        FailureMode::Strict
+    } else {
+        FailureMode::Continue
+    };
+
+    let estimate = match arguments.estimate {
+        EstimateStrategy::Average => Estimate::Average(arguments.average_crate_size),
+        EstimateStrategy::Head => Estimate::Head {
+            fallback: arguments.average_crate_size,
+        },
+    };
 
-    tracing_subscriber::fmt()
-        .with_max_level(arguments.log_level)
-        .init();
+    let permissions = Permissions {
+        file_mode: arguments.file_mode,
+        directory_mode: arguments.directory_mode,
+        owner: arguments.owner,
+        group: arguments.group,
+    };
+
+    // The lock is held until the action finishes.
+    let _lock = if arguments.action.changes_cache() {
+        Some(Cache::lock(&path, arguments.lock.wait, arguments.lock.force_unlock).await?)
+    } else {
+        None
+    };
+
+    notify::ready();
+    let _supervisor = notify::supervise();
 
     match arguments.action {
-        Action::New { url } => new(arguments.path, url).await,
+        Action::New {
+            url,
+            authentication,
+            layout,
+            rewrite_dl,
+            crates,
+        } => {
+            let client = client(arguments.contact.as_deref(), &arguments.connection)?;
+            new(
+                path,
+                url,
+                authentication.into(),
+                layout,
+                rewrite_dl,
+                crates,
+                &client,
+                arguments.jobs,
+            )
+            .await
+        }
+        Action::Rewrite { dl } => rewrite(path, dl).await,
+        Action::ConfigureCargo { arguments: each } => configure_cargo(path, each).await,
+        Action::ExportSparse => export_sparse(path, arguments.lenient).await,
+        Action::ExportCargo { output, source } => {
+            let filter = Filter {
+                since: arguments.since,
+                yanked: arguments.yanked,
+                top: arguments.top,
+                preset: arguments.preset,
+                constraints: arguments.constraint,
+                dependents: arguments.dependents_of,
+                latest: arguments.keep_latest,
+                exclude_prerelease: arguments.exclude_prerelease,
+                deny: Vec::new(),
+            };
+            export_cargo(
+                path,
+                arguments.jobs,
+                &filter,
+                &output,
+                source.as_deref(),
+                arguments.lenient,
+            )
+            .await
+        }
+        Action::Read {
+            name,
+            version,
+            check,
+        } => read(path, &name, &version, check, permissions, arguments.lenient).await,
+        Action::Dependencies { name, version } => {
+            dependencies(path, &name, &version, arguments.lenient).await
+        }
+        Action::Dependents { name, direct } => {
+            dependents(path, name, !direct, arguments.lenient).await
+        }
+        Action::Changes { json } => {
+            let client = client(arguments.contact.as_deref(), &arguments.connection)?;
+            changes(path, &client, arguments.jobs, json, arguments.lenient).await
+        }
+        Action::Sbom {
+            output,
+            signing_key,
+        } => sbom(path, arguments.jobs, output, signing_key, arguments.lenient).await,
+        Action::Metalink { mirror } => {
+            metalink(path, arguments.jobs, &mirror, arguments.lenient).await
+        }
+        Action::Bundle {
+            from,
+            to,
+            output,
+            signing_key,
+        } => bundle(path, from, to, output, signing_key, arguments.lenient).await,
+        Action::Status => status(path).await,
+        Action::Stats { largest } => statistics(path, largest).await,
+        Action::Du { top } => usage(path, top).await,
+        Action::Doctor => {
+            let client = client(arguments.contact.as_deref(), &arguments.connection)?;
+            doctor(path, &client).await
+        }
+        Action::List => list(path).await,
+        Action::Pins => pins(path).await,
+        Action::IngestDump { dump } => ingest_dump(path, dump).await,
+        Action::MigrateLayout { layout } => {
+            migrate_layout(path, layout, permissions, arguments.lenient).await
+        }
+        Action::PruneArchive { older_than } => prune_archive(path, older_than).await,
+        Action::Unpin => unpin(path).await,
+        Action::Snapshot {
+            action: SnapshotAction::Create { name },
+        } => create_snapshot(path, name).await,
+        Action::Snapshot {
+            action: SnapshotAction::List,
+        } => snapshots(path).await,
+        Action::Snapshot {
+            action: SnapshotAction::Diff { from, to, json },
+        } => diff_snapshots(path, from, to, json, arguments.lenient).await,
         action => {
-            let mut builder = ClientBuilder::new();
-            builder = match arguments.contact {
-                Some(contact) => builder.user_agent(format!("{} ({})", USER_AGENT, contact)),
-                None => builder.user_agent(USER_AGENT),
+            let (operation, invocation) = match action {
+                Action::Verify {
+                    dry_run,
+                    refresh,
+                    deep,
+                    size_only,
+                    all,
+                    report,
+                    fsck,
+                    archives,
+                    crates,
+                } => (
+                    if crates.is_empty() {
+                        Operation::Verify
+                    } else {
+                        Operation::VerifyCrates(crates)
+                    },
+                    Invocation {
+                        dry_run,
+                        scope: refresh.scope(),
+                        order: refresh.order,
+                        check: if deep {
+                            Check::Every
+                        } else if size_only {
+                            Check::Size
+                        } else {
+                            Check::Changed
+                        },
+                        report,
+                        full: all || deep || size_only,
+                        fsck,
+                        archives,
+                        ..Invocation::default()
+                    },
+                ),
+                Action::Synchronise {
+                    dry_run,
+                    full,
+                    at,
+                    check_size,
+                    refresh,
+                } => (
+                    Operation::Synchronise,
+                    Invocation {
+                        dry_run,
+                        scope: refresh.scope(),
+                        order: refresh.order,
+                        check: if check_size {
+                            Check::RecordedSize
+                        } else {
+                            Check::Changed
+                        },
+                        full: full || check_size,
+                        snapshot: at,
+                        ..Invocation::default()
+                    },
+                ),
+                Action::ApplyBundle { bundle } => {
+                    (Operation::ApplyBundle(bundle), Invocation::default())
+                }
+                Action::Pin { commit } => (Operation::Pin(commit), Invocation::default()),
+                Action::Refetch { key } => (Operation::Refetch(key), Invocation::default()),
+                Action::Remove { selector, deny } => {
+                    (Operation::Remove(selector, deny), Invocation::default())
+                }
+                Action::Rollback => (Operation::Rollback, Invocation::default()),
+                Action::Snapshot {
+                    action: SnapshotAction::Restore { name },
+                } => (Operation::RestoreSnapshot(name), Invocation::default()),
+                Action::Prune { dry_run } => (
+                    Operation::Prune,
+                    Invocation {
+                        dry_run,
+                        ..Invocation::default()
+                    },
+                ),
+                Action::RepairIndex { url, order } => (
+                    Operation::RepairIndex(url),
+                    Invocation {
+                        order,
+                        full: true,
+                        ..Invocation::default()
+                    },
+                ),
+
+                // Already covered.
+                Action::New { .. }
+                | Action::Rewrite { .. }
+                | Action::ConfigureCargo { .. }
+                | Action::ExportSparse
+                | Action::ExportCargo { .. }
+                | Action::Read { .. }
+                | Action::Dependencies { .. }
+                | Action::Dependents { .. }
+                | Action::Changes { .. }
+                | Action::Sbom { .. }
+                | Action::Metalink { .. }
+                | Action::Bundle { .. }
+                | Action::Status
+                | Action::Stats { .. }
+                | Action::Du { .. }
+                | Action::Doctor
+                | Action::List
+                | Action::Pins
+                | Action::IngestDump { .. }
+                | Action::MigrateLayout { .. }
+                | Action::PruneArchive { .. }
+                | Action::Unpin
+                | Action::Snapshot {
+                    action:
+                        SnapshotAction::Create { .. }
+                        | SnapshotAction::List
+                        | SnapshotAction::Diff { .. },
+                } => {
+                    unreachable!()
+                }
             };
-            let client = builder.build()?;
 
-            match action {
-                Action::Verify => verify(arguments.path, arguments.jobs, &client).await,
-                Action::Synchronise => synchronise(arguments.path, arguments.jobs, &client).await,
+            let context = Context {
+                client: client(arguments.contact.as_deref(), &arguments.connection)?,
+                mode,
+                jobs: arguments.jobs,
+                in_flight_bytes: arguments.in_flight_bytes,
+                estimate,
+                check_space: !arguments.skip_space_check,
+                force: arguments.force,
+                filter: Filter {
+                    since: arguments.since,
+                    yanked: arguments.yanked,
+                    top: arguments.top,
+                    preset: arguments.preset,
+                    constraints: arguments.constraint,
+                    dependents: arguments.dependents_of,
+                    latest: arguments.keep_latest,
+                    exclude_prerelease: arguments.exclude_prerelease,
+                    deny: Vec::new(),
+                },
+                removal: if arguments.archive {
+                    RemovalStrategy::Archive
+                } else {
+                    RemovalStrategy::Delete
+                },
+                parents: arguments.parent,
+                feed: arguments
+                    .feed
+                    .as_ref()
+                    .map(|url| Feed::new(url, arguments.feed_entries))
+                    .transpose()?,
+                ipfs: arguments.ipfs.as_ref().map(Ipfs::new).transpose()?,
+                oci: arguments
+                    .oci
+                    .as_ref()
+                    .map(|url| {
+                        let credentials =
+                            arguments
+                                .oci_username
+                                .clone()
+                                .map(|username| oci::Credentials {
+                                    username,
+                                    password: arguments
+                                        .oci_password_env
+                                        .as_ref()
+                                        .and_then(|variable| env::var(variable).ok())
+                                        .unwrap_or_default(),
+                                });
 
-                // Already covered.
-                Action::New { url: _ } => unreachable!(),
+                        Oci::new(url, credentials)
+                            .ok_or_else(|| eyre!("{url} does not name a repository"))
+                    })
+                    .transpose()?,
+                verifier: arguments.signature_verifier.map(Verifier::new),
+                download: download::Options {
+                    storage: arguments.storage,
+                    durability: arguments.durability,
+                    permissions,
+                    segmentation: arguments.segment_threshold.map(|threshold| {
+                        download::Segmentation {
+                            threshold,
+                            segments: arguments.segments,
+                        }
+                    }),
+                    http3: arguments.connection.http3,
+                    ..download::Options::default()
+                },
+                hashing: digest::Pool::new(arguments.hash_jobs.unwrap_or_else(|| {
+                    thread::available_parallelism()
+                        .unwrap_or_else(|_| NonZeroUsize::new(1).expect("one is not zero"))
+                })),
+                check: invocation.check,
+                report: invocation.report,
+                full: invocation.full,
+                fsck: invocation.fsck,
+                archives: invocation.archives,
+                snapshot: invocation.snapshot,
+                authorization: None,
+                lenient: arguments.lenient,
+            };
+
+            let Some(config) = arguments.config else {
+                return operation
+                    .perform(
+                        path,
+                        &context,
+                        invocation.dry_run,
+                        &invocation.scope,
+                        invocation.order,
+                    )
+                    .await;
+            };
+
+            // Each bundle is written from a single cache.
+            if let Operation::ApplyBundle(_) = operation {
+                return Err(eyre!(
+                    "a bundle can only be applied to a single cache; use --path instead of --config"
+                ));
+            }
+
+            // A commit only names a state of one index.
+            if let Operation::Pin(_) = operation {
+                return Err(eyre!(
+                    "an index can only be pinned to a commit in a single cache; use --path \
+                     instead of --config"
+                ));
+            }
+
+            // A crate is downloaded again in a single cache.
+            if let Operation::Refetch(_) = operation {
+                return Err(eyre!(
+                    "a crate can only be downloaded again in a single cache; use --path instead \
+                     of --config"
+                ));
+            }
+
+            // The crates of one registry are not the crates of another with the same name.
+            if let Operation::Remove(..) = operation {
+                return Err(eyre!(
+                    "crates can only be removed from a single cache; use --path instead of \
+                     --config"
+                ));
+            }
+
+            // Snapshots are taken of a single cache.
+            if let Operation::RestoreSnapshot(_) = operation {
+                return Err(eyre!(
+                    "a snapshot can only be restored to a single cache; use --path instead of \
+                     --config"
+                ));
+            }
+
+            // The URL of one index can not be used for every registry.
+            if let Operation::RepairIndex(Some(_)) = operation {
+                return Err(eyre!(
+                    "the URL of an index can only be given for a single cache; use --path instead \
+                     of --config"
+                ));
+            }
+
+            let config = Config::load(&config).await?;
+            let mut outcome = Outcome::default();
+            let mut failure = None;
+
+            for registry in config.registries {
+                let path = path.join(&registry.name);
+                let context = Context {
+                    authorization: authorization(registry.token())?,
+                    client: client(arguments.contact.as_deref(), &arguments.connection)?,
+                    filter: Filter {
+                        since: registry.since.or(context.filter.since),
+                        yanked: registry.yanked.unwrap_or(context.filter.yanked),
+                        top: registry.top.or(context.filter.top),
+                        preset: registry.preset.or(context.filter.preset),
+                        constraints: if registry.versions.is_empty() {
+                            context.filter.constraints.clone()
+                        } else {
+                            registry.constraints()
+                        },
+                        dependents: if registry.dependents_of.is_empty() {
+                            context.filter.dependents.clone()
+                        } else {
+                            registry.dependents_of.clone()
+                        },
+                        latest: registry.keep_latest.or(context.filter.latest),
+                        exclude_prerelease: registry
+                            .exclude_prerelease
+                            .unwrap_or(context.filter.exclude_prerelease),
+                        deny: context.filter.deny.clone(),
+                    },
+                    parents: if registry.parents.is_empty() {
+                        context.parents.clone()
+                    } else {
+                        registry.parents.clone()
+                    },
+                    verifier: registry
+                        .signature_verifier
+                        .clone()
+                        .map(Verifier::new)
+                        .or_else(|| context.verifier.clone()),
+                    feed: context
+                        .feed
+                        .as_ref()
+                        .map(|feed| feed.within(&registry.name))
+                        .transpose()?,
+                    ..context.clone()
+                };
+
+                let result = async {
+                    if operation == Operation::Synchronise && !path.exists() {
+                        drop(
+                            create(
+                                path.clone(),
+                                &registry.index,
+                                registry.authentication(),
+                                registry.layout.unwrap_or_default(),
+                                &registry.crates,
+                                &context.client,
+                                context.jobs,
+                            )
+                            .await?,
+                        );
+                    }
+
+                    operation
+                        .perform(
+                            path,
+                            &context,
+                            invocation.dry_run,
+                            &invocation.scope,
+                            invocation.order,
+                        )
+                        .await
+                }
+                .instrument(info_span!("registry", name = registry.name.as_str()))
+                .await;
+
+                match result {
+                    Ok(each) => outcome = outcome.merge(each),
+                    Err(report) => {
+                        error!("failed to {} {}: {:?}", operation, registry.name, report);
+                        failure.get_or_insert(report);
+                    }
+                }
+            }
+
+            failure.map_or(Ok(outcome), Err)
+        }
+    }
+}
+
+/// Completes when the program is interrupted. It never completes if interrupts can not be handled.
+async fn interrupted() {
+    if let Err(error) = signal::ctrl_c().await {
+        warn!("failed to handle interrupts: {}", error);
+        future::pending::<()>().await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let arguments = Arguments::parse();
+
+    let file = match arguments
+        .log
+        .file
+        .as_deref()
+        .map(|path| {
+            logging::file(
+                path,
+                arguments.log.rotation,
+                arguments.log.max_size,
+                arguments.log.max_files,
+            )
+        })
+        .transpose()
+    {
+        Ok(file) => file,
+        Err(error) => {
+            eprintln!("Error: failed to open the log file: {error}");
+            return Failure::Other.into();
+        }
+    };
+
+    let target = match logging::target(arguments.log.target, arguments.log.format) {
+        Ok(target) => target,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            return Failure::Other.into();
+        }
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(logging::levels(
+            arguments.log_level,
+            arguments.log.trace_http,
+        ))
+        .with(target.with_filter(logging::filter(arguments.log.quiet)))
+        .with(file.map(|file| logging::layer(arguments.log.file_format, false, file)));
+
+    // The exporter is dropped when the program exits so that spans that have not been exported
+    // are exported.
+    #[cfg(feature = "otlp")]
+    let (subscriber, _exporter) = {
+        let exporter = match arguments
+            .otlp_endpoint
+            .as_ref()
+            .map(telemetry::Exporter::new)
+            .transpose()
+        {
+            Ok(exporter) => exporter,
+            Err(error) => {
+                eprintln!("Error: failed to create the trace exporter: {error}");
+                return Failure::Other.into();
             }
+        };
+
+        (
+            subscriber.with(exporter.as_ref().map(telemetry::Exporter::layer)),
+            exporter,
+        )
+    };
+
+    subscriber.init();
+
+    let result = tokio::select! {
+        result = run(arguments) => result,
+        () = interrupted() => {
+            // Dropping the operation cancels in-flight downloads. Crates are written atomically
+            // and the journal is recorded as each directory is refreshed so the next operation
+            // resumes from where this one stopped.
+            warn!(target: SUMMARY, "interrupted; the operation was cancelled");
+            notify::stopping();
+            return Failure::Interrupted.into();
+        }
+    };
+
+    notify::stopping();
+
+    match result {
+        Ok(outcome) if outcome.is_complete() => ExitCode::SUCCESS,
+        Ok(outcome) => {
+            warn!(
+                target: SUMMARY,
+                failed = outcome.failed,
+                "{} crates could not be downloaded",
+                outcome.failed
+            );
+            Failure::Partial.into()
+        }
+        Err(report) => {
+            eprintln!("Error: {report:?}");
+            Failure::categorise(&report).into()
         }
     }
 }
@@ -0,0 +1,203 @@
+#[cfg(test)]
+mod tests;
+
+use crate::file::{self, Durability, Permissions};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2b_simd::{Params, State};
+use ring::signature::Ed25519KeyPair;
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use tokio::{fs, task};
+
+/// The signature algorithm of keys.
+const ALGORITHM: &[u8; 2] = b"Ed";
+
+/// The signature algorithm of signatures whose message is hashed with BLAKE2b-512 before it is
+/// signed.
+const HASHED_ALGORITHM: &[u8; 2] = b"ED";
+
+/// The key derivation algorithm of secret keys that are not encrypted.
+const NO_KDF: &[u8; 2] = b"\0\0";
+
+/// The checksum algorithm of secret keys.
+const CHECKSUM_ALGORITHM: &[u8; 2] = b"B2";
+
+/// The length of a decoded secret key: the algorithms, the key derivation parameters, the key
+/// identifier, the secret and public keys, and the checksum.
+const SECRET_KEY_LENGTH: usize = 2 + 2 + 2 + 32 + 8 + 8 + 8 + 64 + 32;
+
+/// The extension of the file that a signature is written to.
+const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// The error type for loading a secret key.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LoadSecretKeyError {
+    Io(io::Error),
+    /// The file is not a minisign secret key.
+    Malformed,
+    /// The secret key is encrypted with a password.
+    Encrypted,
+}
+
+impl From<io::Error> for LoadSecretKeyError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for LoadSecretKeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Malformed => f.write_str("not a minisign secret key"),
+            Self::Encrypted => f.write_str(
+                "the secret key is encrypted; create a key without a password with `minisign -G -W`",
+            ),
+        }
+    }
+}
+
+impl Error for LoadSecretKeyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::Malformed | Self::Encrypted => None,
+        }
+    }
+}
+
+/// A minisign secret key that signs files so that they can be verified with `minisign -V`.
+///
+/// Only keys that are not encrypted with a password are supported.
+pub struct SecretKey {
+    id: [u8; 8],
+    pair: Ed25519KeyPair,
+}
+
+impl SecretKey {
+    /// Loads the secret key at `path`.
+    pub async fn load(path: &Path) -> Result<Self, LoadSecretKeyError> {
+        Self::parse(&fs::read_to_string(path).await?)
+    }
+
+    /// Parses a secret key from the contents of a secret key file.
+    pub fn parse(contents: &str) -> Result<Self, LoadSecretKeyError> {
+        let encoded = contents
+            .lines()
+            .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .ok_or(LoadSecretKeyError::Malformed)?;
+        let decoded = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| LoadSecretKeyError::Malformed)?;
+        if decoded.len() != SECRET_KEY_LENGTH
+            || &decoded[..2] != ALGORITHM
+            || &decoded[4..6] != CHECKSUM_ALGORITHM
+        {
+            return Err(LoadSecretKeyError::Malformed);
+        }
+
+        if &decoded[2..4] != NO_KDF {
+            return Err(LoadSecretKeyError::Encrypted);
+        }
+
+        let (keys, checksum) = decoded[54..].split_at(8 + 64);
+        let (id, secret) = keys.split_at(8);
+        let expected = Params::new()
+            .hash_length(32)
+            .to_state()
+            .update(ALGORITHM)
+            .update(id)
+            .update(secret)
+            .finalize();
+        if expected.as_bytes() != checksum {
+            return Err(LoadSecretKeyError::Malformed);
+        }
+
+        let (seed, public) = secret.split_at(32);
+        let pair = Ed25519KeyPair::from_seed_and_public_key(seed, public)
+            .map_err(|_| LoadSecretKeyError::Malformed)?;
+
+        Ok(Self {
+            id: id.try_into().expect("identifier has eight bytes"),
+            pair,
+        })
+    }
+
+    /// Returns the contents of a signature file for a message with the BLAKE2b-512 `digest`. The
+    /// trusted comment records `time` as the number of seconds since the Unix epoch and the name of
+    /// the signed file.
+    #[must_use]
+    pub fn signature(&self, digest: &[u8], name: &str, time: u64) -> String {
+        let mut signature = Vec::with_capacity(2 + 8 + 64);
+        signature.extend_from_slice(HASHED_ALGORITHM);
+        signature.extend_from_slice(&self.id);
+        let signed = self.pair.sign(digest);
+        signature.extend_from_slice(signed.as_ref());
+
+        let comment = format!("timestamp:{time}\tfile:{name}\thashed");
+        let mut global = signed.as_ref().to_vec();
+        global.extend_from_slice(comment.as_bytes());
+
+        format!(
+            "untrusted comment: signature from crateful secret key\n{}\ntrusted comment: {}\n{}\n",
+            STANDARD.encode(signature),
+            comment,
+            STANDARD.encode(self.pair.sign(&global))
+        )
+    }
+
+    /// Signs the file at `path` and writes the signature to the path with `.minisig` appended.
+    /// Returns the path of the signature.
+    pub async fn sign(&self, path: &Path, time: u64) -> Result<PathBuf, io::Error> {
+        let file = path.to_path_buf();
+        let digest = task::spawn_blocking(move || self::digest(File::open(file)?))
+            .await
+            .expect("panicked while hashing file")?;
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut destination = OsString::from(path.as_os_str());
+        destination.push(".");
+        destination.push(SIGNATURE_EXTENSION);
+        let destination = PathBuf::from(destination);
+
+        file::write(
+            &destination,
+            self.signature(&digest, &name, time).into_bytes(),
+            Durability::Rename,
+            Permissions::default(),
+        )
+        .await?;
+        Ok(destination)
+    }
+}
+
+/// Writes bytes into the state of a hash.
+struct Writer<'a>(&'a mut State);
+
+impl io::Write for Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the BLAKE2b-512 digest of `reader` like minisign hashes files before it signs them.
+pub fn digest(mut reader: impl Read) -> Result<Vec<u8>, io::Error> {
+    let mut state = State::new();
+    io::copy(&mut reader, &mut Writer(&mut state))?;
+    Ok(state.finalize().as_bytes().to_vec())
+}
@@ -0,0 +1,105 @@
+use super::{digest, LoadSecretKeyError, SecretKey};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use blake2b_simd::Params;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+
+const SEED: [u8; 32] = [7; 32];
+const ID: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+/// Returns the public key and the contents of a secret key file that holds it with the key
+/// derivation algorithm `kdf`.
+fn secret_key(kdf: [u8; 2]) -> (Vec<u8>, String) {
+    let public = Ed25519KeyPair::from_seed_unchecked(&SEED)
+        .expect("failed to derive key pair")
+        .public_key()
+        .as_ref()
+        .to_vec();
+    let secret = [SEED.as_slice(), &public].concat();
+    let checksum = Params::new()
+        .hash_length(32)
+        .to_state()
+        .update(b"Ed")
+        .update(&ID)
+        .update(&secret)
+        .finalize();
+
+    let decoded = [
+        b"Ed".as_slice(),
+        &kdf,
+        b"B2",
+        &[0; 32 + 8 + 8],
+        &ID,
+        &secret,
+        checksum.as_bytes(),
+    ]
+    .concat();
+
+    (
+        public,
+        format!(
+            "untrusted comment: minisign secret key\n{}\n",
+            STANDARD.encode(decoded)
+        ),
+    )
+}
+
+#[test]
+fn test_sign() {
+    let (public, contents) = secret_key(*b"\0\0");
+    let key = SecretKey::parse(&contents).expect("failed to parse secret key");
+
+    let message = b"crateful".as_slice();
+    let signature = key.signature(
+        &digest(message).expect("failed to hash message"),
+        "bundle.tar",
+        1,
+    );
+    let lines = signature.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[2],
+        "trusted comment: timestamp:1\tfile:bundle.tar\thashed"
+    );
+
+    let verifier = UnparsedPublicKey::new(&ED25519, &public);
+    let decoded = STANDARD.decode(lines[1]).expect("signature is not base64");
+    assert_eq!(&decoded[..2], b"ED");
+    assert_eq!(&decoded[2..10], &ID);
+    let hashed = Params::new().hash_length(64).hash(message);
+    assert!(verifier.verify(hashed.as_bytes(), &decoded[10..]).is_ok());
+
+    let global = STANDARD
+        .decode(lines[3])
+        .expect("global signature is not base64");
+    let comment = lines[2]
+        .strip_prefix("trusted comment: ")
+        .expect("trusted comment is missing");
+    let signed = [&decoded[10..], comment.as_bytes()].concat();
+    assert!(verifier.verify(&signed, &global).is_ok());
+}
+
+#[test]
+fn test_parse_secret_key_rejects_encrypted_keys() {
+    let (_, contents) = secret_key(*b"Sc");
+    assert!(matches!(
+        SecretKey::parse(&contents),
+        Err(LoadSecretKeyError::Encrypted)
+    ));
+}
+
+#[test]
+fn test_parse_secret_key_rejects_malformed_keys() {
+    let (_, contents) = secret_key(*b"\0\0");
+    let mut decoded = STANDARD
+        .decode(contents.lines().nth(1).expect("key is missing"))
+        .expect("key is not base64");
+    decoded[60] ^= 1;
+    assert!(matches!(
+        SecretKey::parse(&STANDARD.encode(decoded)),
+        Err(LoadSecretKeyError::Malformed)
+    ));
+    assert!(matches!(
+        SecretKey::parse("untrusted comment: nothing\n"),
+        Err(LoadSecretKeyError::Malformed)
+    ));
+}
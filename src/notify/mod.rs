@@ -0,0 +1,233 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{task::JoinHandle, time};
+
+/// The interval that the status of the service is updated at.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The number of crates that the current operation has handled.
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of crates that are being handled.
+static HANDLING: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of bytes that the current operation has downloaded.
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The number of units of work (eg. directories or changed packages) that the current operation
+/// is expected to do.
+static EXPECTED: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of units of work that the current operation has done.
+static DONE: AtomicUsize = AtomicUsize::new(0);
+
+/// A description of the current operation.
+static OPERATION: Mutex<String> = Mutex::new(String::new());
+
+/// The time that the current operation began.
+static BEGAN: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Sends a notification to the service manager. Nothing is sent if the program was not started
+/// by a service manager that asked for notifications.
+fn send(state: &str) {
+    #[cfg(target_os = "linux")]
+    if let Err(error) = sd_notify::notify(false, &[sd_notify::NotifyState::Custom(state)]) {
+        tracing::debug!("failed to notify the service manager: {}", error);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = state;
+}
+
+/// Returns the watchdog timeout that the service manager expects or nothing if the watchdog is not
+/// enabled.
+fn watchdog() -> Option<Duration> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut timeout = 0;
+        sd_notify::watchdog_enabled(false, &mut timeout).then(|| Duration::from_micros(timeout))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// A crate that is being handled. The crate is counted as handled when this is dropped.
+pub struct Handling(());
+
+impl Handling {
+    pub fn start() -> Self {
+        HANDLING.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        HANDLING.fetch_sub(1, Ordering::Relaxed);
+        HANDLED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A task that supervises the program on behalf of the service manager. The task is stopped when
+/// this is dropped.
+pub struct Supervisor(JoinHandle<()>);
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Tells the service manager that the program has started.
+pub fn ready() {
+    send("READY=1");
+}
+
+/// Tells the service manager that the program is stopping.
+pub fn stopping() {
+    send("STOPPING=1");
+}
+
+/// Describes `operation` in the status of the service and resets its progress.
+pub fn begin(operation: String) {
+    send(&format!("STATUS={operation}"));
+    HANDLED.store(0, Ordering::Relaxed);
+    BYTES.store(0, Ordering::Relaxed);
+    EXPECTED.store(0, Ordering::Relaxed);
+    DONE.store(0, Ordering::Relaxed);
+    *OPERATION.lock().expect("lock is poisoned") = operation;
+    *BEGAN.lock().expect("lock is poisoned") = Some(Instant::now());
+}
+
+/// Records that the current operation downloaded `bytes`.
+pub fn transferred(bytes: u64) {
+    BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Records that the current operation has `units` more units of work to do.
+pub fn expect(units: usize) {
+    EXPECTED.fetch_add(units, Ordering::Relaxed);
+}
+
+/// Records that the current operation has done a unit of work.
+pub fn advance() {
+    DONE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The progress of an operation at an instant.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+struct Progress {
+    /// The number of crates that were handled.
+    handled: usize,
+    /// The number of bytes that were downloaded.
+    bytes: u64,
+    /// The number of bytes that were downloaded during the last interval.
+    recent: u64,
+    /// The length of the last interval.
+    interval: Duration,
+    /// The time since the operation began.
+    elapsed: Duration,
+    /// The number of units of work that are expected.
+    expected: usize,
+    /// The number of units of work that were done.
+    done: usize,
+}
+
+/// Formats a rate of `bytes` in `duration` as megabytes per second.
+fn rate(bytes: u64, duration: Duration) -> String {
+    let tenths = u128::from(bytes) / 100 / duration.as_millis().max(1);
+    format!("{}.{} MB/s", tenths / 10, tenths % 10)
+}
+
+/// Formats a duration in hours, minutes, and seconds.
+fn duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{seconds}s"),
+        (0, minutes, seconds) => format!("{minutes}m {seconds}s"),
+        (hours, minutes, _) => format!("{hours}h {minutes}m"),
+    }
+}
+
+/// Describes the progress of `operation` with the current and average throughput and, once some
+/// of the expected work has been done, the estimated time that the rest will take.
+fn status(operation: &str, progress: Progress) -> String {
+    let estimate = if progress.done > 0 && progress.expected > progress.done {
+        let remaining = progress.elapsed.as_millis() * (progress.expected - progress.done) as u128
+            / progress.done as u128;
+        format!(
+            ", about {} remaining",
+            duration(Duration::from_millis(
+                u64::try_from(remaining).unwrap_or(u64::MAX)
+            ))
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{operation}: {} crates handled, {} now, {} on average{estimate}",
+        progress.handled,
+        rate(progress.recent, progress.interval),
+        rate(progress.bytes, progress.elapsed)
+    )
+}
+
+/// Starts a task that updates the status of the service with the progress of the current operation
+/// and feeds the watchdog of the service manager. Nothing is started if the program was not
+/// started by a service manager that asked for notifications.
+///
+/// The watchdog is only fed while crates are being handled if some have been handled since it was
+/// last fed so that the service manager restarts an operation that has stalled.
+pub fn supervise() -> Option<Supervisor> {
+    env::var_os("NOTIFY_SOCKET")?;
+
+    let watchdog = watchdog();
+    let interval = watchdog.map_or(STATUS_INTERVAL, |timeout| {
+        (timeout / 2).min(STATUS_INTERVAL)
+    });
+
+    Some(Supervisor(tokio::spawn(async move {
+        let mut fed = HANDLED.load(Ordering::Relaxed);
+        let mut last = BYTES.load(Ordering::Relaxed);
+        loop {
+            time::sleep(interval).await;
+
+            let handled = HANDLED.load(Ordering::Relaxed);
+            let bytes = BYTES.load(Ordering::Relaxed);
+            let elapsed = BEGAN
+                .lock()
+                .expect("lock is poisoned")
+                .map_or(Duration::ZERO, |began| began.elapsed());
+            let progress = Progress {
+                handled,
+                bytes,
+                // The counter is reset when the next operation begins.
+                recent: bytes.saturating_sub(last),
+                interval,
+                elapsed,
+                expected: EXPECTED.load(Ordering::Relaxed),
+                done: DONE.load(Ordering::Relaxed),
+            };
+            last = bytes;
+
+            let status = status(&OPERATION.lock().expect("lock is poisoned"), progress);
+            send(&format!("STATUS={status}"));
+
+            if watchdog.is_some() && (handled != fed || HANDLING.load(Ordering::Relaxed) == 0) {
+                send("WATCHDOG=1");
+                fed = handled;
+            }
+        }
+    })))
+}
@@ -0,0 +1,32 @@
+use super::{status, Progress};
+use std::time::Duration;
+
+#[test]
+fn test_status() {
+    let progress = Progress {
+        handled: 40,
+        bytes: 12_000_000,
+        recent: 2_500_000,
+        interval: Duration::from_secs(5),
+        elapsed: Duration::from_mins(1),
+        expected: 4,
+        done: 1,
+    };
+    assert_eq!(
+        status("synchronise /cache", progress),
+        "synchronise /cache: 40 crates handled, 0.5 MB/s now, 0.2 MB/s on average, about 3m 0s \
+         remaining"
+    );
+
+    // Nothing is estimated until some of the expected work is done.
+    assert_eq!(
+        status(
+            "verify /cache",
+            Progress {
+                done: 0,
+                ..progress
+            }
+        ),
+        "verify /cache: 40 crates handled, 0.5 MB/s now, 0.2 MB/s on average"
+    );
+}
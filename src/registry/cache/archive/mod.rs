@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests;
+
+use super::{prune_directories, PruneDirectoriesError};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+use tracing::{debug, warn};
+
+/// The error type for pruning the archive.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PruneArchiveError {
+    Io(io::Error),
+    PruneDirectories(PruneDirectoriesError),
+}
+
+impl From<io::Error> for PruneArchiveError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<PruneDirectoriesError> for PruneArchiveError {
+    fn from(error: PruneDirectoriesError) -> Self {
+        Self::PruneDirectories(error)
+    }
+}
+
+impl Display for PruneArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::PruneDirectories(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for PruneArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::PruneDirectories(error) => error.source(),
+        }
+    }
+}
+
+/// Returns the number of seconds between the Unix epoch and `time`.
+pub fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Returns a location in `directory` for a crate that is archived at `time`. Crates that are
+/// archived within the same second are distinguished by a numeric suffix.
+pub async fn destination(directory: &Path, time: SystemTime) -> Result<PathBuf, io::Error> {
+    let timestamp = timestamp(time);
+    let mut destination = directory.join(timestamp.to_string());
+    let mut suffix = 1_u64;
+    while fs::try_exists(&destination).await? {
+        destination = directory.join(format!("{timestamp}.{suffix}"));
+        suffix += 1;
+    }
+
+    Ok(destination)
+}
+
+/// Returns the time that a crate was archived at from the name of its file in the archive.
+fn archived_at(name: &str) -> Option<u64> {
+    name.split_once('.')
+        .map_or(name, |(timestamp, _)| timestamp)
+        .parse()
+        .ok()
+}
+
+/// Returns the entries of a directory or nothing if the directory does not exist.
+async fn entries(path: &Path) -> Result<Vec<fs::DirEntry>, io::Error> {
+    let mut directory = match fs::read_dir(path).await {
+        Ok(directory) => directory,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut entries = Vec::new();
+    while let Some(entry) = directory.next_entry().await? {
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Removes the crates in the archive at `path` that were archived at or before `before`. Returns
+/// the number of crates that were removed.
+///
+/// Archived crates are stored at `<name>/<version>/<timestamp>` where the timestamp is the number
+/// of seconds between the Unix epoch and the time that the crate was archived, followed by a
+/// numeric suffix if another crate was archived at the same location within the same second.
+pub async fn prune(path: &Path, before: SystemTime) -> Result<usize, PruneArchiveError> {
+    let before = timestamp(before);
+    let mut removed = 0;
+
+    for name in entries(path).await? {
+        for version in entries(&name.path()).await? {
+            for file in entries(&version.path()).await? {
+                let location = file.path();
+                let Some(archived) = file.file_name().to_str().and_then(archived_at) else {
+                    warn!(
+                        "ignored unexpected file in the archive: {}",
+                        location.to_string_lossy()
+                    );
+                    continue;
+                };
+
+                if archived <= before {
+                    fs::remove_file(&location).await?;
+                    removed += 1;
+                    debug!("removed {} from the archive", location.to_string_lossy());
+                }
+            }
+
+            prune_directories(&version.path(), path).await?;
+        }
+    }
+
+    Ok(removed)
+}
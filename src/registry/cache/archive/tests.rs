@@ -0,0 +1,64 @@
+use super::{archived_at, destination, prune, timestamp};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use tokio::fs;
+
+#[test]
+fn test_archived_at() {
+    assert_eq!(archived_at("1700000000"), Some(1_700_000_000));
+    assert_eq!(archived_at("1700000000.2"), Some(1_700_000_000));
+    assert_eq!(archived_at("download"), None);
+}
+
+#[tokio::test]
+async fn test_destination_is_unique() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let first = destination(directory.path(), time)
+        .await
+        .expect("failed to choose destination");
+    assert_eq!(first, directory.path().join("1700000000"));
+    fs::write(&first, "0")
+        .await
+        .expect("failed to archive crate");
+
+    // A crate that is archived within the same second must not replace the first.
+    let second = destination(directory.path(), time)
+        .await
+        .expect("failed to choose destination");
+    assert_eq!(second, directory.path().join("1700000000.1"));
+    fs::write(&second, "1")
+        .await
+        .expect("failed to archive crate");
+
+    let third = destination(directory.path(), time)
+        .await
+        .expect("failed to choose destination");
+    assert_eq!(third, directory.path().join("1700000000.2"));
+}
+
+#[tokio::test]
+async fn test_prune_suffixed_crates() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let archived = directory.path().join("a/0.0.1");
+    fs::create_dir_all(&archived)
+        .await
+        .expect("failed to create archive");
+
+    let now = SystemTime::now();
+    let time = timestamp(now);
+    for name in [time.to_string(), format!("{time}.1")] {
+        fs::write(archived.join(name), "0")
+            .await
+            .expect("failed to archive crate");
+    }
+
+    assert_eq!(
+        prune(directory.path(), now)
+            .await
+            .expect("failed to prune archive"),
+        2
+    );
+    assert!(fs::metadata(directory.path().join("a")).await.is_err());
+}
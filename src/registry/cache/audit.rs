@@ -0,0 +1,113 @@
+use crate::{download, registry::index::package::Crate};
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task;
+
+/// Describes whether a downloaded crate had the expected checksum.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Checksum {
+    /// The crate had the expected checksum.
+    Matched,
+    /// The crate did not have the expected checksum.
+    Mismatched,
+    /// The attempt failed before a checksum that can be relied on was compared.
+    Unchecked,
+}
+
+/// Describes an attempt to download a crate.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct Record {
+    /// The time that the attempt finished as the number of seconds since the Unix epoch.
+    pub time: u64,
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    /// True if the crate was downloaded and stored.
+    pub success: bool,
+    /// The HTTP status of an unsuccessful response.
+    pub status: Option<u16>,
+    /// The number of bytes that were downloaded.
+    pub bytes: Option<u64>,
+    /// The number of milliseconds that the attempt took.
+    pub duration: u64,
+    pub checksum: Checksum,
+    /// The error that the attempt failed with.
+    pub error: Option<String>,
+}
+
+impl Record {
+    /// Returns a record of an attempt to download `item` from `url` that finished at `time` after
+    /// `duration`. The outcome of a successful attempt is the number of bytes that it downloaded.
+    pub fn new(
+        item: &Crate,
+        url: String,
+        outcome: Result<u64, &download::Error>,
+        duration: Duration,
+        time: u64,
+    ) -> Self {
+        let (status, checksum) = match outcome {
+            // Signatures are only verified once the checksum has matched.
+            Ok(_) | Err(download::Error::SignatureRejected { .. }) => (None, Checksum::Matched),
+            Err(download::Error::ChecksumMismatch { .. }) => (None, Checksum::Mismatched),
+            Err(download::Error::Http { status, .. }) => {
+                (Some(status.as_u16()), Checksum::Unchecked)
+            }
+            Err(_) => (None, Checksum::Unchecked),
+        };
+
+        Self {
+            time,
+            name: item.name.clone(),
+            version: item.version.clone(),
+            url,
+            success: outcome.is_ok(),
+            status,
+            bytes: outcome.ok(),
+            duration: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+            checksum,
+            error: outcome.err().map(ToString::to_string),
+        }
+    }
+}
+
+/// An append-only log of every attempt to download a crate into the cache. Each attempt is written
+/// as a line of JSON.
+#[derive(Clone, Debug)]
+pub struct Audit {
+    path: PathBuf,
+    /// Serialises appends so that concurrent records are not interleaved.
+    lock: Arc<Mutex<()>>,
+}
+
+impl Audit {
+    /// Returns the audit log at `path`. The log is created when the first attempt is recorded.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Appends `record` to the log.
+    pub async fn record(&self, record: &Record) -> Result<(), io::Error> {
+        let mut line = serde_json::to_vec(record).expect("failed to serialise record");
+        line.push(b'\n');
+
+        let (path, lock) = (self.path.clone(), self.lock.clone());
+        task::spawn_blocking(move || {
+            let _guard = lock.lock().expect("lock is poisoned");
+            let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+            file.write_all(&line)?;
+            file.sync_data()
+        })
+        .await
+        .expect("panicked while appending to the audit log")
+    }
+}
@@ -0,0 +1,94 @@
+use super::{layout::Layout, Cache};
+use crate::{file::Partial, registry::index::package::Crate};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+use tar::{Archive, Builder, Header};
+use tokio::task;
+
+/// The path of the Git bundle of the index commits within a bundle.
+pub const INDEX_PATH: &str = "index.bundle";
+
+/// Returns the path of a crate within a bundle. Crates are laid out with the portable layout so
+/// that bundles can be unpacked on any file system.
+pub fn crate_path(item: &Crate) -> PathBuf {
+    Path::new(Cache::CRATES_SUBDIRECTORY).join(Layout::Portable.locate(
+        &item.name,
+        &item.version,
+        &item.checksum,
+    ))
+}
+
+/// Appends a file to a tar archive.
+///
+/// This blocks while the file is written.
+fn append(builder: &mut Builder<File>, path: &Path, bytes: &[u8]) -> Result<(), io::Error> {
+    let mut header = Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, path, bytes)
+}
+
+/// Writes a bundle, a tar archive that holds a Git bundle of index commits and the crates that
+/// they published.
+///
+/// The archive is written beside its destination and is only moved there once it is finished so
+/// that an interrupted write never leaves an incomplete bundle behind.
+pub struct Writer {
+    builder: Option<Builder<File>>,
+    partial: Partial,
+}
+
+impl Writer {
+    /// Starts a bundle at `path` that holds the Git bundle `index`.
+    pub async fn create(path: &Path, index: Vec<u8>) -> Result<Self, io::Error> {
+        let partial = Partial::new(path);
+        let file = partial.path().to_path_buf();
+        let builder = task::spawn_blocking(move || {
+            let mut builder = Builder::new(File::create(file)?);
+            append(&mut builder, Path::new(INDEX_PATH), &index)?;
+            Ok::<_, io::Error>(builder)
+        })
+        .await
+        .expect("panicked while writing the bundle")?;
+
+        Ok(Self {
+            builder: Some(builder),
+            partial,
+        })
+    }
+
+    /// Appends the contents of a crate to the bundle.
+    pub async fn append(&mut self, item: &Crate, bytes: Vec<u8>) -> Result<(), io::Error> {
+        let mut builder = self.builder.take().expect("bundle has been finished");
+        let path = crate_path(item);
+        let (builder, result) = task::spawn_blocking(move || {
+            let result = append(&mut builder, &path, &bytes);
+            (builder, result)
+        })
+        .await
+        .expect("panicked while writing the bundle");
+
+        self.builder = Some(builder);
+        result
+    }
+
+    /// Finishes the bundle and moves it to `path`.
+    pub async fn finish(mut self, path: &Path) -> Result<(), io::Error> {
+        let builder = self.builder.take().expect("bundle has been finished");
+        task::spawn_blocking(move || builder.into_inner()?.sync_all())
+            .await
+            .expect("panicked while writing the bundle")?;
+
+        self.partial.persist(path).await
+    }
+}
+
+/// Unpacks the bundle at `path` into the directory `destination`.
+pub async fn unpack(path: PathBuf, destination: PathBuf) -> Result<(), io::Error> {
+    task::spawn_blocking(move || Archive::new(File::open(path)?).unpack(destination))
+        .await
+        .expect("panicked while unpacking the bundle")
+}
@@ -0,0 +1,132 @@
+use crate::file::{self, Durability, Permissions};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+use tokio::fs;
+use tracing::{debug, warn};
+
+/// The persisted state of a cache that is known to be consistent with its index.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+struct State {
+    /// The index commit that the cache is consistent with.
+    head: String,
+    /// The filter that selected the crates in the cache.
+    filter: String,
+}
+
+/// Returns true if the cache was recorded as consistent with the index commit `head` when its
+/// crates were selected by `filter`.
+pub async fn is_consistent(path: &Path, head: String, filter: String) -> Result<bool, io::Error> {
+    let expected = State { head, filter };
+    match fs::read(path).await {
+        Ok(bytes) => match serde_json::from_slice::<State>(&bytes) {
+            Ok(state) if state == expected => Ok(true),
+            Ok(_) => {
+                debug!("the cache was consistent with a different index or filter");
+                Ok(false)
+            }
+
+            Err(error) => {
+                warn!("discarded unreadable consistency record: {}", error);
+                Ok(false)
+            }
+        },
+
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Records that the cache is consistent with the index commit `head` when its crates are selected
+/// by `filter`.
+///
+/// The record is replaced atomically so that it is never left partially written.
+pub async fn record(path: &Path, head: String, filter: String) -> Result<(), io::Error> {
+    let temporary = path.with_extension("tmp");
+    fs::write(
+        &temporary,
+        serde_json::to_vec(&State { head, filter }).expect("failed to serialise consistency"),
+    )
+    .await?;
+    fs::rename(&temporary, path).await
+}
+
+/// Returns the index commit that every crate in the cache was last verified at when its crates were
+/// selected by `filter`. Nothing is returned if the cache was never verified with the filter.
+pub async fn verified_at(path: &Path, filter: String) -> Result<Option<String>, io::Error> {
+    match fs::read(path).await {
+        Ok(bytes) => match serde_json::from_slice::<State>(&bytes) {
+            Ok(state) if state.filter == filter => Ok(Some(state.head)),
+            Ok(_) => {
+                debug!("the cache was verified with a different filter");
+                Ok(None)
+            }
+
+            Err(error) => {
+                warn!("discarded unreadable verification record: {}", error);
+                Ok(None)
+            }
+        },
+
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Records that every crate in the cache was verified at the index commit `head` when its crates
+/// were selected by `filter`. The record is written with `permissions`.
+pub async fn record_verification(
+    path: &Path,
+    head: String,
+    filter: String,
+    permissions: Permissions,
+) -> Result<(), io::Error> {
+    file::write(
+        path,
+        serde_json::to_vec(&State { head, filter }).expect("failed to serialise verification"),
+        Durability::Rename,
+        permissions,
+    )
+    .await
+}
+
+/// Marks the time that a synchronisation last left the cache complete.
+#[derive(Clone, Debug, Serialize, Eq, PartialEq)]
+struct Marker {
+    /// The index commit that the cache is complete for.
+    head: String,
+    /// The time that the cache was completed as the number of seconds since the Unix epoch.
+    time: u64,
+}
+
+/// Marks that the cache was complete for the index commit `head` at `time`.
+///
+/// The marker is only moved forward once every crate has been written so that replicas can tell
+/// whether the state that they copied was complete. It is replaced atomically and written with
+/// `permissions`.
+pub async fn mark_complete(
+    path: &Path,
+    head: String,
+    time: u64,
+    permissions: Permissions,
+) -> Result<(), io::Error> {
+    file::write(
+        path,
+        serde_json::to_vec(&Marker { head, time }).expect("failed to serialise marker"),
+        Durability::FsyncDir,
+        permissions,
+    )
+    .await
+}
+
+/// Removes the marker so that replicas do not treat the cache as complete while it is changed.
+pub async fn unmark(path: &Path) -> Result<(), io::Error> {
+    clear(path).await
+}
+
+/// Forgets that the cache is consistent with its index.
+pub async fn clear(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
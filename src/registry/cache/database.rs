@@ -0,0 +1,1011 @@
+use super::feed;
+use crate::{digest::Digest, download::Validators, registry::index::package::Crate, storage::Stat};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::task;
+
+/// The schema of the database. Every statement is idempotent so that it can be applied whenever
+/// the database is opened.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS crates (
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        digest TEXT NOT NULL,
+        downloaded INTEGER,
+        verified INTEGER,
+        PRIMARY KEY (name, version)
+    );
+
+    CREATE TABLE IF NOT EXISTS validators (
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        url TEXT NOT NULL,
+        etag TEXT,
+        last_modified TEXT,
+        PRIMARY KEY (name, version)
+    );
+
+    CREATE TABLE IF NOT EXISTS failures (
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        error TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS changes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        time INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS downloads (
+        name TEXT PRIMARY KEY,
+        downloads INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS pins (
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        cid TEXT NOT NULL,
+        PRIMARY KEY (name, version)
+    );
+
+    CREATE TABLE IF NOT EXISTS synchronisations (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        time INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS listing (
+        directory TEXT NOT NULL,
+        package TEXT NOT NULL,
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        checksum TEXT NOT NULL,
+        yanked INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS listing_directory ON listing (directory, package);
+
+    CREATE TABLE IF NOT EXISTS listed (
+        head TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS quarantine (
+        name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        time INTEGER NOT NULL,
+        PRIMARY KEY (name, version)
+    );
+";
+
+/// Returns the checksum in the column at `index` of `row`.
+fn checksum(row: &Row<'_>, index: usize) -> Result<Digest, rusqlite::Error> {
+    row.get::<_, String>(index)?.parse().map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(error))
+    })
+}
+
+/// A crate that is recorded in the database.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Entry {
+    pub name: String,
+    pub version: String,
+    /// The checksum of the crate that is published by the registry.
+    pub checksum: Digest,
+    /// The number of bytes that the crate occupies in the cache.
+    pub size: u64,
+    /// The time that the crate was downloaded as the number of seconds since the Unix epoch.
+    pub downloaded: Option<u64>,
+    /// The time that the integrity of the crate was last verified as the number of seconds since
+    /// the Unix epoch.
+    pub verified: Option<u64>,
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let time =
+            |time: Option<u64>| time.map_or_else(|| String::from("-"), |time| time.to_string());
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.name,
+            self.version,
+            self.size,
+            time(self.downloaded),
+            time(self.verified)
+        )
+    }
+}
+
+/// A crate that was pinned to an IPFS node.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Pin {
+    pub name: String,
+    pub version: String,
+    /// The content identifier of the crate.
+    pub cid: String,
+}
+
+impl Display for Pin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.name, self.version, self.cid)
+    }
+}
+
+/// A crate that was quarantined because it was corrupt and is waiting to be downloaded again.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Quarantined {
+    pub name: String,
+    pub version: String,
+    /// The time that the crate was quarantined as the number of seconds since the Unix epoch.
+    pub time: u64,
+}
+
+/// The validators that a crate was downloaded with.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Revalidation {
+    /// The URL that the crate was downloaded from.
+    pub url: String,
+    pub validators: Validators,
+    /// The checksum that the crate had in the index when it was recorded.
+    pub checksum: Digest,
+    /// The digest of the stored crate when it was downloaded.
+    pub digest: Digest,
+}
+
+/// A summary of the crates that are recorded in the database.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Status {
+    /// The number of crates.
+    pub crates: u64,
+    /// The number of bytes that the crates occupy in the cache.
+    pub bytes: u64,
+    /// The number of crates whose integrity has never been verified.
+    pub unverified: u64,
+    /// The earliest time that the integrity of a crate was last verified.
+    pub oldest_verification: Option<u64>,
+    /// The number of failed downloads.
+    pub failures: u64,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "crates: {}", self.crates)?;
+        writeln!(f, "bytes: {}", self.bytes)?;
+        writeln!(f, "unverified: {}", self.unverified)?;
+        match self.oldest_verification {
+            Some(time) => writeln!(f, "oldest verification: {time}")?,
+            None => writeln!(f, "oldest verification: never")?,
+        }
+        write!(f, "failures: {}", self.failures)
+    }
+}
+
+/// The exclusive upper bounds in bytes of the buckets of the size histogram. The last bucket holds
+/// every larger crate.
+pub const HISTOGRAM_BOUNDS: [u64; 4] = [10_000, 100_000, 1_000_000, 10_000_000];
+
+/// The disk usage of every version of a crate.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Usage {
+    pub name: String,
+    /// The number of versions of the crate.
+    pub versions: u64,
+    /// The number of bytes that the versions occupy in the cache.
+    pub bytes: u64,
+}
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.name, self.versions, self.bytes)
+    }
+}
+
+/// Aggregate statistics about the crates that are recorded in the database.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Statistics {
+    /// The number of distinct crates.
+    pub crates: u64,
+    /// The number of versions of every crate.
+    pub versions: u64,
+    /// The number of bytes that the crates occupy in the cache.
+    pub bytes: u64,
+    /// The number of versions in each bucket of [`HISTOGRAM_BOUNDS`] followed by the number of
+    /// larger versions.
+    pub histogram: [u64; HISTOGRAM_BOUNDS.len() + 1],
+    /// The crates that occupy the most space.
+    pub largest: Vec<Usage>,
+    /// The time that the last synchronisation started.
+    pub synchronised: Option<u64>,
+    /// The number of versions that were downloaded since the last synchronisation started.
+    pub downloaded: u64,
+    /// The number of bytes that were downloaded since the last synchronisation started.
+    pub downloaded_bytes: u64,
+    /// The number of failed downloads.
+    pub failures: u64,
+    /// The number of failed downloads since the last synchronisation started.
+    pub recent_failures: u64,
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "crates: {}", self.crates)?;
+        writeln!(f, "versions: {}", self.versions)?;
+        writeln!(f, "bytes: {}", self.bytes)?;
+        writeln!(f, "sizes:")?;
+        for (bound, count) in HISTOGRAM_BOUNDS.iter().zip(self.histogram) {
+            writeln!(f, "  under {bound} bytes: {count}")?;
+        }
+
+        if let (Some(bound), Some(count)) = (HISTOGRAM_BOUNDS.last(), self.histogram.last()) {
+            writeln!(f, "  {bound} bytes or more: {count}")?;
+        }
+
+        writeln!(f, "largest crates:")?;
+        for usage in &self.largest {
+            writeln!(f, "  {usage}")?;
+        }
+
+        match self.synchronised {
+            Some(time) => writeln!(f, "last synchronisation: {time}")?,
+            None => writeln!(f, "last synchronisation: never")?,
+        }
+        writeln!(f, "versions since synchronisation: {}", self.downloaded)?;
+        writeln!(f, "bytes since synchronisation: {}", self.downloaded_bytes)?;
+        writeln!(f, "failures: {}", self.failures)?;
+        write!(
+            f,
+            "failures since synchronisation: {}",
+            self.recent_failures
+        )
+    }
+}
+
+/// A database records metadata about the crates in the cache so that it can be queried without
+/// walking the crates directory.
+///
+/// The crates directory is the source of truth and the database may not record crates that were
+/// downloaded before it was created.
+#[derive(Clone, Debug)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    /// Opens the database at `path` and creates it if it does not exist.
+    pub async fn open(path: PathBuf) -> Result<Self, rusqlite::Error> {
+        task::spawn_blocking(move || {
+            let connection = Connection::open(path)?;
+            connection.execute_batch(SCHEMA)?;
+
+            Ok(Self {
+                connection: Arc::new(Mutex::new(connection)),
+            })
+        })
+        .await
+        .expect("panicked while opening database")
+    }
+
+    /// Runs `operation` with the connection from a context where blocking is permitted.
+    async fn with<F, T>(&self, operation: F) -> Result<T, rusqlite::Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        task::spawn_blocking(move || operation(&connection.lock().expect("lock is poisoned")))
+            .await
+            .expect("panicked while querying database")
+    }
+
+    /// Records that a crate was downloaded at `time`. A downloaded crate has been verified.
+    ///
+    /// The `digest` of the stored crate is recorded so that its integrity can be checked again
+    /// without the checksum that is published by the registry.
+    pub async fn record_download(
+        &self,
+        item: &Crate,
+        stat: Stat,
+        digest: Digest,
+        time: u64,
+    ) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT INTO crates
+                    (name, version, checksum, size, modified, digest, downloaded, verified)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+                 ON CONFLICT (name, version) DO UPDATE SET
+                    checksum = excluded.checksum,
+                    size = excluded.size,
+                    modified = excluded.modified,
+                    digest = excluded.digest,
+                    downloaded = excluded.downloaded,
+                    verified = excluded.verified",
+                params![
+                    item.name,
+                    item.version,
+                    item.checksum.to_string(),
+                    stat.size,
+                    stat.modified,
+                    digest.to_string(),
+                    time
+                ],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the integrity of a crate with `digest` was verified at `time`.
+    pub async fn record_verification(
+        &self,
+        item: &Crate,
+        stat: Stat,
+        digest: Digest,
+        time: u64,
+    ) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT INTO crates (name, version, checksum, size, modified, digest, verified)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (name, version) DO UPDATE SET
+                    checksum = excluded.checksum,
+                    size = excluded.size,
+                    modified = excluded.modified,
+                    digest = excluded.digest,
+                    verified = excluded.verified",
+                params![
+                    item.name,
+                    item.version,
+                    item.checksum.to_string(),
+                    stat.size,
+                    stat.modified,
+                    digest.to_string(),
+                    time
+                ],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns true if a crate was recorded with its checksum when it had the same file system
+    /// metadata as `stat`. The crate is assumed to be unchanged since its integrity was checked.
+    pub async fn is_unchanged(&self, item: &Crate, stat: Stat) -> Result<bool, rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection
+                .prepare_cached(
+                    "SELECT 1 FROM crates
+                     WHERE name = ?1 AND version = ?2 AND checksum = ?3 AND size = ?4
+                        AND modified = ?5",
+                )?
+                .exists(params![
+                    item.name,
+                    item.version,
+                    item.checksum.to_string(),
+                    stat.size,
+                    stat.modified
+                ])
+        })
+        .await
+    }
+
+    /// Returns the number of bytes that a crate occupied when it was recorded with its checksum or
+    /// nothing if it is not recorded.
+    pub async fn size(&self, item: &Crate) -> Result<Option<u64>, rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection
+                .query_row(
+                    "SELECT size FROM crates WHERE name = ?1 AND version = ?2 AND checksum = ?3",
+                    params![item.name, item.version, item.checksum.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Returns the digest of the stored crate that was recorded with its checksum or nothing if it
+    /// is not recorded.
+    pub async fn digest(&self, item: &Crate) -> Result<Option<Digest>, rusqlite::Error> {
+        let item = item.clone();
+        let digest = self
+            .with(move |connection| {
+                connection
+                    .query_row(
+                        "SELECT digest FROM crates
+                         WHERE name = ?1 AND version = ?2 AND checksum = ?3",
+                        params![item.name, item.version, item.checksum.to_string()],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .optional()
+            })
+            .await?;
+
+        // A digest that can not be parsed is treated as though it were not recorded.
+        Ok(digest.and_then(|digest| digest.parse().ok()))
+    }
+
+    /// Records the validators that a crate was downloaded from `url` with. Validators that were
+    /// previously recorded are forgotten if there are none.
+    pub async fn record_validators(
+        &self,
+        item: &Crate,
+        url: String,
+        validators: Validators,
+    ) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            if validators.is_empty() {
+                connection.execute(
+                    "DELETE FROM validators WHERE name = ?1 AND version = ?2",
+                    params![item.name, item.version],
+                )
+            } else {
+                connection.execute(
+                    "INSERT INTO validators (name, version, url, etag, last_modified)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (name, version) DO UPDATE SET
+                        url = excluded.url,
+                        etag = excluded.etag,
+                        last_modified = excluded.last_modified",
+                    params![
+                        item.name,
+                        item.version,
+                        url,
+                        validators.etag,
+                        validators.last_modified
+                    ],
+                )
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the validators that a crate was downloaded with and the digest of the crate when it
+    /// was downloaded, regardless of its checksum. Nothing is returned if they are not recorded.
+    pub async fn revalidation(
+        &self,
+        item: &Crate,
+    ) -> Result<Option<Revalidation>, rusqlite::Error> {
+        let item = item.clone();
+        let recorded = self
+            .with(move |connection| {
+                connection
+                    .query_row(
+                        "SELECT validators.url, validators.etag, validators.last_modified,
+                            crates.checksum, crates.digest
+                         FROM validators JOIN crates
+                            ON crates.name = validators.name
+                            AND crates.version = validators.version
+                         WHERE validators.name = ?1 AND validators.version = ?2",
+                        params![item.name, item.version],
+                        |row| {
+                            Ok((
+                                row.get::<_, String>(0)?,
+                                Validators {
+                                    etag: row.get(1)?,
+                                    last_modified: row.get(2)?,
+                                },
+                                row.get::<_, String>(3)?,
+                                row.get::<_, String>(4)?,
+                            ))
+                        },
+                    )
+                    .optional()
+            })
+            .await?;
+
+        // A digest that can not be parsed is treated as though it were not recorded.
+        Ok(recorded.and_then(|(url, validators, checksum, digest)| {
+            Some(Revalidation {
+                url,
+                validators,
+                checksum: checksum.parse().ok()?,
+                digest: digest.parse().ok()?,
+            })
+        }))
+    }
+
+    /// Records that a crate could not be downloaded at `time`.
+    pub async fn record_failure(
+        &self,
+        item: &Crate,
+        error: String,
+        time: u64,
+    ) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT INTO failures (name, version, time, error) VALUES (?1, ?2, ?3, ?4)",
+                params![item.name, item.version, time, error],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records changes to the index that were applied at `time` in the feed. Only the `retain`
+    /// most recent changes are kept.
+    pub async fn record_changes(
+        &self,
+        changes: Vec<(Crate, feed::Kind)>,
+        time: u64,
+        retain: usize,
+    ) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            let transaction = connection.unchecked_transaction()?;
+            for (item, kind) in changes {
+                transaction.execute(
+                    "INSERT INTO changes (name, version, kind, time) VALUES (?1, ?2, ?3, ?4)",
+                    params![item.name, item.version, kind.to_string(), time],
+                )?;
+            }
+
+            transaction.execute(
+                "DELETE FROM changes WHERE id NOT IN
+                 (SELECT id FROM changes ORDER BY id DESC LIMIT ?1)",
+                params![retain],
+            )?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Returns the changes in the feed ordered from the most recent.
+    pub async fn changes(&self) -> Result<Vec<feed::Entry>, rusqlite::Error> {
+        self.with(|connection| {
+            let mut statement = connection
+                .prepare("SELECT id, name, version, kind, time FROM changes ORDER BY id DESC")?;
+
+            let entries = statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get(4)?,
+                    ))
+                })?
+                .filter_map(|row| match row {
+                    // Changes of a kind that is not known are skipped.
+                    Ok((id, name, version, kind, time)) => kind.parse().ok().map(|kind| {
+                        Ok(feed::Entry {
+                            id,
+                            name,
+                            version,
+                            kind,
+                            time,
+                        })
+                    }),
+                    Err(error) => Some(Err(error)),
+                })
+                .collect();
+
+            entries
+        })
+        .await
+    }
+
+    /// Replaces the number of times that each crate has been downloaded from the registry.
+    pub async fn record_downloads(
+        &self,
+        counts: Vec<(String, u64)>,
+    ) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute("DELETE FROM downloads", [])?;
+            for (name, downloads) in counts {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO downloads (name, downloads) VALUES (?1, ?2)",
+                    params![name, downloads],
+                )?;
+            }
+
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Returns the names of the `count` crates that have been downloaded from the registry the most
+    /// times.
+    pub async fn most_downloaded(&self, count: usize) -> Result<Vec<String>, rusqlite::Error> {
+        self.with(move |connection| {
+            let mut statement = connection
+                .prepare("SELECT name FROM downloads ORDER BY downloads DESC, name LIMIT ?1")?;
+
+            let names = statement.query_map([count], |row| row.get(0))?.collect();
+            names
+        })
+        .await
+    }
+
+    /// Records the content identifier that a crate was pinned to an IPFS node with.
+    pub async fn record_pin(&self, item: &Crate, cid: String) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO pins (name, version, cid) VALUES (?1, ?2, ?3)",
+                params![item.name, item.version, cid],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns every crate that was pinned to an IPFS node ordered by name and version.
+    pub async fn pins(&self) -> Result<Vec<Pin>, rusqlite::Error> {
+        self.with(|connection| {
+            let mut statement =
+                connection.prepare("SELECT name, version, cid FROM pins ORDER BY name, version")?;
+
+            let pins = statement
+                .query_map([], |row| {
+                    Ok(Pin {
+                        name: row.get(0)?,
+                        version: row.get(1)?,
+                        cid: row.get(2)?,
+                    })
+                })?
+                .collect();
+
+            pins
+        })
+        .await
+    }
+
+    /// Records that a corrupt crate was quarantined at `time` so that it is downloaded again.
+    pub async fn record_quarantine(&self, item: &Crate, time: u64) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO quarantine (name, version, time) VALUES (?1, ?2, ?3)",
+                params![item.name, item.version, time],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns every crate that is waiting to be downloaded again ordered by the time that it was
+    /// quarantined.
+    pub async fn quarantined(&self) -> Result<Vec<Quarantined>, rusqlite::Error> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT name, version, time FROM quarantine ORDER BY time, name, version",
+            )?;
+
+            let quarantined = statement
+                .query_map([], |row| {
+                    Ok(Quarantined {
+                        name: row.get(0)?,
+                        version: row.get(1)?,
+                        time: row.get(2)?,
+                    })
+                })?
+                .collect();
+
+            quarantined
+        })
+        .await
+    }
+
+    /// Forgets that a crate is waiting to be downloaded again.
+    pub async fn release_quarantine(
+        &self,
+        name: String,
+        version: String,
+    ) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            connection.execute(
+                "DELETE FROM quarantine WHERE name = ?1 AND version = ?2",
+                params![name, version],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets a crate that was removed from the cache. Its failure history is retained.
+    pub async fn remove(&self, item: &Crate) -> Result<(), rusqlite::Error> {
+        let item = item.clone();
+        self.with(move |connection| {
+            connection.execute(
+                "DELETE FROM validators WHERE name = ?1 AND version = ?2",
+                params![item.name, item.version],
+            )?;
+            connection.execute(
+                "DELETE FROM pins WHERE name = ?1 AND version = ?2",
+                params![item.name, item.version],
+            )?;
+            connection.execute(
+                "DELETE FROM crates WHERE name = ?1 AND version = ?2",
+                params![item.name, item.version],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns a summary of the crates in the database.
+    pub async fn status(&self) -> Result<Status, rusqlite::Error> {
+        self.with(|connection| {
+            let (crates, bytes, unverified, oldest_verification) = connection.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0), COUNT(*) - COUNT(verified), MIN(verified)
+                 FROM crates",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+
+            let failures =
+                connection.query_row("SELECT COUNT(*) FROM failures", [], |row| row.get(0))?;
+
+            Ok(Status {
+                crates,
+                bytes,
+                unverified,
+                oldest_verification,
+                failures,
+            })
+        })
+        .await
+    }
+
+    /// Records that a synchronisation started at `time`.
+    pub async fn record_synchronisation(&self, time: u64) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            connection.execute(
+                "INSERT INTO synchronisations (time) VALUES (?1)",
+                params![time],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the disk usage of the `count` crates that occupy the most space, or of every crate
+    /// if no count is given, from largest to smallest.
+    pub async fn usage(&self, count: Option<usize>) -> Result<Vec<Usage>, rusqlite::Error> {
+        self.with(move |connection| Self::query_usage(connection, count))
+            .await
+    }
+
+    fn query_usage(
+        connection: &Connection,
+        count: Option<usize>,
+    ) -> Result<Vec<Usage>, rusqlite::Error> {
+        // A negative limit does not limit the number of rows.
+        let limit = count.map_or(-1, |count| i64::try_from(count).unwrap_or(i64::MAX));
+        let mut statement = connection.prepare(
+            "SELECT name, COUNT(*), SUM(size) FROM crates
+             GROUP BY name ORDER BY SUM(size) DESC, name LIMIT ?1",
+        )?;
+
+        let usage = statement
+            .query_map([limit], |row| {
+                Ok(Usage {
+                    name: row.get(0)?,
+                    versions: row.get(1)?,
+                    bytes: row.get(2)?,
+                })
+            })?
+            .collect();
+
+        usage
+    }
+
+    /// Returns aggregate statistics about the crates in the database with the `largest` crates
+    /// that occupy the most space.
+    pub async fn statistics(&self, largest: usize) -> Result<Statistics, rusqlite::Error> {
+        self.with(move |connection| {
+            let (crates, versions, bytes) = connection.query_row(
+                "SELECT COUNT(DISTINCT name), COUNT(*), COALESCE(SUM(size), 0) FROM crates",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+
+            let mut histogram = [0; HISTOGRAM_BOUNDS.len() + 1];
+            let mut statement = connection.prepare("SELECT size FROM crates")?;
+            for size in statement.query_map([], |row| row.get::<_, u64>(0))? {
+                let size = size?;
+                histogram[HISTOGRAM_BOUNDS.partition_point(|bound| *bound <= size)] += 1;
+            }
+
+            let synchronised: Option<u64> =
+                connection.query_row("SELECT MAX(time) FROM synchronisations", [], |row| {
+                    row.get(0)
+                })?;
+
+            // Every crate was downloaded since a synchronisation that never happened.
+            let since = synchronised.unwrap_or(0);
+            let (downloaded, downloaded_bytes) = connection.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM crates WHERE downloaded >= ?1",
+                params![since],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+
+            let (failures, recent_failures) = connection.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(time >= ?1), 0) FROM failures",
+                params![since],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+
+            Ok(Statistics {
+                crates,
+                versions,
+                bytes,
+                histogram,
+                largest: Self::query_usage(connection, Some(largest))?,
+                synchronised,
+                downloaded,
+                downloaded_bytes,
+                failures,
+                recent_failures,
+            })
+        })
+        .await
+    }
+
+    /// Returns every crate in the database ordered by name and version.
+    pub async fn list(&self) -> Result<Vec<Entry>, rusqlite::Error> {
+        self.with(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT name, version, checksum, size, downloaded, verified FROM crates
+                 ORDER BY name, version",
+            )?;
+
+            let entries = statement
+                .query_map([], |row| {
+                    Ok(Entry {
+                        name: row.get(0)?,
+                        version: row.get(1)?,
+                        checksum: checksum(row, 2)?,
+                        size: row.get(3)?,
+                        downloaded: row.get(4)?,
+                        verified: row.get(5)?,
+                    })
+                })?
+                .collect();
+
+            entries
+        })
+        .await
+    }
+
+    /// Returns the top-level directories of the listing in order if every package in the index was
+    /// listed when HEAD was `head`. Nothing is returned if the listing is incomplete or was made at
+    /// another commit.
+    pub async fn listed_directories(
+        &self,
+        head: String,
+    ) -> Result<Option<Vec<String>>, rusqlite::Error> {
+        self.with(move |connection| {
+            let listed = connection
+                .query_row("SELECT head FROM listed", [], |row| row.get::<_, String>(0))
+                .optional()?;
+
+            if listed.as_deref() != Some(head.as_str()) {
+                return Ok(None);
+            }
+
+            let mut statement =
+                connection.prepare("SELECT DISTINCT directory FROM listing ORDER BY directory")?;
+            let directories = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+
+            Ok(Some(directories))
+        })
+        .await
+    }
+
+    /// Returns the crates in the top-level `directory` of the listing with the paths of the
+    /// packages that hold them. Crates are ordered by the path of their package.
+    pub async fn listing(
+        &self,
+        directory: String,
+    ) -> Result<Vec<(String, Crate)>, rusqlite::Error> {
+        self.with(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT package, name, version, checksum, yanked FROM listing
+                 WHERE directory = ?1 ORDER BY package",
+            )?;
+
+            let crates = statement
+                .query_map([directory], |row| {
+                    Ok((
+                        row.get(0)?,
+                        Crate {
+                            name: row.get(1)?,
+                            version: row.get(2)?,
+                            checksum: checksum(row, 3)?,
+                            yanked: row.get(4)?,
+                            dependencies: None,
+                        },
+                    ))
+                })?
+                .collect();
+
+            crates
+        })
+        .await
+    }
+
+    /// Forgets the listing so that a new listing can be recorded.
+    pub async fn clear_listing(&self) -> Result<(), rusqlite::Error> {
+        self.with(|connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute("DELETE FROM listed", [])?;
+            transaction.execute("DELETE FROM listing", [])?;
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Records the crates in the top-level `directory` in the listing with the paths of the
+    /// packages that hold them.
+    pub async fn record_listing(
+        &self,
+        directory: String,
+        crates: Vec<(String, Crate)>,
+    ) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            let transaction = connection.unchecked_transaction()?;
+            {
+                let mut statement = transaction.prepare(
+                    "INSERT INTO listing (directory, package, name, version, checksum, yanked)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+
+                for (package, item) in crates {
+                    statement.execute(params![
+                        directory,
+                        package,
+                        item.name,
+                        item.version,
+                        item.checksum.to_string(),
+                        item.yanked
+                    ])?;
+                }
+            }
+
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Records that every package in the index was listed when HEAD was `head`.
+    pub async fn record_listed(&self, head: String) -> Result<(), rusqlite::Error> {
+        self.with(move |connection| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute("DELETE FROM listed", [])?;
+            transaction.execute("INSERT INTO listed (head) VALUES (?1)", [head])?;
+            transaction.commit()
+        })
+        .await
+    }
+}
@@ -0,0 +1,338 @@
+#[cfg(test)]
+mod tests;
+
+use super::{
+    filter::Date,
+    lock::{self, LockError},
+    Cache,
+};
+use crate::registry::index::{GetConfigurationError, Index};
+use reqwest::{header, Client};
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::Path,
+    time::SystemTime,
+};
+use tokio::fs;
+use url::Url;
+
+/// The largest difference in seconds between the local clock and the clock of the registry that is
+/// not reported.
+const MAX_CLOCK_SKEW: i64 = 60;
+
+/// The file that is written to check that the cache is writable.
+const PROBE_FILENAME: &str = ".crateful-doctor";
+
+/// How serious a finding is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    /// The check passed.
+    Ok,
+    /// The check found something that may cause problems.
+    Warning,
+    /// The check found something that prevents the cache from being synchronised.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The result of a check.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Finding {
+    /// The name of the check.
+    pub check: &'static str,
+    pub severity: Severity,
+    /// What was found and, if the check did not pass, what can be done about it.
+    pub message: String,
+}
+
+impl Finding {
+    fn new(check: &'static str, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.severity, self.check, self.message)
+    }
+}
+
+/// Returns the time in an HTTP date (eg. `Sun, 06 Nov 1994 08:49:37 GMT`) as the number of seconds
+/// since the Unix epoch.
+pub fn parse_http_date(value: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = value.split_whitespace().skip(1);
+    let day = parts.next()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|each| *each == month)? + 1;
+    let year = parts.next()?;
+    let mut time = parts.next()?.splitn(3, ':').map(str::parse::<i64>);
+    if parts.next() != Some("GMT") {
+        return None;
+    }
+
+    let date = format!("{year}-{month}-{day}").parse::<Date>().ok()?;
+    let (hours, minutes, seconds) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    Some(date.timestamp() + hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Checks the index repository of the cache and returns its download template if it is valid.
+async fn check_index(path: &Path, findings: &mut Vec<Finding>) -> Option<Url> {
+    let index = match Index::from_path(path.join(Cache::INDEX_SUBDIRECTORY)).await {
+        Ok(index) => index,
+        Err(error) => {
+            findings.push(Finding::new(
+                "index",
+                Severity::Error,
+                format!(
+                    "the index repository can not be opened ({error}); check that the path is a \
+                     cache or create the cache again with `new`"
+                ),
+            ));
+            return None;
+        }
+    };
+
+    findings.push(match index.tracking().await {
+        Ok(Some((branch, upstream))) => {
+            Finding::new("index", Severity::Ok, format!("{branch} tracks {upstream}"))
+        }
+        Ok(None) => Finding::new(
+            "index",
+            Severity::Error,
+            "HEAD is not a branch so the index can not be updated; check out the branch that \
+             tracks the registry in the index repository",
+        ),
+        Err(error) => Finding::new(
+            "index",
+            Severity::Error,
+            format!(
+                "the branch of the index does not track a remote branch ({error}); set it with \
+                 `git branch --set-upstream-to` in the index repository"
+            ),
+        ),
+    });
+
+    if index.is_empty().await.unwrap_or(false) {
+        findings.push(Finding::new(
+            "configuration",
+            Severity::Warning,
+            "the index has no commits yet; crates are mirrored once the registry publishes its \
+             first commit",
+        ));
+        return None;
+    }
+
+    let configuration = match index.configuration().await {
+        Ok(configuration) => configuration,
+        Err(error) => {
+            let advice = match error {
+                GetConfigurationError::Git(_) => "repair or clone the index again",
+                _ => "the registry must publish a valid config.json at the root of its index",
+            };
+            findings.push(Finding::new(
+                "configuration",
+                Severity::Error,
+                format!("{} ({error}); {advice}", Index::CONFIGURATION_FILENAME),
+            ));
+            return None;
+        }
+    };
+
+    if let Some(api) = &configuration.api {
+        findings.push(Finding::new(
+            "configuration",
+            Severity::Ok,
+            format!("the web API of the registry is at {api}"),
+        ));
+    }
+
+    if configuration.auth_required {
+        findings.push(Finding::new(
+            "configuration",
+            Severity::Warning,
+            "the registry requires authentication; give it a token or token-env in a configuration \
+             file so that crates can be downloaded",
+        ));
+    }
+
+    match Url::parse(&configuration.template) {
+        Ok(url) => {
+            findings.push(Finding::new(
+                "configuration",
+                Severity::Ok,
+                format!("crates are downloaded from {}", configuration.template),
+            ));
+            Some(url)
+        }
+        Err(error) => {
+            findings.push(Finding::new(
+                "configuration",
+                Severity::Error,
+                format!(
+                    "the download template {} is not a URL ({error}); the registry must publish \
+                     a valid dl field in {}",
+                    configuration.template,
+                    Index::CONFIGURATION_FILENAME
+                ),
+            ));
+            None
+        }
+    }
+}
+
+/// Checks that a file can be written to the cache.
+async fn check_writable(path: &Path) -> Finding {
+    let probe = path.join(PROBE_FILENAME);
+    let result = async {
+        fs::write(&probe, []).await?;
+        fs::remove_file(&probe).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => Finding::new("writable", Severity::Ok, "the cache can be written to"),
+        Err(error) => Finding::new(
+            "writable",
+            Severity::Error,
+            format!(
+                "the cache can not be written to ({error}); check the permissions and free space \
+                 of {}",
+                path.display()
+            ),
+        ),
+    }
+}
+
+/// Checks that the host of the download template responds and that its clock agrees with the
+/// local clock.
+async fn check_network(client: &Client, mut url: Url, findings: &mut Vec<Finding>) {
+    url.set_path("/");
+    url.set_query(None);
+
+    let response = match client.head(url.clone()).send().await {
+        Ok(response) => response,
+        Err(error) => {
+            findings.push(Finding::new(
+                "network",
+                Severity::Error,
+                format!(
+                    "{url} can not be reached ({error}); check DNS, proxies, and firewalls or \
+                     use --resolve"
+                ),
+            ));
+            return;
+        }
+    };
+
+    // Any response shows that the host can be reached.
+    findings.push(Finding::new(
+        "network",
+        Severity::Ok,
+        format!("{url} responded with {}", response.status()),
+    ));
+
+    let Some(remote) = response
+        .headers()
+        .get(header::DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_http_date)
+    else {
+        findings.push(Finding::new(
+            "clock",
+            Severity::Warning,
+            format!("{url} did not report its time so the clock could not be checked"),
+        ));
+        return;
+    };
+
+    let local = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| {
+            i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)
+        });
+    let skew = local - remote;
+    findings.push(if skew.abs() > MAX_CLOCK_SKEW {
+        Finding::new(
+            "clock",
+            Severity::Warning,
+            format!(
+                "the local clock is {} seconds {} the registry; synchronise it with NTP so that \
+                 certificates and timestamps are trusted",
+                skew.abs(),
+                if skew > 0 { "ahead of" } else { "behind" }
+            ),
+        )
+    } else {
+        Finding::new(
+            "clock",
+            Severity::Ok,
+            format!("the local clock is within {MAX_CLOCK_SKEW} seconds of the registry"),
+        )
+    });
+}
+
+/// Checks whether another process has locked the cache.
+async fn check_lock(path: &Path) -> Finding {
+    match lock::probe(path.join(Cache::LOCK_FILENAME)).await {
+        Ok(()) => Finding::new("lock", Severity::Ok, "the cache is not locked"),
+        Err(error @ LockError::Held(_)) => Finding::new(
+            "lock",
+            Severity::Warning,
+            format!(
+                "{error}; wait for it to finish, or use --force-unlock if the process has hung"
+            ),
+        ),
+        Err(error) => Finding::new(
+            "lock",
+            Severity::Error,
+            format!("the lock can not be checked ({error}); check the permissions of the cache"),
+        ),
+    }
+}
+
+/// Checks the cache at `path` for the problems that most often prevent it from being synchronised.
+/// Every check is run even if an earlier check fails unless it depends on it.
+pub async fn diagnose(path: &Path, client: &Client) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if let Err(error) = fs::metadata(path).await {
+        let advice = if error.kind() == io::ErrorKind::NotFound {
+            "create it with `new` or give the path of an existing cache"
+        } else {
+            "check the permissions of its parent directories"
+        };
+
+        findings.push(Finding::new(
+            "cache",
+            Severity::Error,
+            format!("{} can not be read ({error}); {advice}", path.display()),
+        ));
+        return findings;
+    }
+
+    let template = check_index(path, &mut findings).await;
+    findings.push(check_writable(path).await);
+    if let Some(url) = template {
+        check_network(client, url, &mut findings).await;
+    }
+
+    findings.push(check_lock(path).await);
+    findings
+}
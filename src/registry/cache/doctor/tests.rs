@@ -0,0 +1,32 @@
+use super::*;
+use tempfile::TempDir;
+
+#[test]
+fn test_parse_http_date() {
+    assert_eq!(
+        parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+        Some(784_111_777)
+    );
+    assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+    assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+}
+
+#[tokio::test]
+async fn test_diagnose_missing_index() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let findings = diagnose(directory.path(), &Client::new()).await;
+
+    let severity = |check| {
+        findings
+            .iter()
+            .find(|finding| finding.check == check)
+            .map(|finding| finding.severity)
+    };
+
+    assert_eq!(severity("index"), Some(Severity::Error));
+    assert_eq!(severity("writable"), Some(Severity::Ok));
+    assert_eq!(severity("network"), None);
+    assert_eq!(severity("lock"), Some(Severity::Ok));
+}
@@ -0,0 +1,111 @@
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use tar::Archive;
+
+/// The path of the table of crates within a database dump.
+const CRATES_PATH: &str = "data/crates.csv";
+
+/// The error type for reading a database dump.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ReadDumpError {
+    Io(io::Error),
+    Csv(csv::Error),
+    /// The dump does not hold a table of crates.
+    MissingCrates(PathBuf),
+}
+
+impl From<io::Error> for ReadDumpError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<csv::Error> for ReadDumpError {
+    fn from(error: csv::Error) -> Self {
+        Self::Csv(error)
+    }
+}
+
+impl Display for ReadDumpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Csv(error) => write!(f, "malformed table of crates: {error}"),
+            Self::MissingCrates(path) => {
+                write!(f, "{} does not hold {CRATES_PATH}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for ReadDumpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::Csv(error) => Some(error),
+            Self::MissingCrates(_) => None,
+        }
+    }
+}
+
+/// A row of the table of crates. Columns that are not needed are ignored.
+#[derive(Deserialize)]
+struct Row {
+    name: String,
+    downloads: u64,
+}
+
+/// Reads the number of times that each crate has been downloaded from the crates.io database dump
+/// at `path`.
+///
+/// The dump may be the archive that is published by crates.io, the directory that it extracts to,
+/// or the table of crates itself. The archive is read as it is decompressed so that it does not
+/// need to be extracted.
+///
+/// This blocks while the dump is read.
+pub fn read(path: &Path) -> Result<Vec<(String, u64)>, ReadDumpError> {
+    if path.is_dir() {
+        return downloads(File::open(path.join(CRATES_PATH)).map_err(|error| {
+            if error.kind() == io::ErrorKind::NotFound {
+                ReadDumpError::MissingCrates(path.to_owned())
+            } else {
+                error.into()
+            }
+        })?);
+    }
+
+    if path.extension().is_some_and(|extension| extension == "csv") {
+        return downloads(File::open(path)?);
+    }
+
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let entry = entry?;
+
+        // The tables are held in a directory that is named after the time of the dump.
+        if entry.path()?.ends_with(CRATES_PATH) {
+            return downloads(entry);
+        }
+    }
+
+    Err(ReadDumpError::MissingCrates(path.to_owned()))
+}
+
+/// Reads the number of times that each crate has been downloaded from a table of crates.
+fn downloads<R: Read>(table: R) -> Result<Vec<(String, u64)>, ReadDumpError> {
+    csv::Reader::from_reader(table)
+        .deserialize()
+        .map(|row| {
+            let Row { name, downloads } = row?;
+            Ok((name, downloads))
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    file::{self, Durability, Permissions},
+    registry::index::package::Crate,
+};
+use flate2::read::GzDecoder;
+use std::{
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+use tar::{Archive, Entry, EntryType};
+use tokio::{fs, task};
+
+/// The file that marks a crate as completely unpacked. Cargo writes and checks the same file.
+const MARKER_FILENAME: &str = ".cargo-ok";
+
+/// The contents of the marker that current versions of Cargo write.
+const MARKER: &[u8] = br#"{"v":1}"#;
+
+/// Returns the directory that a crate is unpacked to within `directory`.
+fn root(directory: &Path, item: &Crate) -> PathBuf {
+    directory.join(format!("{}-{}", item.name, item.version))
+}
+
+/// Returns true if a crate has been completely unpacked within `directory`.
+pub async fn is_unpacked(directory: &Path, item: &Crate) -> Result<bool, io::Error> {
+    fs::try_exists(root(directory, item).join(MARKER_FILENAME)).await
+}
+
+/// Returns an error if an entry of a crate is not within `root` or is a link whose target is not
+/// within `root`.
+fn check<R: Read>(entry: &Entry<'_, R>, root: &Path) -> Result<(), io::Error> {
+    let path = entry.path()?;
+    let within = |path: &Path| {
+        path.starts_with(root)
+            && path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+    };
+
+    let link = match entry.header().entry_type() {
+        // Hard links are relative to the root of the archive.
+        EntryType::Link => entry.link_name()?.map(|target| within(&target)),
+        // Symbolic links are relative to the directory that holds them.
+        EntryType::Symlink => entry.link_name()?.map(|target| {
+            let mut depth = path.components().count().saturating_sub(1);
+            target.components().all(|component| match component {
+                Component::Normal(_) => {
+                    depth += 1;
+                    true
+                }
+                Component::CurDir => true,
+                // A link may lead out of a directory but not out of the root.
+                Component::ParentDir if depth > 1 => {
+                    depth -= 1;
+                    true
+                }
+                _ => false,
+            })
+        }),
+        _ => None,
+    };
+
+    if !within(&path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not within {}", path.display(), root.display()),
+        ));
+    }
+
+    if link == Some(false) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} links outside of {}", path.display(), root.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the bytes of a crate are a well-formed gzipped tarball whose entries are all
+/// within `<name>-<version>`. Returns an error that describes the first problem that is found.
+pub fn validate(bytes: &[u8], item: &Crate) -> Result<(), io::Error> {
+    let root = PathBuf::from(format!("{}-{}", item.name, item.version));
+    let mut archive = Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        check(&entry, &root)?;
+        io::copy(&mut entry, &mut io::sink())?;
+    }
+
+    Ok(())
+}
+
+/// Unpacks the bytes of a crate into `<name>-<version>` within `directory` like Cargo unpacks
+/// crates into `~/.cargo/registry/src`.
+///
+/// Every entry of the crate must be within `<name>-<version>`. A crate that was partially unpacked
+/// is unpacked again.
+pub async fn unpack(bytes: Vec<u8>, directory: &Path, item: &Crate) -> Result<(), io::Error> {
+    let root = root(directory, item);
+    let prefix = PathBuf::from(root.file_name().expect("root must have a name"));
+    let destination = directory.to_path_buf();
+
+    task::spawn_blocking(move || {
+        let mut archive = Archive::new(GzDecoder::new(bytes.as_slice()));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            check(&entry, &prefix)?;
+            entry.unpack_in(&destination)?;
+        }
+
+        Ok::<_, io::Error>(())
+    })
+    .await
+    .expect("panicked while unpacking crate")?;
+
+    file::write(
+        &root.join(MARKER_FILENAME),
+        MARKER.to_vec(),
+        Durability::Rename,
+        Permissions::default(),
+    )
+    .await
+}
@@ -0,0 +1,88 @@
+use super::validate;
+use crate::{digest::Digest, registry::index::package::Crate};
+use flate2::{write::GzEncoder, Compression};
+use tar::{Builder, EntryType, Header};
+
+/// An entry of an archive. Paths are written as they are so that malicious archives can be built.
+struct Entry<'a> {
+    path: &'a str,
+    kind: EntryType,
+    link: &'a str,
+}
+
+impl<'a> Entry<'a> {
+    const fn file(path: &'a str) -> Self {
+        Self {
+            path,
+            kind: EntryType::Regular,
+            link: "",
+        }
+    }
+
+    const fn link(path: &'a str, kind: EntryType, link: &'a str) -> Self {
+        Self { path, kind, link }
+    }
+}
+
+fn archive(entries: &[Entry<'_>]) -> Vec<u8> {
+    let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for entry in entries {
+        let mut header = Header::new_gnu();
+        let gnu = header.as_gnu_mut().expect("header must be gnu");
+        gnu.name[..entry.path.len()].copy_from_slice(entry.path.as_bytes());
+        gnu.linkname[..entry.link.len()].copy_from_slice(entry.link.as_bytes());
+        header.set_entry_type(entry.kind);
+        header.set_size(0);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append(&header, &[][..])
+            .expect("failed to append entry");
+    }
+
+    builder
+        .into_inner()
+        .expect("failed to write archive")
+        .finish()
+        .expect("failed to compress archive")
+}
+
+fn item() -> Crate {
+    Crate {
+        name: String::from("a"),
+        version: String::from("0.1.0"),
+        checksum: Digest::Sha256([0; 32]),
+        yanked: false,
+        dependencies: None,
+    }
+}
+
+#[test]
+fn test_validate() {
+    let bytes = archive(&[
+        Entry::file("a-0.1.0/Cargo.toml"),
+        Entry::file("a-0.1.0/src/lib.rs"),
+        Entry::link("a-0.1.0/README.md", EntryType::Symlink, "src/lib.rs"),
+        Entry::link("a-0.1.0/src/main.rs", EntryType::Symlink, "../Cargo.toml"),
+        Entry::link("a-0.1.0/LICENSE", EntryType::Link, "a-0.1.0/Cargo.toml"),
+    ]);
+    assert!(validate(&bytes, &item()).is_ok());
+}
+
+#[test]
+fn test_validate_suspicious() {
+    for entry in [
+        Entry::file("b-0.1.0/Cargo.toml"),
+        Entry::file("a-0.1.0/../escape"),
+        Entry::file("/a-0.1.0/Cargo.toml"),
+        Entry::link("a-0.1.0/link", EntryType::Symlink, "../escape"),
+        Entry::link("a-0.1.0/link", EntryType::Symlink, "/etc/passwd"),
+        Entry::link("a-0.1.0/src/link", EntryType::Symlink, "../../escape"),
+        Entry::link("a-0.1.0/link", EntryType::Link, "a-0.1.0/../escape"),
+    ] {
+        let bytes = archive(&[Entry::file("a-0.1.0/Cargo.toml"), entry]);
+        assert!(validate(&bytes, &item()).is_err());
+    }
+
+    assert!(validate(b"0", &item()).is_err());
+}
@@ -0,0 +1,187 @@
+use super::Cache;
+use crate::registry::index::{Change, ChangeKind};
+use std::{
+    fmt::{self, Display, Formatter},
+    num::NonZeroUsize,
+    str::FromStr,
+};
+use url::{ParseError, Url};
+
+/// Describes the feed of index changes that an update appends to.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Feed {
+    /// The URL that the feed is published at. It identifies the feed and its entries.
+    pub url: Url,
+    /// The number of the most recent changes that the feed holds.
+    pub entries: NonZeroUsize,
+}
+
+impl Feed {
+    /// Returns the feed of a cache that is published at `cache`. The feed holds the `entries` most
+    /// recent changes.
+    pub fn new(cache: &Url, entries: NonZeroUsize) -> Result<Self, ParseError> {
+        let mut cache = cache.clone();
+        if !cache.path().ends_with('/') {
+            cache.set_path(&format!("{}/", cache.path()));
+        }
+
+        Ok(Self {
+            url: cache.join(Cache::FEED_FILENAME)?,
+            entries,
+        })
+    }
+
+    /// Returns the feed of the cache that is held in the directory called `name` of this cache.
+    pub fn within(&self, name: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            url: self.url.join(&format!("{name}/{}", Cache::FEED_FILENAME))?,
+            entries: self.entries,
+        })
+    }
+}
+
+/// The kind of change that an entry of the feed describes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Kind {
+    /// A crate was published.
+    Published,
+    /// A crate was removed from the registry.
+    Removed,
+    /// The metadata of a crate was modified.
+    Modified,
+    /// A crate was yanked.
+    Yanked,
+    /// A crate was unyanked.
+    Unyanked,
+}
+
+impl From<&Change> for Kind {
+    fn from(change: &Change) -> Self {
+        match change.kind {
+            ChangeKind::Added => Self::Published,
+            ChangeKind::Removed => Self::Removed,
+            ChangeKind::Modified => Self::Modified,
+            ChangeKind::YankStatusChanged if change.on.yanked => Self::Yanked,
+            ChangeKind::YankStatusChanged => Self::Unyanked,
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Published => "published",
+            Self::Removed => "removed",
+            Self::Modified => "modified",
+            Self::Yanked => "yanked",
+            Self::Unyanked => "unyanked",
+        })
+    }
+}
+
+impl FromStr for Kind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "published" => Ok(Self::Published),
+            "removed" => Ok(Self::Removed),
+            "modified" => Ok(Self::Modified),
+            "yanked" => Ok(Self::Yanked),
+            "unyanked" => Ok(Self::Unyanked),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A change that is recorded in the feed.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Entry {
+    /// The identifier of the change. Changes that were recorded later have greater identifiers.
+    pub id: u64,
+    pub name: String,
+    pub version: String,
+    pub kind: Kind,
+    /// The time that the change was recorded as the number of seconds since the Unix epoch.
+    pub time: u64,
+}
+
+/// Returns `time`, the number of seconds since the Unix epoch, as an RFC 3339 timestamp in UTC.
+pub fn timestamp(time: u64) -> String {
+    // This is Howard Hinnant's `civil_from_days` algorithm.
+    let days = time.div_euclid(86_400);
+    let seconds = time.rem_euclid(86_400);
+    let shifted = days + 719_468;
+    let era = shifted / 146_097;
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Escapes the characters of `text` that are not permitted in XML character data or attributes.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+/// An Atom feed of changes that is published at `url`. The `entries` are ordered from the most
+/// recent. The feed was last updated at `time` if it has no entries.
+pub struct Atom<'a> {
+    pub url: &'a Url,
+    pub entries: &'a [Entry],
+    pub time: u64,
+}
+
+impl Display for Atom<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let url = escape(self.url.as_str());
+        let updated = self.entries.first().map_or(self.time, |entry| entry.time);
+
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(f, "<feed xmlns=\"http://www.w3.org/2005/Atom\">")?;
+        writeln!(f, "  <title>Index changes</title>")?;
+        writeln!(f, "  <id>{url}</id>")?;
+        writeln!(f, "  <link rel=\"self\" href=\"{url}\"/>")?;
+        writeln!(f, "  <updated>{}</updated>", timestamp(updated))?;
+        writeln!(f, "  <generator>{}</generator>", env!("CARGO_PKG_NAME"))?;
+
+        for entry in self.entries {
+            let (name, version, kind) = (escape(&entry.name), escape(&entry.version), entry.kind);
+            writeln!(f, "  <entry>")?;
+            writeln!(f, "    <id>{url}#{}</id>", entry.id)?;
+            writeln!(f, "    <title>{name} {version} was {kind}</title>")?;
+            writeln!(f, "    <updated>{}</updated>", timestamp(entry.time))?;
+            writeln!(f, "    <category term=\"{kind}\"/>")?;
+            writeln!(
+                f,
+                "    <content type=\"text\">{name} {version} was {kind} in the index.</content>"
+            )?;
+            writeln!(f, "  </entry>")?;
+        }
+
+        writeln!(f, "</feed>")
+    }
+}
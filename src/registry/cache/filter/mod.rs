@@ -0,0 +1,525 @@
+#[cfg(test)]
+pub mod tests;
+
+use super::{database::Database, selector::Selector};
+use crate::registry::index::{
+    package::{Crate, CrateKey, Package},
+    GetPackagesError, Index,
+};
+use ahash::AHashSet;
+use clap::ArgEnum;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::{
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    num::NonZeroUsize,
+    str::FromStr,
+};
+
+/// The error type for resolving a filter.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ResolveFilterError {
+    GetPackages(GetPackagesError),
+    Database(rusqlite::Error),
+    /// The number of times that crates have been downloaded is not known because a database dump
+    /// has not been ingested.
+    MissingDownloads,
+}
+
+impl From<GetPackagesError> for ResolveFilterError {
+    fn from(error: GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<rusqlite::Error> for ResolveFilterError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl Display for ResolveFilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GetPackages(error) => error.fmt(f),
+            Self::Database(error) => write!(f, "failed to read download counts: {error}"),
+            Self::MissingDownloads => write!(
+                f,
+                "download counts are not known; a database dump must be ingested first"
+            ),
+        }
+    }
+}
+
+impl Error for ResolveFilterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::GetPackages(error) => error.source(),
+            Self::Database(error) => Some(error),
+            Self::MissingDownloads => None,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseDateError;
+
+impl Display for ParseDateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "date must be written as <year>-<month>-<day>")
+    }
+}
+
+impl Error for ParseDateError {}
+
+/// A calendar date in UTC.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[serde(try_from = "String")]
+pub struct Date {
+    year: i64,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Returns the number of seconds between the Unix epoch and the start of the date.
+    #[must_use]
+    pub const fn timestamp(&self) -> i64 {
+        // This is Howard Hinnant's `days_from_civil` algorithm.
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * 86_400
+    }
+
+    /// Returns the number of days in a month.
+    const fn days_in_month(year: i64, month: u32) -> u32 {
+        match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl FromStr for Date {
+    type Err = ParseDateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().ok_or(ParseDateError);
+        let year = next()?.parse().map_err(|_| ParseDateError)?;
+        let month = next()?.parse().map_err(|_| ParseDateError)?;
+        let day = next()?.parse().map_err(|_| ParseDateError)?;
+
+        if !(1..=12).contains(&month) || day == 0 || day > Self::days_in_month(year, month) {
+            return Err(ParseDateError);
+        }
+
+        Ok(Self { year, month, day })
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = ParseDateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseConstraintError {
+    /// The constraint does not name a crate and a requirement.
+    Malformed,
+    Requirement(semver::Error),
+}
+
+impl Display for ParseConstraintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "constraint must be written as <name>=<requirement>"),
+            Self::Requirement(error) => write!(f, "invalid version requirement: {error}"),
+        }
+    }
+}
+
+impl Error for ParseConstraintError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Malformed => None,
+            Self::Requirement(error) => Some(error),
+        }
+    }
+}
+
+/// Restricts the versions of a crate that are mirrored to those that match a semver requirement.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Constraint {
+    /// The name of the crate that is constrained.
+    pub name: String,
+    pub requirement: VersionReq,
+}
+
+impl Constraint {
+    /// Returns true if the constraint permits a crate. Other crates are always permitted and
+    /// versions that are not valid semver are never permitted.
+    #[must_use]
+    pub fn permits(&self, item: &Crate) -> bool {
+        item.name != self.name
+            || Version::parse(&item.version).is_ok_and(|version| self.requirement.matches(&version))
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = ParseConstraintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, requirement) = s.split_once('=').ok_or(ParseConstraintError::Malformed)?;
+        let name = name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ParseConstraintError::Malformed);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            requirement: requirement
+                .trim()
+                .parse()
+                .map_err(ParseConstraintError::Requirement)?,
+        })
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.requirement)
+    }
+}
+
+/// Specifies how crates that have been yanked from the registry are handled.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum YankPolicy {
+    /// Yanked crates are mirrored.
+    #[default]
+    Mirror,
+    /// Yanked crates are not downloaded. Crates that are already cached are kept.
+    Skip,
+    /// Yanked crates are not downloaded and crates that are yanked during an update are removed
+    /// from the cache.
+    Delete,
+}
+
+/// The crates that the popular preset selects.
+const POPULAR: &str = include_str!("popular.txt");
+
+/// A built-in selection of crates that are mirrored with every crate that they depend on.
+#[derive(ArgEnum, Clone, Copy, Debug, Deserialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// A curated set of the most widely used crates.
+    Popular,
+}
+
+impl Preset {
+    /// Returns the data that the preset is built from.
+    const fn data(self) -> &'static str {
+        match self {
+            Self::Popular => POPULAR,
+        }
+    }
+
+    /// Returns the names of the crates that the preset selects.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        self.data()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    }
+
+    /// Returns a short digest of the data that the preset is built from. The revision changes
+    /// whenever the selected crates change.
+    #[must_use]
+    pub fn revision(self) -> String {
+        blake3::hash(self.data().as_bytes()).to_hex()[..12].to_owned()
+    }
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Popular => write!(f, "popular"),
+        }
+    }
+}
+
+/// Selects the crate versions that are mirrored.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Filter {
+    /// Only versions that were published on or after this date are mirrored. All versions are
+    /// mirrored if there is no date.
+    pub since: Option<Date>,
+    /// How yanked versions are handled.
+    pub yanked: YankPolicy,
+    /// Only this many of the most downloaded crates and the crates that they depend on are
+    /// mirrored. Every crate is mirrored if there is no limit.
+    pub top: Option<NonZeroUsize>,
+    /// Only the crates that this preset selects and the crates that they depend on are mirrored.
+    /// The most downloaded crates are also mirrored if there is a limit.
+    pub preset: Option<Preset>,
+    /// Only the versions of each constrained crate that match every constraint on it are mirrored.
+    pub constraints: Vec<Constraint>,
+    /// Only these crates, every crate that directly or transitively depends on them, and the
+    /// crates that those depend on are mirrored.
+    pub dependents: Vec<String>,
+    /// Only this many of the latest versions of each crate that are otherwise selected are
+    /// mirrored. Every selected version is mirrored if there is no limit.
+    pub latest: Option<NonZeroUsize>,
+    /// Pre-release versions are not mirrored.
+    pub exclude_prerelease: bool,
+    /// The crates that these selectors select are never mirrored.
+    pub deny: Vec<Selector>,
+}
+
+impl Filter {
+    /// Returns true if a crate is accepted without consulting the history of the index.
+    #[must_use]
+    pub fn accepts(&self, item: &Crate) -> bool {
+        (!item.yanked || self.yanked == YankPolicy::Mirror)
+            && !(self.exclude_prerelease && is_prerelease(item))
+            && self.deny.iter().all(|selector| !selector.selects(item))
+            && self
+                .constraints
+                .iter()
+                .all(|constraint| constraint.permits(item))
+    }
+
+    /// Resolves the filter against an index and the download counts recorded in `database`.
+    pub async fn resolve(
+        &self,
+        index: &Index,
+        database: &Database,
+    ) -> Result<Selection, ResolveFilterError> {
+        let published = match self.since {
+            Some(since) => Some(index.published_since(since.timestamp()).await?),
+            None => None,
+        };
+
+        Ok(Selection {
+            filter: self.clone(),
+            published,
+            popular: self.popular(index, database).await?,
+        })
+    }
+
+    /// Resolves the filter for the changes that an update applies. Changes are recent so the
+    /// history of the index is not consulted.
+    pub async fn resolve_update(
+        &self,
+        index: &Index,
+        database: &Database,
+    ) -> Result<Selection, ResolveFilterError> {
+        Ok(Selection {
+            filter: self.clone(),
+            published: None,
+            popular: self.popular(index, database).await?,
+        })
+    }
+
+    /// Returns the names of the most downloaded crates, the crates selected by the preset and the
+    /// crates that depend on the roots of the dependents along with every crate that they
+    /// transitively depend on. Nothing is returned if every crate is mirrored.
+    async fn popular(
+        &self,
+        index: &Index,
+        database: &Database,
+    ) -> Result<Option<AHashSet<String>>, ResolveFilterError> {
+        if self.top.is_none() && self.preset.is_none() && self.dependents.is_empty() {
+            return Ok(None);
+        }
+
+        let mut popular = AHashSet::new();
+        if let Some(top) = self.top {
+            let names = database.most_downloaded(top.get()).await?;
+            if names.is_empty() {
+                return Err(ResolveFilterError::MissingDownloads);
+            }
+
+            popular.extend(names);
+        }
+
+        if let Some(preset) = self.preset {
+            popular.extend(preset.names().map(String::from));
+        }
+
+        if !self.dependents.is_empty() {
+            popular.extend(self.dependents.iter().cloned());
+            popular.extend(index.dependents(self.dependents.clone(), true).await?);
+        }
+
+        // The dependencies of each crate are only read once.
+        let mut frontier = popular.iter().cloned().collect::<Vec<_>>();
+        while !frontier.is_empty() {
+            frontier = index
+                .dependencies(frontier)
+                .await?
+                .into_iter()
+                .filter(|name| popular.insert(name.clone()))
+                .collect();
+        }
+
+        Ok(Some(popular))
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let stable = if self.exclude_prerelease {
+            "stable "
+        } else {
+            ""
+        };
+        match (self.latest, self.since) {
+            (None, None) => write!(f, "all {stable}versions")?,
+            (None, Some(since)) => write!(f, "{stable}versions published since {since}")?,
+            (Some(latest), None) => write!(f, "the latest {latest} {stable}versions")?,
+            (Some(latest), Some(since)) => write!(
+                f,
+                "the latest {latest} {stable}versions published since {since}"
+            )?,
+        }
+
+        // The revision of a preset is included so that caches are refreshed when it changes.
+        let mut roots = Vec::new();
+        if let Some(top) = self.top {
+            roots.push(format!("the {top} most downloaded crates"));
+        }
+
+        if let Some(preset) = self.preset {
+            roots.push(format!(
+                "the {preset} preset (revision {})",
+                preset.revision()
+            ));
+        }
+
+        if !self.dependents.is_empty() {
+            roots.push(format!(
+                "{} and their dependents",
+                self.dependents.join(", ")
+            ));
+        }
+
+        if !roots.is_empty() {
+            write!(f, " of {} and their dependencies", roots.join(" and "))?;
+        }
+
+        if !self.constraints.is_empty() {
+            write!(f, " restricted to ")?;
+            for (index, constraint) in self.constraints.iter().enumerate() {
+                if index > 0 {
+                    write!(f, " and ")?;
+                }
+
+                write!(f, "{constraint}")?;
+            }
+        }
+
+        if self.yanked != YankPolicy::Mirror {
+            write!(f, " that are not yanked")?;
+        }
+
+        if !self.deny.is_empty() {
+            let denied = self
+                .deny
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            write!(f, " except {}", denied.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A filter that has been resolved against the index.
+#[derive(Clone, Debug)]
+pub struct Selection {
+    filter: Filter,
+    /// The versions that were published after the date of the filter, if it has one.
+    published: Option<AHashSet<CrateKey>>,
+    /// The names of the crates that are popular enough to be mirrored, if the filter has a limit.
+    popular: Option<AHashSet<String>>,
+}
+
+impl Selection {
+    /// Returns true if a crate is selected.
+    #[must_use]
+    pub fn contains(&self, item: &Crate) -> bool {
+        self.filter.accepts(item)
+            && self
+                .published
+                .as_ref()
+                .is_none_or(|published| published.contains(&item.key()))
+            && self
+                .popular
+                .as_ref()
+                .is_none_or(|popular| popular.contains(&item.name))
+    }
+
+    /// Returns true if whether a crate is selected depends on the other versions in its package,
+    /// so a crate can not be selected on its own.
+    #[must_use]
+    pub const fn depends_on_package(&self) -> bool {
+        self.filter.latest.is_some()
+    }
+
+    /// Returns the crates of a package that are selected. This is the policy that every operation
+    /// applies: each crate must be selected on its own and only the latest versions of those are
+    /// retained when the filter has a limit.
+    #[must_use]
+    pub fn retain(&self, package: Package) -> Vec<Crate> {
+        let mut crates = package
+            .into_crates()
+            .filter(|each| self.contains(each))
+            .collect::<Vec<_>>();
+
+        if let Some(latest) = self.filter.latest {
+            // Versions that are not valid semver are the oldest.
+            crates.sort_by_cached_key(|each| Reverse(Version::parse(&each.version).ok()));
+            crates.truncate(latest.get());
+        }
+
+        crates
+    }
+}
+
+/// Returns true if a crate is a pre-release. Versions that are not valid semver are not
+/// pre-releases.
+fn is_prerelease(item: &Crate) -> bool {
+    Version::parse(&item.version).is_ok_and(|version| !version.pre.is_empty())
+}
@@ -0,0 +1,185 @@
+use super::*;
+
+#[test]
+fn test_parse_date() {
+    let date = Date::from_str("2020-02-29").expect("failed to parse date");
+    assert_eq!(date.to_string(), "2020-02-29");
+}
+
+#[test]
+fn test_parse_malformed_date() {
+    assert_eq!(Date::from_str("2020"), Err(ParseDateError));
+    assert_eq!(Date::from_str("2020-13-01"), Err(ParseDateError));
+    assert_eq!(Date::from_str("2021-02-29"), Err(ParseDateError));
+    assert_eq!(Date::from_str("2020-01-01T00:00"), Err(ParseDateError));
+}
+
+#[test]
+fn test_date_timestamp() {
+    let timestamp = |date| {
+        Date::from_str(date)
+            .expect("failed to parse date")
+            .timestamp()
+    };
+
+    assert_eq!(timestamp("1970-01-01"), 0);
+    assert_eq!(timestamp("1969-12-31"), -86_400);
+    assert_eq!(timestamp("2000-03-01"), 951_868_800);
+    assert_eq!(timestamp("2022-02-15"), 1_644_883_200);
+}
+
+#[test]
+fn test_popular_preset_names() {
+    let names = Preset::Popular.names().collect::<Vec<_>>();
+    assert!(names.contains(&"serde"));
+    assert_eq!(
+        names.iter().collect::<AHashSet<_>>().len(),
+        names.len(),
+        "preset has duplicate names"
+    );
+    assert!(names.iter().all(|name| name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')));
+}
+
+#[test]
+fn test_display_filter_with_preset() {
+    let filter = Filter {
+        preset: Some(Preset::Popular),
+        ..Filter::default()
+    };
+
+    assert_eq!(
+        filter.to_string(),
+        format!(
+            "all versions of the popular preset (revision {}) and their dependencies",
+            Preset::Popular.revision()
+        )
+    );
+}
+
+#[test]
+fn test_constraint_permits() {
+    let constraint = Constraint::from_str("a = >=1.0, <2").expect("failed to parse constraint");
+    let item = |name: &str, version: &str| Crate {
+        name: String::from(name),
+        version: String::from(version),
+        checksum: crate::digest::Digest::Sha256([0; 32]),
+        yanked: false,
+        dependencies: None,
+    };
+
+    assert_eq!(constraint.to_string(), "a >=1.0, <2");
+    assert!(constraint.permits(&item("a", "1.2.3")));
+    assert!(!constraint.permits(&item("a", "0.9.0")));
+    assert!(!constraint.permits(&item("a", "2.0.0")));
+    assert!(!constraint.permits(&item("a", "not semver")));
+    assert!(constraint.permits(&item("b", "2.0.0")));
+}
+
+#[test]
+fn test_parse_malformed_constraint() {
+    assert!(matches!(
+        Constraint::from_str(">=1.0"),
+        Err(ParseConstraintError::Malformed)
+    ));
+    assert!(matches!(
+        Constraint::from_str("=1.0"),
+        Err(ParseConstraintError::Malformed)
+    ));
+    assert!(matches!(
+        Constraint::from_str("a=one"),
+        Err(ParseConstraintError::Requirement(_))
+    ));
+}
+
+#[test]
+fn test_display_filter_with_dependents() {
+    let filter = Filter {
+        top: NonZeroUsize::new(10),
+        dependents: vec![String::from("a"), String::from("b")],
+        ..Filter::default()
+    };
+
+    assert_eq!(
+        filter.to_string(),
+        "all versions of the 10 most downloaded crates and a, b and their dependents and their \
+         dependencies"
+    );
+}
+
+#[test]
+fn test_retain_latest_stable_versions() {
+    let line = |version: &str, yanked: bool| {
+        format!(
+            r#"{{"name":"a","vers":"{version}","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":{yanked}}}"#
+        )
+    };
+    let package = [
+        line("0.9.0", false),
+        line("1.10.0", false),
+        line("1.2.0", false),
+        line("2.0.0-rc.1", false),
+        line("1.11.0", true),
+    ]
+    .join("\n");
+    let package = Package::from_slice(package.as_bytes(), false).expect("failed to parse package");
+
+    let selection = Selection {
+        filter: Filter {
+            yanked: YankPolicy::Skip,
+            latest: NonZeroUsize::new(2),
+            exclude_prerelease: true,
+            ..Filter::default()
+        },
+        published: None,
+        popular: None,
+    };
+    assert!(selection.depends_on_package());
+
+    let versions = selection
+        .retain(package)
+        .into_iter()
+        .map(|each| each.version)
+        .collect::<Vec<_>>();
+    assert_eq!(versions, ["1.10.0", "1.2.0"]);
+}
+
+#[test]
+fn test_display_filter_with_latest() {
+    let filter = Filter {
+        latest: NonZeroUsize::new(3),
+        exclude_prerelease: true,
+        yanked: YankPolicy::Skip,
+        ..Filter::default()
+    };
+
+    assert_eq!(
+        filter.to_string(),
+        "the latest 3 stable versions that are not yanked"
+    );
+}
+
+#[test]
+fn test_deny() {
+    let filter = Filter {
+        deny: vec![
+            "a@1.*".parse().expect("failed to parse selector"),
+            "b".parse().expect("failed to parse selector"),
+        ],
+        ..Filter::default()
+    };
+    let item = |name: &str, version: &str| Crate {
+        name: String::from(name),
+        version: String::from(version),
+        checksum: crate::digest::Digest::Sha256([0; 32]),
+        yanked: false,
+        dependencies: None,
+    };
+
+    assert!(!filter.accepts(&item("a", "1.0.0")));
+    assert!(filter.accepts(&item("a", "2.0.0")));
+    assert!(!filter.accepts(&item("b", "0.1.0")));
+    assert!(filter.accepts(&item("c", "0.1.0")));
+    assert_eq!(filter.to_string(), "all versions except a@1.*, b");
+}
@@ -0,0 +1,101 @@
+use reqwest::{
+    multipart::{Form, Part},
+    Client, StatusCode,
+};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use url::{ParseError, Url};
+
+/// The error type for pinning a crate to an IPFS node.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PinError {
+    Http(reqwest::Error),
+    /// The node responded with an unsuccessful status.
+    Status(StatusCode),
+    /// The node responded with something other than the file that was added.
+    Malformed(serde_json::Error),
+}
+
+impl From<reqwest::Error> for PinError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Http(error)
+    }
+}
+
+impl Display for PinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(error) => write!(f, "failed to reach the IPFS node: {error}"),
+            Self::Status(status) => write!(f, "the IPFS node responded with {status}"),
+            Self::Malformed(error) => write!(f, "the IPFS node responded unexpectedly: {error}"),
+        }
+    }
+}
+
+impl Error for PinError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Http(error) => Some(error),
+            Self::Malformed(error) => Some(error),
+            Self::Status(_) => None,
+        }
+    }
+}
+
+/// The response of the node to a file being added.
+#[derive(Deserialize)]
+struct Added {
+    #[serde(rename = "Hash")]
+    cid: String,
+}
+
+/// An IPFS node that crates are pinned to through its RPC API.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Ipfs {
+    /// The endpoint that adds files to the node.
+    add: Url,
+}
+
+impl Ipfs {
+    /// Returns the node whose RPC API is served at `api` (eg. `http://127.0.0.1:5001`).
+    pub fn new(api: &Url) -> Result<Self, ParseError> {
+        let mut api = api.clone();
+        if !api.path().ends_with('/') {
+            api.set_path(&format!("{}/", api.path()));
+        }
+
+        let mut add = api.join("api/v0/add")?;
+
+        // CIDv1 is used so that the identifiers can be used in subdomain gateways.
+        add.query_pairs_mut()
+            .append_pair("pin", "true")
+            .append_pair("cid-version", "1");
+
+        Ok(Self { add })
+    }
+
+    /// Adds `bytes` to the node as the file `name` and pins it. Returns the content identifier of
+    /// the file.
+    pub async fn pin(
+        &self,
+        client: &Client,
+        name: String,
+        bytes: Vec<u8>,
+    ) -> Result<String, PinError> {
+        let form = Form::new().part("file", Part::bytes(bytes).file_name(name));
+        let response = client.post(self.add.clone()).multipart(form).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(PinError::Status(status));
+        }
+
+        let added = serde_json::from_slice::<Added>(&response.bytes().await?)
+            .map_err(PinError::Malformed)?;
+        Ok(added.cid)
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    download::PreservationStrategy,
+    file::{self, Durability, Permissions},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, io, path::PathBuf};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::{debug, info, warn};
+
+/// The refresh that a journal records the progress of. It is the first line of the journal.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+struct Header {
+    /// The index commit that is being refreshed.
+    target: String,
+    /// The preservation strategy that the refresh uses.
+    preserve: PreservationStrategy,
+    /// The scope of the index that is being refreshed.
+    scope: String,
+    /// The filter that selects the crates that are refreshed.
+    #[serde(default)]
+    filter: String,
+}
+
+/// A journal records the progress of a refresh so that an interrupted refresh can be resumed.
+///
+/// Progress is recorded for each top-level directory of the index. The journal starts with a
+/// header that describes the refresh and a line is appended for each directory that has been
+/// refreshed. A journal is only resumed when the index commit, preservation strategy, scope, and
+/// filter match the interrupted refresh.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    /// The directories that were refreshed before the refresh was resumed.
+    completed: BTreeSet<String>,
+    /// The journal that completed directories are appended to.
+    file: Mutex<Option<File>>,
+}
+
+/// Reads the directories that an interrupted refresh of `expected` completed. Nothing is returned
+/// if the journal belongs to a different refresh or can not be read.
+fn resume(contents: &str, expected: &Header) -> Option<BTreeSet<String>> {
+    let mut lines = contents.lines();
+    match serde_json::from_str::<Header>(lines.next()?) {
+        Ok(header) if header == *expected => (),
+        Ok(_) => {
+            debug!("discarded journal for a different refresh");
+            return None;
+        }
+        Err(error) => {
+            warn!("discarded unreadable journal: {}", error);
+            return None;
+        }
+    }
+
+    // The last line may have been cut short when the refresh was interrupted. Its directory is
+    // refreshed again.
+    Some(
+        lines
+            .filter_map(|line| serde_json::from_str::<String>(line).ok())
+            .collect(),
+    )
+}
+
+impl Journal {
+    /// Opens the journal at `path` for a refresh of `target`. A new journal is started if the
+    /// existing journal belongs to a different refresh. The header of a new journal is written
+    /// with `durability` and `permissions`.
+    pub async fn open(
+        path: PathBuf,
+        target: String,
+        preserve: PreservationStrategy,
+        scope: String,
+        filter: String,
+        durability: Durability,
+        permissions: Permissions,
+    ) -> Result<Self, io::Error> {
+        let header = Header {
+            target,
+            preserve,
+            scope,
+            filter,
+        };
+
+        let (completed, terminated) = match fs::read_to_string(&path).await {
+            Ok(contents) => (resume(&contents, &header), contents.ends_with('\n')),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (None, true),
+            Err(error) => return Err(error),
+        };
+
+        let (completed, terminated) = if let Some(completed) = completed {
+            info!(
+                "resuming refresh with {} completed directories",
+                completed.len()
+            );
+            (completed, terminated)
+        } else {
+            let mut bytes = serde_json::to_vec(&header).expect("failed to serialise journal");
+            bytes.push(b'\n');
+            file::write(&path, bytes, durability, permissions).await?;
+            (BTreeSet::new(), true)
+        };
+
+        let mut file = OpenOptions::new().append(true).open(&path).await?;
+        if !terminated {
+            // The line that was cut short is ended so that it is not joined to the next line.
+            file.write_all(b"\n").await?;
+            file.flush().await?;
+        }
+
+        Ok(Self {
+            path,
+            completed,
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    /// Returns true if a directory was refreshed before the refresh was resumed.
+    pub fn is_complete(&self, directory: &str) -> bool {
+        self.completed.contains(directory)
+    }
+
+    /// Records that a directory has been refreshed.
+    ///
+    /// A line is appended to the journal for the directory. A line that is lost or cut short only
+    /// causes the directory to be refreshed again.
+    pub async fn complete(&self, directory: &str) -> Result<(), io::Error> {
+        let mut line = serde_json::to_vec(directory).expect("failed to serialise directory");
+        line.push(b'\n');
+
+        let mut file = self.file.lock().await;
+        let file = file.as_mut().expect("journal is finished");
+        file.write_all(&line).await?;
+        file.flush().await
+    }
+
+    /// Removes the journal once the refresh has finished.
+    pub async fn finish(&self) -> Result<(), io::Error> {
+        drop(self.file.lock().await.take());
+        match fs::remove_file(&self.path).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
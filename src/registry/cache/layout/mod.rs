@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{digest::Digest, registry::index::package};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::fs;
+
+/// The name of the file that holds a crate in the layouts that give each crate a directory.
+const DOWNLOAD_FILENAME: &str = "download";
+
+/// The names that Windows reserves for devices. Files can not be given these names in any case
+/// and with any extension.
+const RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Specifies how crates are laid out in the crates directory of a cache.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Crates are stored at `<name>/<version>/download` to match the default crate download
+    /// locations so that the directory can be served by a static file server.
+    #[default]
+    Plain,
+    /// Crates are stored at `<prefix>/<name>/<version>/download` where the prefix is the
+    /// lowercase prefix of the index and the name and version are escaped so that they are
+    /// distinct on case-insensitive file systems and are not reserved on Windows.
+    Portable,
+    /// Crates are stored at `<prefix>/<name>/<version>/download` where the prefix is the
+    /// lowercase prefix of the index so that no directory holds too many crates. The directory can
+    /// be served with the `{lowerprefix}` marker of a download template.
+    Sharded,
+    /// Crates are stored at `<algorithm>/<shard>/<checksum>.crate` where the shard is the first
+    /// two characters of the checksum so that crates are found by their contents.
+    ContentAddressed,
+    /// Crates are stored at `<name>-<version>.crate` to match the registry cache of Cargo.
+    Cargo,
+}
+
+impl Layout {
+    /// Returns the file that holds the crate called `name` at `version` with `checksum` relative
+    /// to the directory that holds every crate.
+    #[must_use]
+    pub fn locate(self, name: &str, version: &str, checksum: &Digest) -> PathBuf {
+        match self {
+            Self::Plain => PathBuf::from(name).join(version).join(DOWNLOAD_FILENAME),
+            Self::Portable => PathBuf::from(package::prefix(name).to_lowercase())
+                .join(escape(name))
+                .join(escape(version))
+                .join(DOWNLOAD_FILENAME),
+            Self::Sharded => PathBuf::from(package::prefix(name).to_lowercase())
+                .join(name)
+                .join(version)
+                .join(DOWNLOAD_FILENAME),
+            Self::ContentAddressed => {
+                let encoded = hex::encode(checksum.as_bytes());
+                PathBuf::from(checksum.algorithm().to_string())
+                    .join(&encoded[..2])
+                    .join(format!("{encoded}.crate"))
+            }
+            Self::Cargo => PathBuf::from(format!("{name}-{version}.crate")),
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Plain => "plain",
+            Self::Portable => "portable",
+            Self::Sharded => "sharded",
+            Self::ContentAddressed => "content-addressed",
+            Self::Cargo => "cargo",
+        })
+    }
+}
+
+impl FromStr for Layout {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "portable" => Ok(Self::Portable),
+            "sharded" => Ok(Self::Sharded),
+            "content-addressed" => Ok(Self::ContentAddressed),
+            "cargo" => Ok(Self::Cargo),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Escapes a name or version so that it can be used as the name of a file on any file system.
+///
+/// Uppercase letters are written as `!` followed by the lowercase letter so that names that only
+/// differ by case are distinct on case-insensitive file systems. Names that are reserved on
+/// Windows are followed by `!`. Neither names nor versions can hold `!` so distinct names are
+/// always escaped distinctly.
+#[must_use]
+pub fn escape(component: &str) -> String {
+    let mut escaped = String::with_capacity(component.len());
+    for character in component.chars() {
+        if character.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(character.to_ascii_lowercase());
+        } else {
+            escaped.push(character);
+        }
+    }
+
+    if RESERVED.contains(&escaped.as_str()) {
+        escaped.push('!');
+    }
+
+    escaped
+}
+
+/// Reads the layout that is recorded at `path`. The layout is plain if none is recorded.
+pub async fn read(path: &Path) -> Result<Layout, io::Error> {
+    match fs::read_to_string(path).await {
+        Ok(recorded) => recorded.trim().parse().map_err(|()| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not name a layout", path.display()),
+            )
+        }),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Layout::Plain),
+        Err(error) => Err(error),
+    }
+}
+
+/// Records `layout` at `path`.
+pub async fn write(path: &Path, layout: Layout) -> Result<(), io::Error> {
+    fs::write(path, format!("{layout}\n")).await
+}
@@ -0,0 +1,73 @@
+use super::{escape, Layout};
+use crate::digest::Digest;
+use std::{path::PathBuf, str::FromStr};
+
+#[test]
+fn test_escape() {
+    assert_eq!(escape("serde"), "serde");
+    assert_eq!(escape("Inflector"), "!inflector");
+    assert_eq!(escape("con"), "con!");
+    assert_eq!(escape("Con"), "!con");
+    assert_eq!(escape("lpt1"), "lpt1!");
+    assert_eq!(escape("1.0.0-Alpha"), "1.0.0-!alpha");
+}
+
+#[test]
+fn test_escape_is_distinct_without_case() {
+    let names = ["foo_bar", "Foo_bar", "FOO_BAR", "foo_Bar"];
+    let mut escaped = names
+        .iter()
+        .map(|name| escape(name).to_lowercase())
+        .collect::<Vec<_>>();
+
+    escaped.sort();
+    escaped.dedup();
+    assert_eq!(escaped.len(), names.len());
+}
+
+#[test]
+fn test_locate() {
+    let checksum =
+        Digest::from_str("5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9")
+            .expect("failed to parse checksum");
+
+    assert_eq!(
+        Layout::Plain.locate("Serde", "1.0.0", &checksum),
+        PathBuf::from("Serde/1.0.0/download")
+    );
+    assert_eq!(
+        Layout::Portable.locate("Serde", "1.0.0", &checksum),
+        PathBuf::from("se/rd/!serde/1.0.0/download")
+    );
+    assert_eq!(
+        Layout::Portable.locate("nul", "0.1.0", &checksum),
+        PathBuf::from("3/n/nul!/0.1.0/download")
+    );
+    assert_eq!(
+        Layout::Sharded.locate("Serde", "1.0.0", &checksum),
+        PathBuf::from("se/rd/Serde/1.0.0/download")
+    );
+    assert_eq!(
+        Layout::ContentAddressed.locate("Serde", "1.0.0", &checksum),
+        PathBuf::from(
+            "sha256/5f/5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9.crate"
+        )
+    );
+    assert_eq!(
+        Layout::Cargo.locate("Serde", "1.0.0", &checksum),
+        PathBuf::from("Serde-1.0.0.crate")
+    );
+}
+
+#[test]
+fn test_parse_layout() {
+    for layout in [
+        Layout::Plain,
+        Layout::Portable,
+        Layout::Sharded,
+        Layout::ContentAddressed,
+        Layout::Cargo,
+    ] {
+        assert_eq!(Layout::from_str(&layout.to_string()), Ok(layout));
+    }
+}
@@ -0,0 +1,167 @@
+use fs2::FileExt;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{task, time};
+use tracing::{debug, info, warn};
+
+/// The interval that a contended lock is retried at while waiting for it to be released.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LockError {
+    /// The lock is held by another process. The identifier of the process is included when it
+    /// was recorded.
+    Held(Option<u32>),
+    Io(io::Error),
+}
+
+impl From<io::Error> for LockError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Held(Some(holder)) => write!(f, "the cache is locked by process {holder}"),
+            Self::Held(None) => write!(f, "the cache is locked by another process"),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for LockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Held(_) => None,
+            Self::Io(error) => Some(error),
+        }
+    }
+}
+
+/// An exclusive advisory lock on a cache. The lock is released when it is dropped or when the
+/// process that holds it exits.
+///
+/// The lock file is never removed so that every process locks the same file. It records the
+/// identifier of the process that last held the lock.
+#[derive(Debug)]
+pub struct Lock {
+    _file: Arc<File>,
+}
+
+/// Attempts to lock `file` without blocking. Returns false if another process holds the lock.
+fn try_lock(file: &File) -> Result<bool, io::Error> {
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(true),
+        Err(error) if error.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the identifier of the process that last held the lock of `file`.
+fn holder(mut file: &File) -> Result<Option<u32>, io::Error> {
+    let mut contents = String::new();
+    file.rewind()?;
+    file.read_to_string(&mut contents)?;
+    Ok(contents.trim().parse().ok())
+}
+
+/// Returns an error if another process holds the lock of the cache with the lock file at `path`.
+/// The lock is released again if it is acquired.
+pub async fn probe(path: PathBuf) -> Result<(), LockError> {
+    task::spawn_blocking(move || {
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+
+        if try_lock(&file)? {
+            file.unlock()?;
+            return Ok(());
+        }
+
+        Err(LockError::Held(holder(&file)?))
+    })
+    .await
+    .expect("panicked while probing the lock")
+}
+
+/// Locks the cache with the lock file at `path`.
+///
+/// The lock is retried until it is released when `wait` is set and an error is returned
+/// otherwise. The lock file is removed before it is locked when `force` is set so that a lock held
+/// by a process that has hung is ignored.
+pub async fn acquire(path: PathBuf, wait: bool, force: bool) -> Result<Lock, LockError> {
+    let file = task::spawn_blocking(move || {
+        if force {
+            match fs::remove_file(&path) {
+                Ok(()) => warn!("removed the lock of the cache"),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    })
+    .await
+    .expect("panicked while opening the lock")?;
+
+    let file = Arc::new(file);
+    let mut waiting = false;
+    loop {
+        let attempt = file.clone();
+        let locked = task::spawn_blocking(move || {
+            if try_lock(&attempt)? {
+                return Ok(None);
+            }
+
+            holder(&attempt).map(Some)
+        })
+        .await
+        .expect("panicked while locking the cache")?;
+
+        match locked {
+            None => break,
+            Some(holder) if !wait => return Err(LockError::Held(holder)),
+            Some(holder) => {
+                if !waiting {
+                    info!("{}; waiting for it to be unlocked", LockError::Held(holder));
+                    waiting = true;
+                }
+
+                time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+
+    let record = file.clone();
+    task::spawn_blocking(move || {
+        let mut record = &*record;
+        record.set_len(0)?;
+        record.rewind()?;
+        write!(record, "{}", process::id())
+    })
+    .await
+    .expect("panicked while recording the lock holder")?;
+
+    debug!("locked the cache");
+    Ok(Lock { _file: file })
+}
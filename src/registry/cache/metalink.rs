@@ -0,0 +1,97 @@
+use super::feed::{self, escape};
+use crate::{
+    digest::{Algorithm, Digest},
+    registry::index::package::Crate,
+};
+use std::fmt::{self, Display, Formatter};
+use url::Url;
+
+/// A crate that is described by a Metalink document.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct File {
+    name: String,
+    /// The size of the crate in bytes if it is known.
+    size: Option<u64>,
+    checksum: Digest,
+    /// The URLs that the crate can be downloaded from in order of preference.
+    urls: Vec<Url>,
+}
+
+impl File {
+    /// Returns the description of `item` that can be downloaded from `urls`.
+    pub fn new(item: &Crate, size: Option<u64>, urls: Vec<Url>) -> Self {
+        Self {
+            name: format!("{}-{}.crate", item.name, item.version),
+            size,
+            checksum: item.checksum,
+            urls,
+        }
+    }
+}
+
+/// Returns the name of the hash function that Metalink documents use for `algorithm`.
+const fn hash_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::Sha256 => "sha-256",
+        Algorithm::Sha512 => "sha-512",
+        Algorithm::Blake3 => "blake3",
+    }
+}
+
+/// A Metalink 4 document (RFC 5854) that describes the crates held in a cache so that they can be
+/// downloaded from several mirrors at once and checked against the checksums published in the
+/// index. The document was published at `time`.
+pub struct Metalink {
+    files: Vec<File>,
+    time: u64,
+}
+
+impl Metalink {
+    /// Returns a document that was published at `time`, the number of seconds since the Unix
+    /// epoch, and that describes `files`.
+    pub fn new(mut files: Vec<File>, time: u64) -> Self {
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { files, time }
+    }
+}
+
+impl Display for Metalink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(f, "<metalink xmlns=\"urn:ietf:params:xml:ns:metalink\">")?;
+        writeln!(
+            f,
+            "  <generator>{}/{}</generator>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(f, "  <published>{}</published>", feed::timestamp(self.time))?;
+
+        for file in &self.files {
+            writeln!(f, "  <file name=\"{}\">", escape(&file.name))?;
+            if let Some(size) = file.size {
+                writeln!(f, "    <size>{size}</size>")?;
+            }
+
+            writeln!(
+                f,
+                "    <hash type=\"{}\">{}</hash>",
+                hash_name(file.checksum.algorithm()),
+                hex::encode(file.checksum.as_bytes())
+            )?;
+
+            for (priority, url) in file.urls.iter().enumerate() {
+                writeln!(
+                    f,
+                    "    <url priority=\"{}\">{}</url>",
+                    priority + 1,
+                    escape(url.as_str())
+                )?;
+            }
+
+            writeln!(f, "  </file>")?;
+        }
+
+        writeln!(f, "</metalink>")
+    }
+}
@@ -0,0 +1,4353 @@
+pub mod archive;
+pub mod audit;
+pub mod bundle;
+pub mod consistency;
+pub mod database;
+pub mod doctor;
+pub mod dump;
+pub mod extract;
+pub mod feed;
+pub mod filter;
+pub mod ipfs;
+pub mod journal;
+pub mod layout;
+pub mod lock;
+pub mod metalink;
+pub mod oci;
+pub mod plan;
+pub mod report;
+pub mod sbom;
+pub mod selector;
+pub mod snapshot;
+pub mod sparse;
+pub mod tally;
+
+use crate::{
+    digest::{self, Algorithm, Digest},
+    download::{self, Download, PreservationStrategy, Transfer, Validators},
+    file::{self, Durability, Permissions},
+    logging::HTTP,
+    notify,
+    registry::{
+        index::{
+            self,
+            authentication::Authentication,
+            configuration::{Configuration, TemplateUrlError},
+            package::{Crate, Dependency, Package},
+            scope::Scope,
+            ChangeKind, Directory, Index,
+        },
+        sparse::{OpenSparseIndexError, SparseIndex, UpdateSparseIndexError},
+    },
+    signature::{Signature, Verifier},
+    storage::{self, Form, Storage},
+};
+use ahash::AHashSet;
+use archive::PruneArchiveError;
+use audit::{Audit, Record};
+use clap::ArgEnum;
+use database::{Database, Entry, Pin, Statistics, Status, Usage};
+use dump::ReadDumpError;
+use feed::{Atom, Feed};
+use filter::{Date, Filter, ResolveFilterError, Selection, YankPolicy};
+use futures::{
+    future::{self, Either},
+    stream, Stream, StreamExt, TryStreamExt,
+};
+use git2::Oid;
+use ipfs::Ipfs;
+use itertools::Itertools;
+use journal::Journal;
+use layout::Layout;
+use lock::{Lock, LockError};
+use metalink::Metalink;
+use oci::Oci;
+use plan::{Estimate, Plan, PreflightError};
+use report::{Defect, PendingChange, Problem};
+use reqwest::{header::HeaderValue, Client};
+use sbom::{Bom, Component};
+use selector::Selector;
+use snapshot::{Difference, Linked, Snapshot, SnapshotError};
+use sparse::ExportSparseError;
+use std::{
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tally::Tally;
+use tokio::{fs, task};
+use tracing::{debug, field, info, info_span, trace, warn, Span};
+use tracing_futures::Instrument;
+use url::Url;
+
+/// The error type for pruning directories.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PruneDirectoriesError {
+    Io(io::Error),
+    /// It is not possible to traverse from the start directory to the finish directory.
+    TraversalIsImpossible,
+}
+
+impl From<io::Error> for PruneDirectoriesError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for PruneDirectoriesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::TraversalIsImpossible => write!(
+                f,
+                "impossible to traverse from the start directory to the finish directory"
+            ),
+        }
+    }
+}
+
+impl Error for PruneDirectoriesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Traverses upwards from `from` to `until` and removes any empty directories found directly on
+/// this traversal. `until` is never removed.
+///
+/// The traversal stops once a directory was removed or filled by another task since the directories
+/// above it are then in use.
+async fn prune_directories(mut from: &Path, until: &Path) -> Result<(), PruneDirectoriesError> {
+    if !from.starts_with(until) {
+        return Err(PruneDirectoriesError::TraversalIsImpossible);
+    }
+
+    while from != until {
+        debug_assert!(from.starts_with(until));
+
+        // Check if the directory is empty.
+        let mut entries = match fs::read_dir(from).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => break,
+            Err(error) => return Err(error.into()),
+        };
+
+        if entries.next_entry().await?.is_none() {
+            match fs::remove_dir(from).await {
+                Ok(()) => (),
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::NotFound | io::ErrorKind::DirectoryNotEmpty
+                    ) =>
+                {
+                    break
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        // Traverse upwards.
+        from = from.parent().expect("a parent must exist");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct CrateDownloadError {
+    source: download::Error,
+    name: String,
+    version: String,
+}
+
+impl Display for CrateDownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to download crate {} version {}",
+            self.name, self.version
+        )
+    }
+}
+
+impl Error for CrateDownloadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// The error type for fetching a single crate.
+#[derive(Debug)]
+enum FetchError {
+    CrateDownload(CrateDownloadError),
+    MalformedDownloadTemplate(TemplateUrlError),
+}
+
+impl From<CrateDownloadError> for FetchError {
+    fn from(error: CrateDownloadError) -> Self {
+        Self::CrateDownload(error)
+    }
+}
+
+impl From<TemplateUrlError> for FetchError {
+    fn from(error: TemplateUrlError) -> Self {
+        Self::MalformedDownloadTemplate(error)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RefreshCacheError {
+    CrateDownload(CrateDownloadError),
+    /// The listing of the index could not be read from the metadata database.
+    Database(rusqlite::Error),
+    GetConfiguration(index::GetConfigurationError),
+    GetPackages(index::GetPackagesError),
+    Git(git2::Error),
+    Io(io::Error),
+    MalformedDownloadTemplate(TemplateUrlError),
+    ResolveFilter(ResolveFilterError),
+    /// The registry requires credentials to download crates and none were given.
+    Unauthenticated,
+}
+
+impl From<git2::Error> for RefreshCacheError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<io::Error> for RefreshCacheError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<rusqlite::Error> for RefreshCacheError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl From<FetchError> for RefreshCacheError {
+    fn from(error: FetchError) -> Self {
+        match error {
+            FetchError::CrateDownload(error) => Self::CrateDownload(error),
+            FetchError::MalformedDownloadTemplate(error) => Self::MalformedDownloadTemplate(error),
+        }
+    }
+}
+
+impl From<CrateDownloadError> for RefreshCacheError {
+    fn from(error: CrateDownloadError) -> Self {
+        Self::CrateDownload(error)
+    }
+}
+
+impl From<TemplateUrlError> for RefreshCacheError {
+    fn from(error: TemplateUrlError) -> Self {
+        Self::MalformedDownloadTemplate(error)
+    }
+}
+
+impl From<index::GetConfigurationError> for RefreshCacheError {
+    fn from(error: index::GetConfigurationError) -> Self {
+        Self::GetConfiguration(error)
+    }
+}
+
+impl From<index::GetPackagesError> for RefreshCacheError {
+    fn from(error: index::GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<ResolveFilterError> for RefreshCacheError {
+    fn from(error: ResolveFilterError) -> Self {
+        Self::ResolveFilter(error)
+    }
+}
+
+impl Display for RefreshCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedDownloadTemplate(_) => {
+                write!(f, "configuration download template is malformed")
+            }
+            Self::CrateDownload(error) => error.fmt(f),
+            Self::Database(error) => write!(f, "failed to read the listing of the index: {error}"),
+            Self::GetConfiguration(error) => error.fmt(f),
+            Self::GetPackages(error) => error.fmt(f),
+            Self::Git(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+            Self::ResolveFilter(error) => error.fmt(f),
+            Self::Unauthenticated => write!(
+                f,
+                "the registry requires authentication but no token was given for it"
+            ),
+        }
+    }
+}
+
+impl Error for RefreshCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::MalformedDownloadTemplate(error) => Some(error),
+            Self::CrateDownload(error) => error.source(),
+            Self::Database(error) => Some(error),
+            Self::GetConfiguration(error) => error.source(),
+            Self::GetPackages(error) => error.source(),
+            Self::Git(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::ResolveFilter(error) => error.source(),
+            Self::Unauthenticated => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UpdateError {
+    CommitUpdate(index::CommitUpdateError),
+    CrateDownload(CrateDownloadError),
+    GetConfiguration(index::GetConfigurationError),
+    GetPackages(index::GetPackagesError),
+    GetUpdate(index::GetUpdateError),
+    Io(io::Error),
+    MalformedDownloadTemplate(TemplateUrlError),
+    PruneDirectories(PruneDirectoriesError),
+    ExportSparse(ExportSparseError),
+    OpenSparseIndex(OpenSparseIndexError),
+    UpdateSparseIndex(UpdateSparseIndexError),
+    ResolveFilter(ResolveFilterError),
+}
+
+impl From<index::GetUpdateError> for UpdateError {
+    fn from(error: index::GetUpdateError) -> Self {
+        Self::GetUpdate(error)
+    }
+}
+
+impl From<index::GetPackagesError> for UpdateError {
+    fn from(error: index::GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<index::GetConfigurationError> for UpdateError {
+    fn from(error: index::GetConfigurationError) -> Self {
+        Self::GetConfiguration(error)
+    }
+}
+
+impl From<TemplateUrlError> for UpdateError {
+    fn from(error: TemplateUrlError) -> Self {
+        Self::MalformedDownloadTemplate(error)
+    }
+}
+
+impl From<FetchError> for UpdateError {
+    fn from(error: FetchError) -> Self {
+        match error {
+            FetchError::CrateDownload(error) => Self::CrateDownload(error),
+            FetchError::MalformedDownloadTemplate(error) => Self::MalformedDownloadTemplate(error),
+        }
+    }
+}
+
+impl From<CrateDownloadError> for UpdateError {
+    fn from(error: CrateDownloadError) -> Self {
+        Self::CrateDownload(error)
+    }
+}
+
+impl From<index::CommitUpdateError> for UpdateError {
+    fn from(error: index::CommitUpdateError) -> Self {
+        Self::CommitUpdate(error)
+    }
+}
+
+impl From<io::Error> for UpdateError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<PruneDirectoriesError> for UpdateError {
+    fn from(error: PruneDirectoriesError) -> Self {
+        Self::PruneDirectories(error)
+    }
+}
+
+impl From<ExportSparseError> for UpdateError {
+    fn from(error: ExportSparseError) -> Self {
+        Self::ExportSparse(error)
+    }
+}
+
+impl From<OpenSparseIndexError> for UpdateError {
+    fn from(error: OpenSparseIndexError) -> Self {
+        Self::OpenSparseIndex(error)
+    }
+}
+
+impl From<UpdateSparseIndexError> for UpdateError {
+    fn from(error: UpdateSparseIndexError) -> Self {
+        Self::UpdateSparseIndex(error)
+    }
+}
+
+impl From<ResolveFilterError> for UpdateError {
+    fn from(error: ResolveFilterError) -> Self {
+        Self::ResolveFilter(error)
+    }
+}
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CommitUpdate(error) => error.fmt(f),
+            Self::CrateDownload(error) => error.fmt(f),
+            Self::GetConfiguration(error) => error.fmt(f),
+            Self::GetPackages(error) => error.fmt(f),
+            Self::GetUpdate(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+            Self::MalformedDownloadTemplate(_) => {
+                write!(f, "configuration download template is malformed")
+            }
+            Self::PruneDirectories(error) => error.fmt(f),
+            Self::ExportSparse(error) => error.fmt(f),
+            Self::OpenSparseIndex(error) => error.fmt(f),
+            Self::UpdateSparseIndex(error) => error.fmt(f),
+            Self::ResolveFilter(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for UpdateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::MalformedDownloadTemplate(error) => Some(error),
+            Self::CommitUpdate(error) => error.source(),
+            Self::CrateDownload(error) => error.source(),
+            Self::GetConfiguration(error) => error.source(),
+            Self::GetPackages(error) => error.source(),
+            Self::GetUpdate(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::PruneDirectories(error) => error.source(),
+            Self::ExportSparse(error) => error.source(),
+            Self::OpenSparseIndex(error) => error.source(),
+            Self::UpdateSparseIndex(error) => error.source(),
+            Self::ResolveFilter(error) => error.source(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SynchroniseError {
+    Refresh(RefreshCacheError),
+    Update(UpdateError),
+    Preflight(PreflightError),
+}
+
+impl From<RefreshCacheError> for SynchroniseError {
+    fn from(error: RefreshCacheError) -> Self {
+        Self::Refresh(error)
+    }
+}
+
+impl From<UpdateError> for SynchroniseError {
+    fn from(error: UpdateError) -> Self {
+        Self::Update(error)
+    }
+}
+
+impl From<PreflightError> for SynchroniseError {
+    fn from(error: PreflightError) -> Self {
+        Self::Preflight(error)
+    }
+}
+
+impl Display for SynchroniseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refresh(error) => error.fmt(f),
+            Self::Update(error) => error.fmt(f),
+            Self::Preflight(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for SynchroniseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Refresh(error) => error.source(),
+            Self::Update(error) => error.source(),
+            Self::Preflight(error) => error.source(),
+        }
+    }
+}
+
+/// The error type for removing crates from a cache.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PruneCacheError {
+    Refresh(RefreshCacheError),
+    Remove(UpdateError),
+}
+
+impl From<RefreshCacheError> for PruneCacheError {
+    fn from(error: RefreshCacheError) -> Self {
+        Self::Refresh(error)
+    }
+}
+
+impl From<UpdateError> for PruneCacheError {
+    fn from(error: UpdateError) -> Self {
+        Self::Remove(error)
+    }
+}
+
+impl Display for PruneCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refresh(error) => error.fmt(f),
+            Self::Remove(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for PruneCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Refresh(error) => error.source(),
+            Self::Remove(error) => error.source(),
+        }
+    }
+}
+
+/// The error type for ingesting a database dump.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IngestDumpError {
+    Read(ReadDumpError),
+    Database(rusqlite::Error),
+}
+
+impl From<ReadDumpError> for IngestDumpError {
+    fn from(error: ReadDumpError) -> Self {
+        Self::Read(error)
+    }
+}
+
+impl From<rusqlite::Error> for IngestDumpError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl Display for IngestDumpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(error) => write!(f, "failed to read database dump: {error}"),
+            Self::Database(error) => write!(f, "failed to record download counts: {error}"),
+        }
+    }
+}
+
+impl Error for IngestDumpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read(error) => Some(error),
+            Self::Database(error) => Some(error),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ReadCrateError {
+    GetPackages(index::GetPackagesError),
+    Io(io::Error),
+    Database(rusqlite::Error),
+    /// The crate does not match its checksum. It was quarantined and is downloaded again by the
+    /// next synchronisation.
+    Corrupt {
+        name: String,
+        version: String,
+    },
+}
+
+impl From<index::GetPackagesError> for ReadCrateError {
+    fn from(error: index::GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<io::Error> for ReadCrateError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<rusqlite::Error> for ReadCrateError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl Display for ReadCrateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GetPackages(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+            Self::Database(error) => write!(f, "failed to record the quarantine: {error}"),
+            Self::Corrupt { name, version } => write!(
+                f,
+                "{name} {version} does not match its checksum and was quarantined"
+            ),
+        }
+    }
+}
+
+impl Error for ReadCrateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::GetPackages(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::Database(error) => Some(error),
+            Self::Corrupt { .. } => None,
+        }
+    }
+}
+
+/// The error type for migrating the crates directory to another layout.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MigrateLayoutError {
+    Refresh(RefreshCacheError),
+    Io(io::Error),
+    PruneDirectories(PruneDirectoriesError),
+}
+
+impl From<RefreshCacheError> for MigrateLayoutError {
+    fn from(error: RefreshCacheError) -> Self {
+        Self::Refresh(error)
+    }
+}
+
+impl From<io::Error> for MigrateLayoutError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<PruneDirectoriesError> for MigrateLayoutError {
+    fn from(error: PruneDirectoriesError) -> Self {
+        Self::PruneDirectories(error)
+    }
+}
+
+impl Display for MigrateLayoutError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refresh(error) => write!(f, "failed to list crates: {error}"),
+            Self::Io(error) => write!(f, "failed to move crates: {error}"),
+            Self::PruneDirectories(error) => write!(f, "failed to remove directories: {error}"),
+        }
+    }
+}
+
+impl Error for MigrateLayoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Refresh(error) => Some(error),
+            Self::Io(error) => Some(error),
+            Self::PruneDirectories(error) => Some(error),
+        }
+    }
+}
+
+/// The error type for writing a bundle.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BundleError {
+    Git(git2::Error),
+    GetPackages(index::GetPackagesError),
+    Io(io::Error),
+}
+
+impl From<git2::Error> for BundleError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<index::GetPackagesError> for BundleError {
+    fn from(error: index::GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<io::Error> for BundleError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for BundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => error.fmt(f),
+            Self::GetPackages(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for BundleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::GetPackages(error) => error.source(),
+            Self::Io(error) => error.source(),
+        }
+    }
+}
+
+/// The error type for applying a bundle.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ApplyBundleError {
+    Io(io::Error),
+    Unbundle(index::UnbundleError),
+    Update(UpdateError),
+    Consistency(RefreshCacheError),
+}
+
+impl From<io::Error> for ApplyBundleError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<index::UnbundleError> for ApplyBundleError {
+    fn from(error: index::UnbundleError) -> Self {
+        Self::Unbundle(error)
+    }
+}
+
+impl From<UpdateError> for ApplyBundleError {
+    fn from(error: UpdateError) -> Self {
+        Self::Update(error)
+    }
+}
+
+impl From<RefreshCacheError> for ApplyBundleError {
+    fn from(error: RefreshCacheError) -> Self {
+        Self::Consistency(error)
+    }
+}
+
+impl Display for ApplyBundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read bundle: {error}"),
+            Self::Unbundle(error) => write!(f, "failed to import index commits: {error}"),
+            Self::Update(error) => error.fmt(f),
+            Self::Consistency(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ApplyBundleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Unbundle(error) => Some(error),
+            Self::Update(error) => error.source(),
+            Self::Consistency(error) => error.source(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CreateCacheError {
+    CloneIndex(index::CloneIndexError),
+    Database(rusqlite::Error),
+    Io(io::Error),
+    UpdateSparseIndex(UpdateSparseIndexError),
+}
+
+impl Display for CreateCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CloneIndex(error) => error.fmt(f),
+            Self::Database(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+            Self::UpdateSparseIndex(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for CreateCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CloneIndex(error) => error.source(),
+            Self::Database(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::UpdateSparseIndex(error) => error.source(),
+        }
+    }
+}
+
+impl From<index::CloneIndexError> for CreateCacheError {
+    fn from(error: index::CloneIndexError) -> Self {
+        Self::CloneIndex(error)
+    }
+}
+
+impl From<rusqlite::Error> for CreateCacheError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl From<io::Error> for CreateCacheError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<UpdateSparseIndexError> for CreateCacheError {
+    fn from(error: UpdateSparseIndexError) -> Self {
+        Self::UpdateSparseIndex(error)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LoadCacheError {
+    Database(rusqlite::Error),
+    OpenIndex(index::OpenIndexError),
+    Io(io::Error),
+}
+
+impl Display for LoadCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load cache")
+    }
+}
+
+impl Error for LoadCacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Database(error) => error.source(),
+            Self::OpenIndex(error) => error.source(),
+            Self::Io(error) => Some(error),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LoadCacheError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Database(error)
+    }
+}
+
+impl From<index::OpenIndexError> for LoadCacheError {
+    fn from(error: index::OpenIndexError) -> Self {
+        Self::OpenIndex(error)
+    }
+}
+
+impl From<io::Error> for LoadCacheError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// The error type for cloning the index of a cache again.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RepairIndexError {
+    CloneIndex(index::CloneIndexError),
+    ExportSparse(ExportSparseError),
+    GetConfiguration(index::GetConfigurationError),
+    Io(io::Error),
+    Load(LoadCacheError),
+    /// The remote of the index could not be read.
+    Remote(git2::Error),
+    /// The remote of the index is not a URL.
+    RemoteUrl(url::ParseError),
+}
+
+impl From<index::CloneIndexError> for RepairIndexError {
+    fn from(error: index::CloneIndexError) -> Self {
+        Self::CloneIndex(error)
+    }
+}
+
+impl From<ExportSparseError> for RepairIndexError {
+    fn from(error: ExportSparseError) -> Self {
+        Self::ExportSparse(error)
+    }
+}
+
+impl From<index::GetConfigurationError> for RepairIndexError {
+    fn from(error: index::GetConfigurationError) -> Self {
+        Self::GetConfiguration(error)
+    }
+}
+
+impl From<io::Error> for RepairIndexError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<LoadCacheError> for RepairIndexError {
+    fn from(error: LoadCacheError) -> Self {
+        Self::Load(error)
+    }
+}
+
+impl From<url::ParseError> for RepairIndexError {
+    fn from(error: url::ParseError) -> Self {
+        Self::RemoteUrl(error)
+    }
+}
+
+impl Display for RepairIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CloneIndex(error) => write!(f, "failed to clone the index: {error}"),
+            Self::ExportSparse(error) => write!(f, "failed to export the sparse index: {error}"),
+            Self::GetConfiguration(error) => {
+                write!(f, "failed to rewrite the download template: {error}")
+            }
+            Self::Io(error) => write!(f, "failed to replace the index: {error}"),
+            Self::Load(error) => error.fmt(f),
+            Self::Remote(error) => write!(
+                f,
+                "failed to read the remote of the index: {error}; give its URL with --url"
+            ),
+            Self::RemoteUrl(error) => write!(
+                f,
+                "the remote of the index is not a URL: {error}; give its URL with --url"
+            ),
+        }
+    }
+}
+
+impl Error for RepairIndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CloneIndex(error) => Some(error),
+            Self::ExportSparse(error) => Some(error),
+            Self::GetConfiguration(error) => Some(error),
+            Self::Io(error) => Some(error),
+            Self::Load(error) => Some(error),
+            Self::Remote(error) => Some(error),
+            Self::RemoteUrl(error) => Some(error),
+        }
+    }
+}
+
+/// Specifies how failures to download individual crates are handled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum FailureMode {
+    /// Crates that can not be downloaded are reported and the operation continues.
+    Continue,
+    /// The operation is aborted when any crate can not be downloaded.
+    Strict,
+}
+
+/// Specifies what happens to crates that are removed from the cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum RemovalStrategy {
+    /// Crates are deleted.
+    Delete,
+    /// Crates are moved into the archive.
+    Archive,
+}
+
+/// Describes the result of an operation that was permitted to continue past failures.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[must_use]
+pub struct Outcome {
+    /// The number of crates that were checked.
+    pub checked: usize,
+    /// The number of crates that were downloaded.
+    pub downloaded: usize,
+    /// The number of crates that were removed.
+    pub removed: usize,
+    /// The number of bytes that were downloaded.
+    pub bytes: u64,
+    /// The number of crates that could not be downloaded.
+    pub failed: usize,
+}
+
+impl Outcome {
+    /// Returns true if every crate was handled successfully.
+    #[must_use]
+    pub const fn is_complete(self) -> bool {
+        self.failed == 0
+    }
+
+    /// Combines two outcomes.
+    pub const fn merge(self, other: Self) -> Self {
+        Self {
+            checked: self.checked + other.checked,
+            downloaded: self.downloaded + other.downloaded,
+            removed: self.removed + other.removed,
+            bytes: self.bytes + other.bytes,
+            failed: self.failed + other.failed,
+        }
+    }
+}
+
+/// The checksums of a crate before and after it was downloaded again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Refetched {
+    /// The checksum of the crate that was stored or nothing if it was not stored.
+    pub old: Option<Digest>,
+    /// The checksum of the crate that was downloaded or nothing if it could not be downloaded.
+    pub new: Option<Digest>,
+}
+
+/// Counts the crates that an export copied and unpacked.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Exported {
+    /// The number of crates that were copied.
+    pub copied: usize,
+    /// The number of crates that were unpacked.
+    pub unpacked: usize,
+}
+
+/// Settings for operations that download crates.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// How crates are downloaded.
+    pub download: download::Options,
+    /// How failures to download crates are handled.
+    pub mode: FailureMode,
+    /// The number of requests that are sent to the registry concurrently.
+    pub jobs: NonZeroUsize,
+    /// The limit on the number of crates that are downloaded concurrently.
+    pub connections: download::Connections,
+    /// The crate versions that are mirrored.
+    pub filter: Filter,
+    /// The crates that are downloaded again regardless of the preservation strategy.
+    pub force: Vec<Selector>,
+    /// The index is updated to the last commit that was made at or before this date instead of
+    /// the latest commit if there is one.
+    pub snapshot: Option<Date>,
+    /// The feed that updates append the changes to the index to. No feed is written if there is
+    /// none.
+    pub feed: Option<Feed>,
+    /// What happens to crates that are removed or replaced.
+    pub removal: RemovalStrategy,
+    /// The mirrors that crates are downloaded from before the registry.
+    pub parents: Vec<Url>,
+    /// The IPFS node that downloaded crates are pinned to. Crates are not pinned if there is none.
+    pub ipfs: Option<Ipfs>,
+    /// The OCI repository that downloaded crates are pushed to. Crates are not pushed if there is
+    /// none.
+    pub oci: Option<Oci>,
+    /// The program that verifies the signatures of downloaded crates before they are stored.
+    /// Signatures are not verified if there is none.
+    pub verifier: Option<Verifier>,
+    /// Check the integrity of every crate when integrity is checked. Otherwise, crates that have
+    /// not changed since their integrity was last checked are preserved without being checked.
+    pub deep: bool,
+    /// The pool that computes the digests of crates.
+    pub hashing: digest::Pool,
+    /// True if credentials are sent with requests to download crates.
+    pub authenticated: bool,
+}
+
+impl Settings {
+    /// Returns the number of crates that are acted on concurrently. Enough crates are acted on to
+    /// occupy the jobs and the hashing pool so that checking stored crates is not limited by the
+    /// number of downloads.
+    pub fn concurrency(&self) -> usize {
+        self.jobs.max(self.hashing.threads()).get()
+    }
+}
+
+/// The progress of a refresh through a top-level directory of the index.
+#[derive(Debug)]
+struct Progress {
+    /// The name of the directory.
+    name: String,
+    /// The number of crates that remain to be refreshed in the directory.
+    remaining: AtomicUsize,
+    /// Whether any crate in the directory could not be downloaded.
+    failed: AtomicBool,
+}
+
+/// Specifies the order that crates are downloaded in during a refresh.
+#[derive(ArgEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Order {
+    /// Crates are downloaded in the order that they are stored in the index.
+    Index,
+    /// Crates are downloaded in alphabetical order of name and version.
+    Alphabetical,
+    /// Crates whose packages were most recently modified in the index are downloaded first.
+    Recent,
+    /// The smallest crates are downloaded first. The size of every crate is requested from the
+    /// registry before any crate is downloaded.
+    Smallest,
+}
+
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    index: Index,
+    database: Database,
+    audit: Audit,
+    layout: Layout,
+    /// The selectors of the crates that are never mirrored.
+    deny: Vec<Selector>,
+    /// The credentials that are sent with requests to download crates from the registry.
+    authorization: Option<HeaderValue>,
+}
+
+impl Cache {
+    /// The directory in the cache that holds the index.
+    pub const INDEX_SUBDIRECTORY: &'static str = "index";
+
+    /// The directory in the cache that holds the crates.
+    pub const CRATES_SUBDIRECTORY: &'static str = "crates";
+
+    /// The directory in the cache that holds removed and replaced crates.
+    pub const ARCHIVE_SUBDIRECTORY: &'static str = "archive";
+
+    /// The directory in the cache that holds crates that were found to be corrupt when they were
+    /// read.
+    pub const QUARANTINE_SUBDIRECTORY: &'static str = "quarantine";
+
+    /// The file in the cache that holds the log of every attempt to download a crate.
+    pub const AUDIT_FILENAME: &'static str = "audit.log";
+
+    /// The file in the cache that holds the feed of changes to the index.
+    pub const FEED_FILENAME: &'static str = "feed.xml";
+
+    /// The directory in the cache that holds the index using the layout of a sparse index.
+    pub const SPARSE_SUBDIRECTORY: &'static str = "sparse";
+
+    /// The directory in the cache that holds the Git mirror of a sparse index.
+    pub const UPSTREAM_SUBDIRECTORY: &'static str = "upstream";
+
+    /// The file in the cache that holds the metadata database.
+    pub const DATABASE_FILENAME: &'static str = "metadata.sqlite";
+
+    /// The file in the cache that records the progress of a refresh.
+    pub const JOURNAL_FILENAME: &'static str = "journal.json";
+
+    /// The file in the cache that records the index commit that the cache is consistent with.
+    pub const CONSISTENCY_FILENAME: &'static str = "consistency.json";
+
+    /// The file in the cache that records the index commit that every crate was last verified at.
+    pub const VERIFICATION_FILENAME: &'static str = "verified.json";
+
+    /// The file in the cache that records the last time that a synchronisation left every crate in
+    /// place. Replicas compare it before and after copying the cache.
+    pub const MARKER_FILENAME: &'static str = "last-complete";
+
+    /// The directory in the cache that a bundle is unpacked into while it is applied.
+    pub const BUNDLE_SUBDIRECTORY: &'static str = "bundle";
+
+    /// The file in the cache that records the layout of the crates directory. The crates directory
+    /// has the plain layout if there is none.
+    pub const LAYOUT_FILENAME: &'static str = "layout";
+
+    /// The file in the cache that records the selectors of the crates that are never mirrored, one
+    /// on each line.
+    pub const DENY_FILENAME: &'static str = "deny";
+
+    /// The file in the cache that is locked by the process that is changing the cache.
+    pub const LOCK_FILENAME: &'static str = "crateful.lock";
+
+    /// The directory in the cache that holds named snapshots of the crates directory.
+    pub const SNAPSHOTS_SUBDIRECTORY: &'static str = "snapshots";
+
+    /// The directory in the cache that the index is cloned into before it replaces the index.
+    pub const REPAIR_SUBDIRECTORY: &'static str = "index.repair";
+
+    /// The algorithm that the digests of stored crates are recorded in the manifest with. Crates
+    /// are still checked against the checksum that is published by the registry when they are
+    /// downloaded.
+    const MANIFEST_ALGORITHM: Algorithm = Algorithm::Blake3;
+
+    /// Returns the path to the cache.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path to the crates directory.
+    #[must_use]
+    pub fn crates_path(&self) -> PathBuf {
+        self.path.join(Self::CRATES_SUBDIRECTORY)
+    }
+
+    /// Returns the path to the index directory.
+    #[must_use]
+    pub fn index_path(&self) -> PathBuf {
+        self.path.join(Self::INDEX_SUBDIRECTORY)
+    }
+
+    /// Returns the path to the archive directory.
+    #[must_use]
+    pub fn archive_path(&self) -> PathBuf {
+        self.path.join(Self::ARCHIVE_SUBDIRECTORY)
+    }
+
+    /// Returns the path to the quarantine directory.
+    #[must_use]
+    pub fn quarantine_path(&self) -> PathBuf {
+        self.path.join(Self::QUARANTINE_SUBDIRECTORY)
+    }
+
+    /// Returns the path to the snapshots directory.
+    #[must_use]
+    pub fn snapshots_path(&self) -> PathBuf {
+        self.path.join(Self::SNAPSHOTS_SUBDIRECTORY)
+    }
+
+    /// Returns the path to the sparse index directory.
+    #[must_use]
+    pub fn sparse_path(&self) -> PathBuf {
+        self.path.join(Self::SPARSE_SUBDIRECTORY)
+    }
+
+    /// Locks the cache at `path` so that it is not changed by several processes at once. The
+    /// directory is created if it does not exist so that a new cache can be locked.
+    ///
+    /// Another process that holds the lock is waited for when `wait` is set. A lock that is held
+    /// by a process that has hung is ignored when `force` is set.
+    pub async fn lock(path: &Path, wait: bool, force: bool) -> Result<Lock, LockError> {
+        fs::create_dir_all(path).await?;
+        lock::acquire(path.join(Self::LOCK_FILENAME), wait, force).await
+    }
+
+    /// Creates a new cache.
+    ///
+    /// The index and the metadata database are opened at `path` as it is given. Every other file
+    /// in the cache is found through the extended-length form of `path` on Windows so that crates
+    /// with long names can be stored deep in the file system.
+    ///
+    /// The remote of the index is authenticated as `authentication` describes.
+    pub async fn new(
+        path: PathBuf,
+        index: Url,
+        authentication: Authentication,
+    ) -> Result<Self, CreateCacheError> {
+        let index =
+            Index::from_url(index, path.join(Self::INDEX_SUBDIRECTORY), authentication).await?;
+        let database = Database::open(path.join(Self::DATABASE_FILENAME)).await?;
+        let path = file::extended(&path)?;
+        Ok(Self {
+            audit: Audit::new(path.join(Self::AUDIT_FILENAME)),
+            path,
+            index,
+            database,
+            layout: Layout::Plain,
+            deny: Vec::new(),
+            authorization: None,
+        })
+    }
+
+    /// Creates a new cache for the sparse index at `index`. The index is held in a Git mirror of the
+    /// sparse index that tracks `crates` and every crate that they depend on.
+    pub async fn from_sparse(
+        path: PathBuf,
+        index: &Url,
+        crates: &[String],
+        client: &Client,
+        jobs: NonZeroUsize,
+    ) -> Result<Self, CreateCacheError> {
+        let upstream = path.join(Self::UPSTREAM_SUBDIRECTORY);
+        SparseIndex::create(index, upstream.clone(), crates, client, jobs).await?;
+
+        let upstream = fs::canonicalize(upstream).await?;
+        let url = Url::from_directory_path(&upstream).map_err(|()| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} can not be used as a URL", upstream.display()),
+            )
+        })?;
+
+        Self::new(path, url, Authentication::default()).await
+    }
+
+    /// Returns a cache from a file system path. Files in the cache are found as they are by
+    /// [`Self::new`].
+    pub async fn from_path(path: PathBuf) -> Result<Self, LoadCacheError> {
+        let index = Index::from_path(path.join(Self::INDEX_SUBDIRECTORY)).await?;
+        let database = Database::open(path.join(Self::DATABASE_FILENAME)).await?;
+        let path = file::extended(&path)?;
+        let layout = layout::read(&path.join(Self::LAYOUT_FILENAME)).await?;
+        let deny = selector::read(&path.join(Self::DENY_FILENAME)).await?;
+
+        Ok(Self {
+            audit: Audit::new(path.join(Self::AUDIT_FILENAME)),
+            path,
+            index,
+            database,
+            layout,
+            deny,
+            authorization: None,
+        })
+    }
+
+    /// Clones the index of the cache at `path` again and returns the cache. The index is cloned
+    /// from `url` or, if it is not given, from the remote that the index was cloned from. The
+    /// crates directory and every other file in the cache are preserved.
+    ///
+    /// The index is cloned beside the index that it replaces so that the cache is left as it was
+    /// if the index can not be cloned. The remote is authenticated as it was when the index was
+    /// cloned. A rewritten download template is rewritten again and an exported sparse index is
+    /// exported again if they can be read from the index that is replaced. The cache must be
+    /// refreshed afterwards as the index may no longer be at the commit that the crates were
+    /// downloaded for.
+    pub async fn repair_index(path: PathBuf, url: Option<Url>) -> Result<Self, RepairIndexError> {
+        let index = path.join(Self::INDEX_SUBDIRECTORY);
+        let url = match url {
+            Some(url) => url,
+            None => Url::parse(
+                &Index::remote_url(index.clone())
+                    .await
+                    .map_err(RepairIndexError::Remote)?,
+            )?,
+        };
+
+        let authentication = Index::authentication(index.clone())
+            .await
+            .unwrap_or_else(|error| {
+                warn!("failed to read the authentication of the index: {}", error);
+                Authentication::default()
+            });
+
+        let template = match Index::from_path(index.clone()).await {
+            Ok(previous) => previous.rewritten_template().await.unwrap_or_else(|error| {
+                warn!("failed to read the rewritten download template: {}", error);
+                None
+            }),
+            Err(error) => {
+                warn!("failed to open the index: {}", error);
+                None
+            }
+        };
+
+        // A clone that was interrupted is cloned again.
+        let clone = path.join(Self::REPAIR_SUBDIRECTORY);
+        if let Err(error) = fs::remove_dir_all(&clone).await {
+            if error.kind() != io::ErrorKind::NotFound {
+                return Err(error.into());
+            }
+        }
+
+        drop(Index::from_url(url, clone.clone(), authentication).await?);
+        debug!("cloned the index again");
+
+        if let Err(error) = fs::remove_dir_all(&index).await {
+            if error.kind() != io::ErrorKind::NotFound {
+                return Err(error.into());
+            }
+        }
+
+        fs::rename(&clone, &index).await?;
+        let cache = Self::from_path(path).await?;
+
+        if let Some(template) = template {
+            cache.rewrite(template).await?;
+            debug!("rewrote the download template again");
+        }
+
+        if fs::metadata(cache.sparse_path()).await.is_ok() {
+            cache.export_sparse().await?;
+            debug!("exported the sparse index again");
+        }
+
+        Ok(cache)
+    }
+
+    /// Returns the rewritten download template of the index held by the cache if the download
+    /// template has been rewritten.
+    pub async fn rewritten_template(&self) -> Result<Option<String>, index::GetConfigurationError> {
+        self.index.rewritten_template().await
+    }
+
+    /// Returns the host that crates are downloaded from or nothing if it is not known.
+    pub async fn download_host(&self) -> Option<String> {
+        self.index
+            .configuration()
+            .await
+            .ok()
+            .and_then(|configuration| configuration.host())
+    }
+
+    /// Rewrites the download template of the index held by the cache so that clients of the index
+    /// download crates from `template`.
+    pub async fn rewrite(&self, template: String) -> Result<(), index::GetConfigurationError> {
+        self.index.rewrite(template).await
+    }
+
+    /// Exports the index held by the cache using the layout of a sparse index so that it can be
+    /// served by a static file server. Once exported, the sparse index is kept up to date by
+    /// [`Self::update`].
+    pub async fn export_sparse(&self) -> Result<(), ExportSparseError> {
+        sparse::export(&self.index, &self.sparse_path()).await
+    }
+
+    /// Locates a crate in the cache. The crate is not guaranteed to exist.
+    #[must_use]
+    pub fn locate_crate(&self, item: &Crate) -> PathBuf {
+        self.crates_path().join(
+            self.layout
+                .locate(&item.name, &item.version, &item.checksum),
+        )
+    }
+
+    /// Sends `authorization` with the requests that download crates from the registry. It is never
+    /// sent to parent mirrors or to the services that crates are distributed to.
+    pub fn set_authorization(&mut self, authorization: Option<HeaderValue>) {
+        self.authorization = authorization;
+    }
+
+    /// Skips corrupt lines in the packages of the index with a warning instead of failing if
+    /// `lenient` is true.
+    pub const fn set_lenient(&mut self, lenient: bool) {
+        self.index.set_lenient(lenient);
+    }
+
+    /// Returns the number of corrupt lines in the packages of the index that were skipped.
+    #[must_use]
+    pub fn skipped_index_lines(&self) -> usize {
+        self.index.skipped()
+    }
+
+    /// Returns the layout of the crates directory.
+    #[must_use]
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Moves every stored crate to where `layout` locates it and records that the crates directory
+    /// has the layout. Returns the number of crates that were moved.
+    ///
+    /// Crates are found through the index because the metadata database may not record every
+    /// stored crate. A migration that was interrupted can be resumed because crates that were
+    /// already moved are skipped.
+    pub async fn migrate_layout(
+        &mut self,
+        layout: Layout,
+        permissions: Permissions,
+    ) -> Result<usize, MigrateLayoutError> {
+        let crates = &self.crates_path();
+        let (current, path) = (self.layout, &self.path);
+
+        let moved = if layout == current {
+            0
+        } else {
+            self.crates(&Scope::default())
+                .map_err(MigrateLayoutError::from)
+                .try_fold(0, |moved, item| async move {
+                    let from =
+                        crates.join(current.locate(&item.name, &item.version, &item.checksum));
+                    let to = crates.join(layout.locate(&item.name, &item.version, &item.checksum));
+                    if storage::form(&from).await?.is_none() {
+                        return Ok(moved);
+                    }
+
+                    file::create_dir_all(
+                        to.parent().expect("crate file must have a parent"),
+                        permissions,
+                    )
+                    .await?;
+                    storage::rename(&from, &to).await?;
+                    prune_directories(from.parent().expect("crate file must have a parent"), path)
+                        .await?;
+
+                    Ok(moved + 1)
+                })
+                .await?
+        };
+
+        layout::write(&self.path.join(Self::LAYOUT_FILENAME), layout).await?;
+        self.layout = layout;
+        Ok(moved)
+    }
+
+    /// Returns the selectors of the crates that are never mirrored by the cache.
+    #[must_use]
+    pub fn denied(&self) -> &[Selector] {
+        &self.deny
+    }
+
+    /// Deletes every stored crate that `selector` selects and removes any directories that are left
+    /// empty. Returns the crates that were deleted.
+    ///
+    /// The selector is added to the deny list of the cache first if `deny` is set so that the crates
+    /// are never mirrored again. Otherwise, the cache is no longer consistent with the index and the
+    /// crates are downloaded again by the next synchronisation. Crates are deleted instead of
+    /// archived so that nothing of them remains.
+    pub async fn evict(
+        &mut self,
+        selector: Selector,
+        deny: bool,
+    ) -> Result<Vec<Crate>, PruneCacheError> {
+        if deny && !self.deny.contains(&selector) {
+            self.deny.push(selector.clone());
+            selector::write(&self.path.join(Self::DENY_FILENAME), &self.deny)
+                .await
+                .map_err(UpdateError::from)?;
+        }
+
+        // A selector that names a crate is looked up directly instead of reading the whole index.
+        let crates = match selector.name() {
+            Some(name) => self
+                .index
+                .package(name.to_owned(), false)
+                .await
+                .map_err(RefreshCacheError::from)?
+                .map(|package| {
+                    package
+                        .into_crates()
+                        .filter(|each| selector.selects(each))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => {
+                self.crates(&Scope::default())
+                    .try_filter(|each| future::ready(selector.selects(each)))
+                    .try_collect::<Vec<_>>()
+                    .await?
+            }
+        };
+
+        let mut evicted = Vec::new();
+        for each in crates {
+            if self
+                .remove(&each, RemovalStrategy::Delete, Permissions::default())
+                .await?
+            {
+                info!(
+                    name = each.name.as_str(),
+                    version = each.version.as_str(),
+                    "removed a crate"
+                );
+                evicted.push(each);
+            }
+        }
+
+        // Denied crates are no longer selected, so the cache remains consistent without them.
+        if !deny && !evicted.is_empty() {
+            consistency::clear(&self.path.join(Self::CONSISTENCY_FILENAME))
+                .await
+                .map_err(UpdateError::from)?;
+        }
+
+        Ok(evicted)
+    }
+
+    /// Returns true if a crate is stored in the cache in any form.
+    async fn is_stored(&self, item: &Crate) -> Result<bool, io::Error> {
+        Ok(storage::form(&self.locate_crate(item)).await?.is_some())
+    }
+
+    /// Checks that every object that the index needs can be read. See [`Index::check_integrity`].
+    pub async fn check_index(&self) -> Result<(), index::CheckIntegrityError> {
+        self.index.check_integrity().await
+    }
+
+    /// Returns the changes to the index that the next update will apply without applying them.
+    ///
+    /// The latest changes are fetched from the registry but they are not applied to the cache.
+    pub async fn pending_changes(
+        &self,
+        client: &Client,
+        jobs: NonZeroUsize,
+    ) -> Result<Vec<PendingChange>, UpdateError> {
+        self.fetch_index(client, jobs).await?;
+        if self
+            .index
+            .is_empty()
+            .await
+            .map_err(index::GetUpdateError::from)?
+        {
+            return Ok(Vec::new());
+        }
+
+        // The update is dropped without being committed.
+        let mut pending = self.index.stage(None).await?;
+        let changes = pending
+            .changes()
+            .map_ok(PendingChange::from)
+            .try_collect()
+            .await?;
+
+        Ok(changes)
+    }
+
+    /// Records the number of times that each crate has been downloaded from the crates.io database
+    /// dump at `path` so that filters can select the most popular crates. Counts that were
+    /// ingested before are replaced. Returns the number of crates that were recorded.
+    pub async fn ingest_dump(&self, path: PathBuf) -> Result<usize, IngestDumpError> {
+        let counts = task::spawn_blocking(move || dump::read(&path))
+            .await
+            .expect("panicked while reading the database dump")?;
+
+        let count = counts.len();
+        self.database.record_downloads(counts).await?;
+        Ok(count)
+    }
+
+    /// Returns a summary of the crates that are recorded in the metadata database.
+    pub async fn status(&self) -> Result<Status, rusqlite::Error> {
+        self.database.status().await
+    }
+
+    /// Returns aggregate statistics about the crates that are recorded in the metadata database with
+    /// the `largest` crates that occupy the most space.
+    pub async fn statistics(&self, largest: usize) -> Result<Statistics, rusqlite::Error> {
+        self.database.statistics(largest).await
+    }
+
+    /// Returns the disk usage of the `count` crates that occupy the most space, or of every crate
+    /// if no count is given, summed over their versions.
+    pub async fn usage(&self, count: Option<usize>) -> Result<Vec<Usage>, rusqlite::Error> {
+        self.database.usage(count).await
+    }
+
+    /// Returns the crates that are recorded in the metadata database.
+    pub async fn list(&self) -> Result<Vec<Entry>, rusqlite::Error> {
+        self.database.list().await
+    }
+
+    /// Pins the index to the commit that `revision` names so that synchronisations move the index
+    /// to it instead of the latest commit from the remote index. Returns the ID of the commit.
+    pub async fn pin(&self, revision: String) -> Result<Oid, git2::Error> {
+        self.index.pin(revision).await
+    }
+
+    /// Pins the index to the commit that it held before the last update. Returns the ID of the
+    /// commit or nothing if no update has been applied.
+    pub async fn rollback(&self) -> Result<Option<Oid>, git2::Error> {
+        match self.index.previous().await? {
+            Some(previous) => self.index.pin(previous.to_string()).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Releases the pin on the index. Returns false if the index was not pinned.
+    pub async fn unpin(&self) -> Result<bool, git2::Error> {
+        self.index.unpin().await
+    }
+
+    /// Records the index commit that the cache holds and hard links every stored crate into a
+    /// snapshot called `name`. Crates that can not be linked are copied.
+    pub async fn create_snapshot(&self, name: &str) -> Result<Snapshot, SnapshotError> {
+        snapshot::create(
+            &self.snapshots_path(),
+            &self.crates_path(),
+            name,
+            self.index.head().await?.to_string(),
+            self.layout,
+            archive::timestamp(SystemTime::now()),
+        )
+        .await
+    }
+
+    /// Returns the snapshots of the cache ordered by the time that they were taken.
+    pub async fn snapshots(&self) -> Result<Vec<Snapshot>, SnapshotError> {
+        snapshot::list(&self.snapshots_path()).await
+    }
+
+    /// Links the crates of the snapshot called `name` that are missing from the crates directory
+    /// back into it and pins the index to the commit that the snapshot recorded. The cache must
+    /// be synchronised afterwards to remove the crates that were added since.
+    pub async fn restore_snapshot(&self, name: &str) -> Result<(Snapshot, Linked), SnapshotError> {
+        let snapshots = self.snapshots_path();
+        let snapshot = snapshot::find(&snapshots, name).await?;
+        if snapshot.layout != self.layout {
+            return Err(SnapshotError::LayoutMismatch {
+                snapshot: snapshot.layout,
+                cache: self.layout,
+            });
+        }
+
+        let linked = snapshot::restore(&snapshots, &self.crates_path(), &snapshot).await?;
+        self.index.pin(snapshot.commit.clone()).await?;
+        Ok((snapshot, linked))
+    }
+
+    /// Returns the crates that were added, removed, or changed between the snapshot called `from`
+    /// and the snapshot called `to`, or the cache if there is none.
+    ///
+    /// The crates of each state are those that the index held at its commit and that are stored
+    /// in its crates directory.
+    pub async fn diff_snapshots(
+        &self,
+        from: &str,
+        to: Option<&str>,
+    ) -> Result<Vec<Difference>, SnapshotError> {
+        let snapshots = self.snapshots_path();
+        let from = snapshot::find(&snapshots, from).await?;
+        let to = match to {
+            Some(to) => {
+                let to = snapshot::find(&snapshots, to).await?;
+                let directory = snapshot::crates_path(&snapshots, &to.name)?;
+                (Oid::from_str(&to.commit)?, directory, to.layout)
+            }
+            None => (self.index.head().await?, self.crates_path(), self.layout),
+        };
+
+        let mut stored = Vec::with_capacity(2);
+        for (commit, directory, layout) in [
+            (
+                Oid::from_str(&from.commit)?,
+                snapshot::crates_path(&snapshots, &from.name)?,
+                from.layout,
+            ),
+            to,
+        ] {
+            let crates = self.index.crates_at(commit).await?;
+            stored.push(snapshot::stored(directory, layout, crates).await?);
+        }
+
+        Ok(snapshot::compare(&stored[0], &stored[1]))
+    }
+
+    /// Returns the crates that were pinned to an IPFS node with their content identifiers.
+    pub async fn pins(&self) -> Result<Vec<Pin>, rusqlite::Error> {
+        self.database.pins().await
+    }
+
+    /// Returns a software bill of materials that describes every crate that is stored in the
+    /// cache with its checksum. The document is dated `time`.
+    pub async fn bill_of_materials(
+        &self,
+        jobs: NonZeroUsize,
+        time: u64,
+    ) -> Result<Bom, RefreshCacheError> {
+        let configuration = &self.index.configuration().await?;
+
+        let components = self
+            .directories(&Scope::default())
+            .map_ok(|directory| {
+                stream::iter(
+                    directory
+                        .packages
+                        .into_iter()
+                        .flat_map(Package::into_crates)
+                        .map(Ok),
+                )
+            })
+            .try_flatten()
+            .map_ok(|each| async move {
+                if !self.is_stored(&each).await? {
+                    return Ok(None);
+                }
+
+                let url = configuration.locate(&each)?;
+                Ok::<_, RefreshCacheError>(Some(Component::new(&each, &url)))
+            })
+            .try_buffer_unordered(jobs.get())
+            .try_filter_map(|component| async move { Ok(component) })
+            .try_collect()
+            .await?;
+
+        Ok(Bom::new(components, time))
+    }
+
+    /// Returns a Metalink document that describes every crate that is stored in the cache with its
+    /// checksum. Each crate can be downloaded from the crates directories of `mirrors`, in order,
+    /// and then from the registry. The document is dated `time`.
+    pub async fn metalink(
+        &self,
+        jobs: NonZeroUsize,
+        mirrors: &[Url],
+        time: u64,
+    ) -> Result<Metalink, RefreshCacheError> {
+        let configuration = &self.index.configuration().await?;
+
+        let files = self
+            .directories(&Scope::default())
+            .map_ok(|directory| {
+                stream::iter(
+                    directory
+                        .packages
+                        .into_iter()
+                        .flat_map(Package::into_crates)
+                        .map(Ok),
+                )
+            })
+            .try_flatten()
+            .map_ok(|each| async move {
+                let location = self.locate_crate(&each);
+
+                // The size of a compressed crate is only known once it is decompressed.
+                let size = match storage::form(&location).await? {
+                    Some(Form::Plain) => Some(fs::metadata(&location).await?.len()),
+                    Some(Form::Compressed) => None,
+                    None => return Ok(None),
+                };
+
+                let urls = self
+                    .downloads(configuration, &each, mirrors)?
+                    .into_iter()
+                    .map(|(_, download)| download.url)
+                    .collect();
+
+                Ok::<_, RefreshCacheError>(Some(metalink::File::new(&each, size, urls)))
+            })
+            .try_buffer_unordered(jobs.get())
+            .try_filter_map(|file| async move { Ok(file) })
+            .try_collect()
+            .await?;
+
+        Ok(Metalink::new(files, time))
+    }
+
+    /// Copies every crate that is stored in the cache and selected by `filter` to `output` with
+    /// the names that the registry cache of Cargo uses (eg. `serde-1.0.0.crate`). Compressed
+    /// crates are decompressed. If there is a `source` directory, the crates are also unpacked into
+    /// it like Cargo unpacks crates into `~/.cargo/registry/src`.
+    ///
+    /// Crates that were already copied or unpacked are skipped so that an export can be repeated to
+    /// only export the crates that were downloaded since. Crates that can not be unpacked are
+    /// reported and skipped.
+    pub async fn export_cargo(
+        &self,
+        jobs: NonZeroUsize,
+        filter: &Filter,
+        output: &Path,
+        source: Option<&Path>,
+    ) -> Result<Exported, RefreshCacheError> {
+        let selection = filter.resolve(&self.index, &self.database).await?;
+
+        file::create_dir_all(output, Permissions::default()).await?;
+        if let Some(source) = source {
+            file::create_dir_all(source, Permissions::default()).await?;
+        }
+
+        self.selected(&Scope::default(), &selection)
+            .map_ok(|each| async move {
+                let mut exported = Exported::default();
+                let destination =
+                    output.join(Layout::Cargo.locate(&each.name, &each.version, &each.checksum));
+                let copy = !fs::try_exists(&destination).await?;
+                let unpack = match source {
+                    Some(source) => !extract::is_unpacked(source, &each).await?,
+                    None => false,
+                };
+                if !copy && !unpack {
+                    return Ok(exported);
+                }
+
+                let Some(bytes) = storage::load(&self.locate_crate(&each)).await? else {
+                    return Ok(exported);
+                };
+
+                if copy {
+                    file::write(
+                        &destination,
+                        bytes.clone(),
+                        Durability::Rename,
+                        Permissions::default(),
+                    )
+                    .await?;
+                    exported.copied += 1;
+                }
+
+                if let (Some(source), true) = (source, unpack) {
+                    match extract::unpack(bytes, source, &each).await {
+                        Ok(()) => exported.unpacked += 1,
+                        Err(error) => {
+                            warn!("failed to unpack {} {}: {}", each.name, each.version, error);
+                        }
+                    }
+                }
+
+                Ok::<_, RefreshCacheError>(exported)
+            })
+            .try_buffer_unordered(jobs.get())
+            .try_fold(Exported::default(), |total, exported| async move {
+                Ok(Exported {
+                    copied: total.copied + exported.copied,
+                    unpacked: total.unpacked + exported.unpacked,
+                })
+            })
+            .await
+    }
+
+    /// Writes a bundle to `path` that holds the index commits between `from` and `to` and the
+    /// crates that they published or modified. `to` is the latest commit of the index unless it is
+    /// given. Returns the number of crates in the bundle.
+    ///
+    /// Crates that are not stored in the cache are left out of the bundle.
+    pub async fn bundle(
+        &self,
+        from: Oid,
+        to: Option<Oid>,
+        path: &Path,
+    ) -> Result<usize, BundleError> {
+        let to = match to {
+            Some(to) => to,
+            None => self.index.head().await?,
+        };
+
+        let changes = self.index.changes(from, to).await?;
+        let mut writer = bundle::Writer::create(path, self.index.bundle(from, to).await?).await?;
+
+        let mut count = 0;
+        for change in changes {
+            let published = match change.kind {
+                ChangeKind::Added | ChangeKind::Modified => true,
+                // The crate may have been skipped while it was yanked.
+                ChangeKind::YankStatusChanged => !change.on.yanked,
+                ChangeKind::Removed => false,
+            };
+
+            if !published {
+                continue;
+            }
+
+            if let Some(bytes) = storage::load(&self.locate_crate(&change.on)).await? {
+                writer.append(&change.on, bytes).await?;
+                count += 1;
+            }
+        }
+
+        writer.finish(path).await?;
+        Ok(count)
+    }
+
+    /// Returns true if the metadata database records that a crate has not changed since its
+    /// integrity was last checked.
+    async fn is_unchanged(&self, item: &Crate) -> bool {
+        let stat = match storage::stat(&self.locate_crate(item)).await {
+            Ok(Some(stat)) => stat,
+            Ok(None) => return false,
+            Err(error) => {
+                warn!("failed to get the metadata of a crate: {}", error);
+                return false;
+            }
+        };
+
+        self.database
+            .is_unchanged(item, stat)
+            .await
+            .unwrap_or_else(|error| {
+                warn!("failed to query metadata: {}", error);
+                false
+            })
+    }
+
+    /// Returns the file system metadata of a stored crate or nothing if it is not stored.
+    async fn stat(&self, item: &Crate) -> Option<storage::Stat> {
+        match storage::stat(&self.locate_crate(item)).await {
+            Ok(stat) => stat,
+            Err(error) => {
+                warn!("failed to get the metadata of a crate: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns true if a crate with the file system metadata `stat` has the size that was recorded
+    /// in the metadata database or nothing if no size was recorded.
+    async fn matches_recorded_size(&self, item: &Crate, stat: storage::Stat) -> Option<bool> {
+        match self.database.size(item).await {
+            Ok(size) => size.map(|size| size == stat.size),
+            Err(error) => {
+                warn!("failed to query metadata: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns true if a crate is stored with the size that was recorded in the metadata database.
+    async fn has_recorded_size(&self, item: &Crate) -> bool {
+        match self.stat(item).await {
+            Some(stat) => self.matches_recorded_size(item, stat).await == Some(true),
+            None => false,
+        }
+    }
+
+    /// Returns true if a crate has the size that was recorded in the metadata database or, if it is
+    /// not recorded, the size that is reported by the registry.
+    async fn has_expected_size(
+        &self,
+        download: &Download,
+        item: &Crate,
+        client: &Client,
+        connections: &download::Connections,
+    ) -> bool {
+        let Some(stat) = self.stat(item).await else {
+            return false;
+        };
+
+        if let Some(matches) = self.matches_recorded_size(item, stat).await {
+            return matches;
+        }
+
+        let location = self.locate_crate(item);
+
+        // The registry reports the size of the crate as it was downloaded.
+        if !matches!(storage::form(&location).await, Ok(Some(Form::Plain))) {
+            return false;
+        }
+
+        let _connection = connections.acquire().await;
+        match download.length(client).await {
+            Ok(length) => length == Some(stat.size),
+            Err(error) => {
+                debug!("failed to request length: {}", error);
+                false
+            }
+        }
+    }
+
+    /// Computes the digest of a crate in the form that it is stored in with `algorithm`. Nothing is
+    /// returned if the crate is not stored.
+    async fn local_digest(
+        &self,
+        item: &Crate,
+        algorithm: Algorithm,
+        pool: &digest::Pool,
+    ) -> Option<Digest> {
+        match storage::read_stored(&self.locate_crate(item)).await {
+            Ok(Some(bytes)) => Some(pool.digest(algorithm, bytes).await.1),
+            Ok(None) => None,
+            Err(error) => {
+                warn!("failed to read a crate: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Returns the digest of a crate if it matches the digest that is recorded in the manifest.
+    ///
+    /// Only crates that are stored in the configured storage are checked so that crates are still
+    /// converted when their integrity is checked.
+    async fn check_manifest(&self, item: &Crate, settings: &Settings) -> Option<Digest> {
+        let recorded = match self.database.digest(item).await {
+            Ok(recorded) => recorded?,
+            Err(error) => {
+                warn!("failed to query metadata: {}", error);
+                return None;
+            }
+        };
+
+        let expected = match settings.download.storage {
+            Storage::Plain => Form::Plain,
+            Storage::Zstd => Form::Compressed,
+        };
+
+        if !matches!(storage::form(&self.locate_crate(item)).await, Ok(Some(form)) if form == expected)
+        {
+            return None;
+        }
+
+        let digest = self
+            .local_digest(item, recorded.algorithm(), &settings.hashing)
+            .await?;
+        (digest == recorded).then_some(digest)
+    }
+
+    /// Records the outcome of fetching a crate in the metadata database. The metadata database is
+    /// advisory so failures to record are reported and otherwise ignored.
+    async fn record(
+        &self,
+        item: &Crate,
+        outcome: Result<Transfer, &download::Error>,
+        pool: &digest::Pool,
+    ) {
+        match outcome {
+            Ok(Transfer::Preserved) => (),
+            Ok(transfer) => {
+                // Crates that were digested as they were written are not read again.
+                let digest = match &transfer {
+                    Transfer::Downloaded {
+                        digest: Some(digest),
+                        ..
+                    } => Some(*digest),
+                    _ => {
+                        self.local_digest(item, Self::MANIFEST_ALGORITHM, pool)
+                            .await
+                    }
+                };
+
+                if let Some(digest) = digest {
+                    self.record_intact(item, transfer, digest).await;
+                }
+            }
+
+            Err(error) => {
+                let time = archive::timestamp(SystemTime::now());
+                if let Err(error) = self
+                    .database
+                    .record_failure(item, error.to_string(), time)
+                    .await
+                {
+                    warn!("failed to record metadata: {}", error);
+                }
+            }
+        }
+    }
+
+    /// Records an attempt to download a crate that finished with `result` after `duration` in the
+    /// audit log. Crates that were already stored are not downloaded so they are not recorded.
+    async fn audit(
+        &self,
+        item: &Crate,
+        download: &Download,
+        result: &Result<Transfer, download::Error>,
+        duration: Duration,
+    ) {
+        let outcome = match result {
+            Ok(Transfer::Downloaded { size, .. }) => Ok(*size),
+            Ok(Transfer::Preserved | Transfer::Verified) => return,
+            Err(error) => Err(error),
+        };
+
+        let record = Record::new(
+            item,
+            download.url.to_string(),
+            outcome,
+            duration,
+            archive::timestamp(SystemTime::now()),
+        );
+
+        if let Err(error) = self.audit.record(&record).await {
+            warn!("failed to record the download in the audit log: {}", error);
+        }
+    }
+
+    /// Appends `changes` to the feed and writes it to the cache. The feed is advisory so failures
+    /// to write it are reported and otherwise ignored.
+    async fn publish(
+        &self,
+        feed: &Feed,
+        changes: Vec<(Crate, feed::Kind)>,
+        permissions: Permissions,
+    ) {
+        let time = archive::timestamp(SystemTime::now());
+        let entries = match self
+            .database
+            .record_changes(changes, time, feed.entries.get())
+            .await
+        {
+            Ok(()) => self.database.changes().await,
+            Err(error) => Err(error),
+        };
+
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(error) => {
+                warn!("failed to record the feed: {}", error);
+                return;
+            }
+        };
+
+        let atom = Atom {
+            url: &feed.url,
+            entries: &entries,
+            time,
+        };
+
+        match file::write(
+            &self.path.join(Self::FEED_FILENAME),
+            atom.to_string().into(),
+            Durability::Rename,
+            permissions,
+        )
+        .await
+        {
+            Ok(()) => debug!("wrote the feed"),
+            Err(error) => warn!("failed to write the feed: {}", error),
+        }
+    }
+
+    /// Records that a crate whose stored form has `digest` was downloaded or verified.
+    async fn record_intact(&self, item: &Crate, transfer: Transfer, digest: Digest) {
+        let time = archive::timestamp(SystemTime::now());
+        let stat = match storage::stat(&self.locate_crate(item)).await {
+            Ok(Some(stat)) => stat,
+            Ok(None) => return,
+            Err(error) => {
+                warn!("failed to get the metadata of a crate: {}", error);
+                return;
+            }
+        };
+
+        let result = match transfer {
+            Transfer::Downloaded { .. } => {
+                self.database
+                    .record_download(item, stat, digest, time)
+                    .await
+            }
+            Transfer::Verified | Transfer::Preserved => {
+                self.database
+                    .record_verification(item, stat, digest, time)
+                    .await
+            }
+        };
+
+        if let Err(error) = result {
+            warn!("failed to record metadata: {}", error);
+        }
+    }
+
+    /// Reads a crate from the cache and decompresses it if necessary. Nothing is returned if the
+    /// crate is not in the cache.
+    ///
+    /// The crate is found in the index so that it can be located in any layout.
+    ///
+    /// If `check` is true, a crate that has changed since its integrity was last checked is hashed
+    /// before it is returned and the manifest is trusted afterwards. A crate that does not match
+    /// its checksum is never returned; it is moved to the quarantine directory with `permissions`
+    /// and downloaded again by the next synchronisation.
+    pub async fn read(
+        &self,
+        name: &str,
+        version: &str,
+        check: bool,
+        permissions: Permissions,
+    ) -> Result<Option<Vec<u8>>, ReadCrateError> {
+        let item = self
+            .index
+            .package(name.to_owned(), false)
+            .await?
+            .and_then(|package| {
+                package
+                    .into_crates()
+                    .find(|each| each.name == name && each.version == version)
+            });
+
+        let Some(item) = item else {
+            return Ok(None);
+        };
+
+        let Some(bytes) = storage::load(&self.locate_crate(&item)).await? else {
+            return Ok(None);
+        };
+
+        if !check || self.is_unchanged(&item).await {
+            return Ok(Some(bytes));
+        }
+
+        let pool = &digest::Pool::new(NonZeroUsize::MIN);
+        let (bytes, digest) = pool.digest(item.checksum.algorithm(), bytes).await;
+        if digest != item.checksum {
+            self.quarantine(&item, permissions).await?;
+            return Err(ReadCrateError::Corrupt {
+                name: item.name,
+                version: item.version,
+            });
+        }
+
+        if let Some(digest) = self
+            .local_digest(&item, Self::MANIFEST_ALGORITHM, pool)
+            .await
+        {
+            self.record_intact(&item, Transfer::Verified, digest).await;
+        }
+
+        Ok(Some(bytes))
+    }
+
+    /// Deletes a crate and downloads it again. Nothing is returned if the crate is not in the
+    /// index.
+    ///
+    /// The crate is checked against its checksum when it is downloaded. A crate that can not be
+    /// downloaded again remains deleted and the cache is no longer consistent with its index, so
+    /// the next synchronisation downloads it.
+    pub async fn refetch(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<Refetched>, RefreshCacheError> {
+        let item = self
+            .index
+            .package(name.to_owned(), false)
+            .await?
+            .and_then(|package| {
+                package
+                    .into_crates()
+                    .find(|each| each.name == name && each.version == version)
+            });
+
+        let Some(item) = item else {
+            return Ok(None);
+        };
+
+        let location = self.locate_crate(&item);
+        let old = match storage::load(&location).await? {
+            Some(bytes) => Some(
+                settings
+                    .hashing
+                    .digest(item.checksum.algorithm(), bytes)
+                    .await
+                    .1,
+            ),
+            None => None,
+        };
+
+        // The cache is no longer consistent with the index while the crate is missing, so the next
+        // synchronisation downloads it if it can not be downloaded again now.
+        let consistency = self.path.join(Self::CONSISTENCY_FILENAME);
+        let consistent = self.is_consistent(settings).await?;
+        consistency::clear(&consistency).await?;
+
+        storage::remove(&location).await?;
+        debug!("deleted the stored crate");
+
+        let configuration = &self.index.configuration().await?;
+        self.fetch(configuration, &item, client, settings, &Tally::default())
+            .await?;
+
+        if !self.is_stored(&item).await? {
+            return Ok(Some(Refetched { old, new: None }));
+        }
+
+        if consistent {
+            consistency::record(
+                &consistency,
+                self.index.head().await?.to_string(),
+                settings.filter.to_string(),
+            )
+            .await?;
+        }
+
+        if let Err(error) = self
+            .database
+            .release_quarantine(item.name.clone(), item.version.clone())
+            .await
+        {
+            warn!("failed to record metadata: {}", error);
+        }
+
+        Ok(Some(Refetched {
+            old,
+            new: Some(item.checksum),
+        }))
+    }
+
+    /// Returns the dependencies of a crate in the index. Nothing is returned if the crate is not in
+    /// the index.
+    pub async fn dependencies(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<Box<[Dependency]>>, index::GetPackagesError> {
+        Ok(self
+            .index
+            .package(name.to_owned(), true)
+            .await?
+            .and_then(|package| {
+                package
+                    .into_crates()
+                    .find(|each| each.name == name && each.version == version)
+            })
+            .and_then(|item| item.dependencies))
+    }
+
+    /// Returns the names of the crates in the index that depend on the crate named `name`. Crates
+    /// that depend on it through other crates are included if `transitive` is true.
+    pub async fn dependents(
+        &self,
+        name: String,
+        transitive: bool,
+    ) -> Result<AHashSet<String>, index::GetPackagesError> {
+        self.index.dependents(vec![name], transitive).await
+    }
+
+    /// Moves a corrupt crate to the quarantine directory and queues it to be downloaded again.
+    async fn quarantine(
+        &self,
+        item: &Crate,
+        permissions: Permissions,
+    ) -> Result<(), ReadCrateError> {
+        // The crate is missing once it is moved so the next synchronisation must refresh the cache
+        // even if the crate is never restored from the quarantine.
+        consistency::clear(&self.path.join(Self::CONSISTENCY_FILENAME)).await?;
+        self.database
+            .record_quarantine(item, archive::timestamp(SystemTime::now()))
+            .await?;
+
+        let location = self.quarantine_path().join(self.layout.locate(
+            &item.name,
+            &item.version,
+            &item.checksum,
+        ));
+        file::create_dir_all(
+            location.parent().expect("file path must have a parent"),
+            permissions,
+        )
+        .await?;
+        storage::rename(&self.locate_crate(item), &location).await?;
+        warn!(
+            "quarantined {} {} because it does not match its checksum",
+            item.name, item.version
+        );
+
+        if let Err(error) = self.database.remove(item).await {
+            warn!("failed to record metadata: {}", error);
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the crates that were quarantined again. A crate is released from the quarantine
+    /// once it is stored or when it is no longer in the index.
+    async fn restore_quarantined(
+        &self,
+        client: &Client,
+        settings: &Settings,
+    ) -> Result<Outcome, RefreshCacheError> {
+        let quarantined = match self.database.quarantined().await {
+            Ok(quarantined) => quarantined,
+            Err(error) => {
+                warn!("failed to query metadata: {}", error);
+                return Ok(Outcome::default());
+            }
+        };
+
+        if quarantined.is_empty() {
+            return Ok(Outcome::default());
+        }
+
+        info!("downloading {} quarantined crates", quarantined.len());
+        let configuration = &self.index.configuration().await?;
+        let tally = &Tally::default();
+        for each in quarantined {
+            let item = self
+                .index
+                .package(each.name.clone(), false)
+                .await?
+                .and_then(|package| {
+                    package
+                        .into_crates()
+                        .find(|item| item.name == each.name && item.version == each.version)
+                });
+
+            if let Some(item) = &item {
+                self.fetch(configuration, item, client, settings, tally)
+                    .await?;
+                if !self.is_stored(item).await? {
+                    continue;
+                }
+            }
+
+            if let Err(error) = self
+                .database
+                .release_quarantine(each.name, each.version)
+                .await
+            {
+                warn!("failed to record metadata: {}", error);
+            }
+        }
+
+        Ok(tally.finish())
+    }
+
+    /// Downloads the crates that are selected by the forced selectors of `settings` again. Crates
+    /// that the filter does not select are not downloaded.
+    async fn redownload(
+        &self,
+        client: &Client,
+        settings: &Settings,
+    ) -> Result<Outcome, RefreshCacheError> {
+        if settings.force.is_empty() || self.index.is_empty().await? {
+            return Ok(Outcome::default());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        let selection = &settings.filter.resolve(&self.index, &self.database).await?;
+        let crates = self.matching(&settings.force, selection).await?;
+
+        info!("downloading {} forced crates again", crates.len());
+        let settings = &Settings {
+            download: download::Options {
+                preserve: PreservationStrategy::Never,
+                ..settings.download
+            },
+            ..settings.clone()
+        };
+        self.fetch_all(configuration, crates, client, settings)
+            .await
+    }
+
+    /// Verifies only the crates that `selectors` select instead of every crate in the index.
+    /// Crates that the filter does not select are not verified.
+    ///
+    /// The integrity of every selected crate is checked even if it has not changed since it was
+    /// last checked. Crates that are missing or corrupt are downloaded again.
+    pub async fn verify_crates(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        selectors: &[Selector],
+    ) -> Result<Outcome, RefreshCacheError> {
+        if self.index.is_empty().await? {
+            return Ok(Outcome::default());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        let selection = &settings.filter.resolve(&self.index, &self.database).await?;
+        let crates = self.matching(selectors, selection).await?;
+
+        info!("verifying {} crates", crates.len());
+        let settings = &Settings {
+            deep: true,
+            ..settings.clone()
+        };
+        self.fetch_all(configuration, crates, client, settings)
+            .await
+    }
+
+    /// Returns the crates that are selected by `selection` and by any of `selectors`.
+    async fn matching(
+        &self,
+        selectors: &[Selector],
+        selection: &Selection,
+    ) -> Result<Vec<Crate>, RefreshCacheError> {
+        let selected = |item: &Crate| selectors.iter().any(|each| each.selects(item));
+
+        // Selectors that name a crate are looked up directly instead of reading the whole index.
+        let names = selectors
+            .iter()
+            .map(Selector::name)
+            .collect::<Option<AHashSet<_>>>();
+        match names {
+            Some(names) => {
+                let mut crates = Vec::new();
+                for name in names {
+                    if let Some(package) = self.index.package(name.to_owned(), false).await? {
+                        crates.extend(selection.retain(package).into_iter().filter(selected));
+                    }
+                }
+
+                Ok(crates)
+            }
+            None => {
+                self.selected(&Scope::default(), selection)
+                    .try_filter(|item| future::ready(selected(item)))
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+
+    /// Fetches `crates` as [`Self::fetch`] does and returns the outcome.
+    async fn fetch_all(
+        &self,
+        configuration: &Configuration,
+        crates: Vec<Crate>,
+        client: &Client,
+        settings: &Settings,
+    ) -> Result<Outcome, RefreshCacheError> {
+        let tally = &Tally::default();
+        stream::iter(crates)
+            .map(|item| async move {
+                let span = info_span!(
+                    "download",
+                    name = item.name.as_str(),
+                    version = item.version.as_str(),
+                    bytes = field::Empty,
+                    attempts = field::Empty
+                );
+                self.fetch(configuration, &item, client, settings, tally)
+                    .instrument(span)
+                    .await
+            })
+            .buffer_unordered(settings.concurrency())
+            .try_collect::<()>()
+            .await?;
+
+        Ok(tally.finish())
+    }
+
+    /// Creates a download for a crate from the registry that carries the credentials of the
+    /// registry.
+    fn download(
+        &self,
+        configuration: &Configuration,
+        item: &Crate,
+    ) -> Result<Download, TemplateUrlError> {
+        let url = configuration.locate(item)?;
+        let destination = self.locate_crate(item);
+
+        Ok(Download {
+            url,
+            destination,
+            checksum: item.checksum,
+            digest: Some(Self::MANIFEST_ALGORITHM),
+            authorization: self.authorization.clone(),
+        })
+    }
+
+    /// Returns the downloads for a crate from each source in the order that they are tried. Each
+    /// download is labelled with its source. Parent mirrors are tried before the registry.
+    fn downloads(
+        &self,
+        configuration: &Configuration,
+        item: &Crate,
+        parents: &[Url],
+    ) -> Result<Vec<(String, Download)>, TemplateUrlError> {
+        let mut downloads = parents
+            .iter()
+            .map(|parent| {
+                let parent = Configuration {
+                    template: parent.as_str().trim_end_matches('/').to_owned(),
+                    api: None,
+                    auth_required: false,
+                };
+
+                let download = Download {
+                    authorization: None,
+                    ..self.download(&parent, item)?
+                };
+                Ok((parent.template, download))
+            })
+            .collect::<Result<Vec<_>, TemplateUrlError>>()?;
+
+        downloads.push((
+            configuration.template.clone(),
+            self.download(configuration, item)?,
+        ));
+        Ok(downloads)
+    }
+
+    /// Returns the verification of the signature of a crate or nothing if there is no verifier.
+    fn signature(settings: &Settings, item: &Crate) -> Option<Signature> {
+        settings.verifier.as_ref().map(|verifier| Signature {
+            verifier: verifier.clone(),
+            variables: vec![
+                ("CRATEFUL_NAME", item.name.clone()),
+                ("CRATEFUL_VERSION", item.version.clone()),
+                ("CRATEFUL_CHECKSUM", hex::encode(item.checksum.as_bytes())),
+            ],
+        })
+    }
+
+    /// Downloads a crate.
+    ///
+    /// Parent mirrors are tried in order before the registry. Failures that are known to be caused
+    /// by inconsistencies in the registry are tolerated unless the failure mode is strict.
+    /// Tolerated failures are reported and counted in `tally`.
+    #[allow(clippy::too_many_lines)]
+    async fn fetch(
+        &self,
+        configuration: &Configuration,
+        item: &Crate,
+        client: &Client,
+        settings: &Settings,
+        tally: &Tally,
+    ) -> Result<(), FetchError> {
+        let _handling = notify::Handling::start();
+        tally.check();
+
+        if settings.download.preserve == PreservationStrategy::Checksum
+            && !settings.deep
+            && self.is_unchanged(item).await
+        {
+            debug!("skipped integrity checking of an unchanged crate");
+            return Ok(());
+        }
+
+        if settings.download.preserve == PreservationStrategy::Checksum {
+            if let Some(digest) = self.check_manifest(item, settings).await {
+                debug!("checked integrity against the manifest");
+                self.record_intact(item, Transfer::Verified, digest).await;
+                return Ok(());
+            }
+        }
+
+        if settings.download.preserve == PreservationStrategy::Size
+            && self
+                .has_expected_size(
+                    &self.download(configuration, item)?,
+                    item,
+                    client,
+                    &settings.connections,
+                )
+                .await
+        {
+            debug!("skipped integrity checking of a crate with the expected size");
+            return Ok(());
+        }
+
+        if settings.download.preserve == PreservationStrategy::RecordedSize
+            && self.has_recorded_size(item).await
+        {
+            debug!("skipped integrity checking of a crate with the recorded size");
+            return Ok(());
+        }
+
+        let mut downloads = self
+            .downloads(configuration, item, &settings.parents)?
+            .into_iter()
+            .peekable();
+
+        let signature = Self::signature(settings, item);
+        let mut attempts = 0_u32;
+        while let Some((source, download)) = downloads.next() {
+            attempts += 1;
+            Span::current().record("attempts", attempts);
+
+            let start = Instant::now();
+            let result = download
+                .run(
+                    client,
+                    settings.download,
+                    &settings.hashing,
+                    &settings.connections,
+                    signature.as_ref(),
+                )
+                .await;
+
+            self.audit(item, &download, &result, start.elapsed()).await;
+
+            let error = match result {
+                Ok(transfer) => {
+                    let downloaded = matches!(transfer, Transfer::Downloaded { .. });
+                    if let Transfer::Downloaded {
+                        validators, size, ..
+                    } = &transfer
+                    {
+                        tally.record(&source, true);
+                        tally.download(*size);
+
+                        if let Err(error) = self
+                            .database
+                            .record_validators(item, download.url.to_string(), validators.clone())
+                            .await
+                        {
+                            warn!("failed to record metadata: {}", error);
+                        }
+                    }
+
+                    self.record(item, Ok(transfer), &settings.hashing).await;
+                    if downloaded {
+                        self.distribute(item, client, settings).await;
+                    }
+
+                    return Ok(());
+                }
+                Err(error) => error,
+            };
+
+            // Failures to write the crate do not depend on the source.
+            let local = matches!(error, download::Error::Io { source: _, path: _ });
+            if !local {
+                tally.record(&source, false);
+
+                // A crate has only failed when it can not be downloaded from any source.
+                if downloads.peek().is_none() {
+                    trace!(target: HTTP, "not retrying because every source has failed");
+                    self.record(item, Err(&error), &settings.hashing).await;
+                }
+            }
+
+            match &error {
+                _ if local => trace!(target: HTTP, "not retrying a failure to write the crate"),
+                _ if downloads.peek().is_some() => {
+                    debug!("failed to download from {}: {}", source, error);
+                    trace!(target: HTTP, "retrying from the next source after {}", source);
+                    continue;
+                }
+                // There are crates in the crates.io index and registry with inconsistent
+                // checksums.
+                download::Error::ChecksumMismatch { url: _ }
+                // Crates that are not signed by the registry are not mirrored.
+                | download::Error::SignatureRejected { url: _, reason: _ }
+                // There are known issues with crates.io where it will respond with unsuccessful
+                // HTTP statuses (eg. 403) for crates that are listed in the index.
+                | download::Error::Http { status: _, url: _ }
+                    if settings.mode == FailureMode::Continue =>
+                {
+                    warn!("{}", error);
+                    tally.fail();
+                    return Ok(());
+                }
+                _ => (),
+            }
+
+            return Err(CrateDownloadError {
+                source: error,
+                name: item.name.clone(),
+                version: item.version.clone(),
+            }
+            .into());
+        }
+
+        unreachable!("the registry is always a source")
+    }
+
+    /// Downloads a crate or takes it from the unpacked bundle at `bundle` if one is given.
+    async fn obtain(
+        &self,
+        configuration: &Configuration,
+        item: &Crate,
+        client: &Client,
+        settings: &Settings,
+        tally: &Tally,
+        bundle: Option<&Path>,
+    ) -> Result<(), UpdateError> {
+        let Some(bundle) = bundle else {
+            return Ok(self
+                .fetch(configuration, item, client, settings, tally)
+                .await?);
+        };
+
+        let _handling = notify::Handling::start();
+        tally.check();
+        let path = bundle.join(bundle::crate_path(item));
+        let error = match file::read(&path).await {
+            Ok(bytes) => {
+                let size = bytes.len() as u64;
+                let (bytes, digest) = settings
+                    .hashing
+                    .digest(item.checksum.algorithm(), bytes)
+                    .await;
+
+                if digest == item.checksum {
+                    let location = self.locate_crate(item);
+                    file::create_dir_all(
+                        location.parent().expect("file path must have a parent"),
+                        settings.download.permissions,
+                    )
+                    .await?;
+                    storage::store(
+                        &location,
+                        bytes,
+                        settings.download.storage,
+                        settings.download.durability,
+                        settings.download.permissions,
+                        &settings.hashing,
+                    )
+                    .await?;
+
+                    tally.download(size);
+                    let transfer = Transfer::Downloaded {
+                        validators: Validators::default(),
+                        size,
+                        digest: None,
+                    };
+                    self.record(item, Ok(transfer), &settings.hashing).await;
+                    self.distribute(item, client, settings).await;
+                    debug!("took the crate from the bundle");
+                    return Ok(());
+                }
+
+                download::Error::Io {
+                    source: io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the bundled crate does not have the expected checksum",
+                    ),
+                    path,
+                }
+            }
+
+            // A crate that was already stored does not need to be bundled.
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                if self.is_stored(item).await? {
+                    return Ok(());
+                }
+
+                download::Error::Io {
+                    source: error,
+                    path,
+                }
+            }
+
+            Err(error) => return Err(error.into()),
+        };
+
+        self.record(item, Err(&error), &settings.hashing).await;
+        if settings.mode == FailureMode::Continue {
+            warn!(
+                "{} {} could not be taken from the bundle: {}",
+                item.name, item.version, error
+            );
+            tally.fail();
+            return Ok(());
+        }
+
+        Err(CrateDownloadError {
+            source: error,
+            name: item.name.clone(),
+            version: item.version.clone(),
+        }
+        .into())
+    }
+
+    /// Pins a crate that was stored in the cache to the IPFS node of `settings` and pushes it to
+    /// the OCI repository of `settings` if there are any. Failures are reported without failing
+    /// the download because the crate is still mirrored.
+    async fn distribute(&self, item: &Crate, client: &Client, settings: &Settings) {
+        if settings.ipfs.is_none() && settings.oci.is_none() {
+            return;
+        }
+
+        let bytes = match storage::load(&self.locate_crate(item)).await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return,
+            Err(error) => {
+                warn!("failed to read a crate to distribute: {}", error);
+                return;
+            }
+        };
+
+        if let Some(ipfs) = &settings.ipfs {
+            let name = format!("{}-{}.crate", item.name, item.version);
+            match ipfs.pin(client, name, bytes.clone()).await {
+                Ok(cid) => {
+                    debug!("pinned the crate as {}", cid);
+                    if let Err(error) = self.database.record_pin(item, cid).await {
+                        warn!("failed to record metadata: {}", error);
+                    }
+                }
+                Err(error) => warn!("failed to pin {} {}: {}", item.name, item.version, error),
+            }
+        }
+
+        if let Some(oci) = &settings.oci {
+            match oci.push(client, item, bytes, &settings.hashing).await {
+                Ok(()) => debug!("pushed the crate to the OCI registry"),
+                Err(error) => warn!("failed to push {} {}: {}", item.name, item.version, error),
+            }
+        }
+    }
+
+    /// Returns true if the registry reports that a stored crate has not changed since it was
+    /// downloaded. The crate is requested conditionally with the validators that it was downloaded
+    /// with, which are only used while the stored crate is the crate that was downloaded and the
+    /// index still has the checksum that it was recorded with.
+    async fn is_not_modified(
+        &self,
+        configuration: &Configuration,
+        item: &Crate,
+        client: &Client,
+        settings: &Settings,
+    ) -> Result<bool, TemplateUrlError> {
+        let download = self.download(configuration, item)?;
+        let recorded = match self.database.revalidation(item).await {
+            Ok(Some(recorded)) if recorded.url == download.url.as_str() => recorded,
+            Ok(_) => return Ok(false),
+            Err(error) => {
+                warn!("failed to query metadata: {}", error);
+                return Ok(false);
+            }
+        };
+
+        // A registry that still serves the old crate does not make it match a new checksum.
+        if recorded.checksum != item.checksum {
+            debug!("the checksum of the crate has changed in the index");
+            return Ok(false);
+        }
+
+        if self
+            .local_digest(item, recorded.digest.algorithm(), &settings.hashing)
+            .await
+            != Some(recorded.digest)
+        {
+            debug!("the stored crate has changed since it was downloaded");
+            return Ok(false);
+        }
+
+        match download.is_not_modified(client, &recorded.validators).await {
+            Ok(not_modified) => Ok(not_modified),
+            Err(error) => {
+                debug!("failed to revalidate: {}", error);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Deletes or archives a crate if it exists. Returns the location of the crate.
+    async fn discard(
+        &self,
+        item: &Crate,
+        removal: RemovalStrategy,
+        permissions: Permissions,
+    ) -> Result<PathBuf, io::Error> {
+        let location = self.locate_crate(item);
+
+        // It's possible that this change was already operated on but not committed to the index.
+        let Some(form) = storage::form(&location).await? else {
+            return Ok(location);
+        };
+
+        match removal {
+            RemovalStrategy::Delete => storage::remove(&location).await?,
+            RemovalStrategy::Archive => {
+                let directory = self
+                    .archive_path()
+                    .join(item.name.as_str())
+                    .join(item.version.as_str());
+                file::create_dir_all(&directory, permissions).await?;
+                let destination = archive::destination(&directory, SystemTime::now()).await?;
+
+                // Archived crates are always stored as they were downloaded.
+                if form == Form::Plain {
+                    fs::rename(&location, &destination).await?;
+                } else {
+                    let bytes = storage::load(&location)
+                        .await?
+                        .expect("stored crate should exist");
+                    file::write(&destination, bytes, Durability::Rename, permissions).await?;
+                    storage::remove(&location).await?;
+                }
+
+                debug!("archived crate");
+            }
+        }
+
+        if let Err(error) = self.database.remove(item).await {
+            warn!("failed to record metadata: {}", error);
+        }
+
+        Ok(location)
+    }
+
+    /// Removes the crates in the archive that were archived at or before `before`. Returns the
+    /// number of crates that were removed.
+    pub async fn prune_archive(&self, before: SystemTime) -> Result<usize, PruneArchiveError> {
+        archive::prune(&self.archive_path(), before).await
+    }
+
+    /// Removes a crate and any obsoleted directories if they exist. Returns true if the crate was
+    /// stored.
+    async fn remove(
+        &self,
+        item: &Crate,
+        removal: RemovalStrategy,
+        permissions: Permissions,
+    ) -> Result<bool, UpdateError> {
+        let stored = self.is_stored(item).await?;
+        let location = self.discard(item, removal, permissions).await?;
+        prune_directories(
+            location.parent().expect("file path must have a parent"),
+            &self.path,
+        )
+        .await?;
+
+        Ok(stored)
+    }
+
+    /// Arranges a queue of crates, each tagged with the directory that holds it, in `order`.
+    async fn arrange<T: Send + Sync>(
+        &self,
+        queue: &mut Vec<(T, Crate)>,
+        order: Order,
+        client: &Client,
+        configuration: &Configuration,
+        jobs: NonZeroUsize,
+    ) -> Result<(), RefreshCacheError> {
+        match order {
+            Order::Index => (),
+
+            Order::Alphabetical => {
+                queue.sort_by(|(_, a), (_, b)| {
+                    (a.name.as_str(), a.version.as_str())
+                        .cmp(&(b.name.as_str(), b.version.as_str()))
+                });
+            }
+
+            Order::Recent => {
+                let times = self.index.modification_times().await?;
+                queue.sort_by_cached_key(|(_, each)| {
+                    Reverse(times.get(&each.name.to_lowercase()).copied())
+                });
+            }
+
+            Order::Smallest => {
+                let lengths = stream::iter(queue.iter())
+                    .map(|(_, each)| async move {
+                        match self.download(configuration, each)?.length(client).await {
+                            Ok(size) => Ok(size),
+                            Err(error) => {
+                                debug!("failed to request length: {}", error);
+                                Ok::<_, RefreshCacheError>(None)
+                            }
+                        }
+                    })
+                    .buffered(jobs.get())
+                    .try_collect::<Vec<_>>()
+                    .await?;
+
+                // Crates with an unknown size are downloaded last.
+                let mut sized = lengths
+                    .into_iter()
+                    .map(|size| size.unwrap_or(u64::MAX))
+                    .zip(queue.drain(..))
+                    .collect::<Vec<_>>();
+                sized.sort_by_key(|(size, _)| *size);
+                queue.extend(sized.into_iter().map(|(_, each)| each));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns a stream of the packages in `scope` that are held by HEAD of the index grouped by
+    /// the top-level directories that hold them.
+    ///
+    /// Packages are read from the listing in the metadata database if it was recorded when HEAD
+    /// was the same commit so that an unchanged index is not walked and parsed again. Otherwise,
+    /// they are read from the index and, if every package is in scope, the listing is recorded
+    /// again as they are read.
+    fn directories(
+        &self,
+        scope: &Scope,
+    ) -> impl Stream<Item = Result<Directory, RefreshCacheError>> + Send + 'static {
+        let index = self.index.clone();
+        let database = self.database.clone();
+        let scope = scope.clone();
+
+        stream::once(async move {
+            let head = match index.head().await {
+                Ok(head) => head.to_string(),
+                Err(error) => return stream::once(future::ready(Err(error.into()))).boxed(),
+            };
+
+            match database.listed_directories(head.clone()).await {
+                Ok(Some(directories)) => {
+                    debug!(head = head.as_str(), "read packages from the listing");
+                    return Self::listed(database, directories, scope).boxed();
+                }
+                Ok(None) => (),
+                Err(error) => warn!("failed to query the listing: {}", error),
+            }
+
+            let directories = index.directories(&scope).map_err(RefreshCacheError::from);
+
+            // Only a listing of every package can stand in for the index.
+            if !scope.is_everything() {
+                return directories.boxed();
+            }
+
+            if let Err(error) = database.clear_listing().await {
+                warn!("failed to clear the listing: {}", error);
+                return directories.boxed();
+            }
+
+            Self::record_listing(database, directories, head).boxed()
+        })
+        .flatten()
+    }
+
+    /// Returns a stream of the packages in `scope` that are in the listing grouped by the top-level
+    /// `directories` that hold them.
+    fn listed(
+        database: Database,
+        directories: Vec<String>,
+        scope: Scope,
+    ) -> impl Stream<Item = Result<Directory, RefreshCacheError>> + Send + 'static {
+        let directories = directories
+            .into_iter()
+            .filter(|name| scope.may_contain_directory(name))
+            .collect::<Vec<_>>();
+        notify::expect(directories.len());
+
+        stream::iter(directories).then(move |name| {
+            let database = database.clone();
+            let scope = scope.clone();
+            async move {
+                let crates = database.listing(name.clone()).await?;
+                let packages = crates
+                    .into_iter()
+                    .filter(|(package, _)| scope.contains(package))
+                    .group_by(|(package, _)| package.clone())
+                    .into_iter()
+                    .map(|(_, crates)| crates.map(|(_, each)| each).collect())
+                    .collect();
+
+                Ok(Directory { name, packages })
+            }
+        })
+    }
+
+    /// Records the listing of `directories` as they pass through the returned stream. The listing
+    /// is only marked as made at `head` once every directory was recorded.
+    fn record_listing(
+        database: Database,
+        directories: impl Stream<Item = Result<Directory, RefreshCacheError>> + Send + 'static,
+        head: String,
+    ) -> impl Stream<Item = Result<Directory, RefreshCacheError>> + Send + 'static {
+        let failed = Arc::new(AtomicBool::new(false));
+
+        directories
+            .then({
+                let database = database.clone();
+                let failed = failed.clone();
+                move |directory| {
+                    let database = database.clone();
+                    let failed = failed.clone();
+                    async move {
+                        let Ok(directory) = directory else {
+                            failed.store(true, Ordering::Relaxed);
+                            return directory;
+                        };
+
+                        if !failed.load(Ordering::Relaxed) {
+                            let crates = directory
+                                .packages
+                                .iter()
+                                .flat_map(Package::crates)
+                                .map(|each| (each.path(), each.clone()))
+                                .collect();
+
+                            if let Err(error) = database
+                                .record_listing(directory.name.clone(), crates)
+                                .await
+                            {
+                                warn!("failed to record the listing: {}", error);
+                                failed.store(true, Ordering::Relaxed);
+                            }
+                        }
+
+                        Ok(directory)
+                    }
+                }
+            })
+            .chain(
+                stream::once(async move {
+                    if failed.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    match database.record_listed(head.clone()).await {
+                        Ok(()) => debug!(head = head.as_str(), "recorded the listing"),
+                        Err(error) => warn!("failed to record the listing: {}", error),
+                    }
+                })
+                .filter_map(|()| future::ready(None)),
+            )
+    }
+
+    /// Returns a stream of every crate in `scope` regardless of whether it is selected.
+    fn crates(&self, scope: &Scope) -> impl Stream<Item = Result<Crate, RefreshCacheError>> + Send {
+        self.directories(scope)
+            .map_ok(|directory| {
+                stream::iter(
+                    directory
+                        .packages
+                        .into_iter()
+                        .flat_map(Package::into_crates)
+                        .map(Ok),
+                )
+            })
+            .try_flatten()
+    }
+
+    /// Returns a stream of the crates in `scope` that are selected by `selection`.
+    fn selected<'a>(
+        &self,
+        scope: &Scope,
+        selection: &'a Selection,
+    ) -> impl Stream<Item = Result<Crate, RefreshCacheError>> + Send + 'a {
+        self.directories(scope)
+            .map_ok(move |directory| {
+                stream::iter(
+                    directory
+                        .packages
+                        .into_iter()
+                        .flat_map(move |package| selection.retain(package))
+                        .map(Ok),
+                )
+            })
+            .try_flatten()
+    }
+
+    /// Plans a refresh of the cache without downloading any crates.
+    ///
+    /// Crates that are missing from the cache are counted as downloads. The integrity of crates
+    /// that are present is not checked.
+    pub async fn plan_refresh(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+        estimate: Estimate,
+    ) -> Result<Plan, RefreshCacheError> {
+        if self.index.is_empty().await? {
+            return Ok(Plan::default());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        let selection = settings.filter.resolve(&self.index, &self.database).await?;
+
+        self.selected(scope, &selection)
+            .map_ok(|each| async move {
+                if self.is_stored(&each).await? {
+                    return Ok(Plan::default());
+                }
+
+                let download = self.download(configuration, &each)?;
+                Ok::<_, RefreshCacheError>(Plan {
+                    downloads: 1,
+                    removals: 0,
+                    bytes: estimate.size(&download, client).await,
+                })
+            })
+            .try_buffer_unordered(settings.jobs.get())
+            .try_fold(
+                Plan::default(),
+                |plan, each| async move { Ok(plan.merge(each)) },
+            )
+            .await
+    }
+
+    /// Inspects the crates in the cache without downloading or changing any crates. Returns the
+    /// crates that are missing or corrupt ordered by name and version.
+    ///
+    /// The integrity of each crate is checked as it would be by a refresh with the same settings.
+    pub async fn inspect(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+    ) -> Result<Vec<Defect>, RefreshCacheError> {
+        if self.index.is_empty().await? {
+            return Ok(Vec::new());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        let selection = settings.filter.resolve(&self.index, &self.database).await?;
+
+        let mut defects = self
+            .selected(scope, &selection)
+            .map_ok(|each| async move {
+                let download = self.download(configuration, &each)?;
+                let problem = match storage::form(&download.destination).await? {
+                    None => Some(Problem::Missing),
+                    Some(_)
+                        if settings.download.preserve == PreservationStrategy::Checksum
+                            && !settings.deep
+                            && self.is_unchanged(&each).await =>
+                    {
+                        None
+                    }
+                    Some(_)
+                        if settings.download.preserve == PreservationStrategy::Size
+                            && self
+                                .has_expected_size(&download, &each, client, &settings.connections)
+                                .await =>
+                    {
+                        None
+                    }
+                    Some(_)
+                        if settings.download.preserve == PreservationStrategy::RecordedSize
+                            && self.has_recorded_size(&each).await =>
+                    {
+                        None
+                    }
+                    Some(_)
+                        if settings.download.preserve == PreservationStrategy::Checksum
+                            && self.check_manifest(&each, settings).await.is_some() =>
+                    {
+                        None
+                    }
+                    Some(form) if download.is_intact(form, &settings.hashing).await? => None,
+                    Some(_) => Some(Problem::Corrupt),
+                };
+
+                if let Some(problem) = problem {
+                    debug!(
+                        name = each.name.as_str(),
+                        version = each.version.as_str(),
+                        "found {:?} crate",
+                        problem
+                    );
+                }
+
+                Ok::<_, RefreshCacheError>(problem.map(|problem| Defect {
+                    name: each.name,
+                    version: each.version,
+                    problem,
+                }))
+            })
+            .try_buffer_unordered(settings.concurrency())
+            .try_filter_map(|defect| async move { Ok(defect) })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        defects.sort_by(|a, b| {
+            (a.name.as_str(), a.version.as_str()).cmp(&(b.name.as_str(), b.version.as_str()))
+        });
+        Ok(defects)
+    }
+
+    /// Checks that every stored crate is a well-formed gzipped tarball whose files are all within
+    /// `<name>-<version>`. Returns the suspicious crates ordered by name and version.
+    ///
+    /// Crates are only read, so crates that fail the check are kept.
+    pub async fn inspect_archives(
+        &self,
+        settings: &Settings,
+        scope: &Scope,
+    ) -> Result<Vec<Defect>, RefreshCacheError> {
+        if self.index.is_empty().await? {
+            return Ok(Vec::new());
+        }
+
+        let selection = settings.filter.resolve(&self.index, &self.database).await?;
+
+        let mut defects = self
+            .selected(scope, &selection)
+            .map_ok(|each| async move {
+                let Some(bytes) = storage::load(&self.locate_crate(&each)).await? else {
+                    return Ok(None);
+                };
+
+                let item = each.clone();
+                let validation = task::spawn_blocking(move || extract::validate(&bytes, &item))
+                    .await
+                    .expect("panicked while validating crate");
+                if let Err(error) = validation {
+                    warn!(
+                        "{} {} is a suspicious archive: {}",
+                        each.name, each.version, error
+                    );
+                    return Ok(Some(Defect {
+                        name: each.name,
+                        version: each.version,
+                        problem: Problem::Suspicious,
+                    }));
+                }
+
+                Ok::<_, RefreshCacheError>(None)
+            })
+            .try_buffer_unordered(settings.concurrency())
+            .try_filter_map(|defect| async move { Ok(defect) })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        defects.sort_by(|a, b| {
+            (a.name.as_str(), a.version.as_str()).cmp(&(b.name.as_str(), b.version.as_str()))
+        });
+        Ok(defects)
+    }
+
+    /// Plans an update of the cache without downloading any crates or committing the update.
+    pub async fn plan_update(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        estimate: Estimate,
+    ) -> Result<Plan, UpdateError> {
+        self.fetch_index(client, settings.jobs).await?;
+        self.plan_fetched(client, settings, estimate).await
+    }
+
+    /// Plans the update that was fetched but not yet applied.
+    async fn plan_fetched(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        estimate: Estimate,
+    ) -> Result<Plan, UpdateError> {
+        if self
+            .index
+            .is_empty()
+            .await
+            .map_err(index::GetUpdateError::from)?
+        {
+            return Ok(Plan::default());
+        }
+
+        // The configuration is read before the update is staged because the index is locked while
+        // the changes are being enumerated.
+        let configuration = &self.index.configuration().await?;
+        let selection = &settings
+            .filter
+            .resolve_update(&self.index, &self.database)
+            .await?;
+        let mut pending = self
+            .index
+            .stage(settings.snapshot.as_ref().map(Date::timestamp))
+            .await?;
+
+        pending
+            .changes()
+            .map(|change| async move {
+                let change = change?;
+                let present = self.is_stored(&change.on).await?;
+                let accepted = selection.contains(&change.on);
+                let (download, removal) = match change.kind {
+                    ChangeKind::Added => (accepted && !present, false),
+                    ChangeKind::Removed => (false, present),
+                    ChangeKind::Modified => (accepted, present),
+                    ChangeKind::YankStatusChanged if change.on.yanked => (
+                        false,
+                        present && settings.filter.yanked == YankPolicy::Delete,
+                    ),
+                    ChangeKind::YankStatusChanged => (
+                        !present && settings.filter.yanked != YankPolicy::Mirror && accepted,
+                        false,
+                    ),
+                };
+
+                let bytes = if download {
+                    estimate
+                        .size(&self.download(configuration, &change.on)?, client)
+                        .await
+                } else {
+                    0
+                };
+
+                Ok::<_, UpdateError>(Plan {
+                    downloads: usize::from(download),
+                    removals: usize::from(removal),
+                    bytes,
+                })
+            })
+            .buffer_unordered(settings.jobs.get())
+            .try_fold(
+                Plan::default(),
+                |plan, each| async move { Ok(plan.merge(each)) },
+            )
+            .await
+    }
+
+    /// Refreshes the cache.
+    ///
+    /// The packages that should be in the cache are enumerated and (re)downloaded.
+    ///
+    /// Only the packages in `scope` are refreshed. Crates that are not selected by the filter are
+    /// neither downloaded nor removed.
+    ///
+    /// Progress is recorded in a journal as each top-level directory of the index is refreshed. An
+    /// interrupted refresh resumes from the journal if the index has not changed since.
+    ///
+    /// Packages are read from the index as they are refreshed when crates are refreshed in the
+    /// order of the index. Every other order reads every package before any crate is refreshed.
+    pub async fn refresh(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+        order: Order,
+    ) -> Result<Outcome, RefreshCacheError> {
+        self.refresh_packages(client, settings, scope, order, None)
+            .await
+    }
+
+    /// Verifies the crates in the cache by refreshing them.
+    ///
+    /// When every package is in scope, only the crates in the packages that changed since the
+    /// index commit that every crate was last verified at are refreshed unless `all` is true. The
+    /// commit is recorded once every crate has been verified against its checksum.
+    pub async fn verify(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+        order: Order,
+        all: bool,
+    ) -> Result<Outcome, RefreshCacheError> {
+        let path = self.path.join(Self::VERIFICATION_FILENAME);
+        let head = self.index.head().await?;
+        let filter = settings.filter.to_string();
+        let forced = self.redownload(client, settings).await?;
+
+        let verified = if all || !scope.is_everything() {
+            None
+        } else {
+            consistency::verified_at(&path, filter.clone())
+                .await?
+                .and_then(|from| Oid::from_str(&from).ok())
+        };
+
+        let outcome = match verified {
+            Some(from) => match self.index.changes(from, head).await {
+                Ok(changes) => {
+                    let packages = changes
+                        .into_iter()
+                        .map(|change| change.on.path())
+                        .collect::<AHashSet<_>>();
+                    info!(
+                        "verifying the crates of {} packages that changed since {}",
+                        packages.len(),
+                        from
+                    );
+
+                    if packages.is_empty() {
+                        Outcome::default()
+                    } else {
+                        self.refresh_packages(
+                            client,
+                            settings,
+                            scope,
+                            order,
+                            Some((from, &packages)),
+                        )
+                        .await?
+                    }
+                }
+
+                // The commit is no longer held by the index (eg. when the index was cloned again).
+                Err(error) => {
+                    warn!(
+                        "failed to find the changes since the last verification at {}: {}",
+                        from, error
+                    );
+                    self.refresh(client, settings, scope, order).await?
+                }
+            },
+
+            None => self.refresh(client, settings, scope, order).await?,
+        }
+        .merge(forced);
+
+        // Crates that were only checked by their size were not verified.
+        if scope.is_everything()
+            && outcome.is_complete()
+            && !head.is_zero()
+            && settings.download.preserve == PreservationStrategy::Checksum
+        {
+            consistency::record_verification(
+                &path,
+                head.to_string(),
+                filter,
+                settings.download.permissions,
+            )
+            .await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Refreshes the crates in `scope`. If `changed` is given, only the crates in the packages at
+    /// the paths that changed since the index commit are refreshed.
+    #[allow(clippy::too_many_lines)]
+    async fn refresh_packages(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+        order: Order,
+        changed: Option<(Oid, &AHashSet<String>)>,
+    ) -> Result<Outcome, RefreshCacheError> {
+        // An index that has no commits yet holds no crates.
+        if self.index.is_empty().await? {
+            debug!("skipped the refresh of an empty index");
+            return Ok(Outcome::default());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        if configuration.auth_required && !settings.authenticated {
+            return Err(RefreshCacheError::Unauthenticated);
+        }
+
+        let tally = &Tally::default();
+        // The progress of a refresh of changed packages must not be mistaken for the progress of a
+        // refresh of every package.
+        let journal = Journal::open(
+            self.path.join(Self::JOURNAL_FILENAME),
+            self.index.head().await?.to_string(),
+            settings.download.preserve,
+            match changed {
+                Some((from, _)) => format!("{scope} changed since {from}"),
+                None => scope.to_string(),
+            },
+            settings.filter.to_string(),
+            settings.download.durability,
+            settings.download.permissions,
+        )
+        .await?;
+        let selection = &settings.filter.resolve(&self.index, &self.database).await?;
+        let journal = &journal;
+        let changed = changed.map(|(_, packages)| packages);
+
+        // Directories are read from the index as crates are refreshed.
+        let crates = self
+            .directories(scope)
+            .try_filter_map(|directory| async move {
+                if journal.is_complete(&directory.name) {
+                    debug!(
+                        directory = directory.name.as_str(),
+                        "skipped a refreshed directory"
+                    );
+                    notify::advance();
+                    return Ok(None);
+                }
+
+                let crates = directory
+                    .packages
+                    .into_iter()
+                    .flat_map(|package| selection.retain(package))
+                    .filter(|each| changed.is_none_or(|packages| packages.contains(&each.path())))
+                    .collect::<Vec<_>>();
+                if crates.is_empty() {
+                    notify::advance();
+                }
+                let progress = Arc::new(Progress {
+                    name: directory.name,
+                    remaining: AtomicUsize::new(crates.len()),
+                    failed: AtomicBool::new(false),
+                });
+
+                Ok(Some(
+                    stream::iter(crates.into_iter().map(move |each| (progress.clone(), each)))
+                        .map(Ok),
+                ))
+            })
+            .try_flatten();
+
+        // Every crate must be known before they can be arranged in any order other than the
+        // order of the index.
+        let queue = if order == Order::Index {
+            Either::Left(crates)
+        } else {
+            let mut queue = crates.try_collect::<Vec<_>>().await?;
+            self.arrange(&mut queue, order, client, configuration, settings.jobs)
+                .await?;
+            Either::Right(stream::iter(queue.into_iter().map(Ok)))
+        };
+
+        queue
+            .try_for_each_concurrent(settings.concurrency(), |(progress, each)| {
+                let name = each.name.clone();
+                let version = each.version.clone();
+
+                async move {
+                    // Each crate is counted separately so that a tolerated failure is attributed
+                    // to its directory.
+                    let attempt = Tally::default();
+                    let fetched = self
+                        .fetch(configuration, &each, client, settings, &attempt)
+                        .await;
+                    if attempt.has_failed() {
+                        progress.failed.store(true, Ordering::Release);
+                    }
+                    tally.absorb(attempt);
+                    fetched?;
+
+                    // A directory with a crate that could not be downloaded is refreshed again
+                    // when an interrupted refresh is resumed.
+                    if progress.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        if !progress.failed.load(Ordering::Acquire) {
+                            journal.complete(&progress.name).await?;
+                        }
+                        notify::advance();
+                    }
+
+                    Ok::<_, RefreshCacheError>(())
+                }
+                .instrument(info_span!(
+                    "download",
+                    name = name.as_str(),
+                    version = version.as_str(),
+                    bytes = field::Empty,
+                    attempts = field::Empty
+                ))
+            })
+            .await?;
+
+        journal.finish().await?;
+
+        // The crates that could not be downloaded must be downloaded by a full refresh.
+        let outcome = tally.finish();
+        if !outcome.is_complete() {
+            consistency::clear(&self.path.join(Self::CONSISTENCY_FILENAME)).await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Fetches the latest changes to the index. The upstream mirror of a sparse registry is
+    /// updated first because the index only receives the changes that are committed to it.
+    async fn fetch_index(&self, client: &Client, jobs: NonZeroUsize) -> Result<(), UpdateError> {
+        let upstream = self.path.join(Self::UPSTREAM_SUBDIRECTORY);
+        if fs::metadata(&upstream).await.is_ok() {
+            SparseIndex::from_path(upstream)
+                .await?
+                .update(client, jobs, &[])
+                .await?;
+        }
+
+        self.index.fetch().await?;
+        Ok(())
+    }
+
+    /// Applies the changes to the index that were fetched or imported but not yet applied.
+    ///
+    /// Crates are taken from the unpacked bundle at `bundle` instead of being downloaded when it is
+    /// given.
+    #[allow(clippy::too_many_lines)]
+    async fn apply(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        bundle: Option<&Path>,
+    ) -> Result<Outcome, UpdateError> {
+        // It's possible that an update will modify the configuration.
+        //
+        // It is difficult to recover from a configuration being aggressively deprecated and
+        // disabled as `Self::refresh` must always be run before updates are fetched to ensure that
+        // the cache is consistent. If the current configuration is disabled then `Self::refresh`
+        // will fail.
+        //
+        // This may be resolved in the future by enumerating updates before refreshing the cache and
+        // using the latest available configuration when refreshing the cache and applying an
+        // update.
+        //
+        // The configuration is read before the update is staged because the index is locked while
+        // the changes are being enumerated.
+        //
+        // Nothing has been fetched from a remote index that has no commits yet.
+        if self
+            .index
+            .is_empty()
+            .await
+            .map_err(index::GetUpdateError::from)?
+        {
+            return Ok(Outcome::default());
+        }
+
+        let configuration = &self.index.configuration().await?;
+        let selection = &settings
+            .filter
+            .resolve_update(&self.index, &self.database)
+            .await?;
+        let mut pending = self
+            .index
+            .stage(settings.snapshot.as_ref().map(Date::timestamp))
+            .await?;
+        let tally = &Tally::default();
+
+        // Crates are only obtained as their changes are applied if they can be selected on their
+        // own. Otherwise, the changed packages are retained once the update is committed.
+        let selects = &|item: &Crate| !selection.depends_on_package() && selection.contains(item);
+        let mut changed = selection.depends_on_package().then(Vec::new);
+
+        // The paths of the changed packages are only needed to update the sparse index.
+        let sparse = self.sparse_path();
+        let mut packages = fs::metadata(&sparse).await.ok().map(|_| Vec::new());
+        let mut published = settings.feed.as_ref().map(|_| Vec::new());
+        let mut package = None;
+
+        pending
+            .changes()
+            .map_err(UpdateError::from)
+            .inspect_ok(|change| {
+                // The changes to each package are generated together.
+                let path = change.on.path();
+                if package.as_ref() != Some(&path) {
+                    notify::advance();
+                    package = Some(path);
+                }
+
+                if let Some(changed) = changed.as_mut() {
+                    changed.push(change.on.name.clone());
+                }
+
+                if let Some(packages) = packages.as_mut() {
+                    packages.push(PathBuf::from(change.on.path()));
+                }
+
+                if let Some(published) = published.as_mut() {
+                    published.push((change.on.clone(), feed::Kind::from(change)));
+                }
+            })
+            .try_for_each_concurrent(settings.concurrency(), |change| {
+                let span = info_span!(
+                    "change",
+                    name = change.on.name.as_str(),
+                    version = change.on.version.as_str(),
+                    bytes = field::Empty,
+                    attempts = field::Empty
+                );
+
+                async move {
+                    match change.kind {
+                        ChangeKind::Added => {
+                            if selects(&change.on) {
+                                self.obtain(
+                                    configuration,
+                                    &change.on,
+                                    client,
+                                    settings,
+                                    tally,
+                                    bundle,
+                                )
+                                .await?;
+                            }
+
+                            debug!("processed an addition");
+                        }
+
+                        ChangeKind::Removed => {
+                            if self
+                                .remove(&change.on, settings.removal, settings.download.permissions)
+                                .await?
+                            {
+                                tally.remove();
+                            }
+                            debug!("processed a removal");
+                        }
+
+                        ChangeKind::YankStatusChanged => {
+                            // Nothing is downloaded or removed when yanked crates are mirrored.
+                            if change.on.yanked {
+                                if settings.filter.yanked == YankPolicy::Delete
+                                    && self
+                                        .remove(
+                                            &change.on,
+                                            settings.removal,
+                                            settings.download.permissions,
+                                        )
+                                        .await?
+                                {
+                                    tally.remove();
+                                }
+                            } else if settings.filter.yanked != YankPolicy::Mirror
+                                && selects(&change.on)
+                            {
+                                // The crate was skipped while it was yanked.
+                                self.obtain(
+                                    configuration,
+                                    &change.on,
+                                    client,
+                                    settings,
+                                    tally,
+                                    bundle,
+                                )
+                                .await?;
+                            }
+
+                            debug!("processed a change to the yanked status");
+                        }
+
+                        ChangeKind::Modified => {
+                            // The registry can not be asked whether a bundled crate has changed.
+                            if bundle.is_none()
+                                && selects(&change.on)
+                                && self
+                                    .is_not_modified(configuration, &change.on, client, settings)
+                                    .await?
+                            {
+                                info!("the registry has not changed the crate");
+                            } else {
+                                self.discard(
+                                    &change.on,
+                                    settings.removal,
+                                    settings.download.permissions,
+                                )
+                                .await?;
+
+                                if selects(&change.on) {
+                                    self.obtain(
+                                        configuration,
+                                        &change.on,
+                                        client,
+                                        settings,
+                                        tally,
+                                        bundle,
+                                    )
+                                    .await?;
+                                }
+                            }
+
+                            debug!("processed a modification");
+                        }
+                    }
+
+                    Ok::<_, UpdateError>(())
+                }
+                .instrument(span)
+            })
+            .await?;
+
+        pending.commit().await?;
+        debug!("committed an update to the index");
+
+        if let Some(mut changed) = changed {
+            changed.sort_unstable();
+            changed.dedup();
+            self.retain(
+                configuration,
+                changed,
+                selection,
+                client,
+                settings,
+                tally,
+                bundle,
+            )
+            .await?;
+        }
+
+        if let Some(mut packages) = packages {
+            packages.sort_unstable();
+            packages.dedup();
+            sparse::update(&self.index, &sparse, packages).await?;
+            debug!("updated the sparse index");
+        }
+
+        if let (Some(feed), Some(published)) = (&settings.feed, published) {
+            self.publish(feed, published, settings.download.permissions)
+                .await;
+        }
+
+        Ok(tally.finish())
+    }
+
+    /// Applies the retention of `selection` to the packages named `names` in HEAD. The crates that
+    /// are retained are obtained if they are missing and the crates that are not are removed.
+    #[allow(clippy::too_many_arguments)]
+    async fn retain(
+        &self,
+        configuration: &Configuration,
+        names: Vec<String>,
+        selection: &Selection,
+        client: &Client,
+        settings: &Settings,
+        tally: &Tally,
+        bundle: Option<&Path>,
+    ) -> Result<(), UpdateError> {
+        stream::iter(names)
+            .map(|name| async move {
+                let Some(package) = self.index.package(name, false).await? else {
+                    return Ok(());
+                };
+
+                let retained = selection.retain(package.clone());
+                let keys = retained.iter().map(Crate::key).collect::<AHashSet<_>>();
+                for each in package.into_crates() {
+                    let retain = keys.contains(&each.key());
+                    let present = self.is_stored(&each).await?;
+                    if retain && !present {
+                        self.obtain(configuration, &each, client, settings, tally, bundle)
+                            .await?;
+                    } else if !retain && present {
+                        self.remove(&each, settings.removal, settings.download.permissions)
+                            .await?;
+                        tally.remove();
+                        info!(
+                            name = each.name.as_str(),
+                            version = each.version.as_str(),
+                            "removed a crate that is no longer retained"
+                        );
+                    }
+                }
+
+                Ok::<_, UpdateError>(())
+            })
+            .buffer_unordered(settings.jobs.get())
+            .try_collect::<()>()
+            .await
+    }
+
+    /// Removes the crates in the cache that the filter of `settings` does not retain. Returns the
+    /// number of crates that were removed or, if `dry_run` is true, that would be removed.
+    pub async fn prune(
+        &self,
+        settings: &Settings,
+        dry_run: bool,
+    ) -> Result<usize, PruneCacheError> {
+        if self
+            .index
+            .is_empty()
+            .await
+            .map_err(RefreshCacheError::from)?
+        {
+            return Ok(0);
+        }
+
+        let selection = &settings
+            .filter
+            .resolve(&self.index, &self.database)
+            .await
+            .map_err(RefreshCacheError::from)?;
+        let removed = &AtomicUsize::new(0);
+
+        self.directories(&Scope::default())
+            .map_err(PruneCacheError::from)
+            .map_ok(|directory| {
+                stream::iter(
+                    directory
+                        .packages
+                        .into_iter()
+                        .flat_map(|package| {
+                            let keys = selection
+                                .retain(package.clone())
+                                .iter()
+                                .map(Crate::key)
+                                .collect::<AHashSet<_>>();
+                            package
+                                .into_crates()
+                                .filter(move |each| !keys.contains(&each.key()))
+                        })
+                        .map(Ok::<_, PruneCacheError>),
+                )
+            })
+            .try_flatten()
+            .try_for_each_concurrent(settings.jobs.get(), |each| async move {
+                if !self.is_stored(&each).await.map_err(UpdateError::from)? {
+                    return Ok(());
+                }
+
+                if !dry_run {
+                    self.remove(&each, settings.removal, settings.download.permissions)
+                        .await?;
+                }
+
+                info!(
+                    name = each.name.as_str(),
+                    version = each.version.as_str(),
+                    "pruned a crate that is not retained"
+                );
+                removed.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })
+            .await?;
+
+        Ok(removed.load(Ordering::Relaxed))
+    }
+
+    /// Applies the bundle at `path` to the cache. The index commits of the bundle are imported and
+    /// the changes that they make are applied with the crates from the bundle instead of crates
+    /// from the registry, so nothing is fetched or downloaded.
+    ///
+    /// A cache that was consistent with the index remains consistent if every crate that the
+    /// changes select is in the bundle.
+    pub async fn apply_bundle(
+        &self,
+        path: PathBuf,
+        client: &Client,
+        settings: &Settings,
+    ) -> Result<Outcome, ApplyBundleError> {
+        let marker = self.path.join(Self::MARKER_FILENAME);
+        consistency::unmark(&marker).await?;
+        let consistent = self.is_consistent(settings).await?;
+
+        // A bundle that was unpacked by an interrupted application is unpacked again.
+        let unpacked = self.path.join(Self::BUNDLE_SUBDIRECTORY);
+        if let Err(error) = fs::remove_dir_all(&unpacked).await {
+            if error.kind() != io::ErrorKind::NotFound {
+                return Err(error.into());
+            }
+        }
+
+        bundle::unpack(path, unpacked.clone()).await?;
+        let head = self
+            .index
+            .unbundle(file::read(&unpacked.join(bundle::INDEX_PATH)).await?)
+            .await?;
+        debug!("imported index commits up to {}", head);
+
+        let outcome = self.apply(client, settings, Some(&unpacked)).await?;
+        fs::remove_dir_all(&unpacked).await?;
+
+        let path = self.path.join(Self::CONSISTENCY_FILENAME);
+        if consistent && outcome.is_complete() {
+            let head = self
+                .index
+                .head()
+                .await
+                .map_err(RefreshCacheError::from)?
+                .to_string();
+            consistency::record(&path, head.clone(), settings.filter.to_string()).await?;
+            consistency::mark_complete(
+                &marker,
+                head,
+                archive::timestamp(SystemTime::now()),
+                settings.download.permissions,
+            )
+            .await?;
+        } else {
+            consistency::clear(&path).await?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Returns true if the cache is known to be consistent with the index when crates are selected
+    /// by `settings`.
+    ///
+    /// A cache is consistent once a refresh of the entire index and the update that follows it
+    /// have downloaded every crate. It remains consistent for as long as every update succeeds.
+    pub async fn is_consistent(&self, settings: &Settings) -> Result<bool, RefreshCacheError> {
+        // A refresh was interrupted.
+        if fs::metadata(self.path.join(Self::JOURNAL_FILENAME))
+            .await
+            .is_ok()
+        {
+            return Ok(false);
+        }
+
+        Ok(consistency::is_consistent(
+            &self.path.join(Self::CONSISTENCY_FILENAME),
+            self.index.head().await?.to_string(),
+            settings.filter.to_string(),
+        )
+        .await?)
+    }
+
+    /// Synchronises the cache with the index.
+    ///
+    /// The cache is refreshed before it is updated unless it is known to be consistent with the
+    /// index, in which case only the pending update is applied. `full` forces a refresh.
+    ///
+    /// Only the packages in `scope` are refreshed when it does not cover the entire index. Updates
+    /// always apply to the entire index so the index is not updated.
+    ///
+    /// If `preflight` is given, the space that the refresh and the fetched update need is
+    /// estimated with it and nothing is downloaded unless the file system has enough space.
+    ///
+    /// # Errors
+    ///
+    /// Pending changes that are reported by the [`Index`] are acted on by downloading or removing
+    /// files and the [`Index`] is only updated when these operations have completed
+    /// successfully. This ensures that intermittent network or file system failures do not leave
+    /// the cache in a permanently inconsistent state.
+    ///
+    /// The state of the cache may be temporarily inconsistent when an update fails. This can
+    /// generally be rectified by updating again until the operation is successful.
+    ///
+    /// ## Index Corruption
+    ///
+    /// It is possible that the cache may become permanently inconsistent if the index becomes
+    /// corrupt in any new commit since the cache was initialised. Index corruption makes it
+    /// impossible to deduce what crates were added, removed, or changed. This can be rectified by
+    /// cloning the index again with [`Self::repair_index`] and refreshing the cache.
+    pub async fn synchronise(
+        &self,
+        client: &Client,
+        settings: &Settings,
+        scope: &Scope,
+        order: Order,
+        full: bool,
+        preflight: Option<Estimate>,
+    ) -> Result<Outcome, SynchroniseError> {
+        let marker = self.path.join(Self::MARKER_FILENAME);
+        consistency::unmark(&marker)
+            .await
+            .map_err(RefreshCacheError::from)?;
+
+        // A consistent cache is only updated so credentials are checked before the refresh is
+        // skipped.
+        if !settings.authenticated
+            && !self
+                .index
+                .is_empty()
+                .await
+                .map_err(RefreshCacheError::from)?
+            && self
+                .index
+                .configuration()
+                .await
+                .map_err(RefreshCacheError::from)?
+                .auth_required
+        {
+            return Err(RefreshCacheError::Unauthenticated.into());
+        }
+
+        // The growth of the cache is measured from the start of the last synchronisation.
+        if let Err(error) = self
+            .database
+            .record_synchronisation(archive::timestamp(SystemTime::now()))
+            .await
+        {
+            warn!("failed to record metadata: {}", error);
+        }
+
+        // Updates always apply to the entire index. A scoped synchronisation only refreshes the
+        // packages in scope and leaves the index for an unscoped synchronisation to update.
+        let update = scope.is_everything();
+        if update {
+            self.fetch_index(client, settings.jobs).await?;
+        }
+
+        let refresh = !update || full || !self.is_consistent(settings).await?;
+        if let Some(estimate) = preflight {
+            let mut plan = if refresh {
+                self.plan_refresh(client, settings, scope, estimate).await?
+            } else {
+                Plan::default()
+            };
+
+            if update {
+                plan = plan.merge(self.plan_fetched(client, settings, estimate).await?);
+            }
+
+            plan.check_space(self.path.clone()).await?;
+        }
+
+        // Quarantined and forced crates are downloaded again even when the rest of the cache is
+        // consistent.
+        let mut outcome = self.restore_quarantined(client, settings).await?;
+        outcome = outcome.merge(self.redownload(client, settings).await?);
+        if !update {
+            return Ok(outcome.merge(self.refresh(client, settings, scope, order).await?));
+        }
+
+        let path = self.path.join(Self::CONSISTENCY_FILENAME);
+        if refresh {
+            consistency::clear(&path)
+                .await
+                .map_err(RefreshCacheError::from)?;
+            outcome = outcome.merge(self.refresh(client, settings, scope, order).await?);
+            debug!("refreshed the cache");
+        } else {
+            debug!("skipped the refresh of a consistent cache");
+        }
+
+        // An update that fails before it is committed is applied again by the next update so the
+        // cache remains consistent.
+        outcome = outcome.merge(self.apply(client, settings, None).await?);
+        if outcome.is_complete() {
+            let head = self
+                .index
+                .head()
+                .await
+                .map_err(RefreshCacheError::from)?
+                .to_string();
+            consistency::record(&path, head.clone(), settings.filter.to_string())
+                .await
+                .map_err(UpdateError::from)?;
+
+            // The marker is moved after the consistency record so that it never describes a state
+            // that a later synchronisation would refresh.
+            consistency::mark_complete(
+                &marker,
+                head,
+                archive::timestamp(SystemTime::now()),
+                settings.download.permissions,
+            )
+            .await
+            .map_err(UpdateError::from)?;
+        } else {
+            consistency::clear(&path).await.map_err(UpdateError::from)?;
+        }
+
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,399 @@
+#[cfg(test)]
+mod tests;
+
+use crate::{
+    digest::{self, Algorithm, Digest},
+    registry::index::package::Crate,
+};
+use reqwest::{header, Client, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use url::{ParseError, Url};
+
+/// The type of the artefacts that crates are pushed as.
+const ARTIFACT_TYPE: &str = "application/vnd.crateful.crate.v1";
+
+/// The media type of the layer that holds a crate.
+const LAYER_MEDIA_TYPE: &str = "application/vnd.crateful.crate.v1.tar+gzip";
+
+/// The media type of an empty configuration.
+const EMPTY_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+
+/// The media type of a manifest.
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// The error type for pushing a crate to an OCI registry.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PushError {
+    Http(reqwest::Error),
+    /// The registry responded with an unsuccessful status.
+    Status(StatusCode),
+    /// The registry asked for credentials in a way that is not supported.
+    Unauthorised,
+    /// The registry responded with something that is not understood.
+    Malformed,
+}
+
+impl From<reqwest::Error> for PushError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Http(error)
+    }
+}
+
+impl Display for PushError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(error) => write!(f, "failed to reach the OCI registry: {error}"),
+            Self::Status(status) => write!(f, "the OCI registry responded with {status}"),
+            Self::Unauthorised => write!(f, "the OCI registry did not accept the credentials"),
+            Self::Malformed => write!(f, "the OCI registry responded unexpectedly"),
+        }
+    }
+}
+
+impl Error for PushError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Http(error) => Some(error),
+            Self::Status(_) | Self::Unauthorised | Self::Malformed => None,
+        }
+    }
+}
+
+/// A challenge from the `WWW-Authenticate` header of a response.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct Challenge {
+    scheme: String,
+    parameters: Vec<(String, String)>,
+}
+
+impl Challenge {
+    /// Parses a challenge (eg. `Bearer realm="https://auth.example/token",service="registry"`).
+    fn parse(header: &str) -> Option<Self> {
+        let header = header.trim();
+        let (scheme, rest) = header.split_once(' ').unwrap_or((header, ""));
+        if scheme.is_empty() {
+            return None;
+        }
+
+        let mut parameters = Vec::new();
+        let mut rest = rest.trim();
+        while !rest.is_empty() {
+            let (key, after) = rest.split_once('=')?;
+            let (value, after) = match after.strip_prefix('"') {
+                Some(quoted) => {
+                    let (value, after) = quoted.split_once('"')?;
+                    (value, after)
+                }
+                None => after
+                    .split_once(',')
+                    .map_or((after, ""), |(value, after)| (value, after)),
+            };
+
+            parameters.push((key.trim().to_owned(), value.to_owned()));
+            rest = after.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        }
+
+        Some(Self {
+            scheme: scheme.to_owned(),
+            parameters,
+        })
+    }
+
+    /// Returns the value of the parameter called `name`.
+    fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The response of a token service.
+#[derive(Deserialize)]
+struct Token {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// A descriptor of a blob that a manifest refers to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: &'static str,
+    digest: String,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<Annotations>,
+}
+
+#[derive(Serialize)]
+struct Annotations {
+    #[serde(rename = "org.opencontainers.image.title")]
+    title: String,
+}
+
+/// An OCI image manifest that describes a crate.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    schema_version: u32,
+    media_type: &'static str,
+    artifact_type: &'static str,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+/// How requests to push to a repository are authorised.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+enum Authorisation<'a> {
+    Basic(&'a Credentials),
+    Bearer(String),
+}
+
+/// Credentials for an OCI registry.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A repository in an OCI registry that crates are pushed to as artefacts. Each crate is pushed
+/// to the repository named after it within this repository and is tagged with its version.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Oci {
+    /// The registry (eg. `https://harbor.example/`).
+    registry: Url,
+    /// The repository within the registry (eg. `library/crates`).
+    repository: String,
+    credentials: Option<Credentials>,
+}
+
+impl Oci {
+    /// Returns the repository at `url` (eg. `https://harbor.example/library/crates`). Nothing is
+    /// returned if the URL does not name a repository.
+    pub fn new(url: &Url, credentials: Option<Credentials>) -> Option<Self> {
+        let repository = url.path().trim_matches('/').to_owned();
+        if repository.is_empty() {
+            return None;
+        }
+
+        let mut registry = url.clone();
+        registry.set_path("/");
+        registry.set_query(None);
+
+        Some(Self {
+            registry,
+            repository,
+            credentials,
+        })
+    }
+
+    /// Returns the repository and tag that `item` is pushed to. Repository names must be lowercase
+    /// and tags can not hold `+` so build metadata is separated with `_`.
+    fn reference(&self, item: &Crate) -> (String, String) {
+        (
+            format!("{}/{}", self.repository, item.name.to_lowercase()),
+            item.version.replace('+', "_"),
+        )
+    }
+
+    /// Returns the URL of `path` within the API of the registry.
+    fn endpoint(&self, path: &str) -> Result<Url, PushError> {
+        self.registry
+            .join(path)
+            .map_err(|_: ParseError| PushError::Malformed)
+    }
+
+    /// Returns the value of the `Authorization` header that requests to push to `repository` are
+    /// sent with. Registries that ask for a bearer token are sent the credentials to get one.
+    async fn authorisation(
+        &self,
+        client: &Client,
+        repository: &str,
+    ) -> Result<Option<Authorisation<'_>>, PushError> {
+        let response = client.get(self.endpoint("v2/")?).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Challenge::parse)
+            .ok_or(PushError::Unauthorised)?;
+
+        if challenge.scheme.eq_ignore_ascii_case("basic") {
+            let credentials = self.credentials.as_ref().ok_or(PushError::Unauthorised)?;
+            return Ok(Some(Authorisation::Basic(credentials)));
+        }
+
+        if !challenge.scheme.eq_ignore_ascii_case("bearer") {
+            return Err(PushError::Unauthorised);
+        }
+
+        let mut realm = challenge
+            .parameter("realm")
+            .and_then(|realm| Url::parse(realm).ok())
+            .ok_or(PushError::Malformed)?;
+        {
+            let mut query = realm.query_pairs_mut();
+            if let Some(service) = challenge.parameter("service") {
+                query.append_pair("service", service);
+            }
+
+            query.append_pair("scope", &format!("repository:{repository}:pull,push"));
+        }
+
+        let mut request = client.get(realm);
+        if let Some(credentials) = &self.credentials {
+            request = request.basic_auth(&credentials.username, Some(&credentials.password));
+        }
+
+        let response = successful(request.send().await?)?;
+        let token = serde_json::from_slice::<Token>(&response.bytes().await?)
+            .map_err(|_| PushError::Malformed)?;
+
+        token
+            .token
+            .or(token.access_token)
+            .map(|token| Some(Authorisation::Bearer(token)))
+            .ok_or(PushError::Malformed)
+    }
+
+    /// Uploads a blob to `repository` unless the registry already holds it.
+    async fn upload(
+        &self,
+        client: &Client,
+        authorisation: Option<&Authorisation<'_>>,
+        repository: &str,
+        digest: &str,
+        bytes: Vec<u8>,
+    ) -> Result<(), PushError> {
+        let exists = authorise(
+            client.head(self.endpoint(&format!("v2/{repository}/blobs/{digest}"))?),
+            authorisation,
+        )
+        .send()
+        .await?;
+
+        if exists.status().is_success() {
+            return Ok(());
+        }
+
+        let started = successful(
+            authorise(
+                client.post(self.endpoint(&format!("v2/{repository}/blobs/uploads/"))?),
+                authorisation,
+            )
+            .send()
+            .await?,
+        )?;
+
+        let mut location = started
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| self.registry.join(location).ok())
+            .ok_or(PushError::Malformed)?;
+        location.query_pairs_mut().append_pair("digest", digest);
+
+        successful(
+            authorise(client.put(location), authorisation)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(bytes)
+                .send()
+                .await?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Pushes `bytes`, the contents of `item`, to the registry as an artefact.
+    pub async fn push(
+        &self,
+        client: &Client,
+        item: &Crate,
+        bytes: Vec<u8>,
+        pool: &digest::Pool,
+    ) -> Result<(), PushError> {
+        let (repository, tag) = self.reference(item);
+        let authorisation = self.authorisation(client, &repository).await?;
+        let authorisation = authorisation.as_ref();
+
+        let size = bytes.len() as u64;
+        let (bytes, digest) = match item.checksum {
+            // The checksum that is published in the index is the digest of the crate.
+            checksum @ Digest::Sha256(_) => (bytes, checksum),
+            _ => pool.digest(Algorithm::Sha256, bytes).await,
+        };
+        let digest = digest.to_string();
+
+        let empty = b"{}".to_vec();
+        let empty_digest = Digest::compute(Algorithm::Sha256, &empty).to_string();
+
+        self.upload(client, authorisation, &repository, &empty_digest, empty)
+            .await?;
+        self.upload(client, authorisation, &repository, &digest, bytes)
+            .await?;
+
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: MANIFEST_MEDIA_TYPE,
+            artifact_type: ARTIFACT_TYPE,
+            config: Descriptor {
+                media_type: EMPTY_MEDIA_TYPE,
+                digest: empty_digest,
+                size: 2,
+                annotations: None,
+            },
+            layers: vec![Descriptor {
+                media_type: LAYER_MEDIA_TYPE,
+                digest,
+                size,
+                annotations: Some(Annotations {
+                    title: format!("{}-{}.crate", item.name, item.version),
+                }),
+            }],
+        };
+
+        successful(
+            authorise(
+                client.put(self.endpoint(&format!("v2/{repository}/manifests/{tag}"))?),
+                authorisation,
+            )
+            .header(header::CONTENT_TYPE, MANIFEST_MEDIA_TYPE)
+            .body(serde_json::to_vec(&manifest).expect("failed to serialise manifest"))
+            .send()
+            .await?,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Adds the `Authorization` header to a request if there is one.
+fn authorise(request: RequestBuilder, authorisation: Option<&Authorisation<'_>>) -> RequestBuilder {
+    match authorisation {
+        Some(Authorisation::Basic(credentials)) => {
+            request.basic_auth(&credentials.username, Some(&credentials.password))
+        }
+        Some(Authorisation::Bearer(token)) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Returns the response if it is successful.
+fn successful(response: Response) -> Result<Response, PushError> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(PushError::Status(status))
+    }
+}
@@ -0,0 +1,45 @@
+use super::{Challenge, Oci};
+use url::Url;
+
+#[test]
+fn test_parse_bearer_challenge() {
+    let challenge = Challenge::parse(
+        r#"Bearer realm="https://auth.example/token",service="registry.example",scope="repository:a:pull""#,
+    )
+    .expect("failed to parse challenge");
+
+    assert!(challenge.scheme.eq_ignore_ascii_case("bearer"));
+    assert_eq!(
+        challenge.parameter("realm"),
+        Some("https://auth.example/token")
+    );
+    assert_eq!(challenge.parameter("service"), Some("registry.example"));
+    assert_eq!(challenge.parameter("scope"), Some("repository:a:pull"));
+}
+
+#[test]
+fn test_parse_basic_challenge() {
+    let challenge = Challenge::parse("Basic realm=registry").expect("failed to parse challenge");
+    assert_eq!(challenge.scheme, "Basic");
+    assert_eq!(challenge.parameter("realm"), Some("registry"));
+
+    let challenge = Challenge::parse("Basic").expect("failed to parse challenge");
+    assert!(challenge.parameters.is_empty());
+}
+
+#[test]
+fn test_reference() {
+    let oci = Oci::new(
+        &Url::parse("https://registry.example/library/crates/").expect("failed to parse url"),
+        None,
+    )
+    .expect("failed to name repository");
+
+    assert_eq!(oci.repository, "library/crates");
+    assert_eq!(oci.registry.as_str(), "https://registry.example/");
+    assert!(Oci::new(
+        &Url::parse("https://registry.example/").expect("failed to parse url"),
+        None
+    )
+    .is_none());
+}
@@ -0,0 +1,136 @@
+use crate::download::Download;
+use reqwest::Client;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::PathBuf,
+};
+use tokio::task;
+use tracing::debug;
+
+/// The error type for checking that a plan can be carried out.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PreflightError {
+    /// The file system does not have enough available space for the planned downloads.
+    InsufficientSpace {
+        required: u64,
+        available: u64,
+    },
+    Io(io::Error),
+}
+
+impl From<io::Error> for PreflightError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for PreflightError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientSpace {
+                required,
+                available,
+            } => write!(
+                f,
+                "approximately {required} bytes are required but only {available} bytes are available"
+            ),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for PreflightError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InsufficientSpace {
+                required: _,
+                available: _,
+            } => None,
+            Self::Io(error) => error.source(),
+        }
+    }
+}
+
+/// Specifies how the size of a crate that has not been downloaded is estimated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Estimate {
+    /// Every crate is assumed to have the same size in bytes.
+    Average(u64),
+    /// The size is requested from the registry without downloading the crate. The fallback is
+    /// used when the registry does not report a size.
+    Head { fallback: u64 },
+}
+
+impl Estimate {
+    /// Returns the estimated size of a download in bytes.
+    pub async fn size(self, download: &Download, client: &Client) -> u64 {
+        match self {
+            Self::Average(size) => size,
+            Self::Head { fallback } => match download.length(client).await {
+                Ok(Some(length)) => length,
+                Ok(None) => fallback,
+                Err(error) => {
+                    debug!("failed to request length: {}", error);
+                    fallback
+                }
+            },
+        }
+    }
+}
+
+/// Describes the work that an operation would perform.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[must_use]
+pub struct Plan {
+    /// The number of crates that would be downloaded.
+    pub downloads: usize,
+    /// The number of crates that would be removed.
+    pub removals: usize,
+    /// The estimated number of bytes that would be downloaded.
+    pub bytes: u64,
+}
+
+impl Plan {
+    /// Combines two plans.
+    pub const fn merge(self, other: Self) -> Self {
+        Self {
+            downloads: self.downloads + other.downloads,
+            removals: self.removals + other.removals,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+
+    /// Checks that the file system holding `path` has enough available space for the planned
+    /// downloads. Planned removals are not expected to free any space.
+    pub async fn check_space(&self, path: PathBuf) -> Result<(), PreflightError> {
+        let available = task::spawn_blocking(move || fs2::available_space(path))
+            .await
+            .expect("panicked while querying available space")?;
+
+        if available < self.bytes {
+            return Err(PreflightError::InsufficientSpace {
+                required: self.bytes,
+                available,
+            });
+        }
+
+        debug!(
+            "approximately {} bytes are required and {} bytes are available",
+            self.bytes, available
+        );
+        Ok(())
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} crates to download (approximately {} bytes) and {} crates to remove",
+            self.downloads, self.bytes, self.removals
+        )
+    }
+}
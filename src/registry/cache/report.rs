@@ -0,0 +1,83 @@
+use crate::registry::index::{Change, ChangeKind};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+
+/// Describes what is wrong with a crate in the cache.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Problem {
+    /// The crate is not stored in the cache.
+    Missing,
+    /// The crate does not have the expected checksum or size.
+    Corrupt,
+    /// The crate is not a well-formed archive or has files outside of its directory.
+    Suspicious,
+}
+
+/// A crate that is missing from the cache or fails its integrity check.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct Defect {
+    pub name: String,
+    pub version: String,
+    pub problem: Problem,
+}
+
+/// Describes how a change to the index affects a crate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    /// The crate was added.
+    Added,
+    /// The crate was removed.
+    Removed,
+    /// The crate was modified.
+    Modified,
+    /// The crate was yanked.
+    Yanked,
+    /// The crate was unyanked.
+    Unyanked,
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Modified => "modified",
+            Self::Yanked => "yanked",
+            Self::Unyanked => "unyanked",
+        })
+    }
+}
+
+/// A change to the index that the next update will apply to the cache.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct PendingChange {
+    pub name: String,
+    pub version: String,
+    pub change: Effect,
+}
+
+impl From<Change> for PendingChange {
+    fn from(change: Change) -> Self {
+        let effect = match change.kind {
+            ChangeKind::Added => Effect::Added,
+            ChangeKind::Removed => Effect::Removed,
+            ChangeKind::Modified => Effect::Modified,
+            ChangeKind::YankStatusChanged if change.on.yanked => Effect::Yanked,
+            ChangeKind::YankStatusChanged => Effect::Unyanked,
+        };
+
+        Self {
+            name: change.on.name,
+            version: change.on.version,
+            change: effect,
+        }
+    }
+}
+
+impl Display for PendingChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.change, self.name, self.version)
+    }
+}
@@ -0,0 +1,131 @@
+use super::feed;
+use crate::{
+    digest::{Algorithm, Digest},
+    registry::index::package::Crate,
+};
+use serde::Serialize;
+use url::Url;
+
+/// The version of the CycloneDX specification that documents conform to.
+const SPEC_VERSION: &str = "1.5";
+
+/// A CycloneDX software bill of materials that describes the crates held in a cache.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bom {
+    #[serde(rename = "bomFormat")]
+    format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<Component>,
+}
+
+impl Bom {
+    /// Returns a document that was created at `time`, the number of seconds since the Unix epoch,
+    /// and that describes `components`.
+    pub fn new(mut components: Vec<Component>, time: u64) -> Self {
+        components.sort_by(|a, b| {
+            (a.name.as_str(), a.version.as_str()).cmp(&(b.name.as_str(), b.version.as_str()))
+        });
+
+        Self {
+            format: "CycloneDX",
+            spec_version: SPEC_VERSION,
+            version: 1,
+            metadata: Metadata {
+                timestamp: feed::timestamp(time),
+                tools: Tools {
+                    components: vec![Tool {
+                        kind: "application",
+                        name: env!("CARGO_PKG_NAME"),
+                        version: env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+            },
+            components,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+struct Metadata {
+    timestamp: String,
+    tools: Tools,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+struct Tools {
+    components: Vec<Tool>,
+}
+
+/// The program that created the document.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+struct Tool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'static str,
+    version: &'static str,
+}
+
+/// A crate that is held in the cache.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Component {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: String,
+    version: String,
+    /// The package URL that identifies the crate.
+    purl: String,
+    hashes: Vec<Hash>,
+    external_references: Vec<Reference>,
+}
+
+impl Component {
+    /// Returns the component of `item` that was downloaded from `url`.
+    pub fn new(item: &Crate, url: &Url) -> Self {
+        Self {
+            kind: "library",
+            name: item.name.clone(),
+            version: item.version.clone(),
+            // Build metadata is the only part of a version that must be encoded.
+            purl: format!(
+                "pkg:cargo/{}@{}",
+                item.name,
+                item.version.replace('+', "%2B")
+            ),
+            hashes: vec![Hash::from(&item.checksum)],
+            external_references: vec![Reference {
+                kind: "distribution",
+                url: url.to_string(),
+            }],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+struct Hash {
+    alg: &'static str,
+    content: String,
+}
+
+impl From<&Digest> for Hash {
+    fn from(digest: &Digest) -> Self {
+        Self {
+            alg: match digest.algorithm() {
+                Algorithm::Sha256 => "SHA-256",
+                Algorithm::Sha512 => "SHA-512",
+                Algorithm::Blake3 => "BLAKE3",
+            },
+            content: hex::encode(digest.as_bytes()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+struct Reference {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+}
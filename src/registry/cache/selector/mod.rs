@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests;
+
+use crate::registry::index::package::Crate;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::Path,
+    str::FromStr,
+};
+use tokio::fs;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseSelectorError {
+    /// The selector does not name a crate or has an empty version.
+    Malformed,
+}
+
+impl Display for ParseSelectorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "selector must be written as <name> or <name>@<version>"),
+        }
+    }
+}
+
+impl Error for ParseSelectorError {}
+
+/// Selects crates by their name and optionally their version (eg. `serde`, `serde@1.0.0`, or
+/// `serde_*@1.*`).
+///
+/// Names and versions may contain `*`, which matches any characters, and `?`, which matches one
+/// character. Names are matched as crates.io matches them, so case and the difference between `-`
+/// and `_` are ignored.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Selector {
+    name: String,
+    version: Option<String>,
+}
+
+impl Selector {
+    /// Returns true if the selector selects `item`.
+    #[must_use]
+    pub fn selects(&self, item: &Crate) -> bool {
+        matches(
+            normalise(&self.name).as_bytes(),
+            normalise(&item.name).as_bytes(),
+        ) && self
+            .version
+            .as_ref()
+            .is_none_or(|version| matches(version.as_bytes(), item.version.as_bytes()))
+    }
+
+    /// Returns the name of the only crate that the selector can select or nothing if the name is a
+    /// pattern.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        (!self.name.contains(['*', '?'])).then_some(self.name.as_str())
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ParseSelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, version) = match s.trim().split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (s.trim(), None),
+        };
+
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '*' | '?'))
+            || version.is_some_and(str::is_empty)
+        {
+            return Err(ParseSelectorError::Malformed);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            version: version.map(ToOwned::to_owned),
+        })
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Reads the selectors recorded at `path`, one on each line. Blank lines and lines that start with
+/// `#` are ignored. Nothing is selected if there is no file.
+pub async fn read(path: &Path) -> Result<Vec<Selector>, io::Error> {
+    let recorded = match fs::read_to_string(path).await {
+        Ok(recorded) => recorded,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    recorded
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse().map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} holds an invalid selector: {error}", path.display()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Records `selectors` at `path`, one on each line.
+pub async fn write(path: &Path, selectors: &[Selector]) -> Result<(), io::Error> {
+    let mut recorded = String::new();
+    for each in selectors {
+        recorded.push_str(&each.to_string());
+        recorded.push('\n');
+    }
+
+    fs::write(path, recorded).await
+}
+
+/// Returns a crate name as crates.io compares it.
+fn normalise(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// Returns true if `text` matches the glob `pattern`.
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern and the text that it was tried against.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(b'?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // The `*` consumes one more character.
+                Some((star, consumed)) => {
+                    backtrack = Some((star, consumed + 1));
+                    p = star + 1;
+                    t = consumed + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
@@ -0,0 +1,87 @@
+use super::{read, write, ParseSelectorError, Selector};
+use crate::{digest::Digest, registry::index::package::Crate};
+use tempfile::TempDir;
+use tokio::fs;
+
+fn crate_(name: &str, version: &str) -> Crate {
+    Crate {
+        name: name.to_owned(),
+        version: version.to_owned(),
+        checksum: Digest::Sha256([0; 32]),
+        yanked: false,
+        dependencies: None,
+    }
+}
+
+fn selector(selector: &str) -> Selector {
+    selector.parse().expect("failed to parse selector")
+}
+
+#[test]
+fn test_parse_selector() {
+    assert_eq!(selector("serde").to_string(), "serde");
+    assert_eq!(selector("serde@1.0.0").to_string(), "serde@1.0.0");
+    assert_eq!(selector("serde").name(), Some("serde"));
+    assert_eq!(selector("serde_*").name(), None);
+
+    for malformed in ["", "@1.0.0", "serde@", "ser/de"] {
+        assert!(
+            matches!(
+                malformed.parse::<Selector>(),
+                Err(ParseSelectorError::Malformed)
+            ),
+            "{malformed} is not malformed"
+        );
+    }
+}
+
+#[test]
+fn test_selects() {
+    assert!(selector("serde").selects(&crate_("serde", "1.0.0")));
+    assert!(!selector("serde").selects(&crate_("serde_json", "1.0.0")));
+    assert!(selector("Serde_Json").selects(&crate_("serde-json", "1.0.0")));
+
+    assert!(selector("serde@1.0.0").selects(&crate_("serde", "1.0.0")));
+    assert!(!selector("serde@1.0.0").selects(&crate_("serde", "1.0.1")));
+    assert!(selector("serde@1.*").selects(&crate_("serde", "1.0.1")));
+
+    assert!(selector("serde*").selects(&crate_("serde", "1.0.0")));
+    assert!(selector("serde*").selects(&crate_("serde_json", "1.0.0")));
+    assert!(selector("*json").selects(&crate_("serde_json", "1.0.0")));
+    assert!(selector("s?rde").selects(&crate_("serde", "1.0.0")));
+    assert!(selector("*e*e*").selects(&crate_("serde", "1.0.0")));
+    assert!(!selector("*json").selects(&crate_("serde", "1.0.0")));
+    assert!(!selector("s?rde").selects(&crate_("sde", "1.0.0")));
+}
+
+#[tokio::test]
+async fn test_read_and_write_selectors() {
+    let directory = TempDir::new().expect("failed to create temporary directory");
+    let path = directory.path().join("deny");
+    assert!(read(&path)
+        .await
+        .expect("failed to read selectors")
+        .is_empty());
+
+    let selectors = vec![selector("serde"), selector("tokio-*@1.*")];
+    write(&path, &selectors)
+        .await
+        .expect("failed to write selectors");
+    assert_eq!(
+        read(&path).await.expect("failed to read selectors"),
+        selectors
+    );
+
+    fs::write(&path, "# comment\n\nserde\n")
+        .await
+        .expect("failed to write selectors");
+    assert_eq!(
+        read(&path).await.expect("failed to read selectors"),
+        vec![selector("serde")]
+    );
+
+    fs::write(&path, "ser/de\n")
+        .await
+        .expect("failed to write selectors");
+    assert!(read(&path).await.is_err());
+}
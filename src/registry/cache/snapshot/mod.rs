@@ -0,0 +1,358 @@
+#[cfg(test)]
+mod tests;
+
+use super::{layout::Layout, report::Effect};
+use crate::{
+    digest::Digest,
+    file::{self, Durability, Permissions},
+    registry::index::{package::Crate, GetPackagesError},
+};
+use ahash::AHashMap;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, task};
+use tracing::{debug, warn};
+
+/// The name of the file in the directory of a snapshot that describes it.
+const MANIFEST_FILENAME: &str = "snapshot.json";
+
+/// The name of the directory in the directory of a snapshot that holds its crates.
+const CRATES_SUBDIRECTORY: &str = "crates";
+
+/// The error type for creating, listing, and restoring snapshots.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    Io(io::Error),
+    Git(git2::Error),
+    GetPackages(GetPackagesError),
+    /// The manifest of a snapshot could not be read.
+    Manifest(serde_json::Error),
+    /// The name can not be used as the name of a directory.
+    InvalidName(String),
+    /// A snapshot with the name already exists.
+    Exists(String),
+    /// There is no snapshot with the name.
+    NotFound(String),
+    /// The snapshot was taken while the crates directory had another layout.
+    LayoutMismatch {
+        snapshot: Layout,
+        cache: Layout,
+    },
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<git2::Error> for SnapshotError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<GetPackagesError> for SnapshotError {
+    fn from(error: GetPackagesError) -> Self {
+        Self::GetPackages(error)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Manifest(error)
+    }
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Git(error) => error.fmt(f),
+            Self::GetPackages(error) => error.fmt(f),
+            Self::Manifest(_) => write!(f, "the manifest of the snapshot is corrupt"),
+            Self::InvalidName(name) => write!(f, "{name:?} is not a valid snapshot name"),
+            Self::Exists(name) => write!(f, "the snapshot {name} already exists"),
+            Self::NotFound(name) => write!(f, "there is no snapshot named {name}"),
+            Self::LayoutMismatch { snapshot, cache } => write!(
+                f,
+                "the snapshot uses the {snapshot} layout but the cache uses the {cache} layout"
+            ),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::Git(error) => error.source(),
+            Self::GetPackages(error) => error.source(),
+            Self::Manifest(error) => Some(error),
+            Self::InvalidName(_)
+            | Self::Exists(_)
+            | Self::NotFound(_)
+            | Self::LayoutMismatch { .. } => None,
+        }
+    }
+}
+
+/// A named point-in-time state of a cache.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Snapshot {
+    /// The name of the snapshot.
+    pub name: String,
+    /// The index commit that the cache held.
+    pub commit: String,
+    /// The layout of the crates directory.
+    pub layout: Layout,
+    /// The number of seconds between the Unix epoch and the time that the snapshot was taken.
+    pub time: u64,
+    /// The number of files in the crates directory.
+    pub files: usize,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.name, self.commit, self.time, self.files
+        )
+    }
+}
+
+/// Counts the files that were linked or copied into a directory.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Linked {
+    /// The number of files that were hard linked.
+    pub linked: usize,
+    /// The number of files that were copied because they could not be hard linked.
+    pub copied: usize,
+}
+
+/// Returns the directory of the snapshot named `name` in `snapshots`. The name must be a single
+/// component that is not hidden.
+fn directory(snapshots: &Path, name: &str) -> Result<PathBuf, SnapshotError> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(SnapshotError::InvalidName(name.to_owned()));
+    }
+
+    Ok(snapshots.join(name))
+}
+
+/// Returns the crates directory of the snapshot named `name` in `snapshots`.
+pub fn crates_path(snapshots: &Path, name: &str) -> Result<PathBuf, SnapshotError> {
+    Ok(directory(snapshots, name)?.join(CRATES_SUBDIRECTORY))
+}
+
+/// Hard links every file in `from` into the same place in `to`. Files that already exist in `to`
+/// are kept and files that can not be hard linked (eg. because `to` is on another file system) are
+/// copied.
+async fn link_tree(from: &Path, to: &Path) -> Result<Linked, io::Error> {
+    let mut linked = Linked::default();
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative) = pending.pop() {
+        let mut directory = match fs::read_dir(from.join(&relative)).await {
+            Ok(directory) => directory,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+
+        fs::create_dir_all(to.join(&relative)).await?;
+        while let Some(entry) = directory.next_entry().await? {
+            let relative = relative.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                pending.push(relative);
+                continue;
+            }
+
+            let destination = to.join(&relative);
+            if fs::symlink_metadata(&destination).await.is_ok() {
+                continue;
+            }
+
+            if let Err(error) = fs::hard_link(entry.path(), &destination).await {
+                debug!(
+                    "copying {} because it could not be linked: {}",
+                    relative.to_string_lossy(),
+                    error
+                );
+                fs::copy(entry.path(), &destination).await?;
+                linked.copied += 1;
+            } else {
+                linked.linked += 1;
+            }
+        }
+    }
+
+    Ok(linked)
+}
+
+/// Takes a snapshot named `name` in `snapshots` of the crates directory at `crates` that holds
+/// crates for the index commit `commit` in `layout`. The snapshot is dated `time`.
+pub async fn create(
+    snapshots: &Path,
+    crates: &Path,
+    name: &str,
+    commit: String,
+    layout: Layout,
+    time: u64,
+) -> Result<Snapshot, SnapshotError> {
+    let directory = directory(snapshots, name)?;
+    if fs::symlink_metadata(directory.join(MANIFEST_FILENAME))
+        .await
+        .is_ok()
+    {
+        return Err(SnapshotError::Exists(name.to_owned()));
+    }
+
+    let linked = link_tree(crates, &directory.join(CRATES_SUBDIRECTORY)).await?;
+    if linked.copied > 0 {
+        warn!(
+            "copied {} files into the snapshot because they could not be linked",
+            linked.copied
+        );
+    }
+
+    // The manifest is written last so that a snapshot that was interrupted is not listed and is
+    // completed when it is created again.
+    let snapshot = Snapshot {
+        name: name.to_owned(),
+        commit,
+        layout,
+        time,
+        files: linked.linked + linked.copied,
+    };
+    file::write(
+        &directory.join(MANIFEST_FILENAME),
+        serde_json::to_vec(&snapshot).expect("failed to serialise snapshot"),
+        Durability::Rename,
+        Permissions::default(),
+    )
+    .await?;
+
+    Ok(snapshot)
+}
+
+/// Returns the snapshot named `name` in `snapshots`.
+pub async fn find(snapshots: &Path, name: &str) -> Result<Snapshot, SnapshotError> {
+    match fs::read(directory(snapshots, name)?.join(MANIFEST_FILENAME)).await {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Err(SnapshotError::NotFound(name.to_owned()))
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Returns the snapshots in `snapshots` ordered by the time that they were taken.
+pub async fn list(snapshots: &Path) -> Result<Vec<Snapshot>, SnapshotError> {
+    let mut directory = match fs::read_dir(snapshots).await {
+        Ok(directory) => directory,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut list = Vec::new();
+    while let Some(entry) = directory.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        match find(snapshots, &name).await {
+            Ok(snapshot) => list.push(snapshot),
+            Err(SnapshotError::NotFound(_) | SnapshotError::InvalidName(_)) => {
+                debug!("ignored {} because it is not a snapshot", name);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    list.sort_by(|a, b| (a.time, &a.name).cmp(&(b.time, &b.name)));
+    Ok(list)
+}
+
+/// Links the crates of `snapshot` in `snapshots` that are missing from the crates directory at
+/// `crates` back into it.
+pub async fn restore(
+    snapshots: &Path,
+    crates: &Path,
+    snapshot: &Snapshot,
+) -> Result<Linked, SnapshotError> {
+    Ok(link_tree(&crates_path(snapshots, &snapshot.name)?, crates).await?)
+}
+
+/// A crate that differs between two states of a cache.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct Difference {
+    pub name: String,
+    pub version: String,
+    pub change: Effect,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.change, self.name, self.version)
+    }
+}
+
+/// Returns the checksums of the crates in `crates` that are stored in the crates directory at
+/// `directory` with `layout`, keyed by their name and version.
+pub async fn stored(
+    directory: PathBuf,
+    layout: Layout,
+    crates: Vec<Crate>,
+) -> Result<AHashMap<(String, String), Digest>, io::Error> {
+    task::spawn_blocking(move || {
+        let mut stored = AHashMap::new();
+        for each in crates {
+            let path = directory.join(layout.locate(&each.name, &each.version, &each.checksum));
+            if path.try_exists()? {
+                stored.insert((each.name, each.version), each.checksum);
+            }
+        }
+
+        Ok(stored)
+    })
+    .await
+    .expect("panicked while finding stored crates")
+}
+
+/// Returns the crates that were added, removed, or changed between the stored crates `from` and
+/// `to` ordered by name and version. A crate is changed if its checksum is different.
+#[must_use]
+pub fn compare(
+    from: &AHashMap<(String, String), Digest>,
+    to: &AHashMap<(String, String), Digest>,
+) -> Vec<Difference> {
+    let removed = from
+        .keys()
+        .filter(|key| !to.contains_key(*key))
+        .map(|key| (key, Effect::Removed));
+    let changed = to.iter().filter_map(|(key, checksum)| match from.get(key) {
+        None => Some((key, Effect::Added)),
+        Some(previous) if previous != checksum => Some((key, Effect::Modified)),
+        Some(_) => None,
+    });
+
+    removed
+        .chain(changed)
+        .map(|((name, version), change)| Difference {
+            name: name.clone(),
+            version: version.clone(),
+            change,
+        })
+        .sorted_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)))
+        .collect()
+}
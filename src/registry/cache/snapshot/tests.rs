@@ -0,0 +1,48 @@
+use super::{compare, Difference};
+use crate::{
+    digest::{Algorithm, Digest},
+    registry::cache::report::Effect,
+};
+use ahash::AHashMap;
+
+/// Returns stored crates with the checksums of their contents.
+fn stored(crates: &[(&str, &str, &[u8])]) -> AHashMap<(String, String), Digest> {
+    crates
+        .iter()
+        .map(|(name, version, contents)| {
+            (
+                ((*name).to_owned(), (*version).to_owned()),
+                Digest::compute(Algorithm::Sha256, contents),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_compare() {
+    let from = stored(&[
+        ("a", "0.0.1", b"0"),
+        ("b", "0.0.1", b"0"),
+        ("c", "0.0.1", b"0"),
+    ]);
+    let to = stored(&[
+        ("a", "0.0.1", b"0"),
+        ("b", "0.0.1", b"1"),
+        ("d", "0.0.1", b"0"),
+    ]);
+
+    let difference = |name: &str, change| Difference {
+        name: name.to_owned(),
+        version: "0.0.1".to_owned(),
+        change,
+    };
+    assert_eq!(
+        compare(&from, &to),
+        vec![
+            difference("b", Effect::Modified),
+            difference("c", Effect::Removed),
+            difference("d", Effect::Added),
+        ]
+    );
+    assert!(compare(&from, &from).is_empty());
+}
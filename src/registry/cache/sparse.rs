@@ -0,0 +1,123 @@
+use crate::registry::index::Index;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tokio::task;
+
+/// The name of the file that holds the configuration of an index.
+const CONFIGURATION_FILENAME: &str = "config.json";
+
+/// The error type for exporting a sparse index.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ExportSparseError {
+    Git(git2::Error),
+    Io(io::Error),
+}
+
+impl From<git2::Error> for ExportSparseError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<io::Error> for ExportSparseError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for ExportSparseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ExportSparseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::Io(error) => error.source(),
+        }
+    }
+}
+
+/// Writes `contents` to `path` or removes the file at `path` if there are no contents. Files are
+/// replaced atomically so that a file server never serves a partially written file.
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn write(path: &Path, contents: Option<&[u8]>) -> Result<(), io::Error> {
+    if let Some(contents) = contents {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(&temporary, path)
+    } else {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Exports every file held by `index` to `path` using the layout of a sparse index.
+///
+/// The export is written to a temporary directory that replaces `path` once every file has been
+/// written.
+pub async fn export(index: &Index, path: &Path) -> Result<(), ExportSparseError> {
+    let temporary = path.with_extension("tmp");
+
+    let (destination, stale) = (temporary.clone(), temporary.clone());
+    task::spawn_blocking(move || match fs::remove_dir_all(stale) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    })
+    .await
+    .expect("panicked while removing stale export")?;
+
+    index
+        .visit(None, move |file, contents| {
+            write(&destination.join(file), contents).map_err(ExportSparseError::from)
+        })
+        .await?;
+
+    let path = path.to_owned();
+    task::spawn_blocking(move || {
+        match fs::remove_dir_all(&path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => (),
+        }
+
+        fs::rename(temporary, path)
+    })
+    .await
+    .expect("panicked while replacing export")?;
+
+    Ok(())
+}
+
+/// Updates the packages at `packages` and the configuration in the sparse index at `path` to
+/// match `index`. Packages that no longer exist in `index` are removed.
+pub async fn update(
+    index: &Index,
+    path: &Path,
+    mut packages: Vec<PathBuf>,
+) -> Result<(), ExportSparseError> {
+    packages.push(PathBuf::from(CONFIGURATION_FILENAME));
+
+    let path = path.to_owned();
+    index
+        .visit(Some(packages), move |file, contents| {
+            write(&path.join(file), contents).map_err(ExportSparseError::from)
+        })
+        .await
+}
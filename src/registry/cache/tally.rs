@@ -0,0 +1,117 @@
+use super::Outcome;
+use crate::notify;
+use ahash::AHashMap;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Mutex,
+};
+use tracing::info;
+
+/// Counts the attempts to download crates from a source.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SourceStatistics {
+    /// The number of crates that were downloaded from the source.
+    pub downloads: usize,
+    /// The number of crates that could not be downloaded from the source.
+    pub failures: usize,
+}
+
+/// Records the progress of an operation that downloads crates.
+#[derive(Debug, Default)]
+pub struct Tally {
+    /// The number of crates that were checked.
+    checked: AtomicUsize,
+    /// The number of crates that were downloaded.
+    downloaded: AtomicUsize,
+    /// The number of crates that were removed.
+    removed: AtomicUsize,
+    /// The number of bytes that were downloaded.
+    bytes: AtomicU64,
+    /// The number of crates that could not be downloaded from any source.
+    failed: AtomicUsize,
+    /// The statistics for each source that crates were downloaded from.
+    sources: Mutex<AHashMap<String, SourceStatistics>>,
+}
+
+impl Tally {
+    /// Records a crate that was checked.
+    pub fn check(&self) {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a crate of `size` bytes that was downloaded.
+    pub fn download(&self, size: u64) {
+        self.downloaded.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+        notify::transferred(size);
+    }
+
+    /// Records a crate that was removed.
+    pub fn remove(&self) {
+        self.removed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a crate that could not be downloaded from any source.
+    pub fn fail(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an attempt to download a crate from a source.
+    pub fn record(&self, source: &str, success: bool) {
+        let mut sources = self.sources.lock().expect("lock is poisoned");
+        let statistics = sources.entry(source.to_owned()).or_default();
+        if success {
+            statistics.downloads += 1;
+        } else {
+            statistics.failures += 1;
+        }
+    }
+
+    /// Returns true if any crate could not be downloaded from any source.
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed) > 0
+    }
+
+    /// Adds the counts and statistics that were recorded by `other`.
+    pub fn absorb(&self, other: Self) {
+        self.checked
+            .fetch_add(other.checked.into_inner(), Ordering::Relaxed);
+        self.downloaded
+            .fetch_add(other.downloaded.into_inner(), Ordering::Relaxed);
+        self.removed
+            .fetch_add(other.removed.into_inner(), Ordering::Relaxed);
+        self.bytes
+            .fetch_add(other.bytes.into_inner(), Ordering::Relaxed);
+        self.failed
+            .fetch_add(other.failed.into_inner(), Ordering::Relaxed);
+
+        let mut sources = self.sources.lock().expect("lock is poisoned");
+        for (source, statistics) in other.sources.into_inner().expect("lock is poisoned") {
+            let each = sources.entry(source).or_default();
+            each.downloads += statistics.downloads;
+            each.failures += statistics.failures;
+        }
+    }
+
+    /// Reports the statistics for each source and returns the outcome of the operation.
+    pub fn finish(&self) -> Outcome {
+        let sources = self.sources.lock().expect("lock is poisoned");
+        let mut sources = sources.iter().collect::<Vec<_>>();
+        sources.sort_unstable_by_key(|(source, _)| *source);
+
+        for (source, statistics) in sources {
+            info!(
+                "downloaded {} crates from {} and {} failed",
+                statistics.downloads, source, statistics.failures
+            );
+        }
+
+        Outcome {
+            checked: self.checked.load(Ordering::Relaxed),
+            downloaded: self.downloaded.load(Ordering::Relaxed),
+            removed: self.removed.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+pub mod tests;
+
+use git2::{Config, Cred, CredentialType, ErrorCode, RemoteCallbacks};
+use std::{env, path::PathBuf};
+
+/// The repository configuration key that holds the SSH identity file.
+const IDENTITY_KEY: &str = "crateful.identity";
+
+/// The repository configuration key that holds the user name for HTTPS remotes.
+const USERNAME_KEY: &str = "crateful.username";
+
+/// The repository configuration key that holds the environment variable that holds the token for
+/// HTTPS remotes.
+const TOKEN_ENV_KEY: &str = "crateful.tokenEnv";
+
+/// The user name that is given to a remote when none is known.
+const DEFAULT_USERNAME: &str = "git";
+
+/// Returns the value of a configuration entry or nothing if the entry does not exist.
+fn optional<T>(result: Result<T, git2::Error>) -> Result<Option<T>, git2::Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if error.code() == ErrorCode::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Describes how the remote of an index is authenticated. Secrets are never recorded; only where
+/// they can be found is.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Authentication {
+    /// The private key that SSH remotes are authenticated with. The keys of the SSH agent are used
+    /// if there is none.
+    pub identity: Option<PathBuf>,
+    /// The user name that HTTPS remotes are authenticated with. The user name in the URL of the
+    /// remote is used if there is none.
+    pub username: Option<String>,
+    /// The environment variable that holds the password or token that HTTPS remotes are
+    /// authenticated with. The Git credential helpers are asked for credentials if there is none.
+    pub token_env: Option<String>,
+}
+
+impl Authentication {
+    /// Reads the authentication that is recorded in `config`.
+    ///
+    /// # Async
+    ///
+    /// This is a blocking function and must not be used from an asynchronous context.
+    pub fn read(config: &Config) -> Result<Self, git2::Error> {
+        Ok(Self {
+            identity: optional(config.get_path(IDENTITY_KEY))?,
+            username: optional(config.get_string(USERNAME_KEY))?,
+            token_env: optional(config.get_string(TOKEN_ENV_KEY))?,
+        })
+    }
+
+    /// Records the authentication in `config` so that it can be read again.
+    ///
+    /// # Async
+    ///
+    /// This is a blocking function and must not be used from an asynchronous context.
+    pub fn record(&self, config: &mut Config) -> Result<(), git2::Error> {
+        if let Some(identity) = &self.identity {
+            config.set_str(IDENTITY_KEY, &identity.to_string_lossy())?;
+        }
+
+        if let Some(username) = &self.username {
+            config.set_str(USERNAME_KEY, username)?;
+        }
+
+        if let Some(token_env) = &self.token_env {
+            config.set_str(TOKEN_ENV_KEY, token_env)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns callbacks that authenticate with a remote. `config` is the configuration that the
+    /// Git credential helpers are found in.
+    ///
+    /// Each kind of credentials is only given once because credentials are requested again when
+    /// they are rejected.
+    pub fn callbacks(&self, config: Config) -> RemoteCallbacks<'static> {
+        let authentication = self.clone();
+        let mut attempted = CredentialType::empty();
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username, allowed| {
+            let known = authentication.username.as_deref().or(username);
+            let username = known.unwrap_or(DEFAULT_USERNAME);
+
+            if allowed.contains(CredentialType::USERNAME) {
+                return Cred::username(username);
+            }
+
+            let allowed = allowed - attempted;
+            if allowed.contains(CredentialType::SSH_KEY) {
+                attempted |= CredentialType::SSH_KEY;
+                return authentication.identity.as_deref().map_or_else(
+                    || Cred::ssh_key_from_agent(username),
+                    |identity| Cred::ssh_key(username, None, identity, None),
+                );
+            }
+
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                attempted |= CredentialType::USER_PASS_PLAINTEXT;
+                return match &authentication.token_env {
+                    Some(variable) => {
+                        let token = env::var(variable).map_err(|_| {
+                            git2::Error::from_str(&format!(
+                                "the environment variable {variable} does not hold a token"
+                            ))
+                        })?;
+                        Cred::userpass_plaintext(username, &token)
+                    }
+                    None => Cred::credential_helper(&config, url, known),
+                };
+            }
+
+            Err(git2::Error::from_str(
+                "the remote did not accept any of the available credentials",
+            ))
+        });
+
+        callbacks
+    }
+}
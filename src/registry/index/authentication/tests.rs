@@ -0,0 +1,36 @@
+use super::*;
+use git2::Repository;
+
+#[test]
+fn test_record_and_read_authentication() {
+    let directory = tempfile::tempdir().expect("failed to create temporary directory");
+    let repository = Repository::init_bare(directory.path()).expect("failed to create repository");
+    let mut config = repository.config().expect("failed to open configuration");
+
+    let authentication = Authentication {
+        identity: Some(PathBuf::from("/home/user/.ssh/id_ed25519")),
+        username: Some(String::from("mirror")),
+        token_env: Some(String::from("INDEX_TOKEN")),
+    };
+    authentication
+        .record(&mut config)
+        .expect("failed to record authentication");
+
+    let config = repository.config().expect("failed to open configuration");
+    assert_eq!(
+        Authentication::read(&config).expect("failed to read authentication"),
+        authentication
+    );
+}
+
+#[test]
+fn test_read_missing_authentication() {
+    let directory = tempfile::tempdir().expect("failed to create temporary directory");
+    let repository = Repository::init_bare(directory.path()).expect("failed to create repository");
+    let config = repository.config().expect("failed to open configuration");
+
+    assert_eq!(
+        Authentication::read(&config).expect("failed to read authentication"),
+        Authentication::default()
+    );
+}
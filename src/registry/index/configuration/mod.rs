@@ -1,7 +1,7 @@
 #[cfg(test)]
 pub mod tests;
 
-use crate::registry::index::package::Crate;
+use crate::{digest::Digest, registry::index::package::Crate};
 use serde::Deserialize;
 use std::{
     convert::Into,
@@ -35,8 +35,10 @@ impl Error for DeserialiseConfigurationError {
 
 #[derive(Debug)]
 pub struct TemplateUrlError {
-    source: url::ParseError,
-    crate_: Crate,
+    /// The error that the templated URL could not be parsed with or nothing if the template
+    /// requires a SHA-256 checksum and the crate has a different checksum.
+    source: Option<url::ParseError>,
+    crate_: Box<Crate>,
 }
 
 impl Display for TemplateUrlError {
@@ -44,38 +46,58 @@ impl Display for TemplateUrlError {
         write!(
             f,
             "failed to generate valid URL for crate with name {}, version {}, and checksum {}",
-            self.crate_.name,
-            self.crate_.version,
-            hex::encode(self.crate_.checksum.0)
+            self.crate_.name, self.crate_.version, self.crate_.checksum
         )
     }
 }
 
 impl Error for TemplateUrlError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.source)
+        self.source
+            .as_ref()
+            .map(|error| error as &(dyn Error + 'static))
     }
 }
 
 /// A registry index configuration.
+///
+/// Fields that are not recognised are ignored. They are preserved when the download template is
+/// rewritten because the configuration is never serialised from this type.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Hash)]
 pub struct Configuration {
     #[serde(rename(deserialize = "dl"))]
     pub template: String,
+    /// The URL of the web API of the registry. Registries that do not support publishing may not
+    /// have one.
+    #[serde(default)]
+    pub api: Option<Url>,
+    /// True if the registry requires credentials to download crates.
+    #[serde(default, rename(deserialize = "auth-required"))]
+    pub auth_required: bool,
 }
 
 impl Configuration {
     /// Returns the remote location of `crate_`.
     pub fn locate(&self, crate_: &Crate) -> Result<Url, TemplateUrlError> {
         let prefix = crate_.prefix();
-        let templated = self
+        let mut templated = self
             .template
             .as_str()
             .replace("{crate}", &crate_.name)
             .replace("{version}", &crate_.version)
             .replace("{prefix}", &prefix)
-            .replace("{lowerprefix}", &prefix.to_lowercase())
-            .replace("{sha256-checksum}", &hex::encode(&crate_.checksum.0));
+            .replace("{lowerprefix}", &prefix.to_lowercase());
+
+        if templated.contains("{sha256-checksum}") {
+            let Digest::Sha256(checksum) = crate_.checksum else {
+                return Err(TemplateUrlError {
+                    source: None,
+                    crate_: Box::new(crate_.clone()),
+                });
+            };
+
+            templated = templated.replace("{sha256-checksum}", &hex::encode(checksum));
+        }
 
         let string = if templated == self.template {
             // The documentation mentions that if none of the markers are present then
@@ -90,11 +112,20 @@ impl Configuration {
         // TODO: It would be ideal to guarantee that this is successful by validating the
         // configuration template and crates when they are each deserialised.
         Url::parse(&string).map_err(|error| TemplateUrlError {
-            source: error,
-            crate_: crate_.clone(),
+            source: Some(error),
+            crate_: Box::new(crate_.clone()),
         })
     }
 
+    /// Returns the host that crates are downloaded from or nothing if the download template does
+    /// not name one.
+    pub fn host(&self) -> Option<String> {
+        Url::parse(&self.template)
+            .ok()?
+            .host_str()
+            .map(ToOwned::to_owned)
+    }
+
     /// Deserialises a configuration from a slice.
     pub fn from_slice(slice: &[u8]) -> Result<Self, DeserialiseConfigurationError> {
         serde_json::from_slice(slice).map_err(Into::into)
@@ -1,5 +1,5 @@
 use super::*;
-use crate::digest::Sha256;
+use crate::digest::Digest;
 
 #[test]
 fn test_deserialise_configuration() {
@@ -10,6 +10,8 @@ fn test_deserialise_configuration() {
 
     let expected = Configuration {
         template: "https://static.crates.io/api/v1/crates".into(),
+        api: Some(Url::parse("https://crates.io").expect("failed to parse url")),
+        auth_required: false,
     };
 
     let output =
@@ -18,9 +20,46 @@ fn test_deserialise_configuration() {
     assert_eq!(output, expected);
 }
 
+#[test]
+fn test_deserialise_configuration_with_extended_fields() {
+    let data = r#"{
+  "dl": "https://registry.example/dl",
+  "api": "https://registry.example",
+  "auth-required": true,
+  "future": {"field": 1}
+}"#;
+
+    let expected = Configuration {
+        template: "https://registry.example/dl".into(),
+        api: Some(Url::parse("https://registry.example").expect("failed to parse url")),
+        auth_required: true,
+    };
+
+    let output =
+        Configuration::from_slice(data.as_bytes()).expect("failed to deserialise configuration");
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_configuration_host() {
+    let configuration = Configuration {
+        template: "https://static.crates.io/crates/{crate}/{version}/download".into(),
+        api: None,
+        auth_required: false,
+    };
+    assert_eq!(configuration.host().as_deref(), Some("static.crates.io"));
+
+    let configuration = Configuration {
+        template: "not a url".into(),
+        ..configuration
+    };
+    assert_eq!(configuration.host(), None);
+}
+
 #[test]
 fn test_deserialise_corrupt_configuration_with_missing_fields() {
-    let data = r#""#;
+    let data = r"";
     assert!(Configuration::from_slice(data.as_bytes()).is_err());
 }
 
@@ -29,16 +68,20 @@ fn test_get_default_crate_url() {
     let crate_ = Crate {
         name: String::from("example"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     let configuration = Configuration {
         template: "https://static.crates.io/api/v1/crates".into(),
+        api: None,
+        auth_required: false,
     };
 
     let expected = Url::parse("https://static.crates.io/api/v1/crates/example/1.0.0/download")
@@ -57,16 +100,20 @@ fn test_get_templated_crate_url() {
     let crate_ = Crate {
         name: String::from("EXAMPLE"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     let configuration = Configuration {
         template: "https://static.crates.io/api/v1/crates/{crate}/{version}/{prefix}/{lowerprefix}/{sha256-checksum}".into(),
+        api: None,
+        auth_required: false,
     };
 
     let expected =
@@ -1,20 +1,41 @@
+pub mod authentication;
 pub mod configuration;
 pub mod package;
+pub mod scope;
 
-use ahash::AHashMap;
+use crate::notify;
+use ahash::{AHashMap, AHashSet};
+use authentication::Authentication;
 use configuration::{Configuration, DeserialiseConfigurationError};
-use git2::{Branch, Delta, DiffDelta, FetchOptions, Oid, Repository};
+use futures::{future, stream, Stream, StreamExt};
+use git2::{
+    build::RepoBuilder, Branch, Buf, Commit, Config, Delta, DiffDelta, DiffFile, ErrorCode,
+    FetchOptions, ObjectType, Oid, Reference, Repository, Signature, Sort, Tree, TreeWalkMode,
+    TreeWalkResult,
+};
 use itertools::Itertools;
-use package::{Crate, CrateKey, Package};
+use package::{Crate, CrateKey, DeserialiseCrateError, DeserialisePackageError, Package};
+use scope::Scope;
 use std::{
     convert::Into,
     error::Error,
     fmt::{self, Debug, Display, Formatter},
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    io::{self, Write},
+    iter,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+use tokio::{
+    sync::mpsc::{self, error::TryRecvError},
+    task::{self, JoinHandle},
 };
-use tokio::task;
-use tracing::debug;
+use tracing::{debug, warn};
 use url::Url;
 
 #[derive(Debug)]
@@ -76,19 +97,92 @@ impl Error for CloneIndexError {
 /// A package is corrupt.
 #[derive(Debug)]
 pub struct CorruptPackageError {
-    source: package::DeserialisePackageError,
+    source: DeserialisePackageError,
     path: PathBuf,
 }
 
 impl Display for CorruptPackageError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "corrupt package at {}", self.path.to_string_lossy())
+        let path = self.path.to_string_lossy();
+        match &self.source {
+            DeserialisePackageError::Json {
+                source: DeserialiseCrateError::InvalidField(error),
+                line,
+            } => write!(f, "corrupt package at {path}:{line}: {error}"),
+            DeserialisePackageError::Json { source: _, line } => {
+                write!(f, "corrupt package at {path}:{line}: invalid json")
+            }
+            DeserialisePackageError::Utf8(_) => {
+                write!(f, "corrupt package at {path}: invalid utf-8")
+            }
+        }
     }
 }
 
 impl Error for CorruptPackageError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.source)
+        match &self.source {
+            DeserialisePackageError::Json {
+                source: DeserialiseCrateError::Json(error),
+                line: _,
+            } => Some(error),
+            DeserialisePackageError::Json {
+                source: DeserialiseCrateError::InvalidField(_),
+                line: _,
+            } => None,
+            DeserialisePackageError::Utf8(error) => Some(error),
+        }
+    }
+}
+
+/// Decides whether a corrupt line in a package fails the operation that reads it or is skipped.
+#[derive(Clone, Debug, Default)]
+struct Leniency {
+    enabled: bool,
+    /// The number of corrupt lines that were skipped.
+    skipped: Arc<AtomicUsize>,
+}
+
+impl Leniency {
+    /// Parses the package at `path` in the index. The dependencies of its crates are retained if
+    /// `dependencies` is true. Returns the package and whether any line was skipped.
+    fn parse(
+        &self,
+        slice: &[u8],
+        path: &Path,
+        dependencies: bool,
+    ) -> Result<(Package, bool), CorruptPackageError> {
+        if !self.enabled {
+            let package =
+                Package::from_slice(slice, dependencies).map_err(|error| CorruptPackageError {
+                    source: error,
+                    path: path.to_path_buf(),
+                })?;
+            return Ok((package, false));
+        }
+
+        let (package, errors) = Package::from_slice_lenient(slice, dependencies);
+        let skipped = !errors.is_empty();
+        self.skip(path, errors);
+        Ok((package, skipped))
+    }
+
+    /// Warns about and counts the corrupt lines that were skipped in the package at `path`.
+    fn skip(&self, path: &Path, errors: Vec<DeserialisePackageError>) {
+        for error in errors {
+            let error = CorruptPackageError {
+                source: error,
+                path: path.to_path_buf(),
+            };
+
+            if let Some(source) = error.source() {
+                warn!("skipped a line: {}: {}", error, source);
+            } else {
+                warn!("skipped a line: {}", error);
+            }
+
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
     }
 }
 
@@ -137,6 +231,9 @@ pub enum GetUpdateError {
     /// Implementation limitations prevent the index from being interacted with if it uses an
     /// encoding other than UTF-8.
     IndexUsesUnsupportedEncoding,
+    /// The remote index has no commit that was made at or before the time in seconds since the
+    /// Unix epoch that the update was staged to.
+    NoCommitAt(i64),
     UnexpectedIndexState,
 }
 
@@ -158,6 +255,10 @@ impl Display for GetUpdateError {
             Self::CorruptPackage(error) => Display::fmt(error, f),
             Self::Git(error) => Display::fmt(error, f),
             Self::IndexUsesUnsupportedEncoding => write!(f, "index uses unsupported encoding"),
+            Self::NoCommitAt(time) => write!(
+                f,
+                "the remote index has no commit made at or before {time} seconds since the epoch"
+            ),
             Self::UnexpectedIndexState => write!(f, "unexpected index state"),
         }
     }
@@ -168,7 +269,134 @@ impl Error for GetUpdateError {
         match self {
             Self::CorruptPackage(error) => error.source(),
             Self::Git(error) => error.source(),
-            Self::UnexpectedIndexState | Self::IndexUsesUnsupportedEncoding => None,
+            Self::UnexpectedIndexState
+            | Self::IndexUsesUnsupportedEncoding
+            | Self::NoCommitAt(_) => None,
+        }
+    }
+}
+
+/// The error type for importing the commits of a bundle into the index.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UnbundleError {
+    Git(git2::Error),
+    Io(io::Error),
+    /// The bundle is not a Git bundle.
+    Malformed,
+    /// The bundle depends on a commit that the index does not hold.
+    MissingPrerequisite(Oid),
+    /// The commits of the bundle do not follow on from the latest commit of the index.
+    Diverged,
+    UnexpectedIndexState,
+}
+
+impl From<git2::Error> for UnbundleError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<io::Error> for UnbundleError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for UnbundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => Display::fmt(error, f),
+            Self::Io(error) => Display::fmt(error, f),
+            Self::Malformed => write!(f, "bundle is malformed"),
+            Self::MissingPrerequisite(id) => {
+                write!(
+                    f,
+                    "bundle depends on commit {id} which the index does not hold"
+                )
+            }
+            Self::Diverged => write!(f, "bundle does not follow on from the index"),
+            Self::UnexpectedIndexState => write!(f, "unexpected index state"),
+        }
+    }
+}
+
+impl Error for UnbundleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::Io(error) => Some(error),
+            Self::Malformed
+            | Self::MissingPrerequisite(_)
+            | Self::Diverged
+            | Self::UnexpectedIndexState => None,
+        }
+    }
+}
+
+/// Describes why an object that the index needs can not be read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum FaultKind {
+    /// The object does not exist.
+    Missing,
+    /// The object exists but can not be read or does not match its ID.
+    Corrupt,
+}
+
+/// An object that the index needs but can not be read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Fault {
+    /// The ID of the object.
+    pub id: Oid,
+    /// The kind of object that was expected.
+    pub expected: ObjectType,
+    pub kind: FaultKind,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::Missing => write!(f, "missing {} {}", self.expected, self.id),
+            FaultKind::Corrupt => write!(f, "corrupt {} {}", self.expected, self.id),
+        }
+    }
+}
+
+/// The error type for checking the integrity of the index.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CheckIntegrityError {
+    Git(git2::Error),
+    /// Objects that the index needs can not be read.
+    Damaged(Vec<Fault>),
+}
+
+impl From<git2::Error> for CheckIntegrityError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl Display for CheckIntegrityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => Display::fmt(error, f),
+            Self::Damaged(faults) => {
+                write!(
+                    f,
+                    "{} objects of the index are missing or corrupt",
+                    faults.len()
+                )
+            }
+        }
+    }
+}
+
+impl Error for CheckIntegrityError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::Damaged(_) => None,
         }
     }
 }
@@ -182,6 +410,8 @@ pub enum ChangeKind {
     Removed,
     /// A crate was modified.
     Modified,
+    /// Only the yanked status of a crate was changed. The crate describes the new status.
+    YankStatusChanged,
 }
 
 /// Describes a change to the index. Changes are safe to act on in parallel.
@@ -193,136 +423,230 @@ pub struct Change {
     pub kind: ChangeKind,
 }
 
-/// Generates changes from a series of deltas for individual package files.
-///
-/// # Async
-///
-/// This is a blocking function and must not be used from an asynchronous context.
-#[allow(clippy::too_many_lines)]
-fn changes_from_package_trees<'a>(
-    repository: &'a Repository,
-    deltas: impl Iterator<Item = DiffDelta<'a>> + 'a,
-) -> impl Iterator<Item = Result<Change, GetUpdateError>> + 'a {
-    deltas
-        // At the time of writing, Rust does not support try blocks and this makes it inconvenient
-        // to filter elements while propagating errors. This must done separately.
-        .filter(|diff| {
-            matches!(
-                diff.status(),
-                Delta::Added | Delta::Deleted | Delta::Modified
+/// The number of package deltas whose changes are generated together. The changes of a batch are
+/// only produced once every package in it has been parsed.
+const PACKAGE_DELTA_BATCH: usize = 1024;
+
+/// The least number of package deltas that a thread is given. Fewer deltas are not worth opening
+/// another handle to the repository for.
+const PACKAGE_DELTAS_PER_THREAD: usize = 64;
+
+/// Describes a change to an individual package file by the blobs that it was changed between.
+struct PackageDelta {
+    status: Delta,
+    old: Option<(Oid, PathBuf)>,
+    new: Option<(Oid, PathBuf)>,
+}
+
+impl PackageDelta {
+    /// Returns the package delta for `delta` or nothing if it does not add, remove, or modify a
+    /// package.
+    fn from_diff(delta: &DiffDelta<'_>) -> Option<Self> {
+        let file = |file: DiffFile<'_>| {
+            file.path()
+                .map(|path| (file.id(), path.to_path_buf()))
+                .expect("file path missing")
+        };
+
+        match delta.status() {
+            Delta::Added => Some(Self {
+                status: Delta::Added,
+                old: None,
+                new: Some(file(delta.new_file())),
+            }),
+            Delta::Deleted => Some(Self {
+                status: Delta::Deleted,
+                old: Some(file(delta.old_file())),
+                new: None,
+            }),
+            Delta::Modified => Some(Self {
+                status: Delta::Modified,
+                old: Some(file(delta.old_file())),
+                new: Some(file(delta.new_file())),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads and parses the package held by the blob `id`.
+    ///
+    /// # Async
+    ///
+    /// This is a blocking function and must not be used from an asynchronous context.
+    fn read<E>(
+        repository: &Repository,
+        leniency: &Leniency,
+        (id, path): &(Oid, PathBuf),
+    ) -> Result<(Package, bool), E>
+    where
+        E: From<git2::Error> + From<CorruptPackageError>,
+    {
+        Ok(leniency.parse(repository.find_blob(*id)?.content(), path, false)?)
+    }
+
+    /// Generates the changes to the crates of the package.
+    ///
+    /// # Async
+    ///
+    /// This is a blocking function and must not be used from an asynchronous context.
+    fn changes<E>(&self, repository: &Repository, leniency: &Leniency) -> Result<Vec<Change>, E>
+    where
+        E: From<git2::Error> + From<CorruptPackageError>,
+    {
+        let read = |file: &Option<(Oid, PathBuf)>| {
+            Self::read::<E>(
+                repository,
+                leniency,
+                file.as_ref().expect("package file missing"),
             )
-        })
-        .map(|diff| {
-            let (f, s, t) = match diff.status() {
-                Delta::Added => (
-                    Some(
-                        Package::from_slice(repository.find_blob(diff.new_file().id())?.content())
-                            .map_err(|error| CorruptPackageError {
-                                source: error,
-                                path: diff
-                                    .new_file()
-                                    .path()
-                                    .expect("new file path missing")
-                                    .to_path_buf(),
-                            })?
-                            .into_crates()
-                            .map(|on| Change {
-                                on,
-                                kind: ChangeKind::Added,
-                            }),
-                    ),
-                    None,
-                    None,
-                ),
+        };
+        let crates =
+            |file: &Option<(Oid, PathBuf)>| read(file).map(|(package, _)| package.into_crates());
+
+        match self.status {
+            Delta::Added => Ok(crates(&self.new)?
+                .map(|on| Change {
+                    on,
+                    kind: ChangeKind::Added,
+                })
+                .collect()),
 
-                Delta::Deleted => (
-                    None,
-                    Some(
-                        Package::from_slice(repository.find_blob(diff.old_file().id())?.content())
-                            .map_err(|error| CorruptPackageError {
-                                source: error,
-                                path: diff
-                                    .old_file()
-                                    .path()
-                                    .expect("old path missing")
-                                    .to_path_buf(),
-                            })?
-                            .into_crates()
-                            .map(|on| Change {
-                                on,
-                                kind: ChangeKind::Removed,
-                            }),
-                    ),
-                    None,
-                ),
-
-                Delta::Modified => {
-                    // If a package was modified then a crate could be added, removed, or
-                    // changed. The old crates are enumerated and compared with the new crates to
-                    // determine what change occurred.
-                    let mut after =
-                        Package::from_slice(repository.find_blob(diff.new_file().id())?.content())
-                            .map_err(|error| CorruptPackageError {
-                                source: error,
-                                path: diff
-                                    .new_file()
-                                    .path()
-                                    .expect("new file path missing")
-                                    .to_path_buf(),
-                            })?
-                            .into_crates()
-                            .map(|each| (each.key(), each))
-                            .collect::<AHashMap<CrateKey, Crate>>();
-
-                    let mut changes = Vec::new();
-                    for before in
-                        Package::from_slice(repository.find_blob(diff.old_file().id())?.content())
-                            .map_err(|error| CorruptPackageError {
-                                source: error,
-                                path: diff
-                                    .old_file()
-                                    .path()
-                                    .expect("old file path missing")
-                                    .to_path_buf(),
-                            })?
-                            .into_crates()
-                    {
-                        let key = before.key();
-                        if let Some(after) = after.remove(&key) {
-                            // If the key is present in both collections then either the crate was
-                            // not changed or the file was modified.
-                            if before.checksum != after.checksum {
-                                changes.push(Change {
-                                    on: after,
-                                    kind: ChangeKind::Modified,
-                                });
-                            }
+            Delta::Deleted => Ok(crates(&self.old)?
+                .map(|on| Change {
+                    on,
+                    kind: ChangeKind::Removed,
+                })
+                .collect()),
+
+            Delta::Modified => {
+                // If a package was modified then a crate could be added, removed, or changed. The
+                // old crates are enumerated and compared with the new crates to determine what
+                // change occurred.
+                let (after, skipped) = read(&self.new)?;
+                let mut after = after
+                    .into_crates()
+                    .map(|each| (each.key(), each))
+                    .collect::<AHashMap<CrateKey, Crate>>();
+
+                let mut changes = Vec::new();
+                for before in crates(&self.old)? {
+                    let key = before.key();
+                    if let Some(after) = after.remove(&key) {
+                        // If the key is present in both collections then either the crate was not
+                        // changed, the file was modified, or the crate was (un)yanked.
+                        let kind = if before.checksum != after.checksum {
+                            Some(ChangeKind::Modified)
+                        } else if before.yanked != after.yanked {
+                            Some(ChangeKind::YankStatusChanged)
                         } else {
-                            changes.push(Change {
-                                on: before,
-                                kind: ChangeKind::Removed,
-                            });
+                            None
+                        };
+
+                        if let Some(kind) = kind {
+                            changes.push(Change { on: after, kind });
                         }
+                    } else if !skipped {
+                        // A crate that may be on a skipped line is not removed.
+                        changes.push(Change {
+                            on: before,
+                            kind: ChangeKind::Removed,
+                        });
                     }
+                }
 
-                    // All remaining crates in `after` were added.
-                    changes.reserve(after.len());
-                    changes.extend(after.into_iter().map(|(_, on)| Change {
-                        on,
-                        kind: ChangeKind::Added,
-                    }));
+                // All remaining crates in `after` were added.
+                changes.reserve(after.len());
+                changes.extend(after.into_iter().map(|(_, on)| Change {
+                    on,
+                    kind: ChangeKind::Added,
+                }));
 
-                    (None, None, Some(changes.into_iter()))
-                }
+                Ok(changes)
+            }
 
-                _ => unreachable!(),
-            };
+            _ => unreachable!(),
+        }
+    }
+}
 
-            // This allows the function to "return" any of the iterators without collecting them or
-            // using dynamic dispatch.
-            Ok(f.into_iter()
-                .flatten()
-                .chain(s.into_iter().flatten().chain(t.into_iter().flatten())))
+/// Generates the changes of a batch of package deltas. The batch is shared between the calling
+/// thread, which parses packages with `repository`, and a thread for each of `handles`.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn changes_from_package_batch<E>(
+    repository: &Repository,
+    handles: &mut [Repository],
+    batch: &[PackageDelta],
+    leniency: &Leniency,
+) -> Result<Vec<Change>, E>
+where
+    E: From<git2::Error> + From<CorruptPackageError> + Send,
+{
+    let generate = |repository: &Repository, deltas: &[PackageDelta]| {
+        deltas
+            .iter()
+            .map(|delta| delta.changes::<E>(repository, leniency))
+            .flatten_ok()
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    // The shares are contiguous so that changes are generated in the same order as the deltas.
+    let mut shares = batch.chunks(batch.len().div_ceil(handles.len() + 1).max(1));
+    let local = shares.next().unwrap_or_default();
+    thread::scope(|scope| {
+        let workers = shares
+            .zip(handles.iter_mut())
+            .map(|(share, handle)| scope.spawn(move || generate(handle, share)))
+            .collect::<Vec<_>>();
+
+        let mut changes = generate(repository, local)?;
+        for worker in workers {
+            changes.extend(worker.join().expect("panicked while parsing packages")?);
+        }
+
+        Ok(changes)
+    })
+}
+
+/// Generates changes from a series of deltas for individual package files.
+///
+/// The deltas are collected before any package is parsed. Packages are then parsed in batches on
+/// as many threads as are available, each of which reads blobs through its own handle to the
+/// repository.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn changes_from_package_trees<'a, E>(
+    repository: &'a Repository,
+    deltas: impl Iterator<Item = DiffDelta<'a>>,
+    leniency: Leniency,
+) -> impl Iterator<Item = Result<Change, E>> + 'a
+where
+    E: From<git2::Error> + From<CorruptPackageError> + Send + 'a,
+{
+    let deltas = deltas
+        .filter_map(|delta| PackageDelta::from_diff(&delta))
+        .collect::<Vec<_>>();
+
+    // The calling thread parses packages too and so it needs one fewer handle than threads.
+    let extra = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(deltas.len() / PACKAGE_DELTAS_PER_THREAD)
+        .saturating_sub(1);
+    let mut handles = Vec::with_capacity(extra);
+
+    (0..deltas.len())
+        .step_by(PACKAGE_DELTA_BATCH)
+        .map(move |start| {
+            while handles.len() < extra {
+                handles.push(Repository::open(repository.path())?);
+            }
+
+            let end = deltas.len().min(start + PACKAGE_DELTA_BATCH);
+            changes_from_package_batch(repository, &mut handles, &deltas[start..end], &leniency)
         })
         .flatten_ok()
 }
@@ -330,7 +654,17 @@ fn changes_from_package_trees<'a>(
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum CommitUpdateError {
+    /// The download template could not be rewritten.
+    Configuration(GetConfigurationError),
     Git(git2::Error),
+    /// Not every change in the update was enumerated.
+    Incomplete,
+}
+
+impl From<GetConfigurationError> for CommitUpdateError {
+    fn from(error: GetConfigurationError) -> Self {
+        Self::Configuration(error)
+    }
 }
 
 impl From<git2::Error> for CommitUpdateError {
@@ -342,7 +676,9 @@ impl From<git2::Error> for CommitUpdateError {
 impl Display for CommitUpdateError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
+            Self::Configuration(error) => Display::fmt(error, f),
             Self::Git(error) => Display::fmt(error, f),
+            Self::Incomplete => write!(f, "not every change in the update was enumerated"),
         }
     }
 }
@@ -350,25 +686,72 @@ impl Display for CommitUpdateError {
 impl Error for CommitUpdateError {}
 
 /// represents a pending update to the index.
+///
+/// Changes are generated from the difference between the index and its remote as they are
+/// enumerated rather than held in memory. The index repository is locked until every change has
+/// been enumerated or the pending update is dropped.
 pub struct PendingUpdate {
     repository: Arc<Mutex<Repository>>,
     /// The target is the object that HEAD should point to if the update is committed.
     target: Oid,
-    changes: Vec<Change>,
+    /// The target does not descend from the latest commit from the remote index because the
+    /// history of the remote index was rewritten.
+    rewritten: bool,
+    changes: mpsc::Receiver<Result<Change, GetUpdateError>>,
+    /// Generates the changes. It returns true if every change was generated.
+    producer: JoinHandle<bool>,
 }
 
 impl PendingUpdate {
-    /// Returns the changes in the pending update.
-    pub fn changes(&self) -> impl Iterator<Item = &Change> {
-        self.changes.iter()
+    /// Returns a stream of the changes in the pending update. The stream ends after the first
+    /// error.
+    pub fn changes(&mut self) -> impl Stream<Item = Result<Change, GetUpdateError>> + '_ {
+        stream::poll_fn(|cx| self.changes.poll_recv(cx))
     }
 
-    /// Commits the update.
-    pub async fn commit(self) -> Result<(), CommitUpdateError> {
+    /// Commits the update. Every change must have been enumerated.
+    pub async fn commit(mut self) -> Result<(), CommitUpdateError> {
+        let complete = (&mut self.producer)
+            .await
+            .expect("panicked while collecting update");
+
+        if !complete || !matches!(self.changes.try_recv(), Err(TryRecvError::Disconnected)) {
+            return Err(CommitUpdateError::Incomplete);
+        }
+
         task::spawn_blocking(move || {
             let repo = self.repository.lock().expect("lock is poisoned");
-            repo.head()?
-                .set_target(self.target, "fast forward branch")?;
+
+            // The commit that was held is recorded so that the update can be rolled back.
+            if let Some(current) = upstream_commit(&repo)?.map(|commit| commit.id()) {
+                if current != self.target {
+                    repo.reference(
+                        Index::PREVIOUS_REFERENCE,
+                        current,
+                        true,
+                        "record previous commit",
+                    )?;
+                }
+            }
+
+            if let Some(template) = rewritten_template(&repo)? {
+                rewrite_configuration(&repo, &repo.find_commit(self.target)?, &template)?;
+            } else {
+                let message = if self.rewritten {
+                    "reset branch to rewritten history"
+                } else {
+                    "fast forward branch"
+                };
+
+                if let Some(mut head) = head_reference(&repo)? {
+                    head.set_target(self.target, message)?;
+                } else {
+                    // The first commit of an empty index starts its branch.
+                    let name = head_branch(&repo)?
+                        .ok_or_else(|| git2::Error::from_str("HEAD is not a branch"))?;
+                    repo.reference(&name, self.target, false, message)?;
+                }
+            }
 
             debug!("committed update to the index repository");
             Ok(())
@@ -419,15 +802,226 @@ impl Error for GetConfigurationError {
     }
 }
 
+/// Returns true if a delta describes a package file. Packages are only held in directories that
+/// are not hidden.
+fn holds_package(delta: &DiffDelta<'_>) -> bool {
+    delta.new_file().path().is_some_and(|path| {
+        path.parent().is_some_and(|parent| parent != Path::new(""))
+            && !path.to_string_lossy().starts_with('.')
+    })
+}
+
+/// Returns HEAD or nothing if HEAD is a branch that has no commits yet.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn head_reference(repository: &Repository) -> Result<Option<Reference<'_>>, git2::Error> {
+    match repository.head() {
+        Ok(head) => Ok(Some(head)),
+        Err(error) if error.code() == ErrorCode::UnbornBranch => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the tree of HEAD or nothing if HEAD is a branch that has no commits yet.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn head_tree(repository: &Repository) -> Result<Option<Tree<'_>>, git2::Error> {
+    head_reference(repository)?
+        .map(|head| head.peel_to_tree())
+        .transpose()
+}
+
+/// Returns the full name of the branch that HEAD is on, even if the branch has no commits yet.
+/// Nothing is returned if HEAD is not a branch.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn head_branch(repository: &Repository) -> Result<Option<String>, git2::Error> {
+    Ok(repository
+        .find_reference("HEAD")?
+        .symbolic_target()
+        .map(ToOwned::to_owned))
+}
+
+/// Returns the branch that HEAD tracks or nothing if HEAD is not a branch. HEAD may be a branch
+/// that has no commits yet.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn upstream_branch(repository: &Repository) -> Result<Option<Branch<'_>>, git2::Error> {
+    let Some(name) = head_branch(repository)? else {
+        return Ok(None);
+    };
+
+    let upstream = repository.branch_upstream_name(&name)?;
+    let upstream = upstream
+        .as_str()
+        .ok_or_else(|| git2::Error::from_str("the upstream branch is not valid UTF-8"))?;
+    repository
+        .find_reference(upstream)
+        .map(|reference| Some(Branch::wrap(reference)))
+}
+
+/// Returns the latest commit from the remote index. This is HEAD unless the download template of
+/// the index has been rewritten. Nothing is returned if the index has no commits yet.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn upstream_commit(repository: &Repository) -> Result<Option<Commit<'_>>, git2::Error> {
+    match repository.find_reference(Index::UPSTREAM_REFERENCE) {
+        Ok(reference) => reference.peel_to_commit().map(Some),
+        Err(error) if error.code() == ErrorCode::NotFound => head_reference(repository)?
+            .map(|head| head.peel_to_commit())
+            .transpose(),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the commit that the reference `name` points to or nothing if it does not exist.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn reference_target(repository: &Repository, name: &str) -> Result<Option<Oid>, git2::Error> {
+    match repository.find_reference(name) {
+        Ok(reference) => reference.peel_to_commit().map(|commit| Some(commit.id())),
+        Err(error) if error.code() == ErrorCode::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the rewritten download template if the index has been rewritten.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn rewritten_template(repository: &Repository) -> Result<Option<String>, GetConfigurationError> {
+    match repository.find_reference(Index::UPSTREAM_REFERENCE) {
+        Ok(_) => {
+            let blob = repository.find_blob(
+                repository
+                    .head()?
+                    .peel_to_tree()?
+                    .get_name(Index::CONFIGURATION_FILENAME)
+                    .ok_or(GetConfigurationError::NotFound)?
+                    .id(),
+            )?;
+
+            Ok(Some(Configuration::from_slice(blob.content())?.template))
+        }
+
+        Err(error) if error.code() == ErrorCode::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Commits a configuration with a rewritten download template on top of `upstream` and moves HEAD
+/// to the new commit. Every other part of the configuration is preserved.
+///
+/// # Async
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn rewrite_configuration(
+    repository: &Repository,
+    upstream: &Commit<'_>,
+    template: &str,
+) -> Result<(), GetConfigurationError> {
+    let tree = upstream.tree()?;
+    let blob = repository.find_blob(
+        tree.get_name(Index::CONFIGURATION_FILENAME)
+            .ok_or(GetConfigurationError::NotFound)?
+            .id(),
+    )?;
+
+    let mut configuration =
+        serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(blob.content())
+            .map_err(DeserialiseConfigurationError::from)?;
+    configuration.insert(
+        String::from("dl"),
+        serde_json::Value::String(template.to_owned()),
+    );
+
+    let blob = repository.blob(
+        &serde_json::to_vec_pretty(&configuration).expect("failed to serialise configuration"),
+    )?;
+    let mut builder = repository.treebuilder(Some(&tree))?;
+    builder.insert(Index::CONFIGURATION_FILENAME, blob, 0o100_644)?;
+    let tree = repository.find_tree(builder.write()?)?;
+
+    let signature = Signature::now(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_NAME"))?;
+    let commit = repository.commit(
+        None,
+        &signature,
+        &signature,
+        "Rewrite download template",
+        &tree,
+        &[upstream],
+    )?;
+
+    repository.reference(
+        Index::UPSTREAM_REFERENCE,
+        upstream.id(),
+        true,
+        "record upstream commit",
+    )?;
+    repository
+        .head()?
+        .set_target(commit, "rewrite download template")?;
+
+    debug!("rewrote the download template to {}", template);
+    Ok(())
+}
+
+/// The packages in a top-level directory of the index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Directory {
+    /// The name of the directory.
+    pub name: String,
+    /// The packages in the directory.
+    pub packages: Vec<Package>,
+}
+
 /// An index is a Git repository containing metadata for a crate registry.
 #[derive(Clone)]
 pub struct Index {
     repository: Arc<Mutex<Repository>>,
+    leniency: Leniency,
 }
 
 impl Index {
     pub const CONFIGURATION_FILENAME: &'static str = "config.json";
 
+    /// The reference to the latest commit from the remote index. This only exists when the
+    /// download template of the index has been rewritten.
+    pub const UPSTREAM_REFERENCE: &'static str = "refs/crateful/upstream";
+
+    /// The reference to the commit from the remote index that updates are staged to instead of the
+    /// latest commit. This only exists while the index is pinned.
+    const PIN_REFERENCE: &'static str = "refs/crateful/pin";
+
+    /// The reference to the commit from the remote index that was held before the last update was
+    /// committed.
+    const PREVIOUS_REFERENCE: &'static str = "refs/crateful/previous";
+
+    /// The name of the remote that the index is cloned from.
+    const REMOTE: &'static str = "origin";
+
+    /// The first line of a Git bundle.
+    const BUNDLE_SIGNATURE: &'static str = "# v2 git bundle\n";
+
+    /// The number of changes that are generated ahead of the changes that are being handled.
+    const CHANGE_CAPACITY: usize = 1024;
+
+    /// The number of directories that are read ahead of the directories that are being handled.
+    const DIRECTORY_CAPACITY: usize = 4;
+
     /// Open a registry index from a path.
     pub async fn from_path(path: PathBuf) -> Result<Self, OpenIndexError> {
         task::spawn_blocking(move || Repository::open(path))
@@ -435,29 +1029,98 @@ impl Index {
             .expect("panicked while opening the repository")
             .map(|repository| Self {
                 repository: Arc::new(Mutex::new(repository)),
+                leniency: Leniency::default(),
             })
             .map_err(Into::into)
     }
 
+    /// Skips corrupt lines in packages with a warning instead of failing if `lenient` is true.
+    pub const fn set_lenient(&mut self, lenient: bool) {
+        self.leniency.enabled = lenient;
+    }
+
+    /// Returns the number of corrupt lines in packages that were skipped.
+    #[must_use]
+    pub fn skipped(&self) -> usize {
+        self.leniency.skipped.load(Ordering::Relaxed)
+    }
+
     /// Open a registry index from a url. The registry index is cloned to `destination`.
-    pub async fn from_url(url: Url, destination: PathBuf) -> Result<Self, CloneIndexError> {
-        task::spawn_blocking(move || Repository::clone(url.as_str(), destination))
-            .await
-            .expect("panicked while cloning the repository")
-            .map(|repository| Self {
-                repository: Arc::new(Mutex::new(repository)),
-            })
-            .map_err(Into::into)
+    ///
+    /// The remote is authenticated as `authentication` describes. The authentication is recorded
+    /// in the repository so that updates are fetched with it too.
+    pub async fn from_url(
+        url: Url,
+        destination: PathBuf,
+        authentication: Authentication,
+    ) -> Result<Self, CloneIndexError> {
+        task::spawn_blocking(move || {
+            let mut options = FetchOptions::new();
+            // The credential helpers of the user are found in the global configuration.
+            let config = Config::open_default().or_else(|_| Config::new())?;
+            options.remote_callbacks(authentication.callbacks(config));
+            let repository = RepoBuilder::new()
+                .fetch_options(options)
+                .clone(url.as_str(), &destination)?;
+
+            authentication.record(&mut repository.config()?)?;
+            Ok::<_, git2::Error>(repository)
+        })
+        .await
+        .expect("panicked while cloning the repository")
+        .map(|repository| Self {
+            repository: Arc::new(Mutex::new(repository)),
+            leniency: Leniency::default(),
+        })
+        .map_err(Into::into)
+    }
+
+    /// Returns the URL of the remote that the index at `path` was cloned from. The configuration of
+    /// the repository is read without opening the repository so that the URL can be found when
+    /// the repository is corrupt.
+    pub async fn remote_url(path: PathBuf) -> Result<String, git2::Error> {
+        task::spawn_blocking(move || {
+            Config::open(&path.join(".git").join("config"))?
+                .get_string(&format!("remote.{}.url", Self::REMOTE))
+        })
+        .await
+        .expect("panicked while reading the remote")
     }
 
-    /// Returns the configuration for the index.
+    /// Returns how the remote of the index at `path` is authenticated. The configuration of the
+    /// repository is read as it is by [`Self::remote_url`].
+    pub async fn authentication(path: PathBuf) -> Result<Authentication, git2::Error> {
+        task::spawn_blocking(move || {
+            Authentication::read(&Config::open(&path.join(".git").join("config"))?)
+        })
+        .await
+        .expect("panicked while reading the authentication")
+    }
+
+    /// Returns the configuration for the index. The configuration of the remote index is returned
+    /// if the download template has been rewritten.
+    ///
+    /// The configuration of the latest commit that was fetched from the remote index is returned if
+    /// the index has no commits yet.
     pub async fn configuration(&self) -> Result<Configuration, GetConfigurationError> {
         let repo = self.repository.clone();
         task::spawn_blocking(move || {
             let repo = repo.lock().expect("lock is poisoned");
+            let commit = match upstream_commit(&repo)? {
+                Some(commit) => commit,
+                None => match upstream_branch(&repo) {
+                    Ok(Some(branch)) => branch.get().peel_to_commit()?,
+                    Ok(None) => return Err(GetConfigurationError::NotFound),
+                    Err(error) if error.code() == ErrorCode::NotFound => {
+                        return Err(GetConfigurationError::NotFound)
+                    }
+                    Err(error) => return Err(error.into()),
+                },
+            };
+
             let blob = repo.find_blob(
-                repo.head()?
-                    .peel_to_tree()?
+                commit
+                    .tree()?
                     .get_name(Self::CONFIGURATION_FILENAME)
                     .ok_or(GetConfigurationError::NotFound)?
                     .id(),
@@ -469,127 +1132,920 @@ impl Index {
         .expect("panicked while getting the configuration")
     }
 
-    /// Returns a list of packages that are currently held by the index.
-    pub async fn packages(&self) -> Result<Vec<Package>, GetPackagesError> {
+    /// Returns the latest commit from the remote index. The zero ID is returned if the index has
+    /// no commits yet.
+    pub async fn head(&self) -> Result<Oid, git2::Error> {
         let repo = self.repository.clone();
         task::spawn_blocking(move || {
             let repo = repo.lock().expect("lock is poisoned");
-            let tree = repo.head()?.peel_to_tree()?;
-
-            tree.iter()
-                .filter_map(|entry| {
-                    if let Some(name) = entry.name() {
-                        // Ignore hidden files.
-                        if name.starts_with('.') {
-                            return None;
-                        }
-                    }
+            let target = upstream_commit(&repo)?.map_or_else(Oid::zero, |commit| commit.id());
+            Ok(target)
+        })
+        .await
+        .expect("panicked while getting HEAD")
+    }
 
-                    entry.to_object(&repo).ok()
-                })
-                // Filter all files in the root directory that are not directories. This ensures
-                // that the configuration is not included.
-                .filter_map(|obj| obj.into_tree().ok())
-                .map(|tree| {
-                    repo.diff_tree_to_tree(None, Some(&tree), None)
-                        .map_err(GetPackagesError::from)
-                })
-                .map_ok(|diff| {
-                    diff.deltas()
-                        .into_iter()
-                        .map(|delta| {
-                            let file = delta.new_file();
-                            let blob = repo.find_blob(file.id())?;
-                            Ok::<Package, GetPackagesError>(
-                                Package::from_slice(blob.content()).map_err(|error| {
-                                    CorruptPackageError {
-                                        source: error,
-                                        path: file.path().expect("file missing path").to_path_buf(),
-                                    }
-                                })?,
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                        .into_iter()
-                })
-                .flatten_ok()
-                // Result::flatten is experimental.
-                .map(|result| match result {
-                    Ok(result) => result,
-                    Err(error) => Err(error),
-                })
+    /// Pins the index to the commit that `revision` names (eg. an object ID or a prefix of one) so
+    /// that updates are staged to it instead of the latest commit from the remote index. Returns
+    /// the ID of the commit.
+    pub async fn pin(&self, revision: String) -> Result<Oid, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let id = repo.revparse_single(&revision)?.peel_to_commit()?.id();
+            repo.reference(Self::PIN_REFERENCE, id, true, "pin index")?;
+            Ok(id)
+        })
+        .await
+        .expect("panicked while pinning the index")
+    }
+
+    /// Releases the pin so that updates are staged to the latest commit from the remote index
+    /// again. Returns false if the index was not pinned.
+    pub async fn unpin(&self) -> Result<bool, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let unpinned = match repo.find_reference(Self::PIN_REFERENCE) {
+                Ok(mut reference) => reference.delete().map(|()| true),
+                Err(error) if error.code() == ErrorCode::NotFound => Ok(false),
+                Err(error) => Err(error),
+            };
+            unpinned
+        })
+        .await
+        .expect("panicked while unpinning the index")
+    }
+
+    /// Returns the commit from the remote index that was held before the last update was committed
+    /// or nothing if no update has been committed.
+    pub async fn previous(&self) -> Result<Option<Oid>, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            reference_target(&repo, Self::PREVIOUS_REFERENCE)
+        })
+        .await
+        .expect("panicked while getting the previous commit")
+    }
+
+    /// Returns true if the index has no commits and no commits have been fetched from the remote
+    /// index. An empty index holds no packages.
+    pub async fn is_empty(&self) -> Result<bool, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            if upstream_commit(&repo)?.is_some() {
+                return Ok(false);
+            }
+
+            let empty = match upstream_branch(&repo) {
+                Ok(branch) => branch.is_none(),
+                Err(error) if error.code() == ErrorCode::NotFound => true,
+                Err(error) => return Err(error),
+            };
+
+            Ok(empty)
+        })
+        .await
+        .expect("panicked while getting HEAD")
+    }
+
+    /// Checks that every object that is reachable from HEAD, the latest commit from the remote
+    /// index, and the branch that HEAD tracks can be read and matches its ID.
+    ///
+    /// The entire history of the index is read. Objects that are shared by several commits are
+    /// only read once.
+    pub async fn check_integrity(&self) -> Result<(), CheckIntegrityError> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let odb = repo.odb()?;
+
+            let roots = [
+                head_reference(&repo)?.and_then(|head| head.target()),
+                upstream_commit(&repo)
+                    .ok()
+                    .flatten()
+                    .map(|commit| commit.id()),
+                upstream_branch(&repo)
+                    .ok()
+                    .flatten()
+                    .and_then(|branch| branch.get().target()),
+            ];
+            let mut pending = roots
+                .into_iter()
+                .flatten()
+                .map(|id| (id, ObjectType::Commit))
+                .collect::<Vec<_>>();
+
+            let mut visited = AHashSet::new();
+            let mut faults = Vec::new();
+            while let Some((id, expected)) = pending.pop() {
+                if !visited.insert(id) {
+                    continue;
+                }
+
+                // Reading an object checks that its contents match its ID.
+                let kind = match odb.read(id) {
+                    Ok(object) if object.kind() == expected => None,
+                    Err(error) if error.code() == ErrorCode::NotFound => Some(FaultKind::Missing),
+                    _ => Some(FaultKind::Corrupt),
+                };
+
+                let children = match (kind, expected) {
+                    (None, ObjectType::Commit) => repo.find_commit(id).map(|commit| {
+                        iter::once((commit.tree_id(), ObjectType::Tree))
+                            .chain(commit.parent_ids().map(|id| (id, ObjectType::Commit)))
+                            .collect()
+                    }),
+                    // Submodules are commits in other repositories so they are skipped.
+                    (None, ObjectType::Tree) => repo.find_tree(id).map(|tree| {
+                        tree.iter()
+                            .filter_map(|entry| {
+                                entry
+                                    .kind()
+                                    .filter(|kind| *kind != ObjectType::Commit)
+                                    .map(|kind| (entry.id(), kind))
+                            })
+                            .collect()
+                    }),
+                    _ => Ok(Vec::new()),
+                };
+
+                match (kind, children) {
+                    (Some(kind), _) => faults.push(Fault { id, expected, kind }),
+                    (None, Ok(children)) => pending.extend(children),
+                    (None, Err(_)) => faults.push(Fault {
+                        id,
+                        expected,
+                        kind: FaultKind::Corrupt,
+                    }),
+                }
+            }
+
+            debug!("checked the integrity of {} objects", visited.len());
+            if faults.is_empty() {
+                Ok(())
+            } else {
+                Err(CheckIntegrityError::Damaged(faults))
+            }
+        })
+        .await
+        .expect("panicked while checking the integrity of the index")
+    }
+
+    /// Returns the branch that HEAD is on and the remote branch that it tracks. Nothing is returned
+    /// if HEAD is not a branch.
+    pub async fn tracking(&self) -> Result<Option<(String, String)>, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let Some(branch) = head_branch(&repo)? else {
+                return Ok(None);
+            };
+
+            let upstream = repo.branch_upstream_name(&branch)?;
+            let upstream = String::from_utf8_lossy(&upstream);
+            let upstream = upstream.strip_prefix("refs/remotes/").unwrap_or(&upstream);
+            let branch = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+            Ok(Some((branch.to_owned(), upstream.to_owned())))
+        })
+        .await
+        .expect("panicked while getting the tracked branch")
+    }
+
+    /// Returns the rewritten download template if the download template has been rewritten.
+    pub async fn rewritten_template(&self) -> Result<Option<String>, GetConfigurationError> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            rewritten_template(&repo)
+        })
+        .await
+        .expect("panicked while getting the configuration")
+    }
+
+    /// Rewrites the download template of the index so that clients of the index download crates
+    /// from `template`. The download template of the remote index continues to be used to fetch
+    /// crates and the rewrite is reapplied whenever an update is committed.
+    pub async fn rewrite(&self, template: String) -> Result<(), GetConfigurationError> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let upstream = upstream_commit(&repo)?.ok_or(GetConfigurationError::NotFound)?;
+            rewrite_configuration(&repo, &upstream, &template)
+        })
+        .await
+        .expect("panicked while rewriting the configuration")
+    }
+
+    /// Returns the time that each package was last modified in seconds since the Unix epoch. The
+    /// times are keyed by the file names of the packages. Packages that were last modified before
+    /// the history of the index begins are attributed to its first commit.
+    ///
+    /// The entire history of the index is walked.
+    pub async fn modification_times(&self) -> Result<AHashMap<String, i64>, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let mut times = AHashMap::new();
+            if head_reference(&repo)?.is_none() {
+                return Ok(times);
+            }
+
+            let mut walk = repo.revwalk()?;
+            walk.push_head()?;
+            walk.set_sorting(Sort::TIME)?;
+
+            for id in walk {
+                let commit = repo.find_commit(id?)?;
+                let parent = match commit.parents().next() {
+                    Some(parent) => Some(parent.tree()?),
+                    None => None,
+                };
+
+                let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+                for delta in diff.deltas() {
+                    if let Some(name) = delta.new_file().path().and_then(Path::file_name) {
+                        times
+                            .entry(name.to_string_lossy().into_owned())
+                            .or_insert_with(|| commit.time().seconds());
+                    }
+                }
+            }
+
+            Ok(times)
+        })
+        .await
+        .expect("panicked while walking the history")
+    }
+
+    /// Returns the crates that were added or modified by commits made at or after `time` in
+    /// seconds since the Unix epoch.
+    ///
+    /// The history of the index is walked from HEAD until a commit older than `time` is found.
+    /// Crates that were published before the history of the index begins are attributed to its
+    /// first commit.
+    pub async fn published_since(&self, time: i64) -> Result<AHashSet<CrateKey>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let mut published = AHashSet::new();
+            if head_reference(&repo)?.is_none() {
+                return Ok(published);
+            }
+
+            let mut walk = repo.revwalk()?;
+            walk.push_head()?;
+            walk.set_sorting(Sort::TIME)?;
+
+            for id in walk {
+                let commit = repo.find_commit(id?)?;
+                if commit.time().seconds() < time {
+                    break;
+                }
+
+                let parent = match commit.parents().next() {
+                    Some(parent) => Some(parent.tree()?),
+                    None => None,
+                };
+
+                let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+                for change in changes_from_package_trees::<GetPackagesError>(
+                    &repo,
+                    diff.deltas().filter(holds_package),
+                    leniency.clone(),
+                ) {
+                    let change = change?;
+                    if matches!(change.kind, ChangeKind::Added | ChangeKind::Modified) {
+                        published.insert(change.on.key());
+                    }
+                }
+            }
+
+            debug!("found {} crates published since {}", published.len(), time);
+            Ok(published)
+        })
+        .await
+        .expect("panicked while walking the history")
+    }
+
+    /// Returns the package that holds the crates named `name` in HEAD or nothing if there is none.
+    /// The dependencies of its crates are retained if `dependencies` is true.
+    pub async fn package(
+        &self,
+        name: String,
+        dependencies: bool,
+    ) -> Result<Option<Package>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let Some(tree) = head_tree(&repo)? else {
+                return Ok(None);
+            };
+
+            let path = PathBuf::from(package::path(&name));
+            let entry = match tree.get_path(&path) {
+                Ok(entry) => entry,
+                Err(error) if error.code() == ErrorCode::NotFound => return Ok(None),
+                Err(error) => return Err(error.into()),
+            };
+
+            let blob = repo.find_blob(entry.id())?;
+            let (package, _) = leniency.parse(blob.content(), &path, dependencies)?;
+            Ok(Some(package))
+        })
+        .await
+        .expect("panicked while reading a package")
+    }
+
+    /// Returns the names of the crates that any version of the crates named `names` depends on.
+    /// Development dependencies are ignored. Crates that are not held by HEAD are skipped.
+    pub async fn dependencies(
+        &self,
+        names: Vec<String>,
+    ) -> Result<AHashSet<String>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let mut dependencies = AHashSet::new();
+            let Some(tree) = head_tree(&repo)? else {
+                return Ok(dependencies);
+            };
+
+            for name in names {
+                let path = PathBuf::from(package::path(&name));
+                let entry = match tree.get_path(&path) {
+                    Ok(entry) => entry,
+                    Err(error) if error.code() == ErrorCode::NotFound => continue,
+                    Err(error) => return Err(error.into()),
+                };
+
+                let blob = repo.find_blob(entry.id())?;
+                let (package, _) = leniency.parse(blob.content(), &path, true)?;
+                dependencies.extend(package.dependency_names().map(str::to_owned));
+            }
+
+            Ok(dependencies)
+        })
+        .await
+        .expect("panicked while reading dependencies")
+    }
+
+    /// Returns the names of the crates that have a version which depends on any of the crates
+    /// named `names`. Crates that only depend on them through other crates are included if
+    /// `transitive` is true. Development dependencies are ignored.
+    ///
+    /// Every package held by HEAD is read because the index only records dependencies in one
+    /// direction.
+    pub async fn dependents(
+        &self,
+        names: Vec<String>,
+        transitive: bool,
+    ) -> Result<AHashSet<String>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let mut dependents = AHashSet::new();
+            let Some(tree) = head_tree(&repo)? else {
+                return Ok(dependents);
+            };
+
+            // Names are compared without regard to case as the registry does.
+            let mut reverse = AHashMap::<String, Vec<String>>::new();
+            let mut result = Ok(());
+            tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+                let name = String::from_utf8_lossy(entry.name_bytes());
+
+                // Ignore hidden files and directories.
+                if name.starts_with('.') {
+                    return TreeWalkResult::Skip;
+                }
+
+                // Files in the root directory are not packages.
+                if root.is_empty() || entry.kind() != Some(ObjectType::Blob) {
+                    return TreeWalkResult::Ok;
+                }
+
+                let path = Path::new(root).join(name.as_ref());
+                result = repo
+                    .find_blob(entry.id())
+                    .map_err(GetPackagesError::from)
+                    .and_then(|blob| {
+                        let (package, _) = leniency.parse(blob.content(), &path, true)?;
+                        let Some(dependent) = package.crates().next().map(|item| &item.name) else {
+                            return Ok(());
+                        };
+
+                        let dependencies = package
+                            .dependency_names()
+                            .map(str::to_ascii_lowercase)
+                            .collect::<AHashSet<_>>();
+                        for dependency in dependencies {
+                            reverse
+                                .entry(dependency)
+                                .or_default()
+                                .push(dependent.clone());
+                        }
+
+                        Ok(())
+                    });
+
+                if result.is_ok() {
+                    TreeWalkResult::Ok
+                } else {
+                    TreeWalkResult::Abort
+                }
+            })
+            .or_else(|error| {
+                // The walk is aborted when a package cannot be read.
+                if error.code() == ErrorCode::User {
+                    Ok(())
+                } else {
+                    Err(error)
+                }
+            })?;
+            result?;
+
+            // The dependents of each crate are only found once.
+            let mut frontier = names
+                .iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect::<Vec<_>>();
+            while !frontier.is_empty() {
+                frontier = frontier
+                    .iter()
+                    .filter_map(|name| reverse.get(name))
+                    .flatten()
+                    .filter(|dependent| dependents.insert((*dependent).clone()))
+                    .filter(|_| transitive)
+                    .map(|dependent| dependent.to_ascii_lowercase())
+                    .collect();
+            }
+
+            debug!(
+                "found {} crates that depend on {:?}",
+                dependents.len(),
+                names
+            );
+            Ok(dependents)
+        })
+        .await
+        .expect("panicked while reading dependents")
+    }
+
+    /// Returns the changes to the crates in the index between the commits `from` and `to`.
+    pub async fn changes(&self, from: Oid, to: Oid) -> Result<Vec<Change>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let diff = repo.diff_tree_to_tree(
+                Some(&repo.find_commit(from)?.tree()?),
+                Some(&repo.find_commit(to)?.tree()?),
+                None,
+            )?;
+
+            changes_from_package_trees(&repo, diff.deltas().filter(holds_package), leniency)
                 .collect()
         })
         .await
-        .expect("panicked while getting the packages")
+        .expect("panicked while comparing commits")
     }
 
-    /// Stages an update.
+    /// Returns every crate that the index held at the commit `commit`.
+    pub async fn crates_at(&self, commit: Oid) -> Result<Vec<Crate>, GetPackagesError> {
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let diff =
+                repo.diff_tree_to_tree(None, Some(&repo.find_commit(commit)?.tree()?), None)?;
+
+            // Every crate is added when the commit is compared with an empty tree.
+            changes_from_package_trees(&repo, diff.deltas().filter(holds_package), leniency)
+                .map_ok(|change| change.on)
+                .collect()
+        })
+        .await
+        .expect("panicked while reading commit")
+    }
+
+    /// Returns a Git bundle that holds the commits that lead from `from` to `to`. The bundle can
+    /// only be imported into an index that holds `from`.
+    pub async fn bundle(&self, from: Oid, to: Oid) -> Result<Vec<u8>, git2::Error> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let mut walk = repo.revwalk()?;
+            walk.push(to)?;
+            walk.hide(from)?;
+
+            let mut pack = Buf::new();
+            let mut builder = repo.packbuilder()?;
+            builder.insert_walk(&mut walk)?;
+            builder.write_buf(&mut pack)?;
+
+            let mut bundle =
+                format!("{}-{from}\n{to} HEAD\n\n", Self::BUNDLE_SIGNATURE).into_bytes();
+            bundle.extend_from_slice(&pack);
+            Ok(bundle)
+        })
+        .await
+        .expect("panicked while bundling commits")
+    }
+
+    /// Imports the commits of a Git bundle as though they were fetched from the remote index.
+    /// Returns the latest commit of the remote index.
     ///
-    /// Changes to the index repository are synchronised locally each time an update is staged but
-    /// these changes are not applied. [`PendingUpdate`] can be used to enumerate the pending
-    /// changes. The update can be committed once the changes have been handled.
-    pub async fn update(&self) -> Result<PendingUpdate, GetUpdateError> {
-        let locked_repo = self.repository.clone();
+    /// The changes are not applied. They are staged by the next call to [`Self::stage`]. Nothing
+    /// is imported if the index already holds the commits.
+    pub async fn unbundle(&self, bundle: Vec<u8>) -> Result<Oid, UnbundleError> {
+        let repo = self.repository.clone();
         task::spawn_blocking(move || {
-            let unlocked_repo = locked_repo.clone();
-            let repo = unlocked_repo.lock().expect("lock is poisoned");
+            let repo = repo.lock().expect("lock is poisoned");
+
+            let rest = bundle
+                .strip_prefix(Self::BUNDLE_SIGNATURE.as_bytes())
+                .ok_or(UnbundleError::Malformed)?;
+            let end = rest
+                .windows(2)
+                .position(|window| window == b"\n\n")
+                .ok_or(UnbundleError::Malformed)?;
+            let header = str::from_utf8(&rest[..end]).map_err(|_| UnbundleError::Malformed)?;
+            let pack = &rest[end + 2..];
+
+            let mut target = None;
+            for line in header.lines() {
+                let (id, prerequisite) = line
+                    .strip_prefix('-')
+                    .map_or((line, false), |line| (line, true));
+
+                let id = id
+                    .split(' ')
+                    .next()
+                    .and_then(|id| Oid::from_str(id).ok())
+                    .ok_or(UnbundleError::Malformed)?;
+
+                if !prerequisite {
+                    target.get_or_insert(id);
+                } else if repo.find_commit(id).is_err() {
+                    return Err(UnbundleError::MissingPrerequisite(id));
+                }
+            }
+
+            let target = target.ok_or(UnbundleError::Malformed)?;
+            if repo.find_commit(target).is_err() {
+                let odb = repo.odb()?;
+                let mut writer = odb.packwriter()?;
+                writer.write_all(pack)?;
+                writer.commit()?;
+                debug!("imported the commits of a bundle");
+            }
+
+            let mut upstream =
+                upstream_branch(&repo)?.ok_or(UnbundleError::UnexpectedIndexState)?;
+            let current = upstream
+                .get()
+                .target()
+                .ok_or(UnbundleError::UnexpectedIndexState)?;
+
+            if current == target || repo.graph_descendant_of(current, target)? {
+                return Ok(current);
+            }
+
+            if !repo.graph_descendant_of(target, current)? {
+                return Err(UnbundleError::Diverged);
+            }
+
+            upstream.get_mut().set_target(target, "import bundle")?;
+            Ok(target)
+        })
+        .await
+        .expect("panicked while importing a bundle")
+    }
+
+    /// Calls `visit` with the path and contents of files held by HEAD.
+    ///
+    /// Every file that is not hidden is visited if `paths` is `None`. Otherwise, only the files at
+    /// `paths` are visited and files that do not exist are visited without contents.
+    ///
+    /// `visit` is called from a context where blocking is permitted.
+    pub async fn visit<F, E>(&self, paths: Option<Vec<PathBuf>>, mut visit: F) -> Result<(), E>
+    where
+        F: FnMut(&Path, Option<&[u8]>) -> Result<(), E> + Send + 'static,
+        E: From<git2::Error> + Send + 'static,
+    {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+            let tree = head_tree(&repo)?;
+
+            if let Some(paths) = paths {
+                for path in paths {
+                    // Nothing exists in an index that has no commits yet.
+                    let entry = match tree.as_ref().map(|tree| tree.get_path(&path)).transpose() {
+                        Err(error) if error.code() == ErrorCode::NotFound => None,
+                        entry => entry?,
+                    };
+
+                    match entry {
+                        Some(entry) => visit(&path, Some(repo.find_blob(entry.id())?.content()))?,
+                        None => visit(&path, None)?,
+                    }
+                }
 
-            let head = repo.head()?;
-            if !head.is_branch() {
-                return Err(GetUpdateError::UnexpectedIndexState);
+                return Ok(());
             }
 
-            let name = head
-                .name()
-                .ok_or(GetUpdateError::IndexUsesUnsupportedEncoding)?;
+            let Some(tree) = tree else {
+                return Ok(());
+            };
+
+            let mut result = Ok(());
+            tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+                let name = String::from_utf8_lossy(entry.name_bytes());
+
+                // Ignore hidden files and directories.
+                if name.starts_with('.') {
+                    return TreeWalkResult::Skip;
+                }
+
+                if entry.kind() != Some(ObjectType::Blob) {
+                    return TreeWalkResult::Ok;
+                }
+
+                let path = Path::new(root).join(name.as_ref());
+                result = repo
+                    .find_blob(entry.id())
+                    .map_err(Into::into)
+                    .and_then(|blob| visit(&path, Some(blob.content())));
+
+                if result.is_ok() {
+                    TreeWalkResult::Ok
+                } else {
+                    TreeWalkResult::Abort
+                }
+            })
+            .or_else(|error| {
+                // The walk is aborted when visiting fails.
+                if error.code() == ErrorCode::User {
+                    Ok(())
+                } else {
+                    Err(error)
+                }
+            })?;
+
+            result
+        })
+        .await
+        .expect("panicked while visiting files")
+    }
+
+    /// Returns a stream of the packages in `scope` that are currently held by the index grouped by
+    /// the top-level directories that hold them.
+    ///
+    /// Directories are read from HEAD as the stream is polled so that only a few directories are
+    /// held in memory at once. The index is only locked while a directory is being read. The
+    /// stream ends after the first error.
+    pub fn directories(
+        &self,
+        scope: &Scope,
+    ) -> impl Stream<Item = Result<Directory, GetPackagesError>> + Send + 'static {
+        let (sender, mut directories) = mpsc::channel(Self::DIRECTORY_CAPACITY);
+        let repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        let scope = scope.clone();
+        let producer = task::spawn_blocking(move || {
+            let read_directory = |name: String, id: Oid| {
+                let repo = repo.lock().expect("lock is poisoned");
+                let tree = repo.find_tree(id)?;
+                let packages = repo
+                    .diff_tree_to_tree(None, Some(&tree), None)?
+                    .deltas()
+                    .filter_map(|delta| {
+                        let file = delta.new_file();
+                        let path = Path::new(&name).join(file.path().expect("file missing path"));
+                        scope
+                            .contains(&path.to_string_lossy())
+                            .then(|| (file.id(), path))
+                    })
+                    .map(|(id, path)| {
+                        let blob = repo.find_blob(id)?;
+                        let (package, _) = leniency.parse(blob.content(), &path, false)?;
+                        Ok::<Package, GetPackagesError>(package)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Directory { name, packages })
+            };
+
+            // The trees of the top-level directories are found up front so that the directories
+            // are read from the same commit even if HEAD is moved while they are being read.
+            let trees = {
+                let repo = repo.lock().expect("lock is poisoned");
+                head_tree(&repo).map(|tree| {
+                    tree.iter()
+                        .flat_map(|tree| tree.iter())
+                        .filter_map(|entry| {
+                            let name = String::from_utf8_lossy(entry.name_bytes()).into_owned();
+
+                            // Ignore hidden files and all files in the root directory that are not
+                            // directories. This ensures that the configuration is not included.
+                            (!name.starts_with('.')
+                                && scope.may_contain_directory(&name)
+                                && entry.kind() == Some(ObjectType::Tree))
+                            .then(|| (name, entry.id()))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            };
+
+            let trees = match trees {
+                Ok(trees) => trees,
+                Err(error) => {
+                    drop(sender.blocking_send(Err(error.into())));
+                    return;
+                }
+            };
+            notify::expect(trees.len());
+
+            for (name, id) in trees {
+                let directory = read_directory(name, id);
+                let failed = directory.is_err();
+
+                // The stream was dropped before every directory was read.
+                if sender.blocking_send(directory).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        // The producer is awaited once the directories have been read so that a panic is not
+        // mistaken for the end of the stream.
+        stream::poll_fn(move |cx| directories.poll_recv(cx)).chain(
+            stream::once(producer).filter_map(|result| {
+                result.expect("panicked while getting the packages");
+                future::ready(None)
+            }),
+        )
+    }
+
+    /// Fetches the latest changes from the remote index without staging them.
+    ///
+    /// Changes to the index repository are synchronised locally each time they are fetched but
+    /// these changes are not applied. An update can be staged with [`Self::stage`] and
+    /// [`PendingUpdate`] can be used to enumerate the pending changes. The update can be committed
+    /// once the changes have been handled.
+    pub async fn fetch(&self) -> Result<(), GetUpdateError> {
+        let repo = self.repository.clone();
+        task::spawn_blocking(move || {
+            let repo = repo.lock().expect("lock is poisoned");
+
+            let name = head_branch(&repo)?.ok_or(GetUpdateError::UnexpectedIndexState)?;
             let mut remote = repo.find_remote(
-                repo.branch_upstream_remote(name)?
+                repo.branch_upstream_remote(&name)?
                     .as_str()
                     .ok_or(GetUpdateError::IndexUsesUnsupportedEncoding)?,
             )?;
 
-            remote.fetch(&[name], Some(&mut FetchOptions::new()), None)?;
+            let config = repo.config()?;
+            let mut options = FetchOptions::new();
+            options.remote_callbacks(Authentication::read(&config)?.callbacks(config));
+            remote.fetch(&[&name], Some(&mut options), None)?;
             debug!("fetched the latest changes from the index remote");
+            Ok(())
+        })
+        .await
+        .expect("panicked while fetching update")
+    }
 
-            let branch = Branch::wrap(head);
-            let upstream = branch.upstream()?;
+    /// Stages an update to the latest commit that was fetched from the remote index or imported
+    /// from a bundle. Nothing is fetched.
+    ///
+    /// The changes are found by comparing the tree of the latest commit from the remote index with
+    /// the tree of the target so the update is staged even if the history of the remote index was
+    /// rewritten (eg. squashed) and the target does not descend from the commit that the index
+    /// holds. The branch is reset to the rewritten history when the update is committed.
+    ///
+    /// The update is staged to the last commit on the first-parent history of the remote index
+    /// that was made at or before `until` in seconds since the Unix epoch if it is given.
+    /// Otherwise, it is staged to the commit that the index is pinned to if there is one. Either
+    /// may move the branch back to an earlier commit.
+    pub async fn stage(&self, until: Option<i64>) -> Result<PendingUpdate, GetUpdateError> {
+        let locked_repo = self.repository.clone();
+        let (target, rewritten) = task::spawn_blocking(move || {
+            let repo = locked_repo.lock().expect("lock is poisoned");
+            let upstream = upstream_branch(&repo)?.ok_or(GetUpdateError::UnexpectedIndexState)?;
+            let mut target = upstream
+                .get()
+                .target()
+                .ok_or(GetUpdateError::UnexpectedIndexState)?;
+
+            // A pinned index is only moved to a date when one is given.
+            if until.is_none() {
+                if let Some(pin) = reference_target(&repo, Self::PIN_REFERENCE)? {
+                    target = pin;
+                }
+            }
 
-            let exclude = repo
-                .workdir()
-                .ok_or(GetUpdateError::UnexpectedIndexState)?
-                .join(Self::CONFIGURATION_FILENAME);
+            if let Some(until) = until {
+                let mut walk = repo.revwalk()?;
+                walk.push(target)?;
+                walk.simplify_first_parent()?;
+
+                let mut found = None;
+                for id in walk {
+                    let commit = repo.find_commit(id?)?;
+                    if commit.time().seconds() <= until {
+                        found = Some(commit.id());
+                        break;
+                    }
+                }
+
+                target = found.ok_or(GetUpdateError::NoCommitAt(until))?;
+                debug!("staging the update to {} for {}", target, until);
+            }
 
-            let changes = changes_from_package_trees(
-                &repo,
-                repo.diff_tree_to_tree(
-                    Some(&branch.get().peel_to_tree()?),
-                    Some(&upstream.get().peel_to_tree()?),
+            let current = upstream_commit(&repo)?.map(|commit| commit.id());
+            let rewritten = match current {
+                // Staging an update to an earlier commit does not rewrite the history.
+                Some(current) => {
+                    target != current
+                        && !repo.graph_descendant_of(target, current)?
+                        && !(until.is_some() && repo.graph_descendant_of(current, target)?)
+                }
+                // Everything is added by the first update of an empty index.
+                None => false,
+            };
+
+            if let (true, Some(current)) = (rewritten, current) {
+                warn!(
+                    "the history of the remote index was rewritten; {} does not descend from {}",
+                    target, current
+                );
+            }
+
+            Ok::<_, GetUpdateError>((target, rewritten))
+        })
+        .await
+        .expect("panicked while staging update")?;
+
+        let (sender, changes) = mpsc::channel(Self::CHANGE_CAPACITY);
+        let locked_repo = self.repository.clone();
+        let leniency = self.leniency.clone();
+        let producer = task::spawn_blocking(move || {
+            let repo = locked_repo.lock().expect("lock is poisoned");
+            let generate = || {
+                // Paths in a diff are relative to the root of the repository.
+                let exclude = Path::new(Self::CONFIGURATION_FILENAME);
+
+                let current = upstream_commit(&repo)?
+                    .map(|commit| commit.tree())
+                    .transpose()?;
+                let diff = repo.diff_tree_to_tree(
+                    current.as_ref(),
+                    Some(&repo.find_commit(target)?.tree()?),
                     None,
-                )?
-                .deltas()
-                .filter(|delta| {
-                    let path = match delta.old_file().path() {
-                        Some(path) => Some(path),
-                        None => delta.new_file().path(),
-                    };
+                )?;
+                notify::expect(diff.deltas().len());
+
+                for change in changes_from_package_trees::<GetUpdateError>(
+                    &repo,
+                    diff.deltas().filter(|delta| {
+                        let path = delta.old_file().path().or_else(|| delta.new_file().path());
+
+                        path.is_none_or(|path| path != exclude)
+                    }),
+                    leniency,
+                ) {
+                    // The pending update was dropped before every change was enumerated.
+                    if sender.blocking_send(Ok(change?)).is_err() {
+                        return Ok(false);
+                    }
+                }
 
-                    path.map_or(true, |path| path != exclude)
-                }),
-            )
-            .collect::<Result<Vec<_>, GetUpdateError>>()?;
-
-            Ok(PendingUpdate {
-                target: upstream
-                    .get()
-                    .target()
-                    .ok_or(GetUpdateError::UnexpectedIndexState)?,
-                repository: locked_repo,
-                changes,
+                Ok(true)
+            };
+
+            generate().unwrap_or_else(|error| {
+                drop(sender.blocking_send(Err(error)));
+                false
             })
+        });
+
+        Ok(PendingUpdate {
+            repository: self.repository.clone(),
+            target,
+            rewritten,
+            changes,
+            producer,
         })
-        .await
-        .expect("panicked while collecting update")
     }
 }
 
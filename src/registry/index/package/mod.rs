@@ -1,14 +1,14 @@
 #[cfg(test)]
 pub mod tests;
 
-use crate::digest::Sha256;
+use crate::digest::Digest;
 use ahash::AHashSet;
-use serde::Deserialize;
+use semver::Version;
+use serde::{de::IgnoredAny, Deserialize, Deserializer};
 use std::{
-    convert::Into,
     error::Error,
     fmt::{self, Display, Formatter},
-    str::{self, Utf8Error},
+    str::{self, FromStr, Utf8Error},
 };
 
 /// A crate is uniquely identified by its name, version, and hash. A crate key identifies a crate
@@ -21,58 +21,295 @@ pub struct CrateKey {
     pub version: String,
 }
 
+impl FromStr for CrateKey {
+    type Err = ParseCrateKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, version) = s.trim().split_once('@').ok_or(ParseCrateKeyError)?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            || version.is_empty()
+        {
+            return Err(ParseCrateKeyError);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+        })
+    }
+}
+
+impl Display for CrateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+/// A crate key is not written as `<name>@<version>`.
+#[derive(Debug)]
+pub struct ParseCrateKeyError;
+
+impl Display for ParseCrateKeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "crate must be written as <name>@<version>")
+    }
+}
+
+impl Error for ParseCrateKeyError {}
+
+/// A field of a crate does not have the shape that the registry index format requires.
+#[derive(Debug)]
+pub struct InvalidFieldError {
+    field: &'static str,
+    value: String,
+    reason: String,
+}
+
+impl InvalidFieldError {
+    fn new(field: &'static str, value: &str, reason: impl Display) -> Self {
+        Self {
+            field,
+            value: value.to_owned(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for InvalidFieldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid `{}` {:?}: {}",
+            self.field, self.value, self.reason
+        )
+    }
+}
+
+impl Error for InvalidFieldError {}
+
 #[derive(Debug)]
-pub struct DeserialiseCrateError {
-    inner: serde_json::Error,
+#[non_exhaustive]
+pub enum DeserialiseCrateError {
+    Json(serde_json::Error),
+    InvalidField(InvalidFieldError),
 }
 
 impl From<serde_json::Error> for DeserialiseCrateError {
     fn from(error: serde_json::Error) -> Self {
-        Self { inner: error }
+        Self::Json(error)
+    }
+}
+
+impl From<InvalidFieldError> for DeserialiseCrateError {
+    fn from(error: InvalidFieldError) -> Self {
+        Self::InvalidField(error)
     }
 }
 
 impl Display for DeserialiseCrateError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        self.inner.fmt(f)
+        match self {
+            Self::Json(error) => error.fmt(f),
+            Self::InvalidField(error) => error.fmt(f),
+        }
     }
 }
 
 impl Error for DeserialiseCrateError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        self.inner.source()
+        match self {
+            Self::Json(error) => error.source(),
+            Self::InvalidField(error) => error.source(),
+        }
     }
 }
 
 /// A crate is a minimum required subset of the registry metadata describing a crate.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Hash)]
+#[serde(try_from = "Record<IgnoredAny>")]
 pub struct Crate {
     /// The name of the crate.
     pub name: String,
     /// The version of the crate.
-    #[serde(rename = "vers")]
     pub version: String,
     /// The checksum of the crate.
-    #[serde(rename = "cksum")]
-    pub checksum: Sha256,
+    pub checksum: Digest,
+    /// True if the crate has been yanked from the registry.
+    pub yanked: bool,
+    /// The dependencies of the crate if they were retained when the crate was deserialised.
+    pub dependencies: Option<Box<[Dependency]>>,
+}
+
+/// The kind of a dependency.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    #[default]
+    Normal,
+    Build,
+    Dev,
+}
+
+impl Display for DependencyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => f.write_str("normal"),
+            Self::Build => f.write_str("build"),
+            Self::Dev => f.write_str("dev"),
+        }
+    }
+}
+
+/// Deserialises the kind of a dependency. Dependencies without a kind are normal dependencies.
+fn kind<'de, D>(deserializer: D) -> Result<DependencyKind, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// A dependency of a crate.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Hash)]
+pub struct Dependency {
+    /// The name that the crate refers to the dependency by.
+    pub name: String,
+    /// The version requirement of the dependency.
+    pub req: String,
+    #[serde(default, deserialize_with = "kind")]
+    pub kind: DependencyKind,
+    /// True if the dependency is only enabled by a feature.
+    #[serde(default)]
+    pub optional: bool,
+    /// The name of the crate that is depended on if the dependency was renamed.
+    #[serde(default)]
+    pub package: Option<String>,
+}
+
+impl Dependency {
+    /// Returns the name of the crate that is depended on.
+    #[must_use]
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl Display for Dependency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.crate_name(), self.req, self.kind)?;
+        if self.optional {
+            f.write_str(" optional")?;
+        }
+
+        if self.package.is_some() {
+            write!(f, " as {}", self.name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A crate as it is written in the index before the shapes of its fields are validated. The
+/// dependencies are only deserialised into `D` when they are retained.
+#[derive(Deserialize)]
+struct Record<D> {
+    name: String,
+    vers: String,
+    cksum: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    deps: D,
+}
+
+impl<D> Record<D> {
+    /// Validates the shapes of the fields. Returns the crate without its dependencies and the
+    /// dependencies of the record.
+    fn validate(self) -> Result<(Crate, D), InvalidFieldError> {
+        // Names are used to build paths in the index and the cache so they are restricted to the
+        // characters that crates.io accepts.
+        if self.name.is_empty() {
+            return Err(InvalidFieldError::new("name", &self.name, "empty name"));
+        }
+
+        if let Some(invalid) = self
+            .name
+            .chars()
+            .find(|char| !char.is_ascii_alphanumeric() && *char != '-' && *char != '_')
+        {
+            return Err(InvalidFieldError::new(
+                "name",
+                &self.name,
+                format_args!("invalid character {invalid:?}"),
+            ));
+        }
+
+        if let Err(error) = Version::parse(&self.vers) {
+            return Err(InvalidFieldError::new("vers", &self.vers, error));
+        }
+
+        let checksum = self
+            .cksum
+            .parse()
+            .map_err(|error| InvalidFieldError::new("cksum", &self.cksum, error))?;
+
+        Ok((
+            Crate {
+                name: self.name,
+                version: self.vers,
+                checksum,
+                yanked: self.yanked,
+                dependencies: None,
+            },
+            self.deps,
+        ))
+    }
+}
+
+impl TryFrom<Record<IgnoredAny>> for Crate {
+    type Error = InvalidFieldError;
+
+    fn try_from(record: Record<IgnoredAny>) -> Result<Self, Self::Error> {
+        Ok(record.validate()?.0)
+    }
+}
+
+/// Returns the URL prefix for crates named `name`.
+#[must_use]
+pub fn prefix(name: &str) -> String {
+    let chars: Vec<_> = name.chars().take(4).collect();
+    match chars.len() {
+        1 => String::from("1"),
+        2 => String::from("2"),
+        3 => format!("3/{}", chars[0]),
+        4 => format!(
+            "{}/{}",
+            chars[0..2].iter().collect::<String>(),
+            chars[2..4].iter().collect::<String>()
+        ),
+        _ => unreachable!("unexpected length"),
+    }
+}
+
+/// Returns the path of the package that holds crates named `name` in the index.
+#[must_use]
+pub fn path(name: &str) -> String {
+    format!("{}/{}", prefix(name).to_lowercase(), name.to_lowercase())
 }
 
 impl Crate {
     /// Returns the URL prefix for the crate.
     #[must_use]
     pub fn prefix(&self) -> String {
-        let chars: Vec<_> = self.name.chars().take(4).collect();
-        match chars.len() {
-            1 => String::from("1"),
-            2 => String::from("2"),
-            3 => format!("3/{}", chars[0]),
-            4 => format!(
-                "{}/{}",
-                chars[0..2].iter().collect::<String>(),
-                chars[2..4].iter().collect::<String>()
-            ),
-            _ => unreachable!("unexpected length"),
-        }
+        prefix(&self.name)
+    }
+
+    /// Returns the path of the package that holds the crate in the index.
+    #[must_use]
+    pub fn path(&self) -> String {
+        path(&self.name)
     }
 
     /// Returns the crate as a crate key.
@@ -84,14 +321,25 @@ impl Crate {
         }
     }
 
-    /// Deserialises a crate from a string slice.
-    pub fn from_str(str: &str) -> Result<Self, DeserialiseCrateError> {
-        serde_json::from_str(str).map_err(Into::into)
+    /// Deserialises a crate from a string slice. The shapes of the name, version, and checksum
+    /// are validated. The dependencies of the crate are retained if `dependencies` is true.
+    pub fn parse(str: &str, dependencies: bool) -> Result<Self, DeserialiseCrateError> {
+        if !dependencies {
+            return Ok(Self::try_from(serde_json::from_str::<Record<IgnoredAny>>(
+                str,
+            )?)?);
+        }
+
+        let (mut item, dependencies) =
+            serde_json::from_str::<Record<Vec<Dependency>>>(str)?.validate()?;
+        item.dependencies = Some(dependencies.into_boxed_slice());
+        Ok(item)
     }
 }
 
 #[derive(Debug)]
 pub enum DeserialisePackageError {
+    /// The crate on `line` (counted from one) is invalid.
     Json {
         source: DeserialiseCrateError,
         line: usize,
@@ -103,9 +351,14 @@ pub enum DeserialisePackageError {
 impl Display for DeserialisePackageError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Json { source: _, line } => {
-                write!(f, "invalid json for line {}", line)
-            }
+            Self::Json {
+                source: DeserialiseCrateError::Json(_),
+                line,
+            } => write!(f, "invalid json on line {line}"),
+            Self::Json {
+                source: DeserialiseCrateError::InvalidField(error),
+                line,
+            } => write!(f, "{error} on line {line}"),
 
             Self::Utf8(error) => error.fmt(f),
         }
@@ -115,7 +368,14 @@ impl Display for DeserialisePackageError {
 impl Error for DeserialisePackageError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Json { source, line: _ } => Some(source),
+            Self::Json {
+                source: DeserialiseCrateError::Json(error),
+                line: _,
+            } => Some(error),
+            Self::Json {
+                source: DeserialiseCrateError::InvalidField(_),
+                line: _,
+            } => None,
             Self::Utf8(error) => error.source(),
         }
     }
@@ -130,15 +390,35 @@ impl Package {
         self.0.into_iter()
     }
 
-    /// Deserialises a package from a string slice.
-    pub fn from_str(str: &str) -> Result<Self, DeserialisePackageError> {
-        let crates = str
+    /// Returns references to the crates.
+    pub fn crates(&self) -> impl Iterator<Item = &Crate> {
+        self.0.iter()
+    }
+
+    /// Returns the names of the crates that any crate in the package depends on. Development
+    /// dependencies are ignored because they are not needed to build the crates. Only the
+    /// dependencies that were retained are returned.
+    pub fn dependency_names(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .flat_map(|item| item.dependencies.iter().flat_map(|each| each.iter()))
+            .filter(|dependency| dependency.kind != DependencyKind::Dev)
+            .map(Dependency::crate_name)
+    }
+
+    /// Deserialises a package from a slice of bytes. The dependencies of its crates are retained
+    /// if `dependencies` is true.
+    pub fn from_slice(slice: &[u8], dependencies: bool) -> Result<Self, DeserialisePackageError> {
+        let crates = str::from_utf8(slice)
+            .map_err(DeserialisePackageError::Utf8)?
             .lines()
             .enumerate()
-            .map(|(line, slice)| {
-                Crate::from_str(slice.trim()).map_err(|error| DeserialisePackageError::Json {
-                    source: error,
-                    line,
+            .map(|(index, slice)| {
+                Crate::parse(slice.trim(), dependencies).map_err(|error| {
+                    DeserialisePackageError::Json {
+                        source: error,
+                        line: index + 1,
+                    }
                 })
             })
             .collect::<Result<AHashSet<_>, _>>()?;
@@ -146,8 +426,35 @@ impl Package {
         Ok(Self(crates))
     }
 
-    /// Deserialises a package from a slice of bytes.
-    pub fn from_slice(slice: &[u8]) -> Result<Self, DeserialisePackageError> {
-        Self::from_str(std::str::from_utf8(slice).map_err(DeserialisePackageError::Utf8)?)
+    /// Deserialises a package from a slice of bytes like [`Self::from_slice`], skipping the lines that
+    /// are corrupt. Bytes that are not UTF-8 are replaced so that only the lines that hold them are
+    /// skipped. Returns the package with the errors of the skipped lines.
+    pub fn from_slice_lenient(
+        slice: &[u8],
+        dependencies: bool,
+    ) -> (Self, Vec<DeserialisePackageError>) {
+        let mut errors = Vec::new();
+        let crates = String::from_utf8_lossy(slice)
+            .lines()
+            .enumerate()
+            .filter_map(|(index, slice)| {
+                Crate::parse(slice.trim(), dependencies)
+                    .map_err(|error| {
+                        errors.push(DeserialisePackageError::Json {
+                            source: error,
+                            line: index + 1,
+                        });
+                    })
+                    .ok()
+            })
+            .collect();
+
+        (Self(crates), errors)
+    }
+}
+
+impl FromIterator<Crate> for Package {
+    fn from_iter<I: IntoIterator<Item = Crate>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
     }
 }
@@ -8,18 +8,21 @@ fn test_deserialise_package_with_single_crate() {
         set.insert(Crate {
             name: String::from("a"),
             version: String::from("0.0.1"),
-            checksum: Sha256(
+            checksum: Digest::Sha256(
                 hex::decode("bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643")
                     .expect("failed to decode hex string")
                     .try_into()
                     .expect("hex string has invalid length"),
             ),
+            yanked: false,
+            dependencies: None,
         });
 
         set
     });
 
-    let output = Package::from_slice(data.as_bytes()).expect("failed to deserialise package");
+    let output =
+        Package::from_slice(data.as_bytes(), false).expect("failed to deserialise package");
     assert_eq!(output, expected);
 }
 
@@ -32,18 +35,21 @@ fn test_deserialise_package_with_single_crate_with_trailing_newline() {
         set.insert(Crate {
             name: String::from("a"),
             version: String::from("0.0.1"),
-            checksum: Sha256(
+            checksum: Digest::Sha256(
                 hex::decode("bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643")
                     .expect("failed to decode hex string")
                     .try_into()
                     .expect("hex string has invalid length"),
             ),
+            yanked: false,
+            dependencies: None,
         });
 
         set
     });
 
-    let output = Package::from_slice(data.as_bytes()).expect("failed to deserialise package");
+    let output =
+        Package::from_slice(data.as_bytes(), false).expect("failed to deserialise package");
     assert_eq!(output, expected);
 }
 
@@ -56,34 +62,89 @@ fn test_deserialise_package_with_multiple_crates() {
         set.insert(Crate {
             name: String::from("b"),
             version: String::from("0.1.0"),
-            checksum: Sha256(
+            checksum: Digest::Sha256(
                 hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                     .expect("failed to decode hex string")
                     .try_into()
                     .expect("hex string has invalid length"),
             ),
+            yanked: false,
+            dependencies: None,
         });
         set.insert(Crate {
             name: String::from("b"),
             version: String::from("0.2.0"),
-            checksum: Sha256(
+            checksum: Digest::Sha256(
                 hex::decode("ad71822f94ff0251011da9d7c63248c2520e6a69e56d457be0679b4fe81cbada")
                     .expect("failed to decode hex string")
                     .try_into()
                     .expect("hex string has invalid length"),
             ),
+            yanked: false,
+            dependencies: None,
         });
 
         set
     });
 
-    let output = Package::from_slice(data.as_bytes()).expect("failed to deserialise package");
+    let output =
+        Package::from_slice(data.as_bytes(), false).expect("failed to deserialise package");
     assert_eq!(output, expected);
 }
 
+#[test]
+fn test_deserialise_yanked_crate() {
+    let data = r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643","features":{},"yanked":true}"#;
+    let output = Crate::parse(data, false).expect("failed to deserialise crate");
+    assert!(output.yanked);
+}
+
 #[test]
 fn test_deserialise_corrupt_package_with_missing_fields() {
-    assert!(Package::from_slice(b"{}").is_err());
+    assert!(Package::from_slice(b"{}", false).is_err());
+}
+
+#[test]
+fn test_deserialise_package_with_invalid_fields() {
+    let valid = r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643","features":{},"yanked":false}"#;
+    for (invalid, field) in [
+        (valid.replace(r#""name":"a""#, r#""name":"""#), "name"),
+        (valid.replace(r#""name":"a""#, r#""name":"../a""#), "name"),
+        (
+            valid.replace(r#""vers":"0.0.1""#, r#""vers":"1.0""#),
+            "vers",
+        ),
+        (valid.replace("c643", ""), "cksum"),
+    ] {
+        let data = format!("{valid}\n{invalid}");
+        match Package::from_slice(data.as_bytes(), false) {
+            Err(DeserialisePackageError::Json {
+                source: DeserialiseCrateError::InvalidField(error),
+                line,
+            }) => {
+                assert_eq!(error.field, field);
+                assert_eq!(line, 2);
+            }
+            output => panic!("unexpected output {output:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_deserialise_package_leniently() {
+    let data = b"{\"name\":\"a\",\"vers\":\"0.0.1\",\"deps\":[],\"cksum\":\"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643\",\"features\":{},\"yanked\":false}\n{\n\xff\n";
+    let (package, errors) = Package::from_slice_lenient(data, false);
+    assert_eq!(package.crates().count(), 1);
+    assert_eq!(
+        errors
+            .iter()
+            .map(|error| match error {
+                DeserialisePackageError::Json { source: _, line } => *line,
+                DeserialisePackageError::Utf8(_) => 0,
+            })
+            .collect::<Vec<_>>(),
+        [2, 3]
+    );
 }
 
 #[test]
@@ -91,12 +152,14 @@ fn test_get_single_crate_prefix() {
     let crate_ = Crate {
         name: String::from("a"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     assert_eq!(crate_.prefix().as_str(), "1");
@@ -107,12 +170,14 @@ fn test_get_double_crate_prefix() {
     let crate_ = Crate {
         name: String::from("bb"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     assert_eq!(crate_.prefix().as_str(), "2");
@@ -123,12 +188,14 @@ fn test_get_triple_crate_prefix() {
     let crate_ = Crate {
         name: String::from("ccc"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     assert_eq!(crate_.prefix().as_str(), "3/c");
@@ -139,13 +206,98 @@ fn test_get_quad_crate_prefix() {
     let crate_ = Crate {
         name: String::from("example"),
         version: String::from("1.0.0"),
-        checksum: Sha256(
+        checksum: Digest::Sha256(
             hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
                 .expect("failed to decode hex string")
                 .try_into()
                 .expect("hex string has invalid length"),
         ),
+        yanked: false,
+        dependencies: None,
     };
 
     assert_eq!(crate_.prefix().as_str(), "ex/am");
 }
+
+#[test]
+fn test_get_crate_path() {
+    let crate_ = Crate {
+        name: String::from("Example"),
+        version: String::from("1.0.0"),
+        checksum: Digest::Sha256(
+            hex::decode("fae02128713e38ea8d4973b9d8944273dbd6db36cee7e1bc0e41ee5022933783")
+                .expect("failed to decode hex string")
+                .try_into()
+                .expect("hex string has invalid length"),
+        ),
+        yanked: false,
+        dependencies: None,
+    };
+
+    assert_eq!(crate_.path().as_str(), "ex/am/example");
+}
+
+#[test]
+fn test_dependencies() {
+    let data = r#"{"name":"a","vers":"0.0.1","deps":[{"name":"b","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"}],"cksum":"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[{"name":"c","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"build","package":"d"},{"name":"e","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"dev"}],"cksum":"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643","features":{},"yanked":false}"#;
+
+    let mut expected = AHashSet::new();
+    expected.insert(String::from("b"));
+    expected.insert(String::from("d"));
+
+    let output = Package::from_slice(data.as_bytes(), true)
+        .expect("failed to deserialise package")
+        .dependency_names()
+        .map(str::to_owned)
+        .collect::<AHashSet<_>>();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_deserialise_crate_with_dependencies() {
+    let data = r#"{"name":"a","vers":"0.0.1","deps":[{"name":"b","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":null},{"name":"c","req":"^0.2","features":[],"optional":true,"default_features":true,"target":null,"kind":"build","package":"d"}],"cksum":"bae3d8de1b7fd1fef6c2da3130a7d06d32499fd5292a9c1309681ac79e98c643","features":{},"yanked":false}"#;
+
+    let output = Crate::parse(data, false).expect("failed to deserialise crate");
+    assert_eq!(output.dependencies, None);
+
+    let output = Crate::parse(data, true).expect("failed to deserialise crate");
+    let dependencies = output.dependencies.expect("dependencies were not retained");
+    assert_eq!(
+        dependencies.as_ref(),
+        [
+            Dependency {
+                name: String::from("b"),
+                req: String::from("^1"),
+                kind: DependencyKind::Normal,
+                optional: false,
+                package: None,
+            },
+            Dependency {
+                name: String::from("c"),
+                req: String::from("^0.2"),
+                kind: DependencyKind::Build,
+                optional: true,
+                package: Some(String::from("d")),
+            },
+        ]
+    );
+    assert_eq!(dependencies[1].to_string(), "d ^0.2 build optional as c");
+}
+
+#[test]
+fn test_parse_crate_key() {
+    let key = "serde@1.0.0"
+        .parse::<CrateKey>()
+        .expect("failed to parse crate key");
+    assert_eq!(key.name, "serde");
+    assert_eq!(key.version, "1.0.0");
+    assert_eq!(key.to_string(), "serde@1.0.0");
+
+    for malformed in ["serde", "@1.0.0", "serde@", "ser/de@1.0.0"] {
+        assert!(
+            malformed.parse::<CrateKey>().is_err(),
+            "{malformed} is not malformed"
+        );
+    }
+}
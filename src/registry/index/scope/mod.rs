@@ -0,0 +1,127 @@
+#[cfg(test)]
+pub mod tests;
+
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    num::NonZeroUsize,
+    str::FromStr,
+};
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParseShardError;
+
+impl Display for ParseShardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "shard must be written as <index>/<count> where 1 <= index <= count"
+        )
+    }
+}
+
+impl Error for ParseShardError {}
+
+/// A shard is one of a number of deterministic and disjoint slices of the index.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Shard {
+    /// The one-based index of the shard.
+    index: NonZeroUsize,
+    /// The number of shards.
+    count: NonZeroUsize,
+}
+
+impl Shard {
+    /// Returns true if the package at `path` belongs to the shard.
+    ///
+    /// Packages are assigned to shards by a digest of their path so that the assignment is stable
+    /// across machines and releases.
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        let digest = Sha256::digest(path.as_bytes());
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+
+        // The remainder is always less than the count so it fits in a usize.
+        #[allow(clippy::cast_possible_truncation)]
+        let shard = (u64::from_be_bytes(bytes) % self.count.get() as u64) as usize;
+        shard + 1 == self.index.get()
+    }
+}
+
+impl FromStr for Shard {
+    type Err = ParseShardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s.split_once('/').ok_or(ParseShardError)?;
+        let index = index.parse().map_err(|_| ParseShardError)?;
+        let count = count.parse().map_err(|_| ParseShardError)?;
+
+        if index > count {
+            return Err(ParseShardError);
+        }
+
+        Ok(Self { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Selects the packages in the index that an operation acts on.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Scope {
+    /// Packages are selected if their path in the index starts with any of these prefixes. All
+    /// packages are selected if there are no prefixes.
+    pub prefixes: Vec<String>,
+    /// Packages are selected if they belong to this shard.
+    pub shard: Option<Shard>,
+}
+
+impl Scope {
+    /// Returns true if every package is selected.
+    #[must_use]
+    pub const fn is_everything(&self) -> bool {
+        self.prefixes.is_empty() && self.shard.is_none()
+    }
+
+    /// Returns true if packages in the top-level directory `name` may be selected.
+    #[must_use]
+    pub fn may_contain_directory(&self, name: &str) -> bool {
+        self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| prefix.starts_with(name) || name.starts_with(prefix.as_str()))
+    }
+
+    /// Returns true if the package at `path` is selected.
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        (self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str())))
+            && self.shard.is_none_or(|shard| shard.contains(path))
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_everything() {
+            return write!(f, "everything");
+        }
+
+        write!(f, "prefixes [{}]", self.prefixes.join(", "))?;
+        if let Some(shard) = self.shard {
+            write!(f, " in shard {shard}")?;
+        }
+
+        Ok(())
+    }
+}
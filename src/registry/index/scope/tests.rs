@@ -0,0 +1,56 @@
+use super::*;
+
+#[test]
+fn test_parse_shard() {
+    let shard = Shard::from_str("3/16").expect("failed to parse shard");
+    assert_eq!(shard.to_string(), "3/16");
+}
+
+#[test]
+fn test_parse_shard_with_index_out_of_range() {
+    assert_eq!(Shard::from_str("0/16"), Err(ParseShardError));
+    assert_eq!(Shard::from_str("17/16"), Err(ParseShardError));
+}
+
+#[test]
+fn test_parse_malformed_shard() {
+    assert_eq!(Shard::from_str("3"), Err(ParseShardError));
+    assert_eq!(Shard::from_str("a/b"), Err(ParseShardError));
+}
+
+#[test]
+fn test_shards_are_disjoint_and_complete() {
+    let shards = (1..=4)
+        .map(|index| Shard::from_str(&format!("{index}/4")).expect("failed to parse shard"))
+        .collect::<Vec<_>>();
+
+    for path in ["1/a", "2/bb", "3/c/ccc", "ex/am/example", "se/rd/serde"] {
+        assert_eq!(
+            shards.iter().filter(|shard| shard.contains(path)).count(),
+            1,
+            "{path} does not belong to exactly one shard"
+        );
+    }
+}
+
+#[test]
+fn test_scope_with_prefix() {
+    let scope = Scope {
+        prefixes: vec![String::from("ex/am")],
+        shard: None,
+    };
+
+    assert!(scope.may_contain_directory("ex"));
+    assert!(!scope.may_contain_directory("se"));
+    assert!(scope.contains("ex/am/example"));
+    assert!(!scope.contains("ex/pe/expect"));
+}
+
+#[test]
+fn test_scope_with_everything() {
+    let scope = Scope::default();
+
+    assert!(scope.is_everything());
+    assert!(scope.may_contain_directory("1"));
+    assert!(scope.contains("1/a"));
+}
@@ -1,2 +1,4 @@
 pub mod cache;
 pub mod index;
+pub mod politeness;
+pub mod sparse;
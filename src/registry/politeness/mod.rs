@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests;
+
+use std::{num::NonZeroUsize, thread};
+use tracing::{debug, warn};
+
+/// The most jobs that download from a registry whose capacity is unknown. Alternative registries
+/// are often served by a single server.
+const DEFAULT_CAP: usize = 8;
+
+/// The number of jobs that are run for each available processor by default. Downloads spend most
+/// of their time waiting on the network.
+const JOBS_PER_PROCESSOR: usize = 2;
+
+/// The registries whose capacity is known, by the host that crates are downloaded from, with the
+/// most jobs that download from them.
+const REGISTRIES: &[(&str, &str, usize)] = &[
+    // crates.io serves its sparse index and crates from a CDN but asks that automated clients
+    // are considerate.
+    ("static.crates.io", "crates.io", 16),
+    ("crates.io", "crates.io", 16),
+    ("index.crates.io", "crates.io", 16),
+];
+
+/// The number of jobs that are polite to a registry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Politeness {
+    /// The name of the registry or nothing if its capacity is unknown.
+    pub registry: Option<&'static str>,
+    /// The most jobs that should download from the registry.
+    pub cap: NonZeroUsize,
+}
+
+impl Politeness {
+    /// Returns the politeness of the registry that crates are downloaded from at `host`.
+    #[must_use]
+    pub fn of(host: &str) -> Self {
+        let host = host.to_ascii_lowercase();
+        REGISTRIES
+            .iter()
+            .find(|(known, _, _)| *known == host)
+            .map_or(
+                Self {
+                    registry: None,
+                    cap: NonZeroUsize::new(DEFAULT_CAP).expect("cap is not zero"),
+                },
+                |(_, registry, cap)| Self {
+                    registry: Some(registry),
+                    cap: NonZeroUsize::new(*cap).expect("cap is not zero"),
+                },
+            )
+    }
+
+    /// Returns the number of jobs to run when `requested` were requested and `processors` are
+    /// available. By default, two jobs are run for each processor up to the cap. Requests beyond
+    /// the cap are honoured but are reported because they may get the mirror blocked.
+    #[must_use]
+    pub fn resolve(
+        self,
+        requested: Option<NonZeroUsize>,
+        processors: NonZeroUsize,
+    ) -> NonZeroUsize {
+        let Some(requested) = requested else {
+            return processors
+                .saturating_mul(NonZeroUsize::new(JOBS_PER_PROCESSOR).expect("jobs is not zero"))
+                .min(self.cap);
+        };
+
+        if requested > self.cap {
+            warn!(
+                "{} jobs may get the mirror blocked by {}; at most {} are recommended",
+                requested,
+                self.registry.unwrap_or("the registry"),
+                self.cap
+            );
+        }
+
+        requested
+    }
+}
+
+/// Returns the number of jobs that download from the registry at `host` when `requested` were
+/// requested. The registry is assumed to have an unknown capacity if there is no host.
+#[must_use]
+pub fn jobs(requested: Option<NonZeroUsize>, host: Option<&str>) -> NonZeroUsize {
+    let politeness = host.map_or_else(|| Politeness::of(""), Politeness::of);
+    let processors = thread::available_parallelism()
+        .unwrap_or_else(|_| NonZeroUsize::new(1).expect("one is not zero"));
+    let jobs = politeness.resolve(requested, processors);
+    debug!("running {} jobs", jobs);
+    jobs
+}
+
+/// Returns the number of jobs for an operation that only reads the cache when `requested` were
+/// requested. One job is run for each available processor by default.
+#[must_use]
+pub fn local(requested: Option<NonZeroUsize>) -> NonZeroUsize {
+    requested.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .unwrap_or_else(|_| NonZeroUsize::new(1).expect("one is not zero"))
+    })
+}
@@ -0,0 +1,31 @@
+use super::Politeness;
+use std::num::NonZeroUsize;
+
+fn count(count: usize) -> NonZeroUsize {
+    NonZeroUsize::new(count).expect("count is zero")
+}
+
+#[test]
+fn test_politeness_of_known_registry() {
+    let politeness = Politeness::of("Static.Crates.io");
+    assert_eq!(politeness.registry, Some("crates.io"));
+    assert_eq!(politeness.cap, count(16));
+
+    let politeness = Politeness::of("crates.example.com");
+    assert_eq!(politeness.registry, None);
+    assert_eq!(politeness.cap, count(8));
+}
+
+#[test]
+fn test_resolve_jobs() {
+    let politeness = Politeness::of("static.crates.io");
+    assert_eq!(politeness.resolve(None, count(1)), count(2));
+    assert_eq!(politeness.resolve(None, count(64)), count(16));
+
+    // Requests beyond the cap are honoured.
+    assert_eq!(politeness.resolve(Some(count(32)), count(1)), count(32));
+    assert_eq!(
+        Politeness::of("crates.example.com").resolve(None, count(64)),
+        count(8)
+    );
+}
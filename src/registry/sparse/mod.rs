@@ -0,0 +1,541 @@
+#[cfg(test)]
+pub mod tests;
+
+use super::index::{package, Index};
+use crate::file::{self, Durability, Permissions};
+use ahash::{AHashMap, AHashSet};
+use futures::{stream, StreamExt, TryStreamExt};
+use git2::{
+    IndexEntry, IndexTime, ObjectType, Oid, Repository, Signature, TreeWalkMode, TreeWalkResult,
+};
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io, mem,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str,
+    sync::{Arc, Mutex},
+};
+use tokio::task;
+use tracing::debug;
+use url::{ParseError, Url};
+
+/// The error type for opening a sparse index mirror.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OpenSparseIndexError {
+    Git(git2::Error),
+    MalformedUrl(ParseError),
+}
+
+impl From<git2::Error> for OpenSparseIndexError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<ParseError> for OpenSparseIndexError {
+    fn from(error: ParseError) -> Self {
+        Self::MalformedUrl(error)
+    }
+}
+
+impl Display for OpenSparseIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => error.fmt(f),
+            Self::MalformedUrl(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for OpenSparseIndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::MalformedUrl(error) => error.source(),
+        }
+    }
+}
+
+/// The error type for updating a sparse index mirror.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UpdateSparseIndexError {
+    Git(git2::Error),
+    Http(reqwest::Error),
+    Io(io::Error),
+    MalformedUrl(ParseError),
+    /// The sparse index does not have a configuration.
+    MissingConfiguration,
+    /// The sparse index responded with an unexpected status.
+    UnexpectedStatus {
+        url: Url,
+        status: StatusCode,
+    },
+}
+
+impl From<git2::Error> for UpdateSparseIndexError {
+    fn from(error: git2::Error) -> Self {
+        Self::Git(error)
+    }
+}
+
+impl From<reqwest::Error> for UpdateSparseIndexError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::Http(error)
+    }
+}
+
+impl From<io::Error> for UpdateSparseIndexError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ParseError> for UpdateSparseIndexError {
+    fn from(error: ParseError) -> Self {
+        Self::MalformedUrl(error)
+    }
+}
+
+impl Display for UpdateSparseIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Git(error) => error.fmt(f),
+            Self::Http(error) => error.fmt(f),
+            Self::Io(error) => error.fmt(f),
+            Self::MalformedUrl(error) => error.fmt(f),
+            Self::MissingConfiguration => write!(f, "sparse index does not have a configuration"),
+            Self::UnexpectedStatus { url, status } => {
+                write!(f, "{url} responded with unexpected status {status}")
+            }
+        }
+    }
+}
+
+impl Error for UpdateSparseIndexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Git(error) => error.source(),
+            Self::Http(error) => error.source(),
+            Self::Io(error) => error.source(),
+            Self::MalformedUrl(error) => error.source(),
+            Self::MissingConfiguration | Self::UnexpectedStatus { .. } => None,
+        }
+    }
+}
+
+/// A dependency of a crate version in a package.
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+    package: Option<String>,
+    registry: Option<String>,
+}
+
+/// A crate version in a package. Only the fields that are needed to discover crates are
+/// deserialised.
+#[derive(Deserialize)]
+struct Version {
+    #[serde(default)]
+    deps: Vec<Dependency>,
+}
+
+/// The validators that a sparse index responded with for a file. They are sent with the next
+/// request for the file so that the sparse index can respond that it has not changed.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct Validator {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+impl Validator {
+    /// Returns the validators in the headers of a response.
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        Self {
+            etag: value(ETAG),
+            last_modified: value(LAST_MODIFIED),
+        }
+    }
+
+    /// Returns true if there are no validators.
+    const fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// The response of a sparse index to a request for a file.
+enum Fetched {
+    /// The file has changed and these are its contents.
+    Modified(Vec<u8>, Validator),
+    /// The file has not changed since it was last fetched.
+    Unchanged,
+    /// The file does not exist.
+    Missing,
+}
+
+/// Returns the names of the crates in the same registry that the crates in `package` depend on.
+/// Lines that can not be deserialised are ignored.
+pub fn dependencies(package: &[u8]) -> impl Iterator<Item = String> + '_ {
+    package
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match serde_json::from_slice::<Version>(line) {
+            Ok(version) => Some(version.deps),
+            Err(error) => {
+                debug!("ignored malformed version: {}", error);
+                None
+            }
+        })
+        .flatten()
+        .filter(|dependency| dependency.registry.is_none())
+        .map(|dependency| dependency.package.unwrap_or(dependency.name))
+}
+
+/// Returns the names of the packages and the tree held by HEAD or nothing if HEAD does not exist.
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn tracked(repository: &Repository) -> Result<(Vec<String>, Option<Oid>), git2::Error> {
+    let tree = match repository.head() {
+        Ok(head) => head.peel_to_tree()?,
+        Err(error) if error.code() == git2::ErrorCode::UnbornBranch => {
+            return Ok((Vec::new(), None))
+        }
+        Err(error) => return Err(error),
+    };
+
+    let mut names = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        // Packages are never held at the root of the index.
+        if !root.is_empty() && entry.kind() == Some(ObjectType::Blob) {
+            names.push(String::from_utf8_lossy(entry.name_bytes()).into_owned());
+        }
+
+        TreeWalkResult::Ok
+    })?;
+
+    Ok((names, Some(tree.id())))
+}
+
+/// A sparse index mirror is a Git repository whose commits track the changes that are observed in
+/// a sparse index. It can be used by tooling that requires a Git index.
+///
+/// A sparse index can not be enumerated so the mirror tracks the crates that it is given and every
+/// crate that they depend on in the same registry.
+pub struct SparseIndex {
+    url: Url,
+    repository: Arc<Mutex<Repository>>,
+}
+
+impl SparseIndex {
+    /// The prefix of the scheme of a sparse index URL.
+    pub const SCHEME_PREFIX: &'static str = "sparse+";
+
+    /// The repository configuration key that holds the URL of the sparse index.
+    const URL_KEY: &'static str = "crateful.url";
+
+    /// The name of the file in the repository that holds the validators of each file that the
+    /// mirror holds.
+    const VALIDATORS_FILENAME: &'static str = "crateful-validators.json";
+
+    /// Returns true if `url` is the URL of a sparse index.
+    #[must_use]
+    pub fn is_sparse(url: &Url) -> bool {
+        url.scheme().starts_with(Self::SCHEME_PREFIX)
+    }
+
+    /// Creates a new mirror of the sparse index at `url` in `path` that tracks `crates`.
+    pub async fn create(
+        url: &Url,
+        path: PathBuf,
+        crates: &[String],
+        client: &Client,
+        jobs: NonZeroUsize,
+    ) -> Result<Self, UpdateSparseIndexError> {
+        let mut url = Url::parse(url.as_str().trim_start_matches(Self::SCHEME_PREFIX))?;
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        let location = url.to_string();
+        let repository = task::spawn_blocking(move || {
+            let repository = Repository::init_bare(path)?;
+            repository.config()?.set_str(Self::URL_KEY, &location)?;
+            Ok::<_, git2::Error>(repository)
+        })
+        .await
+        .expect("panicked while creating repository")?;
+
+        let mirror = Self {
+            url,
+            repository: Arc::new(Mutex::new(repository)),
+        };
+
+        mirror.update(client, jobs, crates).await?;
+        Ok(mirror)
+    }
+
+    /// Returns the mirror held at `path`.
+    pub async fn from_path(path: PathBuf) -> Result<Self, OpenSparseIndexError> {
+        let (repository, url) = task::spawn_blocking(move || {
+            let repository = Repository::open_bare(path)?;
+            let url = repository.config()?.get_string(Self::URL_KEY)?;
+            Ok::<_, git2::Error>((repository, url))
+        })
+        .await
+        .expect("panicked while opening repository")?;
+
+        Ok(Self {
+            url: Url::parse(&url)?,
+            repository: Arc::new(Mutex::new(repository)),
+        })
+    }
+
+    /// Requests a file from the sparse index. The request is conditional if `validator` is given
+    /// so that a file that has not changed is not downloaded again.
+    async fn fetch(
+        &self,
+        client: &Client,
+        path: &str,
+        validator: Option<&Validator>,
+    ) -> Result<Fetched, UpdateSparseIndexError> {
+        let url = self.url.join(path)?;
+        let mut request = client.get(url.clone());
+        if let Some(validator) = validator {
+            if let Some(etag) = &validator.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+
+            if let Some(last_modified) = &validator.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::OK => {
+                let validator = Validator::from_headers(response.headers());
+                Ok(Fetched::Modified(
+                    response.bytes().await?.to_vec(),
+                    validator,
+                ))
+            }
+            StatusCode::NOT_MODIFIED if validator.is_some() => Ok(Fetched::Unchanged),
+            StatusCode::NOT_FOUND
+            | StatusCode::GONE
+            | StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => Ok(Fetched::Missing),
+            status => Err(UpdateSparseIndexError::UnexpectedStatus { url, status }),
+        }
+    }
+
+    /// Requests every tracked package and `crates` from the sparse index and commits any changes.
+    /// Returns true if there were changes.
+    ///
+    /// Packages that no longer exist in the sparse index are removed. Files that the mirror holds
+    /// are requested conditionally with the `ETag` and `Last-Modified` validators that the sparse
+    /// index last responded with, and are not downloaded again if they have not changed.
+    #[allow(clippy::too_many_lines)]
+    pub async fn update(
+        &self,
+        client: &Client,
+        jobs: NonZeroUsize,
+        crates: &[String],
+    ) -> Result<bool, UpdateSparseIndexError> {
+        let repository = self.repository.clone();
+        let (names, tree, validators, location) = task::spawn_blocking(move || {
+            let repository = repository.lock().expect("lock is poisoned");
+            let (names, tree) = tracked(&repository)?;
+            let location = repository.path().join(Self::VALIDATORS_FILENAME);
+
+            // Validators are only trusted for the files that the mirror holds so that a file is
+            // never skipped because the commit that held it was not made.
+            let mut validators = read_validators(&location);
+            match tree {
+                Some(tree) => {
+                    let tree = repository.find_tree(tree)?;
+                    validators.retain(|path, _| tree.get_path(Path::new(path)).is_ok());
+                }
+                None => validators.clear(),
+            }
+
+            Ok::<_, git2::Error>((names, tree, validators, location))
+        })
+        .await
+        .expect("panicked while reading tracked packages")?;
+
+        let validators = &validators;
+        let mut renewed = AHashMap::new();
+        let mut files = Vec::new();
+        let path = String::from(Index::CONFIGURATION_FILENAME);
+        match self.fetch(client, &path, validators.get(&path)).await? {
+            Fetched::Modified(contents, validator) => {
+                if !validator.is_empty() {
+                    renewed.insert(path.clone(), validator);
+                }
+
+                files.push((path, Some(contents)));
+            }
+            Fetched::Unchanged => {
+                renewed.insert(path.clone(), validators[&path].clone());
+            }
+            Fetched::Missing => return Err(UpdateSparseIndexError::MissingConfiguration),
+        }
+
+        let mut seen: AHashSet<_> = names
+            .into_iter()
+            .chain(crates.iter().map(|name| name.to_lowercase()))
+            .collect();
+        let mut frontier: Vec<_> = seen.iter().cloned().collect();
+
+        // Packages are requested in waves so that the crates that they depend on are discovered.
+        while !frontier.is_empty() {
+            let fetched: Vec<_> = stream::iter(mem::take(&mut frontier))
+                .map(|name| async move {
+                    let path = package::path(&name);
+                    let fetched = self.fetch(client, &path, validators.get(&path)).await?;
+                    Ok::<_, UpdateSparseIndexError>((path, fetched))
+                })
+                .buffer_unordered(jobs.get())
+                .try_collect()
+                .await?;
+
+            for (path, fetched) in fetched {
+                match fetched {
+                    Fetched::Modified(contents, validator) => {
+                        for name in dependencies(&contents) {
+                            let name = name.to_lowercase();
+                            if seen.insert(name.clone()) {
+                                frontier.push(name);
+                            }
+                        }
+
+                        if !validator.is_empty() {
+                            renewed.insert(path.clone(), validator);
+                        }
+
+                        files.push((path, Some(contents)));
+                    }
+                    // The crates that an unchanged package depends on were tracked when it last
+                    // changed.
+                    Fetched::Unchanged => {
+                        renewed.insert(path.clone(), validators[&path].clone());
+                    }
+                    Fetched::Missing => files.push((path, None)),
+                }
+            }
+        }
+
+        let repository = self.repository.clone();
+        let changed = task::spawn_blocking(move || {
+            let repository = repository.lock().expect("lock is poisoned");
+
+            let mut index = git2::Index::new()?;
+            if let Some(tree) = tree {
+                index.read_tree(&repository.find_tree(tree)?)?;
+            }
+
+            for (path, contents) in files {
+                if let Some(contents) = contents {
+                    index.add(&IndexEntry {
+                        ctime: IndexTime::new(0, 0),
+                        mtime: IndexTime::new(0, 0),
+                        dev: 0,
+                        ino: 0,
+                        mode: 0o100_644,
+                        uid: 0,
+                        gid: 0,
+                        file_size: 0,
+                        id: repository.blob(&contents)?,
+                        flags: 0,
+                        flags_extended: 0,
+                        path: path.into_bytes(),
+                    })?;
+                } else {
+                    index.remove_path(Path::new(&path))?;
+                }
+            }
+
+            let id = index.write_tree_to(&repository)?;
+            if Some(id) == tree {
+                debug!("the sparse index has not changed");
+                return Ok(false);
+            }
+
+            let parent = if tree.is_some() {
+                Some(repository.head()?.peel_to_commit()?)
+            } else {
+                None
+            };
+
+            let signature = Signature::now("crateful", "crateful")?;
+            repository.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                "Update from the sparse index",
+                &repository.find_tree(id)?,
+                parent.iter().collect::<Vec<_>>().as_slice(),
+            )?;
+
+            debug!("committed changes from the sparse index");
+            Ok::<_, UpdateSparseIndexError>(true)
+        })
+        .await
+        .expect("panicked while committing changes")?;
+
+        // The validators are only recorded once the files that they describe have been committed.
+        file::write(
+            &location,
+            serde_json::to_vec(&renewed).expect("failed to serialise validators"),
+            Durability::Rename,
+            Permissions::default(),
+        )
+        .await?;
+
+        Ok(changed)
+    }
+}
+
+/// Returns the validators recorded at `path`. Validators that can not be read are discarded
+/// because they only prevent files from being downloaded again.
+///
+/// This is a blocking function and must not be used from an asynchronous context.
+fn read_validators(path: &Path) -> AHashMap<String, Validator> {
+    match std::fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|error| {
+            debug!("discarded malformed validators: {}", error);
+            AHashMap::new()
+        }),
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                debug!("failed to read validators: {}", error);
+            }
+
+            AHashMap::new()
+        }
+    }
+}
+
+impl fmt::Debug for SparseIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SparseIndex")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
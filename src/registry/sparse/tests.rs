@@ -0,0 +1,34 @@
+use super::{dependencies, Validator};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, LAST_MODIFIED};
+
+#[test]
+fn test_dependencies() {
+    let package = br#"{"name":"a","vers":"0.0.1","deps":[{"name":"b","req":"^1"},{"name":"renamed","package":"c","req":"^1"},{"name":"d","req":"^1","registry":"https://example.com/index"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{}}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{}}
+malformed
+"#;
+
+    assert_eq!(
+        dependencies(package).collect::<Vec<_>>(),
+        vec![String::from("b"), String::from("c")]
+    );
+}
+
+#[test]
+fn test_validator_from_headers() {
+    let mut headers = HeaderMap::new();
+    assert!(Validator::from_headers(&headers).is_empty());
+
+    headers.insert(ETAG, HeaderValue::from_static("\"0\""));
+    headers.insert(
+        LAST_MODIFIED,
+        HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+    );
+    assert_eq!(
+        Validator::from_headers(&headers),
+        Validator {
+            etag: Some(String::from("\"0\"")),
+            last_modified: Some(String::from("Sun, 06 Nov 1994 08:49:37 GMT")),
+        }
+    );
+}
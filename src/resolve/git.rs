@@ -0,0 +1,200 @@
+use super::Override;
+use git2::{
+    transport::{self, Service, SmartSubtransport, SmartSubtransportStream, Transport},
+    Error,
+};
+use reqwest::{
+    blocking::{Client, Response},
+    header,
+};
+use std::{
+    io::{self, Read, Write},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+/// Git servers expect the user agent of a Git client.
+const USER_AGENT: &str = concat!(
+    "git/2.0 (",
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    ")"
+);
+
+/// Returns the path of the request that performs `service`, the content type of its body, and the
+/// content type that the response must have.
+const fn request(service: Service) -> (&'static str, Option<&'static str>, &'static str) {
+    match service {
+        Service::UploadPackLs => (
+            "/info/refs?service=git-upload-pack",
+            None,
+            "application/x-git-upload-pack-advertisement",
+        ),
+        Service::UploadPack => (
+            "/git-upload-pack",
+            Some("application/x-git-upload-pack-request"),
+            "application/x-git-upload-pack-result",
+        ),
+        Service::ReceivePackLs => (
+            "/info/refs?service=git-receive-pack",
+            None,
+            "application/x-git-receive-pack-advertisement",
+        ),
+        Service::ReceivePack => (
+            "/git-receive-pack",
+            Some("application/x-git-receive-pack-request"),
+            "application/x-git-receive-pack-result",
+        ),
+    }
+}
+
+/// A smart HTTP subtransport that makes requests with a client that resolves host names with
+/// overrides.
+struct Subtransport {
+    client: Client,
+    /// The URL of the repository. This changes when the server redirects the first request.
+    base: Arc<Mutex<String>>,
+}
+
+impl SmartSubtransport for Subtransport {
+    fn action(
+        &self,
+        url: &str,
+        service: Service,
+    ) -> Result<Box<dyn SmartSubtransportStream>, Error> {
+        let mut base = self.base.lock().expect("lock is poisoned");
+        if base.is_empty() {
+            url.clone_into(&mut base);
+        }
+
+        Ok(Box::new(Stream {
+            client: self.client.clone(),
+            base: self.base.clone(),
+            service,
+            body: Vec::new(),
+            response: None,
+        }))
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A single request of the smart HTTP protocol. The body of the request is buffered as it is
+/// written and the request is sent when the response is first read.
+struct Stream {
+    client: Client,
+    base: Arc<Mutex<String>>,
+    service: Service,
+    body: Vec<u8>,
+    response: Option<Response>,
+}
+
+impl Stream {
+    fn send(&mut self) -> Result<Response, io::Error> {
+        let (path, content_type, expected) = request(self.service);
+        let mut base = self.base.lock().expect("lock is poisoned");
+        let url = format!("{base}{path}");
+
+        let request = match content_type {
+            Some(content_type) => self
+                .client
+                .post(&url)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::ACCEPT, expected)
+                .body(mem::take(&mut self.body)),
+            None => self.client.get(&url),
+        };
+
+        let response = request.send().map_err(io::Error::other)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(io::Error::other(format!(
+                "a http response had a {status} status for {url}"
+            )));
+        }
+
+        if !response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|value| value.as_bytes().starts_with(expected.as_bytes()))
+        {
+            return Err(io::Error::other(format!(
+                "{url} does not support the smart http protocol"
+            )));
+        }
+
+        // Later requests are sent to wherever the server redirected the first request.
+        if let Some(redirected) = response.url().as_str().strip_suffix(path) {
+            redirected.clone_into(&mut base);
+        }
+
+        Ok(response)
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.response.is_none() {
+            self.response = Some(self.send()?);
+        }
+
+        self.response
+            .as_mut()
+            .expect("request should have been sent")
+            .read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.response.is_some() {
+            return Err(io::Error::other("the request has already been sent"));
+        }
+
+        self.body.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Replaces the HTTP and HTTPS transports of Git with transports that resolve host names with
+/// `overrides`.
+///
+/// This must be called at most once and before any repository is cloned or fetched.
+pub fn register(overrides: &[Override]) -> Result<(), Error> {
+    for scheme in ["http", "https"] {
+        let overrides = overrides.to_vec();
+        let factory = move |remote: &git2::Remote<'_>| {
+            // A client is built for each transport because a blocking client can not be built on
+            // an asynchronous task.
+            let client = overrides
+                .iter()
+                .fold(Client::builder().user_agent(USER_AGENT), |builder, each| {
+                    builder.resolve(&each.host, each.address)
+                })
+                .build()
+                .map_err(|error| Error::from_str(&error.to_string()))?;
+
+            Transport::smart(
+                remote,
+                true,
+                Subtransport {
+                    client,
+                    base: Arc::new(Mutex::new(String::new())),
+                },
+            )
+        };
+
+        // SAFETY: transports are registered before any repository is opened so that registration
+        // can not race with the creation of a transport.
+        unsafe { transport::register(scheme, factory)? };
+    }
+
+    Ok(())
+}
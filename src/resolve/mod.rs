@@ -0,0 +1,81 @@
+#[cfg(test)]
+pub mod tests;
+
+mod git;
+
+pub use git::register as register_git_transport;
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    net::{AddrParseError, IpAddr, SocketAddr},
+    num::ParseIntError,
+    str::FromStr,
+};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseOverrideError {
+    /// The override does not have a host, port, and address separated by colons.
+    Malformed,
+    Port(ParseIntError),
+    Address(AddrParseError),
+}
+
+impl Display for ParseOverrideError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "expected an override like host:port:address"),
+            Self::Port(error) => write!(f, "invalid port: {error}"),
+            Self::Address(error) => write!(f, "invalid address: {error}"),
+        }
+    }
+}
+
+impl Error for ParseOverrideError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Malformed => None,
+            Self::Port(error) => Some(error),
+            Self::Address(error) => Some(error),
+        }
+    }
+}
+
+/// An override of the address that a host name resolves to.
+///
+/// An override is written like the `--resolve` option of curl as a host, port, and address
+/// separated by colons (eg. `static.crates.io:443:10.0.0.1`). An IPv6 address can be enclosed in
+/// brackets.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Override {
+    /// The host name that is overridden.
+    pub host: String,
+    /// The address that connections to the host are made to.
+    pub address: SocketAddr,
+}
+
+impl FromStr for Override {
+    type Err = ParseOverrideError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, rest) = s.split_once(':').ok_or(ParseOverrideError::Malformed)?;
+        let (port, address) = rest.split_once(':').ok_or(ParseOverrideError::Malformed)?;
+        if host.is_empty() {
+            return Err(ParseOverrideError::Malformed);
+        }
+
+        let port = port.parse().map_err(ParseOverrideError::Port)?;
+        let address = address
+            .strip_prefix('[')
+            .and_then(|address| address.strip_suffix(']'))
+            .unwrap_or(address)
+            .parse::<IpAddr>()
+            .map_err(ParseOverrideError::Address)?;
+
+        Ok(Self {
+            host: host.to_owned(),
+            address: SocketAddr::new(address, port),
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use super::*;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+#[test]
+fn test_parse_override() {
+    let each: Override = "static.crates.io:443:10.0.0.1"
+        .parse()
+        .expect("failed to parse override");
+    assert_eq!(
+        each,
+        Override {
+            host: String::from("static.crates.io"),
+            address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 443),
+        }
+    );
+}
+
+#[test]
+fn test_parse_override_with_ipv6_address() {
+    for address in ["::1", "[::1]"] {
+        let each: Override = format!("index.crates.io:80:{address}")
+            .parse()
+            .expect("failed to parse override");
+        assert_eq!(
+            each.address,
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 80)
+        );
+    }
+}
+
+#[test]
+fn test_parse_invalid_override() {
+    assert!(matches!(
+        "static.crates.io".parse::<Override>(),
+        Err(ParseOverrideError::Malformed)
+    ));
+    assert!(matches!(
+        ":443:10.0.0.1".parse::<Override>(),
+        Err(ParseOverrideError::Malformed)
+    ));
+    assert!(matches!(
+        "static.crates.io:https:10.0.0.1".parse::<Override>(),
+        Err(ParseOverrideError::Port(_))
+    ));
+    assert!(matches!(
+        "static.crates.io:443:mirror".parse::<Override>(),
+        Err(ParseOverrideError::Address(_))
+    ));
+}
@@ -0,0 +1,124 @@
+use crate::file::Partial;
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use tokio::{fs, task};
+use url::Url;
+
+/// The error type for verifying the signature of an artefact.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VerifySignatureError {
+    /// The verifier could not be run.
+    Io(io::Error),
+    /// The verifier rejected the artefact. The reason is what it wrote to standard error.
+    Rejected(String),
+}
+
+impl From<io::Error> for VerifySignatureError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl Display for VerifySignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to run the signature verifier: {error}"),
+            Self::Rejected(reason) if reason.is_empty() => {
+                f.write_str("the signature verifier rejected the artefact")
+            }
+            Self::Rejected(reason) => {
+                write!(f, "the signature verifier rejected the artefact: {reason}")
+            }
+        }
+    }
+}
+
+impl Error for VerifySignatureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Rejected(_) => None,
+        }
+    }
+}
+
+/// A program that verifies the signatures that a registry publishes for its artefacts (eg. TUF
+/// metadata or Sigstore bundles).
+///
+/// The program is run with the path of a file that holds a downloaded artefact as its only
+/// argument before the artefact is stored. The URL that the artefact was downloaded from is in
+/// `CRATEFUL_URL` and the artefact is described by other environment variables. The artefact is
+/// accepted if the program exits successfully. The program is responsible for fetching signatures
+/// and trusted keys.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Verifier {
+    program: PathBuf,
+}
+
+impl Verifier {
+    #[must_use]
+    pub const fn new(program: PathBuf) -> Self {
+        Self { program }
+    }
+
+    /// Returns the program that verifies signatures.
+    #[must_use]
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+}
+
+/// The verification of the signature of an artefact.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Signature {
+    pub verifier: Verifier,
+    /// The environment variables that describe the artefact to the verifier.
+    pub variables: Vec<(&'static str, String)>,
+}
+
+impl Signature {
+    /// Verifies that `bytes` were signed by the registry. The bytes were downloaded from `url` and
+    /// are written beside `destination` while they are verified.
+    pub async fn verify(
+        &self,
+        url: &Url,
+        bytes: &[u8],
+        destination: &Path,
+    ) -> Result<(), VerifySignatureError> {
+        let partial = Partial::new(destination);
+        fs::write(partial.path(), bytes).await?;
+        let result = self.verify_file(url, partial.path()).await;
+        drop(partial);
+        result
+    }
+
+    /// Verifies the signature of the artefact that was downloaded from `url` and written to `path`.
+    pub async fn verify_file(&self, url: &Url, path: &Path) -> Result<(), VerifySignatureError> {
+        let mut command = Command::new(self.verifier.program());
+        command
+            .arg(path)
+            .env("CRATEFUL_URL", url.as_str())
+            .envs(self.variables.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let output = task::spawn_blocking(move || command.output())
+            .await
+            .expect("panicked while verifying signature")?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(VerifySignatureError::Rejected(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ))
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    digest::{Algorithm, Pool},
+    file::{self, Durability, Permissions},
+};
+use clap::ArgEnum;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use tokio::{fs, task};
+
+/// Specifies how artefacts are stored.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Storage {
+    /// Artefacts are stored as they were downloaded.
+    #[default]
+    Plain,
+    /// Artefacts are compressed with zstd when it makes them smaller.
+    Zstd,
+}
+
+/// The form that an artefact is stored in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Form {
+    /// The artefact is stored as it was downloaded.
+    Plain,
+    /// The artefact is compressed with zstd. The checksum of the compressed artefact is stored
+    /// alongside it.
+    Compressed,
+}
+
+/// Returns the path of the compressed form of the artefact at `path`.
+fn compressed_path(path: &Path) -> PathBuf {
+    path.with_extension("zst")
+}
+
+/// Returns the path of the checksum of the compressed form of the artefact at `path`.
+fn checksum_path(path: &Path) -> PathBuf {
+    path.with_extension("zst.sha256")
+}
+
+/// Returns true if a file exists.
+async fn exists(path: &Path) -> Result<bool, io::Error> {
+    match fs::metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Removes a file if it exists.
+async fn remove_file(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the form of the artefact stored at `path` or nothing if it is not stored.
+pub async fn form(path: &Path) -> Result<Option<Form>, io::Error> {
+    if exists(&compressed_path(path)).await? {
+        Ok(Some(Form::Compressed))
+    } else if exists(path).await? {
+        Ok(Some(Form::Plain))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The file system metadata of a stored artefact.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Stat {
+    /// The number of bytes that the artefact occupies.
+    pub size: u64,
+    /// The time that the artefact was last modified as the number of nanoseconds since the Unix
+    /// epoch.
+    pub modified: u64,
+}
+
+/// Returns the file system metadata of the artefact stored at `path` or nothing if it is not
+/// stored.
+pub async fn stat(path: &Path) -> Result<Option<Stat>, io::Error> {
+    let stored = match form(path).await? {
+        Some(Form::Plain) => path.to_path_buf(),
+        Some(Form::Compressed) => compressed_path(path),
+        None => return Ok(None),
+    };
+
+    let metadata = fs::metadata(stored).await?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+        });
+
+    Ok(Some(Stat {
+        size: metadata.len(),
+        modified,
+    }))
+}
+
+/// Returns true if the compressed form of the artefact at `path` has the checksum that was
+/// recorded when it was compressed.
+pub async fn verify_compressed(path: &Path, pool: &Pool) -> Result<bool, io::Error> {
+    let checksum = match fs::read_to_string(checksum_path(path)).await {
+        Ok(checksum) => checksum,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+
+    let (_, digest) = pool
+        .digest(Algorithm::Sha256, file::read(&compressed_path(path)).await?)
+        .await;
+    Ok(hex::encode(digest.as_bytes()) == checksum.trim())
+}
+
+/// Reads the artefact stored at `path` in the form that it is stored in. Nothing is returned if the
+/// artefact is not stored.
+pub async fn read_stored(path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+    match form(path).await? {
+        Some(Form::Plain) => Ok(Some(file::read(path).await?)),
+        Some(Form::Compressed) => Ok(Some(file::read(&compressed_path(path)).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the artefact stored at `path` and decompresses it if necessary. Nothing is returned if
+/// the artefact is not stored.
+pub async fn load(path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+    match form(path).await? {
+        Some(Form::Plain) => Ok(Some(file::read(path).await?)),
+        Some(Form::Compressed) => {
+            let compressed = file::read(&compressed_path(path)).await?;
+            task::spawn_blocking(move || zstd::decode_all(compressed.as_slice()))
+                .await
+                .expect("panicked while decompressing")
+                .map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Stores `bytes` as the artefact at `path` with `permissions` and removes any other form of the
+/// artefact. Returns the form that the artefact was stored in.
+pub async fn store(
+    path: &Path,
+    bytes: Vec<u8>,
+    storage: Storage,
+    durability: Durability,
+    permissions: Permissions,
+    pool: &Pool,
+) -> Result<Form, io::Error> {
+    let (bytes, compressed) = match storage {
+        Storage::Plain => (bytes, None),
+        Storage::Zstd => task::spawn_blocking(move || {
+            let compressed = zstd::encode_all(bytes.as_slice(), 0)?;
+            Ok::<_, io::Error>((bytes, Some(compressed)))
+        })
+        .await
+        .expect("panicked while compressing")?,
+    };
+
+    match compressed {
+        // Compressed artefacts are only stored when compression reduces their size.
+        Some(compressed) if compressed.len() < bytes.len() => {
+            let (compressed, digest) = pool.digest(Algorithm::Sha256, compressed).await;
+            file::write(&compressed_path(path), compressed, durability, permissions).await?;
+            file::write(
+                &checksum_path(path),
+                hex::encode(digest.as_bytes()).into_bytes(),
+                durability,
+                permissions,
+            )
+            .await?;
+            remove_file(path).await?;
+            Ok(Form::Compressed)
+        }
+
+        _ => {
+            file::write(path, bytes, durability, permissions).await?;
+            remove_file(&compressed_path(path)).await?;
+            remove_file(&checksum_path(path)).await?;
+            Ok(Form::Plain)
+        }
+    }
+}
+
+/// Moves the artefact that was written by `writer` to `path` in its plain form with `permissions`
+/// and removes any other form of the artefact.
+pub async fn store_written(
+    path: &Path,
+    writer: file::Writer,
+    durability: Durability,
+    permissions: Permissions,
+) -> Result<(), io::Error> {
+    writer.persist(path, durability, permissions).await?;
+    remove_file(&compressed_path(path)).await?;
+    remove_file(&checksum_path(path)).await
+}
+
+/// Removes every form of the artefact stored at `path`.
+pub async fn remove(path: &Path) -> Result<(), io::Error> {
+    remove_file(path).await?;
+    remove_file(&compressed_path(path)).await?;
+    remove_file(&checksum_path(path)).await
+}
+
+/// Moves every form of the artefact stored at `from` to `to`.
+pub async fn rename(from: &Path, to: &Path) -> Result<(), io::Error> {
+    for (from, to) in [
+        (from.to_path_buf(), to.to_path_buf()),
+        (compressed_path(from), compressed_path(to)),
+        (checksum_path(from), checksum_path(to)),
+    ] {
+        if exists(&from).await? {
+            fs::rename(&from, &to).await?;
+        }
+    }
+
+    Ok(())
+}
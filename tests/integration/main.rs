@@ -1,25 +1,44 @@
 #![warn(clippy::all, clippy::cargo, clippy::nursery, clippy::pedantic)]
 
 use core::convert::Into;
+use fs2::FileExt;
 use futures::{
     stream::{self, FuturesUnordered},
     StreamExt,
 };
-use git2::{Index, IndexEntry, IndexTime, Repository, Signature};
+use git2::{Index, IndexEntry, IndexTime, Repository, Signature, Time};
 use serde::Serialize;
 use std::{
+    collections::BTreeMap,
     convert::AsRef,
-    env, io,
+    env,
+    ffi::OsStr,
+    io,
+    net::SocketAddr,
     ops::Range,
     path::{Path, PathBuf},
     process::{ExitStatus, Stdio},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 use tempfile::TempDir;
-use tokio::{fs, process::Command, task::spawn_blocking};
-use tokio_util::sync::CancellationToken;
+#[cfg(target_os = "linux")]
+use tokio::net::UnixDatagram;
+use tokio::{
+    fs,
+    process::{Child, Command},
+    sync::Notify,
+    task::spawn_blocking,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use url::Url;
-use warp::Filter;
+use warp::{
+    http::{Method, Response, StatusCode, Uri},
+    Filter, Rejection, Reply,
+};
 
 async fn assert_exists(
     paths: impl Iterator<Item = impl AsRef<Path> + Send + Sync> + Send,
@@ -91,6 +110,84 @@ impl Crateful {
             .unwrap_or_else(|_| panic!("failed to run {}", self.location.to_string_lossy()))
     }
 
+    /// Invokes crateful on a cache with arbitrary arguments.
+    async fn run<S: AsRef<OsStr> + Send + Sync>(
+        &self,
+        path: impl AsRef<Path> + Send + Sync,
+        arguments: &[S],
+    ) -> ExitStatus {
+        Command::new(&self.location)
+            .arg("--path")
+            .arg(path.as_ref())
+            .args(arguments)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .unwrap_or_else(|_| panic!("failed to run {}", self.location.to_string_lossy()))
+    }
+
+    /// Invokes crateful on a cache with arbitrary arguments and returns its exit status with its
+    /// standard error so that a failure can be explained.
+    async fn run_logged<S: AsRef<OsStr> + Send + Sync>(
+        &self,
+        path: impl AsRef<Path> + Send + Sync,
+        arguments: &[S],
+    ) -> (ExitStatus, String) {
+        let output = Command::new(&self.location)
+            .arg("--path")
+            .arg(path.as_ref())
+            .args(arguments)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .output()
+            .await
+            .unwrap_or_else(|_| panic!("failed to run {}", self.location.to_string_lossy()));
+
+        (
+            output.status,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    /// Starts crateful on a cache with arbitrary arguments without waiting for it to exit.
+    fn spawn<S: AsRef<OsStr> + Send + Sync>(
+        &self,
+        path: impl AsRef<Path> + Send + Sync,
+        arguments: &[S],
+    ) -> Child {
+        Command::new(&self.location)
+            .arg("--path")
+            .arg(path.as_ref())
+            .args(arguments)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|_| panic!("failed to run {}", self.location.to_string_lossy()))
+    }
+
+    /// Invokes crateful with the given arguments and returns its standard output.
+    async fn output<S: AsRef<OsStr> + Send + Sync>(
+        &self,
+        path: impl AsRef<Path> + Send + Sync,
+        arguments: &[S],
+    ) -> Vec<u8> {
+        let output = Command::new(&self.location)
+            .arg("--path")
+            .arg(path.as_ref())
+            .args(arguments)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .await
+            .unwrap_or_else(|_| panic!("failed to run {}", self.location.to_string_lossy()));
+
+        assert!(output.status.success(), "failed to run crateful");
+        output.stdout
+    }
+
     /// Invokes crateful to verify a cache.
     async fn verify(&self, path: impl AsRef<Path> + Send + Sync) -> ExitStatus {
         Command::new(&self.location)
@@ -144,6 +241,57 @@ impl Resources {
 /// Range of permitted ports for a web server.
 const PERMITTED_PORTS: Range<u16> = 1024..2048;
 
+/// Serves `filter` on the first available port in the permitted range. The server is shut down
+/// when the returned guard is dropped.
+fn serve<F>(filter: &F) -> (SocketAddr, DropGuard)
+where
+    F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let parent = CancellationToken::new();
+
+    let (socket, server) = PERMITTED_PORTS
+        .into_iter()
+        .find_map(|port| {
+            let token = parent.child_token();
+            warp::serve(filter.clone())
+                .try_bind_with_graceful_shutdown(([127, 0, 0, 1], port), async move {
+                    token.cancelled().await;
+                })
+                .ok()
+        })
+        .expect("no available port in permitted range");
+
+    tokio::spawn(server);
+    (socket, parent.drop_guard())
+}
+
+/// Creates a registry index at `path` that contains `packages`. Each package is described by its
+/// path in the index and its contents.
+async fn create_registry_index(path: &Path, download: String, packages: &[(&str, &str)]) {
+    let path = path.to_path_buf();
+    let packages = packages
+        .iter()
+        .map(|(path, contents)| (path.as_bytes().to_vec(), contents.as_bytes().to_vec()))
+        .collect::<Vec<_>>();
+
+    spawn_blocking(move || {
+        let repo = Repository::init(&path).expect("failed to initialise registry index");
+        let configuration = serde_json::to_vec(&IndexFormat { download })
+            .expect("failed to serialise index format");
+
+        let mut stager = Stager::new(&repo);
+        stager.add(b"config.json".to_vec(), &configuration);
+        for (path, contents) in packages {
+            stager.add(path, &contents);
+        }
+
+        stager.commit();
+    })
+    .await
+    .expect("failed to prepare registry index");
+}
+
 /// A simple abstraction around a Git repository for staging and eventually committing files.
 struct Stager<'a> {
     repository: &'a Repository,
@@ -191,23 +339,33 @@ impl<'a> Stager<'a> {
 
     /// Commits any staged files.
     fn commit(&mut self) {
-        let parent = match self.repository.head() {
-            Ok(reference) => Some({
-                reference
-                    .peel_to_commit()
-                    .expect("failed to get commit for HEAD")
-            }),
-            Err(_) => None,
-        };
+        self.commit_as(
+            &Signature::now("crateful", "crateful").expect("failed to create signature"),
+        );
+    }
 
-        let parents = parent.as_ref().into_iter().collect::<Vec<_>>();
-        let signature = Signature::now("crateful", "crateful").expect("failed to create signature");
+    /// Commits any staged files as if they were committed `time` seconds after the Unix epoch.
+    fn commit_at(&mut self, time: i64) {
+        self.commit_as(
+            &Signature::new("crateful", "crateful", &Time::new(time, 0))
+                .expect("failed to create signature"),
+        );
+    }
 
+    /// Commits any staged files with a signature.
+    fn commit_as(&mut self, signature: &Signature<'_>) {
+        let parent = self.repository.head().ok().map(|reference| {
+            reference
+                .peel_to_commit()
+                .expect("failed to get commit for HEAD")
+        });
+
+        let parents = parent.as_ref().into_iter().collect::<Vec<_>>();
         self.repository
             .commit(
                 Some("refs/heads/master"),
-                &signature,
-                &signature,
+                signature,
+                signature,
                 "commit",
                 {
                     &self
@@ -1252,68 +1410,196 @@ async fn test_update_with_crate_modification() {
 }
 
 #[tokio::test]
-#[allow(clippy::too_many_lines)]
-async fn test_update_with_crate_removal() {
+async fn test_update_with_unmodified_crate() {
     let resources = Resources::new();
+    let downloads = Arc::new(AtomicUsize::new(0));
+    // The registry serves a new crate once it is republished, but conditional requests for the old
+    // crate are still answered as though it had not changed (eg. by a stale CDN).
+    let republished = Arc::new(AtomicBool::new(false));
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::method())
+            .and(warp::header::optional::<String>("if-none-match"))
+            .and_then({
+                let downloads = downloads.clone();
+                let republished = republished.clone();
+                move |name: String, version: String, method: Method, etag: Option<String>| {
+                    let downloads = downloads.clone();
+                    let republished = republished.clone();
+                    async move {
+                        if (name.as_str(), version.as_str()) != ("a", "0.0.1") {
+                            return Err(warp::reject::not_found());
+                        }
 
-    let filter = warp::path!(String / String / "download").and_then(
+                        let response = if etag.as_deref() == Some("\"0\"") {
+                            Response::builder()
+                                .status(StatusCode::NOT_MODIFIED)
+                                .body(String::new())
+                        } else {
+                            if method == Method::GET {
+                                downloads.fetch_add(1, Ordering::SeqCst);
+                            }
+
+                            let body = if republished.load(Ordering::SeqCst) {
+                                "1"
+                            } else {
+                                "0"
+                            };
+                            Response::builder()
+                                .header("etag", format!("\"{body}\""))
+                                .body(String::from(body))
+                        };
+
+                        Ok(response.expect("failed to build response"))
+                    }
+                }
+            }),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+    let modify = |line: &'static str| {
+        let registry_index = registry_index.clone();
+        async move {
+            spawn_blocking(move || {
+                let repo =
+                    Repository::open(&registry_index).expect("failed to open registry index");
+                Stager::new(&repo)
+                    .add(b"1/a".to_vec(), line.as_bytes())
+                    .commit();
+            })
+            .await
+            .expect("failed to modify crate in registry index");
+        }
+    };
+
+    // A change to the package that keeps the checksum does not download the crate again.
+    modify(
+        r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{"std":[]},"yanked":false}"#,
+    )
+    .await;
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+    // A crate whose checksum changed is downloaded again even though the registry reports that the
+    // stored crate has not changed.
+    republished.store(true, Ordering::SeqCst);
+    modify(
+        r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b","features":{"std":[]},"yanked":false}"#,
+    )
+    .await;
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(downloads.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        fs::read_to_string(cache.join("crates/a/0.0.1/download"))
+            .await
+            .expect("failed to read from cache"),
+        "1"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_with_connection_options() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
         |name: String, version: String| async move {
             match (name.as_str(), version.as_str()) {
                 ("a", "0.0.1") => Ok("0"),
                 _ => Err(warp::reject::not_found()),
             }
         },
-    );
+    ));
 
-    let parent = CancellationToken::new();
-    let child = &parent.child_token();
-    let stream = stream::iter(PERMITTED_PORTS).filter_map(|port| async move {
-        let address = ([127, 0, 0, 1], port);
-        let token = child.clone();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
 
-        match warp::serve(filter)
-            .try_bind_with_graceful_shutdown(address, async move { token.cancelled().await })
-        {
-            Ok((socket, server)) => Some((socket, server)),
-            Err(_) => None,
-        }
-    });
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
 
-    tokio::pin!(stream);
-    let (socket, server) = stream
-        .next()
-        .await
-        .expect("no available port in permitted range");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--pool-size",
+                "1",
+                "--pool-idle-timeout",
+                "5",
+                "--http",
+                "http1",
+                "--tcp-nodelay",
+                "false",
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+}
 
-    let _guard = parent.drop_guard();
-    tokio::spawn(server);
+#[cfg(feature = "http3")]
+#[tokio::test]
+async fn test_sync_with_http3_falls_back() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
 
     let registry_index = resources.workspace().join("index");
-    spawn_blocking({
-        let registry_index = registry_index.clone();
-        move || {
-            let repo =
-                Repository::init(&registry_index).expect("failed to initialise registry index");
-
-            Stager::new(&repo)
-                .add(b"config.json".to_vec(), {
-                    let configuration = IndexFormat {
-                        download: format!("http://127.0.0.1:{}", socket.port()),
-                    };
-
-                    serde_json::to_vec(&configuration)
-                        .expect("failed to serialise index format")
-                        .as_slice()
-                })
-                .add(
-                    b"1/a".to_vec(),
-                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes()
-                )
-                .commit();
-        }
-    })
-    .await
-    .expect("failed to prepare registry index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
 
     let cache = resources.workspace().join("cache");
     let status = resources
@@ -1323,37 +1609,6316 @@ async fn test_update_with_crate_removal() {
             &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
         )
         .await;
-
     assert!(status.success(), "failed to create cache");
-    assert_exists([&cache, &cache.join("index")].into_iter(), true).await;
-    assert_exists([cache.join("crates")].into_iter(), false).await;
 
-    let status = resources.exe().sync(&cache).await;
+    // The registry only serves HTTP/1.1 so the download must fall back.
+    let status = resources.exe().run(&cache, &["--http3", "sync"]).await;
     assert!(status.success(), "failed to sync cache");
-    assert_exists(
-        [
-            &cache,
-            &cache.join("index"),
-            &cache.join("crates"),
-            &cache.join("crates/a/0.0.1/download"),
-        ]
-        .into_iter(),
-        true,
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--http3", "--http", "http1", "sync"])
+        .await;
+    assert!(!status.success(), "--http3 should conflict with --http");
+}
+
+#[cfg(feature = "otlp")]
+#[tokio::test]
+async fn test_sync_exports_traces() {
+    let resources = Resources::new();
+    let exports = Arc::new(AtomicUsize::new(0));
+    let counter = exports.clone();
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and_then(|name: String, version: String| async move {
+                match (name.as_str(), version.as_str()) {
+                    ("a", "0.0.1") => Ok("0"),
+                    _ => Err(warp::reject::not_found()),
+                }
+            })
+            .or(warp::post()
+                .and(warp::path!("v1" / "traces"))
+                .and(warp::body::bytes())
+                .map(move |body: warp::hyper::body::Bytes| {
+                    assert!(!body.is_empty(), "exported no spans");
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    ""
+                })),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
     )
     .await;
 
-    spawn_blocking({
-        move || {
-            let repo = Repository::open(&registry_index).expect("failed to open registry index");
-            Stager::new(&repo).remove(Path::new("1/a")).commit();
-        }
-    })
-    .await
-    .expect("failed to add crate to registry index");
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
 
-    let status = resources.exe().sync(&cache).await;
+    let endpoint = format!("http://127.0.0.1:{}/v1/traces", socket.port());
+    let status = resources
+        .exe()
+        .run(&cache, &["--otlp-endpoint", &endpoint, "sync"])
+        .await;
     assert!(status.success(), "failed to sync cache");
-    assert_exists([&cache, &cache.join("index")].into_iter(), true).await;
-    // There are no crates. Obsolete directories should be removed.
-    assert_exists([&cache.join("cache")].into_iter(), false).await;
+    assert!(
+        exports.load(Ordering::SeqCst) > 0,
+        "traces were not exported"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_with_log_file() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Every event is written to a new file because the maximum size is so small.
+    let log = resources.workspace().join("logs/crateful.log");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--log-file",
+                log.to_str().expect("path should be unicode"),
+                "--log-file-format",
+                "json",
+                "--log-max-size",
+                "1",
+                "--log-max-files",
+                "2",
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    let rotated = log.with_extension("log.1");
+    assert_exists([log.clone(), rotated.clone()].into_iter(), true).await;
+    assert_exists([log.with_extension("log.3")].into_iter(), false).await;
+
+    for path in [log, rotated] {
+        let contents = fs::read_to_string(&path)
+            .await
+            .expect("failed to read log file");
+        for line in contents.lines() {
+            serde_json::from_str::<serde_json::Value>(line).expect("log line should be json");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_sync_with_resolve_override() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://registry.invalid:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--resolve",
+                &format!("registry.invalid:{}:127.0.0.1", socket.port()),
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_sync_with_segmented_download() {
+    const CONTENTS: &str = "abcdefghij";
+
+    let resources = Resources::new();
+    let segments = Arc::new(AtomicUsize::new(0));
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::header::optional::<String>("range"))
+            .and_then({
+                let segments = segments.clone();
+                move |name: String, version: String, range: Option<String>| {
+                    let segments = segments.clone();
+                    async move {
+                        if (name.as_str(), version.as_str()) != ("a", "0.0.1") {
+                            return Err(warp::reject::not_found());
+                        }
+
+                        let response = match range
+                            .as_deref()
+                            .and_then(|range| range.strip_prefix("bytes="))
+                            .and_then(|range| range.split_once('-'))
+                        {
+                            Some((start, end)) => {
+                                segments.fetch_add(1, Ordering::SeqCst);
+                                let start = start.parse::<usize>().expect("invalid range start");
+                                let end = end.parse::<usize>().expect("invalid range end");
+                                Response::builder()
+                                    .status(StatusCode::PARTIAL_CONTENT)
+                                    .body(String::from(&CONTENTS[start..=end]))
+                            }
+                            None => Response::builder()
+                                .header("accept-ranges", "bytes")
+                                .body(String::from(CONTENTS)),
+                        };
+
+                        Ok(response.expect("failed to build response"))
+                    }
+                }
+            }),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"72399361da6a7754fec986dca5b7cbaf1c810a28ded4abaf56b2106d06cb78b0","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["--segment-threshold", "4", "--segments", "3", "sync"],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(segments.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        fs::read_to_string(cache.join("crates/a/0.0.1/download"))
+            .await
+            .expect("failed to read from cache"),
+        CONTENTS
+    );
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_update_with_crate_removal() {
+    let resources = Resources::new();
+
+    let filter = warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    );
+
+    let parent = CancellationToken::new();
+    let child = &parent.child_token();
+    let stream = stream::iter(PERMITTED_PORTS).filter_map(|port| async move {
+        let address = ([127, 0, 0, 1], port);
+        let token = child.clone();
+
+        match warp::serve(filter)
+            .try_bind_with_graceful_shutdown(address, async move { token.cancelled().await })
+        {
+            Ok((socket, server)) => Some((socket, server)),
+            Err(_) => None,
+        }
+    });
+
+    tokio::pin!(stream);
+    let (socket, server) = stream
+        .next()
+        .await
+        .expect("no available port in permitted range");
+
+    let _guard = parent.drop_guard();
+    tokio::spawn(server);
+
+    let registry_index = resources.workspace().join("index");
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo =
+                Repository::init(&registry_index).expect("failed to initialise registry index");
+
+            Stager::new(&repo)
+                .add(b"config.json".to_vec(), {
+                    let configuration = IndexFormat {
+                        download: format!("http://127.0.0.1:{}", socket.port()),
+                    };
+
+                    serde_json::to_vec(&configuration)
+                        .expect("failed to serialise index format")
+                        .as_slice()
+                })
+                .add(
+                    b"1/a".to_vec(),
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes()
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to prepare registry index");
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+
+    assert!(status.success(), "failed to create cache");
+    assert_exists([&cache, &cache.join("index")].into_iter(), true).await;
+    assert_exists([cache.join("crates")].into_iter(), false).await;
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            &cache,
+            &cache.join("index"),
+            &cache.join("crates"),
+            &cache.join("crates/a/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+
+    spawn_blocking({
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo).remove(Path::new("1/a")).commit();
+        }
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([&cache, &cache.join("index")].into_iter(), true).await;
+    // There are no crates. Obsolete directories should be removed.
+    assert_exists([&cache.join("cache")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_sync_with_unavailable_crate() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").and_then(
+            |_: String, _: String| async move { Err::<&str, _>(warp::reject::not_found()) },
+        ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The crate can not be downloaded but the cache is otherwise synchronised.
+    let status = resources.exe().sync(&cache).await;
+    assert_eq!(
+        status.code(),
+        Some(5),
+        "sync did not report partial completion"
+    );
+
+    let status = resources.exe().run(&cache, &["--strict", "sync"]).await;
+    assert_eq!(
+        status.code(),
+        Some(4),
+        "strict sync did not report download failure"
+    );
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sync_with_signature_verifier() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    // Only a is signed.
+    let verifier = resources.workspace().join("verify.sh");
+    fs::write(
+        &verifier,
+        concat!(
+            "#!/bin/sh\n",
+            "if [ \"$CRATEFUL_NAME\" = a ] && [ \"$CRATEFUL_VERSION\" = 0.0.1 ] && ",
+            "[ \"$(cat \"$1\")\" = 0 ] && [ -n \"$CRATEFUL_URL\" ] && ",
+            "[ \"$CRATEFUL_CHECKSUM\" = 5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9 ]; then\n",
+            "  exit 0\n",
+            "fi\n",
+            "echo unsigned >&2\n",
+            "exit 1\n",
+        ),
+    )
+    .await
+    .expect("failed to write verifier");
+    fs::set_permissions(&verifier, std::fs::Permissions::from_mode(0o755))
+        .await
+        .expect("failed to make verifier executable");
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["--signature-verifier", &verifier.to_string_lossy(), "sync"],
+        )
+        .await;
+    assert_eq!(status.code(), Some(5), "sync did not reject b");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_exists(
+        [
+            cache.join("crates/b/0.0.1/download"),
+            cache.join("crates/b/0.0.1/download.partial"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+
+    let log = fs::read_to_string(cache.join("audit.log"))
+        .await
+        .expect("failed to read audit log");
+    assert!(
+        log.contains("unsigned"),
+        "audit log does not hold the reason"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_records_audit_log() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert_eq!(status.code(), Some(5), "sync did not fail to download b");
+
+    let log = fs::read_to_string(cache.join("audit.log"))
+        .await
+        .expect("failed to read audit log");
+    let mut records = log
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("record is not json"))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record["name"].to_string());
+    assert_eq!(records.len(), 2);
+
+    let (a, b) = (&records[0], &records[1]);
+    assert_eq!(a["version"], "0.0.1");
+    assert_eq!(
+        a["url"],
+        format!("http://127.0.0.1:{}/a/0.0.1/download", socket.port())
+    );
+    assert_eq!(a["success"], true);
+    assert_eq!(a["bytes"], 1);
+    assert_eq!(a["checksum"], "matched");
+    assert!(a["error"].is_null());
+    assert!(a["time"].is_u64() && a["duration"].is_u64());
+
+    assert_eq!(b["name"], "b");
+    assert_eq!(b["success"], false);
+    assert_eq!(b["status"], 404);
+    assert_eq!(b["checksum"], "unchecked");
+    assert!(b["bytes"].is_null());
+    assert!(b["error"].is_string());
+
+    // Crates that are already stored are not downloaded again so they are not recorded.
+    let status = resources.exe().run(&cache, &["verify"]).await;
+    assert_eq!(status.code(), Some(5), "verify did not fail to download b");
+    let log = fs::read_to_string(cache.join("audit.log"))
+        .await
+        .expect("failed to read audit log");
+    assert_eq!(log.lines().count(), 3);
+    assert_eq!(log.matches(r#""name":"a""#).count(), 1);
+}
+
+#[tokio::test]
+async fn test_sync_dry_run() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    for arguments in [["sync", "--dry-run"], ["verify", "--dry-run"]] {
+        let status = resources.exe().run(&cache, &arguments).await;
+        assert!(status.success(), "failed to plan {}", arguments[0]);
+        assert_exists([cache.join("crates")].into_iter(), false).await;
+    }
+}
+
+#[tokio::test]
+async fn test_sync_resumes_from_journal() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Simulate an interrupted refresh that completed the directory holding the only crate and was
+    // cut short while recording another directory.
+    let head = spawn_blocking({
+        let index = cache.join("index");
+        move || {
+            Repository::open(index)
+                .expect("failed to open cache index")
+                .head()
+                .expect("failed to get HEAD")
+                .peel_to_commit()
+                .expect("failed to get commit for HEAD")
+                .id()
+                .to_string()
+        }
+    })
+    .await
+    .expect("failed to read cache index");
+
+    let journal = cache.join("journal.json");
+    let header = format!(
+        r#"{{"target":"{head}","preserve":"always","scope":"everything","filter":"all versions"}}"#
+    );
+    fs::write(&journal, format!("{header}\n\"1\"\n\"2"))
+        .await
+        .expect("failed to write journal");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [cache.join("crates/a/0.0.1/download"), journal].into_iter(),
+        false,
+    )
+    .await;
+
+    // The journal is removed once the refresh finishes so the next refresh is complete. The cache
+    // is consistent with the index after the resumed refresh so the refresh must be forced.
+    let status = resources.exe().run(&cache, &["sync", "--full"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sync_is_interrupted_cleanly() {
+    let resources = Resources::new();
+    let requested = Arc::new(Notify::new());
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then({
+        let requested = requested.clone();
+        move |name: String, version: String| {
+            let requested = requested.clone();
+            async move {
+                match (name.as_str(), version.as_str()) {
+                    ("a", "0.0.1") => Ok("0"),
+                    ("bb", "0.0.1") => {
+                        // The download never finishes so that it is in flight when the program
+                        // is interrupted.
+                        requested.notify_one();
+                        futures::future::pending().await
+                    }
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        }
+    }));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "2/bb",
+                r#"{"name":"bb","vers":"0.0.1","deps":[],"cksum":"6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // One job checks and downloads the crates in order so that the first is stored before the
+    // second is requested.
+    let mut child = resources
+        .exe()
+        .spawn(&cache, &["--jobs", "1", "--hash-jobs", "1", "sync"]);
+    requested.notified().await;
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().expect("crateful exited early").to_string())
+        .status()
+        .await
+        .expect("failed to run kill");
+    assert!(status.success(), "failed to interrupt crateful");
+
+    let status = child.wait().await.expect("failed to wait for crateful");
+    assert_eq!(status.code(), Some(130));
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("journal.json"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists(
+        [
+            cache.join("crates/bb/0.0.1/download"),
+            cache.join("crates/bb/0.0.1/download.partial"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sync_resumes_directories_with_failed_crates() {
+    let resources = Resources::new();
+    let requested = Arc::new(Notify::new());
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then({
+        let requested = requested.clone();
+        let interrupted = interrupted.clone();
+        move |name: String, version: String| {
+            let requested = requested.clone();
+            let interrupted = interrupted.clone();
+            async move {
+                let resumed = interrupted.load(Ordering::SeqCst);
+                match (name.as_str(), version.as_str()) {
+                    // The crate can only be downloaded once the refresh has been interrupted.
+                    ("a", "0.0.1") if resumed => Ok(Response::builder()
+                        .body(String::from("0"))
+                        .expect("failed to build response")),
+                    ("a", "0.0.1") => Ok(Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body(String::new())
+                        .expect("failed to build response")),
+                    ("bb", "0.0.1") => Ok(Response::builder()
+                        .body(String::from("1"))
+                        .expect("failed to build response")),
+                    ("ccc", "0.0.1") if resumed => Ok(Response::builder()
+                        .body(String::from("2"))
+                        .expect("failed to build response")),
+                    ("ccc", "0.0.1") => {
+                        requested.notify_one();
+                        futures::future::pending().await
+                    }
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        }
+    }));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "2/bb",
+                r#"{"name":"bb","vers":"0.0.1","deps":[],"cksum":"6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b","features":{},"yanked":false}"#,
+            ),
+            (
+                "3/c/ccc",
+                r#"{"name":"ccc","vers":"0.0.1","deps":[],"cksum":"d4735e3a265e16eee03f59718b9b5d03019c07d8b6c51f90da3a666eec13ab35","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The first crate fails with a tolerated error and the second is stored before the refresh is
+    // interrupted.
+    let mut child = resources
+        .exe()
+        .spawn(&cache, &["--jobs", "1", "--hash-jobs", "1", "sync"]);
+    requested.notified().await;
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().expect("crateful exited early").to_string())
+        .status()
+        .await
+        .expect("failed to run kill");
+    assert!(status.success(), "failed to interrupt crateful");
+
+    let status = child.wait().await.expect("failed to wait for crateful");
+    assert_eq!(status.code(), Some(130));
+    assert_exists(
+        [
+            cache.join("crates/bb/0.0.1/download"),
+            cache.join("journal.json"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+
+    // The directory with the failed crate is refreshed again when the refresh is resumed.
+    interrupted.store(true, Ordering::SeqCst);
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/bb/0.0.1/download"),
+            cache.join("crates/ccc/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_with_locked_cache() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Hold the lock as though another process were synchronising the cache.
+    let lock = std::fs::File::create(cache.join("crateful.lock")).expect("failed to open lock");
+    lock.lock_exclusive().expect("failed to lock cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert_eq!(status.code(), Some(6));
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+
+    // A waiting process continues once the lock is released.
+    let mut child = resources.exe().spawn(&cache, &["--wait", "sync"]);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        child.try_wait().expect("failed to poll crateful").is_none(),
+        "crateful did not wait for the lock"
+    );
+    drop(lock);
+    let status = child.wait().await.expect("failed to wait for crateful");
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    // A stale lock can be removed.
+    let lock = std::fs::File::open(cache.join("crateful.lock")).expect("failed to open lock");
+    lock.lock_exclusive().expect("failed to lock cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--force-unlock", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_sync_notifies_service_manager() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let path = resources.workspace().join("notify");
+    let manager = UnixDatagram::bind(&path).expect("failed to bind notification socket");
+    let status = Command::new(env!("CARGO_BIN_EXE_crateful"))
+        .arg("--path")
+        .arg(&cache)
+        .arg("sync")
+        .env("NOTIFY_SOCKET", &path)
+        .env("WATCHDOG_USEC", "100000")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .expect("failed to run crateful");
+    assert!(status.success(), "failed to sync cache");
+
+    let mut notifications = Vec::new();
+    let mut buffer = [0; 1024];
+    while let Ok(length) = manager.try_recv(&mut buffer) {
+        notifications.push(
+            String::from_utf8_lossy(&buffer[..length])
+                .trim_end()
+                .to_owned(),
+        );
+    }
+
+    assert_eq!(notifications.first().map(String::as_str), Some("READY=1"));
+    assert_eq!(notifications.last().map(String::as_str), Some("STOPPING=1"));
+    assert!(
+        notifications
+            .iter()
+            .any(|notification| notification.starts_with("STATUS=synchronise")),
+        "the status was not described"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_skips_refresh_of_consistent_cache() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("consistency.json")].into_iter(), true).await;
+
+    // A consistent cache is only updated so a crate that is removed behind its back is not noticed.
+    let download = cache.join("crates/a/0.0.1/download");
+    fs::remove_file(&download)
+        .await
+        .expect("failed to remove crate");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([&download].into_iter(), false).await;
+
+    let status = resources.exe().run(&cache, &["sync", "--full"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([&download].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_sync_since() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The only crate was published before this date.
+    let status = resources
+        .exe()
+        .run(&cache, &["--since", "9999-01-01", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--since", "2000-01-01", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_sync_at() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1" | "0.0.2") | ("b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    // The first commit was made on 2020-06-01 and the second is made now.
+    let registry_index = resources.workspace().join("index");
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo =
+                Repository::init(&registry_index).expect("failed to initialise registry index");
+            let configuration = serde_json::to_vec(&IndexFormat {
+                download: format!("http://127.0.0.1:{}", socket.port()),
+            })
+            .expect("failed to serialise index format");
+
+            Stager::new(&repo)
+                .add(b"config.json".to_vec(), &configuration)
+                .add(
+                    b"1/a".to_vec(),
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .commit_at(1_590_969_600);
+
+            Stager::new(&repo)
+                .add(
+                    b"1/a".to_vec(),
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .add(
+                    b"1/b".to_vec(),
+                    r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to prepare registry index");
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let later = [
+        cache.join("crates/a/0.0.2/download"),
+        cache.join("crates/b/0.0.1/download"),
+    ];
+    assert_exists(later.iter(), true).await;
+
+    // The crates that were published after the snapshot are removed.
+    let status = resources
+        .exe()
+        .run(&cache, &["sync", "--at", "2021-01-01"])
+        .await;
+    assert!(status.success(), "failed to sync cache to a snapshot");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_exists(later.iter(), false).await;
+
+    // There is no commit before the first commit.
+    let status = resources
+        .exe()
+        .run(&cache, &["sync", "--at", "2020-01-01"])
+        .await;
+    assert!(!status.success(), "synced cache to a missing snapshot");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(later.iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_pin_and_rollback() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1" | "0.0.2") | ("b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Nothing can be rolled back before an update is applied.
+    let status = resources.exe().run(&cache, &["rollback"]).await;
+    assert!(!status.success(), "rolled back an index without updates");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let first = spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            let first = repo
+                .head()
+                .and_then(|head| head.peel_to_commit())
+                .expect("failed to get commit for HEAD")
+                .id();
+
+            Stager::new(&repo)
+                .add(
+                    b"1/a".to_vec(),
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .add(
+                    b"1/b".to_vec(),
+                    r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .commit();
+
+            first.to_string()
+        }
+    })
+    .await
+    .expect("failed to add crates to registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let later = [
+        cache.join("crates/a/0.0.2/download"),
+        cache.join("crates/b/0.0.1/download"),
+    ];
+    assert_exists(later.iter(), true).await;
+
+    // The crates from the last update are removed and stay removed while the index is pinned.
+    let status = resources.exe().run(&cache, &["rollback"]).await;
+    assert!(status.success(), "failed to roll back cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_exists(later.iter(), false).await;
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(later.iter(), false).await;
+
+    let status = resources.exe().run(&cache, &["unpin"]).await;
+    assert!(status.success(), "failed to unpin index");
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(later.iter(), true).await;
+
+    let status = resources.exe().run(&cache, &["pin", &first[..12]]).await;
+    assert!(status.success(), "failed to pin index");
+    assert_exists(later.iter(), false).await;
+
+    let status = resources.exe().run(&cache, &["pin", "0123456789ab"]).await;
+    assert!(!status.success(), "pinned index to a missing commit");
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_snapshot() {
+    let resources = Resources::new();
+    let downloads = Arc::new(AtomicUsize::new(0));
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then({
+        let downloads = downloads.clone();
+        move |name: String, version: String| {
+            let downloads = downloads.clone();
+            async move {
+                match (name.as_str(), version.as_str()) {
+                    ("a", "0.0.1") => {
+                        downloads.fetch_add(1, Ordering::SeqCst);
+                        Ok("0")
+                    }
+                    ("b", "0.0.1") => Ok("0"),
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        }
+    }));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let (status, log) = resources.exe().run_logged(&cache, &["sync"]).await;
+    assert!(status.success(), "failed to sync cache: {log}");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "create", "audit"])
+        .await;
+    assert!(status.success(), "failed to create snapshot");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "create", "audit"])
+        .await;
+    assert!(!status.success(), "created a snapshot twice");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "create", "../audit"])
+        .await;
+    assert!(!status.success(), "created a snapshot outside the cache");
+
+    let first = spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            let first = repo
+                .head()
+                .and_then(|head| head.peel_to_commit())
+                .expect("failed to get commit for HEAD")
+                .id();
+
+            Stager::new(&repo)
+                .remove(Path::new("1/a"))
+                .add(
+                    b"1/b".to_vec(),
+                    r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+                )
+                .commit();
+
+            first.to_string()
+        }
+    })
+    .await
+    .expect("failed to change registry index");
+
+    let (status, log) = resources.exe().run_logged(&cache, &["sync"]).await;
+    assert!(status.success(), "failed to sync cache: {log}");
+
+    let a = [cache.join("crates/a/0.0.1/download")];
+    let b = [cache.join("crates/b/0.0.1/download")];
+    assert_exists(a.iter(), false).await;
+    assert_exists(b.iter(), true).await;
+
+    let output = resources.exe().output(&cache, &["snapshot", "list"]).await;
+    let output = String::from_utf8(output).expect("output is not UTF-8");
+    assert!(
+        output.starts_with(&format!("audit {first} ")),
+        "snapshot is not listed: {output}"
+    );
+
+    let output = resources
+        .exe()
+        .output(&cache, &["snapshot", "diff", "audit"])
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("output is not UTF-8"),
+        "removed a 0.0.1\nadded b 0.0.1\n"
+    );
+
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "create", "later"])
+        .await;
+    assert!(status.success(), "failed to create snapshot");
+
+    let output = resources
+        .exe()
+        .output(&cache, &["snapshot", "diff", "later", "audit", "--json"])
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("output is not UTF-8"),
+        concat!(
+            r#"{"name":"a","version":"0.0.1","change":"added"}"#,
+            "\n",
+            r#"{"name":"b","version":"0.0.1","change":"removed"}"#,
+            "\n"
+        )
+    );
+
+    // The crates are linked back from the snapshot instead of being downloaded again.
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "restore", "audit"])
+        .await;
+    assert!(status.success(), "failed to restore snapshot");
+    assert_exists(a.iter(), true).await;
+    assert_exists(b.iter(), false).await;
+    assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+    let status = resources
+        .exe()
+        .run(&cache, &["snapshot", "restore", "missing"])
+        .await;
+    assert!(!status.success(), "restored a missing snapshot");
+}
+
+#[tokio::test]
+async fn test_sync_top() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b" | "c" | "d", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[{"name":"c","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"},{"name":"d","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"dev"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/c",
+                r#"{"name":"c","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/d",
+                r#"{"name":"d","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The most popular crates are not known until a dump is ingested.
+    let status = resources.exe().run(&cache, &["--top", "1", "sync"]).await;
+    assert!(!status.success(), "synced cache without download counts");
+
+    let dump = resources.workspace().join("dump");
+    fs::create_dir_all(dump.join("data"))
+        .await
+        .expect("failed to create dump");
+    fs::write(
+        dump.join("data/crates.csv"),
+        "created_at,description,downloads,id,name\n\
+         2020-01-01,\"the most\npopular crate\",100,1,a\n\
+         2020-01-01,,50,2,b\n\
+         2020-01-01,,1,3,c\n\
+         2020-01-01,,1,4,d\n",
+    )
+    .await
+    .expect("failed to write dump");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["ingest-dump", dump.to_str().expect("path is not utf-8")],
+        )
+        .await;
+    assert!(status.success(), "failed to ingest dump");
+
+    // Only the most popular crate and its dependency are mirrored.
+    let status = resources.exe().run(&cache, &["--top", "1", "sync"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/c/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists(
+        [
+            cache.join("crates/b/0.0.1/download"),
+            cache.join("crates/d/0.0.1/download"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_preset() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("serde" | "a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "se/rd/serde",
+                r#"{"name":"serde","vers":"0.0.1","deps":[{"name":"a","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--preset", "popular", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/serde/0.0.1/download"),
+            cache.join("crates/a/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_keep_latest() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, _: String| async move {
+            match name.as_str() {
+                "a" => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.1.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.2.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.3.0-alpha.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let version = |version: &str| cache.join(format!("crates/a/{version}/download"));
+    let filter = ["--keep-latest", "1", "--exclude-prerelease"];
+
+    // Nothing is removed by a dry run.
+    let status = resources
+        .exe()
+        .run(&cache, &[&filter[..], &["prune", "--dry-run"]].concat())
+        .await;
+    assert!(status.success(), "failed to plan prune");
+    assert_exists(
+        ["0.1.0", "0.2.0", "0.3.0-alpha.1"].into_iter().map(version),
+        true,
+    )
+    .await;
+
+    // Only the latest stable version is retained.
+    let status = resources
+        .exe()
+        .run(&cache, &[&filter[..], &["prune"]].concat())
+        .await;
+    assert!(status.success(), "failed to prune cache");
+    assert_exists([version("0.2.0")].into_iter(), true).await;
+    assert_exists(["0.1.0", "0.3.0-alpha.1"].into_iter().map(version), false).await;
+
+    let status = resources
+        .exe()
+        .run(&cache, &[&filter[..], &["sync"]].concat())
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    // A newer version replaces the version that was retained when the cache is updated.
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/a".to_vec(),
+                r#"{"name":"a","vers":"0.1.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.2.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.3.0-alpha.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.3.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes(),
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    let status = resources
+        .exe()
+        .run(&cache, &[&filter[..], &["sync"]].concat())
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([version("0.3.0")].into_iter(), true).await;
+    assert_exists([version("0.2.0")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_sync_dependents_of() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b" | "c" | "e" | "k" | "x", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[{"name":"k","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[{"name":"a","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"build"},{"name":"e","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/c",
+                r#"{"name":"c","vers":"0.0.1","deps":[{"name":"k","req":"^0.0.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"dev"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/e",
+                r#"{"name":"e","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/k",
+                r#"{"name":"k","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/x",
+                r#"{"name":"x","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let output = resources
+        .exe()
+        .output(&cache, &["dependents", "--name", "k"])
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("output is not utf-8"),
+        "a\nb\n"
+    );
+
+    let output = resources
+        .exe()
+        .output(&cache, &["dependents", "--name", "k", "--direct"])
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("output is not utf-8"),
+        "a\n"
+    );
+
+    // The crates that depend on k are mirrored with their dependencies. Development dependencies
+    // are ignored.
+    let status = resources
+        .exe()
+        .run(&cache, &["--dependents-of", "k", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        ["a", "b", "e", "k"]
+            .into_iter()
+            .map(|name| cache.join(format!("crates/{name}/0.0.1/download"))),
+        true,
+    )
+    .await;
+    assert_exists(
+        ["c", "x"]
+            .into_iter()
+            .map(|name| cache.join(format!("crates/{name}/0.0.1/download"))),
+        false,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_with_constraint() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.9.0" | "1.0.0" | "2.0.0") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.9.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"1.0.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"2.0.0","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--constraint", "a=>=1.0, <2", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/1.0.0/download")].into_iter(), true).await;
+    assert_exists(
+        [
+            cache.join("crates/a/0.9.0/download"),
+            cache.join("crates/a/2.0.0/download"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_marks_complete() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().run(&cache, &["sync"]).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let marker = fs::read_to_string(cache.join("last-complete"))
+        .await
+        .expect("failed to read marker");
+    assert!(marker.starts_with(r#"{"head":""#));
+
+    // A crate that can not be downloaded leaves the cache incomplete.
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/b".to_vec(),
+                br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to change registry index");
+
+    resources.exe().run(&cache, &["sync"]).await;
+    assert_exists([cache.join("last-complete")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_update_with_crate_yanked() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":true}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--yanked", "delete", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), false).await;
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/a".to_vec(),
+                br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":true}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to yank crate in registry index");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--yanked", "delete", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_update_with_feed() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1" | "0.0.2") | ("b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let arguments = [
+        "--feed",
+        "https://mirror.example/crates",
+        "--feed-entries",
+        "2",
+        "sync",
+    ];
+    let status = resources.exe().run(&cache, &arguments).await;
+    assert!(status.success(), "failed to sync cache");
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/a".to_vec(),
+                br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .add(
+                b"1/b".to_vec(),
+                br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":true}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to change registry index");
+
+    let status = resources.exe().run(&cache, &arguments).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let feed = fs::read_to_string(cache.join("feed.xml"))
+        .await
+        .expect("failed to read feed");
+    assert!(feed.contains("<id>https://mirror.example/crates/feed.xml</id>"));
+    assert!(feed.contains("<title>a 0.0.2 was published</title>"));
+    assert!(feed.contains("<title>b 0.0.1 was yanked</title>"));
+    assert_eq!(feed.matches("<entry>").count(), 2);
+}
+
+#[tokio::test]
+async fn test_changes() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().run(&cache, &["sync"]).await;
+    assert!(status.success(), "failed to sync cache");
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/a".to_vec(),
+                br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .add(
+                b"1/b".to_vec(),
+                br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":true}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to change registry index");
+
+    let output = String::from_utf8(resources.exe().output(&cache, &["changes", "--json"]).await)
+        .expect("changes are not valid utf-8");
+    let mut lines = output.lines().collect::<Vec<_>>();
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        [
+            r#"{"name":"a","version":"0.0.2","change":"added"}"#,
+            r#"{"name":"b","version":"0.0.1","change":"yanked"}"#,
+        ]
+    );
+
+    // The changes are not applied so they are listed again.
+    let output = String::from_utf8(resources.exe().output(&cache, &["changes"]).await)
+        .expect("changes are not valid utf-8");
+    let mut lines = output.lines().collect::<Vec<_>>();
+    lines.sort_unstable();
+    assert_eq!(lines, ["added a 0.0.2", "yanked b 0.0.1"]);
+    assert_exists([cache.join("crates/a/0.0.2/download")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_update_with_crate_removal_archives_crate() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo).remove(Path::new("1/a")).commit();
+    })
+    .await
+    .expect("failed to remove crate from registry index");
+
+    let status = resources.exe().run(&cache, &["--archive", "sync"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a")].into_iter(), false).await;
+
+    let archived = cache.join("archive/a/0.0.1");
+    let mut entries = fs::read_dir(&archived)
+        .await
+        .expect("failed to read archive");
+    assert!(
+        entries
+            .next_entry()
+            .await
+            .expect("failed to read archive")
+            .is_some(),
+        "crate was not archived"
+    );
+
+    // The crate was archived less than a day ago.
+    let status = resources
+        .exe()
+        .run(&cache, &["prune-archive", "--older-than", "1"])
+        .await;
+    assert!(status.success(), "failed to prune archive");
+    assert_exists([&archived].into_iter(), true).await;
+
+    let status = resources.exe().run(&cache, &["prune-archive"]).await;
+    assert!(status.success(), "failed to prune archive");
+    assert_exists([cache.join("archive/a")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_update_with_crate_unyanked() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":true}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--yanked", "skip", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/a".to_vec(),
+                br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to unyank crate in registry index");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--yanked", "skip", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_rewrite_download_template() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "new",
+                "--url",
+                url.as_str(),
+                "--rewrite-dl",
+                "http://mirror.invalid",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let template = || {
+        let index = cache.join("index");
+        spawn_blocking(move || {
+            let repo = Repository::open(index).expect("failed to open cache index");
+            let tree = repo
+                .head()
+                .expect("failed to get HEAD")
+                .peel_to_tree()
+                .expect("failed to get tree for HEAD");
+            let blob = repo
+                .find_blob(
+                    tree.get_name("config.json")
+                        .expect("configuration is missing")
+                        .id(),
+                )
+                .expect("failed to find configuration");
+
+            serde_json::from_slice::<serde_json::Value>(blob.content())
+                .expect("failed to deserialise configuration")["dl"]
+                .clone()
+        })
+    };
+
+    // Crates are downloaded from the remote index's download template.
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_eq!(
+        template().await.expect("failed to read configuration"),
+        "http://mirror.invalid"
+    );
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .add(
+                b"1/b".to_vec(),
+                br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    // The rewrite is reapplied after an update.
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), true).await;
+    assert_eq!(
+        template().await.expect("failed to read configuration"),
+        "http://mirror.invalid"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_with_config() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(
+                |name: String, version: String, token: Option<String>| async move {
+                    match (name.as_str(), version.as_str(), token.as_deref()) {
+                        ("a", "0.0.1", _) | ("b", "0.0.1", Some("secret")) => Ok("0"),
+                        _ => Err(warp::reject::not_found()),
+                    }
+                },
+            ),
+    );
+
+    let mut registries = Vec::new();
+    for (name, package, token) in [("public", "a", "null"), ("private", "b", r#""secret""#)] {
+        let registry_index = resources.workspace().join(format!("{name}-index"));
+        create_registry_index(
+            &registry_index,
+            format!("http://127.0.0.1:{}", socket.port()),
+            &[(
+                &format!("1/{package}"),
+                &format!(
+                    r#"{{"name":"{package}","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+                ),
+            )],
+        )
+        .await;
+
+        let index =
+            Url::from_file_path(registry_index).expect("failed to get url for registry index");
+        registries.push(format!(
+            r#"{{"name":"{name}","index":"{index}","token":{token}}}"#
+        ));
+    }
+
+    let config = resources.workspace().join("config.json");
+    fs::write(
+        &config,
+        format!(r#"{{"registries":[{}]}}"#, registries.join(",")),
+    )
+    .await
+    .expect("failed to write config");
+
+    let caches = resources.workspace().join("caches");
+    let status = resources
+        .exe()
+        .run(
+            &caches,
+            &[
+                OsStr::new("--config"),
+                config.as_os_str(),
+                OsStr::new("sync"),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync caches");
+    assert_exists(
+        [
+            caches.join("public/crates/a/0.0.1/download"),
+            caches.join("private/crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_auth_required_registry() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(
+                |name: String, version: String, token: Option<String>| async move {
+                    match (name.as_str(), version.as_str(), token.as_deref()) {
+                        ("a", "0.0.1", Some("secret")) => Ok("0"),
+                        _ => Err(warp::reject::not_found()),
+                    }
+                },
+            ),
+    );
+
+    // The configuration has fields that crateful does not know about.
+    let registry_index = resources.workspace().join("index");
+    let download = format!("http://127.0.0.1:{}", socket.port());
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        let download = download.clone();
+        move || {
+            let repo = Repository::init(&registry_index).expect("failed to initialise index");
+            Stager::new(&repo)
+                .add(
+                    b"config.json".to_vec(),
+                    format!(
+                        r#"{{"dl":"{download}","api":"{download}","auth-required":true,"future":1}}"#
+                    )
+                    .as_bytes(),
+                )
+                .add(
+                    b"1/a".to_vec(),
+                    br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to prepare registry index");
+
+    let index = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+
+    // A registry that requires authentication is not synchronised without a token.
+    let cache = resources.workspace().join("cache");
+    let status = resources.exe().create(&cache, &index).await;
+    assert!(status.success(), "failed to create cache");
+    let status = resources.exe().sync(&cache).await;
+    assert_eq!(status.code(), Some(1), "sync should fail without a token");
+    assert_exists([cache.join("crates/a")].into_iter(), false).await;
+
+    let config = resources.workspace().join("config.json");
+    fs::write(
+        &config,
+        format!(r#"{{"registries":[{{"name":"private","index":"{index}","token":"secret"}}]}}"#),
+    )
+    .await
+    .expect("failed to write config");
+
+    let caches = resources.workspace().join("caches");
+    let status = resources
+        .exe()
+        .run(
+            &caches,
+            &[
+                OsStr::new("--config"),
+                config.as_os_str(),
+                OsStr::new("sync"),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache with a token");
+    assert_exists(
+        [caches.join("private/crates/a/0.0.1/download")].into_iter(),
+        true,
+    )
+    .await;
+
+    // Rewriting the download template preserves the other fields of the configuration.
+    let private = caches.join("private");
+    let status = resources
+        .exe()
+        .run(&private, &["rewrite", "--dl", "http://mirror.invalid"])
+        .await;
+    assert!(status.success(), "failed to rewrite download template");
+
+    let configuration = spawn_blocking(move || {
+        let repo = Repository::open(private.join("index")).expect("failed to open cache index");
+        let tree = repo
+            .head()
+            .expect("failed to get HEAD")
+            .peel_to_tree()
+            .expect("failed to get tree for HEAD");
+        let blob = repo
+            .find_blob(
+                tree.get_name("config.json")
+                    .expect("configuration is missing")
+                    .id(),
+            )
+            .expect("failed to find configuration");
+
+        serde_json::from_slice::<serde_json::Value>(blob.content())
+            .expect("failed to deserialise configuration")
+    })
+    .await
+    .expect("failed to read configuration");
+
+    assert_eq!(configuration["dl"], "http://mirror.invalid");
+    assert_eq!(configuration["api"], download);
+    assert_eq!(configuration["auth-required"], true);
+    assert_eq!(configuration["future"], 1);
+}
+
+#[tokio::test]
+async fn test_token_is_only_sent_to_registry() {
+    let resources = Resources::new();
+    let (registry, _registry_guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then(
+                |name: String, version: String, token: Option<String>| async move {
+                    match (name.as_str(), version.as_str(), token.as_deref()) {
+                        ("b", "0.0.1", Some("secret")) => Ok("0"),
+                        _ => Err(warp::reject::not_found()),
+                    }
+                },
+            ),
+    );
+
+    // The parent only serves requests that do not carry the token of the registry.
+    let leaked = Arc::new(AtomicUsize::new(0));
+    let (parent, _parent_guard) = serve(
+        &warp::path!(String / String / "download")
+            .and(warp::header::optional::<String>("authorization"))
+            .and_then({
+                let leaked = leaked.clone();
+                move |name: String, version: String, token: Option<String>| {
+                    let leaked = leaked.clone();
+                    async move {
+                        if token.is_some() {
+                            leaked.fetch_add(1, Ordering::SeqCst);
+                            return Err(warp::reject::not_found());
+                        }
+
+                        match (name.as_str(), version.as_str()) {
+                            ("a", "0.0.1") => Ok("0"),
+                            _ => Err(warp::reject::not_found()),
+                        }
+                    }
+                }
+            }),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", registry.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let index = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let config = resources.workspace().join("config.json");
+    fs::write(
+        &config,
+        format!(
+            r#"{{"registries":[{{"name":"private","index":"{index}","token":"secret","parents":["http://127.0.0.1:{}"]}}]}}"#,
+            parent.port()
+        ),
+    )
+    .await
+    .expect("failed to write config");
+
+    let caches = resources.workspace().join("caches");
+    let status = resources
+        .exe()
+        .run(
+            &caches,
+            &[
+                OsStr::new("--config"),
+                config.as_os_str(),
+                OsStr::new("sync"),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache with a token");
+    assert_eq!(leaked.load(Ordering::SeqCst), 0);
+    assert_exists(
+        [
+            caches.join("private/crates/a/0.0.1/download"),
+            caches.join("private/crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_with_parent_mirror() {
+    let resources = Resources::new();
+    let (registry, _registry_guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+    let (parent, _parent_guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", registry.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The first crate is only available from the parent and the second crate is only available
+    // from the registry.
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--parent",
+                &format!("http://127.0.0.1:{}/", parent.port()),
+                "--strict",
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_export_sparse_index() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "bc", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().run(&cache, &["export-sparse"]).await;
+    assert!(status.success(), "failed to export sparse index");
+    assert_exists(
+        [cache.join("sparse/config.json"), cache.join("sparse/1/a")].into_iter(),
+        true,
+    )
+    .await;
+
+    spawn_blocking(move || {
+        let repo = Repository::open(&registry_index).expect("failed to open registry index");
+        Stager::new(&repo)
+            .remove(Path::new("1/a"))
+            .add(
+                b"2/bc".to_vec(),
+                br#"{"name":"bc","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            )
+            .commit();
+    })
+    .await
+    .expect("failed to update registry index");
+
+    let status = resources.exe().run(&cache, &["sync"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("sparse/1/a")].into_iter(), false).await;
+    assert_eq!(
+        fs::read_to_string(cache.join("sparse/2/bc"))
+            .await
+            .expect("failed to read package from sparse index")
+            .trim_end(),
+        r#"{"name":"bc","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#
+    );
+}
+
+#[tokio::test]
+async fn test_sync_from_sparse_index() {
+    let resources = Resources::new();
+    let sparse_index = resources.workspace().join("sparse");
+    let (socket, _guard) = serve(&warp::fs::dir(sparse_index.clone()));
+
+    let a = r#"{"name":"a","vers":"0.0.1","deps":[{"name":"bc","req":"^0.0.1"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#;
+    for (path, contents) in [
+        (
+            "config.json",
+            format!(r#"{{"dl":"http://127.0.0.1:{}/crates"}}"#, socket.port()),
+        ),
+        ("1/a", String::from(a)),
+        (
+            "2/bc",
+            String::from(
+                r#"{"name":"bc","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ),
+        ("crates/a/0.0.1/download", String::from("0")),
+        ("crates/a/0.0.2/download", String::from("0")),
+        ("crates/bc/0.0.1/download", String::from("0")),
+    ] {
+        let path = sparse_index.join(path);
+        fs::create_dir_all(path.parent().expect("path has no parent"))
+            .await
+            .expect("failed to create sparse index directory");
+        fs::write(path, contents)
+            .await
+            .expect("failed to write sparse index file");
+    }
+
+    let crates = resources.workspace().join("crates.txt");
+    fs::write(&crates, "a\n")
+        .await
+        .expect("failed to write crates file");
+
+    let cache = resources.workspace().join("cache");
+    let url = format!("sparse+http://127.0.0.1:{}/", socket.port());
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["new", "--url", &url, "--crates", &crates.to_string_lossy()],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/bc/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+
+    // The sparse index is requested with the Last-Modified date that it last responded with,
+    // which has a resolution of a second.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    fs::write(
+        sparse_index.join("1/a"),
+        format!(
+            "{a}\n{}",
+            r#"{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#
+        ),
+    )
+    .await
+    .expect("failed to publish crate to sparse index");
+    fs::remove_file(sparse_index.join("2/bc"))
+        .await
+        .expect("failed to remove package from sparse index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.2/download")].into_iter(), true).await;
+    assert_exists([cache.join("crates/bc")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_sync_dry_run_from_sparse_index() {
+    let resources = Resources::new();
+    let sparse_index = resources.workspace().join("sparse");
+    let (socket, _guard) = serve(&warp::fs::dir(sparse_index.clone()));
+
+    let a = r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#;
+    for (path, contents) in [
+        (
+            "config.json",
+            format!(r#"{{"dl":"http://127.0.0.1:{}/crates"}}"#, socket.port()),
+        ),
+        ("1/a", String::from(a)),
+        ("crates/a/0.0.1/download", String::from("0")),
+        ("crates/a/0.0.2/download", String::from("0")),
+    ] {
+        let path = sparse_index.join(path);
+        fs::create_dir_all(path.parent().expect("path has no parent"))
+            .await
+            .expect("failed to create sparse index directory");
+        fs::write(path, contents)
+            .await
+            .expect("failed to write sparse index file");
+    }
+
+    let crates = resources.workspace().join("crates.txt");
+    fs::write(&crates, "a\n")
+        .await
+        .expect("failed to write crates file");
+
+    let cache = resources.workspace().join("cache");
+    let url = format!("sparse+http://127.0.0.1:{}/", socket.port());
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["new", "--url", &url, "--crates", &crates.to_string_lossy()],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // The sparse index is requested with the Last-Modified date that it last responded with,
+    // which has a resolution of a second.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    fs::write(
+        sparse_index.join("1/a"),
+        format!(
+            "{a}\n{}",
+            r#"{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#
+        ),
+    )
+    .await
+    .expect("failed to publish crate to sparse index");
+
+    // The plan includes the crate that was published to the sparse index since the last sync.
+    let output = resources.exe().output(&cache, &["sync", "--dry-run"]).await;
+    let output = String::from_utf8(output).expect("output is not UTF-8");
+    assert!(
+        output.starts_with("1 crates to download"),
+        "unexpected plan: {output}"
+    );
+    assert_exists([cache.join("crates/a/0.0.2/download")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_sync_checks_space_after_fetching_once() {
+    let resources = Resources::new();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let published = Arc::new(AtomicBool::new(false));
+    let port = Arc::new(AtomicUsize::new(0));
+    let (socket, _guard) = serve(&warp::path::tail().and_then({
+        let requests = requests.clone();
+        let published = published.clone();
+        let port = port.clone();
+        move |tail: warp::path::Tail| {
+            let requests = requests.clone();
+            let published = published.load(Ordering::SeqCst);
+            let port = port.load(Ordering::SeqCst);
+            async move {
+                let a = r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#;
+                let contents = match tail.as_str() {
+                    "config.json" => format!(r#"{{"dl":"http://127.0.0.1:{port}/crates"}}"#),
+                    "1/a" => {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        if published {
+                            format!(
+                                "{a}\n{}",
+                                r#"{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#
+                            )
+                        } else {
+                            String::from(a)
+                        }
+                    }
+                    "crates/a/0.0.1/download" | "crates/a/0.0.2/download" => String::from("0"),
+                    _ => return Err(warp::reject::not_found()),
+                };
+
+                Ok(contents)
+            }
+        }
+    }));
+    port.store(usize::from(socket.port()), Ordering::SeqCst);
+
+    let crates = resources.workspace().join("crates.txt");
+    fs::write(&crates, "a\n")
+        .await
+        .expect("failed to write crates file");
+
+    let cache = resources.workspace().join("cache");
+    let url = format!("sparse+http://127.0.0.1:{}/", socket.port());
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["new", "--url", &url, "--crates", &crates.to_string_lossy()],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    // The space that the published crate needs is checked before it is downloaded.
+    published.store(true, Ordering::SeqCst);
+    requests.store(0, Ordering::SeqCst);
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["--average-crate-size", "18446744073709551615", "sync"],
+        )
+        .await;
+    assert!(!status.success(), "synchronised without enough space");
+    assert_exists([cache.join("crates/a/0.0.2/download")].into_iter(), false).await;
+
+    // The index is only fetched once by each synchronisation.
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.2/download")].into_iter(), true).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_sync_from_sparse_index_conditionally() {
+    let resources = Resources::new();
+    let downloads = Arc::new(AtomicUsize::new(0));
+    let port = Arc::new(AtomicUsize::new(0));
+    let (socket, _guard) = serve(
+        &warp::path::tail()
+            .and(warp::header::optional::<String>("if-none-match"))
+            .and_then({
+                let downloads = downloads.clone();
+                let port = port.clone();
+                move |tail: warp::path::Tail, etag: Option<String>| {
+                    let downloads = downloads.clone();
+                    let port = port.load(Ordering::SeqCst);
+                    async move {
+                        let contents = match tail.as_str() {
+                            "config.json" => format!(r#"{{"dl":"http://127.0.0.1:{port}/crates"}}"#),
+                            "1/a" => String::from(
+                                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                            ),
+                            "crates/a/0.0.1/download" => String::from("0"),
+                            _ => return Err(warp::reject::not_found()),
+                        };
+
+                        let tag = format!("\"{}\"", tail.as_str());
+                        let response = if etag.as_deref() == Some(tag.as_str()) {
+                            Response::builder()
+                                .status(StatusCode::NOT_MODIFIED)
+                                .body(String::new())
+                        } else {
+                            if !tail.as_str().starts_with("crates/") {
+                                downloads.fetch_add(1, Ordering::SeqCst);
+                            }
+
+                            Response::builder().header("etag", tag).body(contents)
+                        };
+
+                        Ok(response.expect("failed to build response"))
+                    }
+                }
+            }),
+    );
+    port.store(usize::from(socket.port()), Ordering::SeqCst);
+
+    let crates = resources.workspace().join("crates.txt");
+    fs::write(&crates, "a\n")
+        .await
+        .expect("failed to write crates file");
+
+    let cache = resources.workspace().join("cache");
+    let url = format!("sparse+http://127.0.0.1:{}/", socket.port());
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["new", "--url", &url, "--crates", &crates.to_string_lossy()],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+    assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+    // Files that have not changed are not downloaded again.
+    for _ in 0..2 {
+        let status = resources.exe().sync(&cache).await;
+        assert!(status.success(), "failed to sync cache");
+    }
+
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_eq!(downloads.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_sync_with_zstd_storage() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0".repeat(4096)),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"1d05a1711752d58cd7b1a0fc3b865510186533adc6b73b84fba762884acfa52d","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--storage", "zstd", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download.zst"),
+            cache.join("crates/a/0.0.1/download.zst.sha256"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), false).await;
+
+    let bytes = resources
+        .exe()
+        .output(&cache, &["read", "--name", "a", "--version", "0.0.1"])
+        .await;
+    assert_eq!(bytes, "0".repeat(4096).into_bytes());
+
+    // Verifying every crate with plain storage decompresses the crate.
+    let status = resources.exe().run(&cache, &["verify", "--deep"]).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+    assert_exists(
+        [cache.join("crates/a/0.0.1/download.zst")].into_iter(),
+        false,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_metadata_database() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The crate that can not be downloaded is recorded as a failure.
+    let status = resources.exe().sync(&cache).await;
+    assert!(!status.success(), "sync should partially fail");
+
+    let summary = String::from_utf8(resources.exe().output(&cache, &["status"]).await)
+        .expect("status is not valid utf-8");
+    assert!(summary.contains("crates: 1\n"), "{summary}");
+    assert!(summary.contains("bytes: 1\n"), "{summary}");
+    assert!(summary.contains("unverified: 0\n"), "{summary}");
+    assert!(summary.contains("failures: 1\n"), "{summary}");
+
+    let statistics = String::from_utf8(resources.exe().output(&cache, &["stats"]).await)
+        .expect("stats is not valid utf-8");
+    for line in [
+        "crates: 1\n",
+        "versions: 1\n",
+        "  under 10000 bytes: 1\n",
+        "  10000000 bytes or more: 0\n",
+        "largest crates:\n  a 1 1\n",
+        "versions since synchronisation: 1\n",
+        "failures since synchronisation: 1",
+    ] {
+        assert!(statistics.contains(line), "{statistics}");
+    }
+
+    let entries = String::from_utf8(resources.exe().output(&cache, &["list"]).await)
+        .expect("list is not valid utf-8");
+    let entries: Vec<_> = entries.lines().collect();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].starts_with("a 0.0.1 1 "), "{}", entries[0]);
+}
+
+#[tokio::test]
+async fn test_sync_reads_listing() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b" | "c", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let database = cache.join("metadata.sqlite");
+    let index = cache.join("index");
+    let (listed, head) = spawn_blocking(move || {
+        let listed = rusqlite::Connection::open(database)
+            .expect("failed to open database")
+            .query_row("SELECT head FROM listed", [], |row| row.get::<_, String>(0))
+            .expect("failed to query listing");
+        let head = Repository::open(index)
+            .expect("failed to open cache index")
+            .head()
+            .expect("failed to get HEAD")
+            .target()
+            .expect("HEAD is not direct")
+            .to_string();
+
+        (listed, head)
+    })
+    .await
+    .expect("failed to query database");
+    assert_eq!(listed, head);
+
+    // The listing stands in for the unchanged index so a crate that is removed from it is not
+    // noticed.
+    let database = cache.join("metadata.sqlite");
+    spawn_blocking(move || {
+        rusqlite::Connection::open(database)
+            .expect("failed to open database")
+            .execute("DELETE FROM listing WHERE name = 'b'", [])
+            .expect("failed to update listing")
+    })
+    .await
+    .expect("failed to update database");
+
+    let download = cache.join("crates/b/0.0.1/download");
+    fs::remove_file(&download)
+        .await
+        .expect("failed to remove crate");
+
+    let status = resources.exe().run(&cache, &["sync", "--full"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([&download].into_iter(), false).await;
+
+    // The index is read again once it has changed.
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"1/c".to_vec(),
+                    br#"{"name":"c","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let status = resources.exe().run(&cache, &["sync", "--full"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [&download, &cache.join("crates/c/0.0.1/download")].into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_verify_skips_unchanged_crates() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // Corrupt the crate without changing its size or modification time.
+    let location = cache.join("crates/a/0.0.1/download");
+    spawn_blocking({
+        let location = location.clone();
+        move || {
+            let modified = std::fs::metadata(&location)
+                .and_then(|metadata| metadata.modified())
+                .expect("failed to get modification time");
+            std::fs::write(&location, "1").expect("failed to corrupt crate");
+            std::fs::File::options()
+                .write(true)
+                .open(&location)
+                .and_then(|file| file.set_modified(modified))
+                .expect("failed to restore modification time");
+        }
+    })
+    .await
+    .expect("failed to corrupt crate");
+
+    let status = resources.exe().verify(&cache).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "1"
+    );
+
+    let status = resources.exe().run(&cache, &["verify", "--deep"]).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "0"
+    );
+}
+
+#[tokio::test]
+async fn test_verify_changed_packages() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // The first verification checks every crate and records the commit that it verified.
+    let status = resources.exe().verify(&cache).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_exists([cache.join("verified.json")].into_iter(), true).await;
+
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"1/b".to_vec(),
+                    br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let a = cache.join("crates/a/0.0.1/download");
+    let b = cache.join("crates/b/0.0.1/download");
+    for download in [&a, &b] {
+        fs::remove_file(download)
+            .await
+            .expect("failed to remove crate");
+    }
+
+    // Only the crates in the packages that changed since the last verification are verified.
+    let status = resources.exe().verify(&cache).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_exists([&b].into_iter(), true).await;
+    assert_exists([&a].into_iter(), false).await;
+
+    let status = resources.exe().run(&cache, &["verify", "--all"]).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_exists([&a, &b].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_verify_size_only() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // A crate with the expected size is assumed to be intact.
+    let location = cache.join("crates/a/0.0.1/download");
+    fs::write(&location, "1")
+        .await
+        .expect("failed to corrupt crate");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["verify", "--size-only"])
+        .await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "1"
+    );
+
+    fs::write(&location, "11")
+        .await
+        .expect("failed to corrupt crate");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["verify", "--size-only"])
+        .await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "0"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_check_size() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let location = cache.join("crates/a/0.0.1/download");
+    let read = || async {
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate")
+    };
+
+    // A stored crate is preserved without being checked by default.
+    fs::write(&location, "11")
+        .await
+        .expect("failed to corrupt crate");
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(read().await, "11");
+
+    // A crate that does not have the recorded size is checked and downloaded again.
+    let status = resources.exe().run(&cache, &["sync", "--check-size"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(read().await, "0");
+
+    // A crate with the recorded size is assumed to be intact.
+    fs::write(&location, "1")
+        .await
+        .expect("failed to corrupt crate");
+    let status = resources.exe().run(&cache, &["sync", "--check-size"]).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(read().await, "1");
+}
+
+#[tokio::test]
+async fn test_sync_force() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "2/bb",
+                r#"{"name":"bb","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // The stored crates are replaced without changing their size so that only a forced download
+    // restores them.
+    let a = cache.join("crates/a/0.0.1/download");
+    let bb = cache.join("crates/bb/0.0.1/download");
+    for location in [&a, &bb] {
+        fs::write(location, "1")
+            .await
+            .expect("failed to replace crate");
+    }
+
+    let read = |location| async move {
+        fs::read_to_string(location)
+            .await
+            .expect("failed to read crate")
+    };
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--force", "a@0.0.1", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(read(&a).await, "0");
+    assert_eq!(read(&bb).await, "1");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--force", "b?", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(read(&bb).await, "0");
+}
+
+#[tokio::test]
+async fn test_refetch() {
+    let resources = Resources::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").map({
+        let failing = failing.clone();
+        move |_: String, _: String| {
+            let status = if failing.load(Ordering::SeqCst) {
+                StatusCode::INTERNAL_SERVER_ERROR
+            } else {
+                StatusCode::OK
+            };
+            Response::builder()
+                .status(status)
+                .body(String::from("0"))
+                .expect("failed to build response")
+        }
+    }));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let a = cache.join("crates/a/0.0.1/download");
+    fs::write(&a, "1").await.expect("failed to replace crate");
+
+    let status = resources.exe().run(&cache, &["refetch", "a@0.0.1"]).await;
+    assert!(status.success(), "failed to refetch crate");
+    assert_eq!(
+        fs::read_to_string(&a).await.expect("failed to read crate"),
+        "0"
+    );
+
+    let status = resources
+        .exe()
+        .run(&cache, &["refetch", "missing@0.0.1"])
+        .await;
+    assert!(
+        !status.success(),
+        "refetched a crate that is not in the index"
+    );
+
+    let status = resources.exe().run(&cache, &["refetch", "a"]).await;
+    assert!(!status.success(), "accepted a crate without a version");
+
+    // A crate that fails to be downloaded again is downloaded by the next synchronisation.
+    failing.store(true, Ordering::SeqCst);
+    let status = resources
+        .exe()
+        .run(&cache, &["--strict", "refetch", "a@0.0.1"])
+        .await;
+    assert!(
+        !status.success(),
+        "refetched a crate that could not be downloaded"
+    );
+    assert!(!a.exists());
+
+    failing.store(false, Ordering::SeqCst);
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(
+        fs::read_to_string(&a).await.expect("failed to read crate"),
+        "0"
+    );
+}
+
+#[tokio::test]
+async fn test_remove() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    let line = |name: &str, version: &str| {
+        format!(
+            r#"{{"name":"{name}","vers":"{version}","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+        )
+    };
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            ("1/a", &[line("a", "0.0.1"), line("a", "0.0.2")].join("\n")),
+            ("2/bb", &line("bb", "0.0.1")),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let status = resources.exe().run(&cache, &["remove", "a@0.0.1"]).await;
+    assert!(status.success(), "failed to remove crate");
+    assert!(!cache.join("crates/a/0.0.1").exists());
+    assert!(cache.join("crates/a/0.0.2/download").exists());
+
+    // A crate that is removed without being denied is downloaded again by the next
+    // synchronisation.
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert!(cache.join("crates/a/0.0.1/download").exists());
+
+    let status = resources
+        .exe()
+        .run(&cache, &["remove", "a", "--deny"])
+        .await;
+    assert!(status.success(), "failed to remove crate");
+    assert!(!cache.join("crates/a").exists());
+    assert!(cache.join("crates/bb/0.0.1/download").exists());
+    assert_eq!(
+        fs::read_to_string(cache.join("deny"))
+            .await
+            .expect("failed to read deny list"),
+        "a\n"
+    );
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert!(!cache.join("crates/a").exists());
+}
+
+#[tokio::test]
+async fn test_verify_crate() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    let line = |name: &str, version: &str| {
+        format!(
+            r#"{{"name":"{name}","vers":"{version}","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+        )
+    };
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            ("1/a", &[line("a", "0.0.1"), line("a", "0.0.2")].join("\n")),
+            ("2/bb", &line("bb", "0.0.1")),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let locations = [
+        cache.join("crates/a/0.0.1/download"),
+        cache.join("crates/a/0.0.2/download"),
+        cache.join("crates/bb/0.0.1/download"),
+    ];
+    for location in &locations {
+        fs::write(location, "1")
+            .await
+            .expect("failed to corrupt crate");
+    }
+
+    let read = |location| async move {
+        fs::read_to_string(location)
+            .await
+            .expect("failed to read crate")
+    };
+
+    // Only the selected crate is checked and downloaded again.
+    let status = resources
+        .exe()
+        .run(&cache, &["verify", "--crate", "a@0.0.1"])
+        .await;
+    assert!(status.success(), "failed to verify crate");
+    assert_eq!(read(&locations[0]).await, "0");
+    assert_eq!(read(&locations[1]).await, "1");
+    assert_eq!(read(&locations[2]).await, "1");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["verify", "--crate", "a"])
+        .await;
+    assert!(status.success(), "failed to verify crate");
+    assert_eq!(read(&locations[1]).await, "0");
+    assert_eq!(read(&locations[2]).await, "1");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["verify", "--crate", "missing"])
+        .await;
+    assert!(
+        !status.success(),
+        "verified a crate that is not in the index"
+    );
+}
+
+#[tokio::test]
+async fn test_verify_report() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                ("b", "0.0.1") => Ok("1"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let output = String::from_utf8(
+        resources
+            .exe()
+            .output(&cache, &["verify", "--report", "--deep"])
+            .await,
+    )
+    .expect("report is not valid utf-8");
+    assert_eq!(output, "");
+
+    let corrupt = cache.join("crates/a/0.0.1/download");
+    fs::write(&corrupt, "2")
+        .await
+        .expect("failed to corrupt crate");
+
+    let missing = cache.join("crates/b/0.0.1/download");
+    fs::remove_file(&missing)
+        .await
+        .expect("failed to remove crate");
+
+    let output = String::from_utf8(
+        resources
+            .exe()
+            .output(&cache, &["verify", "--report", "--deep"])
+            .await,
+    )
+    .expect("report is not valid utf-8");
+    assert_eq!(
+        output,
+        concat!(
+            r#"{"name":"a","version":"0.0.1","problem":"corrupt"}"#,
+            "\n",
+            r#"{"name":"b","version":"0.0.1","problem":"missing"}"#,
+            "\n"
+        )
+    );
+
+    // Nothing is downloaded.
+    assert_eq!(
+        fs::read_to_string(&corrupt)
+            .await
+            .expect("failed to read crate"),
+        "2"
+    );
+    assert!(!missing.exists());
+}
+
+#[tokio::test]
+async fn test_verify_against_manifest() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let database = cache.join("metadata.sqlite");
+    let digest = |database: PathBuf| async move {
+        spawn_blocking(move || {
+            rusqlite::Connection::open(database)
+                .expect("failed to open database")
+                .query_row("SELECT digest FROM crates", [], |row| {
+                    row.get::<_, String>(0)
+                })
+                .expect("failed to query digest")
+        })
+        .await
+        .expect("failed to query database")
+    };
+
+    let set_digest = |database: PathBuf, digest: String| async move {
+        spawn_blocking(move || {
+            rusqlite::Connection::open(database)
+                .expect("failed to open database")
+                .execute("UPDATE crates SET digest = ?1", [digest])
+                .expect("failed to update digest")
+        })
+        .await
+        .expect("failed to update database");
+    };
+
+    // The manifest records the BLAKE3 digest of the stored crate.
+    assert_eq!(
+        digest(database.clone()).await,
+        format!("blake3:{}", blake3::hash(b"0").to_hex())
+    );
+
+    // A crate that matches the manifest is assumed to be intact without checking the checksum
+    // that is published by the registry.
+    let location = cache.join("crates/a/0.0.1/download");
+    fs::write(&location, "1")
+        .await
+        .expect("failed to corrupt crate");
+    set_digest(
+        database.clone(),
+        format!("blake3:{}", blake3::hash(b"1").to_hex()),
+    )
+    .await;
+
+    let status = resources.exe().run(&cache, &["verify", "--deep"]).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "1"
+    );
+
+    // A crate that does not match the manifest is checked against the published checksum.
+    set_digest(database.clone(), format!("blake3:{}", "0".repeat(64))).await;
+
+    let status = resources.exe().run(&cache, &["verify", "--deep"]).await;
+    assert!(status.success(), "failed to verify cache");
+    assert_eq!(
+        fs::read_to_string(&location)
+            .await
+            .expect("failed to read crate"),
+        "0"
+    );
+    assert_eq!(
+        digest(database).await,
+        format!("blake3:{}", blake3::hash(b"0").to_hex())
+    );
+}
+
+#[tokio::test]
+async fn test_sbom() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The second crate can not be downloaded so it is not stored in the cache.
+    resources.exe().run(&cache, &["sync"]).await;
+
+    let output = resources.exe().output(&cache, &["sbom"]).await;
+    let bom = serde_json::from_slice::<serde_json::Value>(&output)
+        .expect("bill of materials is not valid json");
+
+    assert_eq!(bom["bomFormat"], "CycloneDX");
+    assert_eq!(bom["specVersion"], "1.5");
+    assert_eq!(
+        bom["metadata"]["tools"]["components"][0]["name"],
+        "crateful"
+    );
+
+    let components = bom["components"]
+        .as_array()
+        .expect("components are not an array");
+    assert_eq!(components.len(), 1);
+    assert_eq!(components[0]["name"], "a");
+    assert_eq!(components[0]["version"], "0.0.1");
+    assert_eq!(components[0]["purl"], "pkg:cargo/a@0.0.1");
+    assert_eq!(components[0]["hashes"][0]["alg"], "SHA-256");
+    assert_eq!(
+        components[0]["hashes"][0]["content"],
+        "5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9"
+    );
+    assert_eq!(
+        components[0]["externalReferences"][0]["url"],
+        format!("http://127.0.0.1:{}/a/0.0.1/download", socket.port()).as_str()
+    );
+}
+
+#[tokio::test]
+async fn test_sbom_signed() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // A minisign secret key that is not encrypted.
+    let seed = [7; 32];
+    let public = Ed25519KeyPair::from_seed_unchecked(&seed)
+        .expect("failed to derive key pair")
+        .public_key()
+        .as_ref()
+        .to_vec();
+    let secret = [seed.as_slice(), &public].concat();
+    let id = [1, 2, 3, 4, 5, 6, 7, 8];
+    let checksum = blake2b_simd::Params::new()
+        .hash_length(32)
+        .to_state()
+        .update(b"Ed")
+        .update(&id)
+        .update(&secret)
+        .finalize();
+    let key = resources.workspace().join("minisign.key");
+    fs::write(
+        &key,
+        format!(
+            "untrusted comment: minisign secret key\n{}\n",
+            STANDARD.encode(
+                [
+                    b"Ed".as_slice(),
+                    b"\0\0B2",
+                    &[0; 48],
+                    &id,
+                    &secret,
+                    checksum.as_bytes()
+                ]
+                .concat()
+            )
+        ),
+    )
+    .await
+    .expect("failed to write secret key");
+
+    let output = resources.workspace().join("bom.json");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "sbom",
+                "--output",
+                &output.to_string_lossy(),
+                "--signing-key",
+                &key.to_string_lossy(),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to write bill of materials");
+
+    let bom = fs::read(&output)
+        .await
+        .expect("failed to read bill of materials");
+    serde_json::from_slice::<serde_json::Value>(&bom).expect("bill of materials is not valid json");
+
+    let signature = fs::read_to_string(resources.workspace().join("bom.json.minisig"))
+        .await
+        .expect("failed to read signature");
+    let lines = signature.lines().collect::<Vec<_>>();
+    let decoded = STANDARD.decode(lines[1]).expect("signature is not base64");
+    assert_eq!(&decoded[..10], [b"ED".as_slice(), &id].concat());
+    assert!(lines[2].ends_with("\tfile:bom.json\thashed"));
+
+    let hashed = blake2b_simd::Params::new().hash_length(64).hash(&bom);
+    assert!(UnparsedPublicKey::new(&ED25519, &public)
+        .verify(hashed.as_bytes(), &decoded[10..])
+        .is_ok());
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_apply_bundle() {
+    let resources = Resources::new();
+    let (socket, guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1" | "0.0.2") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let (source, mirror) = (
+        resources.workspace().join("source"),
+        resources.workspace().join("mirror"),
+    );
+
+    for cache in [&source, &mirror] {
+        assert!(
+            resources.exe().create(cache, &url).await.success(),
+            "failed to create cache"
+        );
+        assert!(
+            resources.exe().sync(cache).await.success(),
+            "failed to sync cache"
+        );
+    }
+
+    let head = |path: PathBuf| {
+        spawn_blocking(move || {
+            Repository::open(path)
+                .expect("failed to open index")
+                .head()
+                .expect("failed to get HEAD")
+                .target()
+                .expect("HEAD is not a commit")
+        })
+    };
+
+    let from = head(mirror.join("index"))
+        .await
+        .expect("failed to read index");
+
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"1/a".to_vec(),
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}
+{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#.as_bytes()
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to add crate to registry index");
+
+    assert!(
+        resources.exe().sync(&source).await.success(),
+        "failed to sync cache"
+    );
+
+    let bundle = resources.workspace().join("bundle.tar");
+    let status = resources
+        .exe()
+        .run(
+            &source,
+            &[
+                OsStr::new("--log-level"),
+                OsStr::new("debug"),
+                OsStr::new("bundle"),
+                OsStr::new("--from"),
+                OsStr::new(&from.to_string()),
+                bundle.as_os_str(),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to write bundle");
+
+    // The mirror can not reach the registry.
+    drop(guard);
+
+    let status = resources
+        .exe()
+        .run(
+            &mirror,
+            &[
+                OsStr::new("--log-level"),
+                OsStr::new("debug"),
+                OsStr::new("apply-bundle"),
+                bundle.as_os_str(),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to apply bundle");
+
+    assert_exists(
+        [
+            mirror.join("crates/a/0.0.1/download"),
+            mirror.join("crates/a/0.0.2/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists([mirror.join("bundle")].into_iter(), false).await;
+
+    assert_eq!(
+        head(mirror.join("index"))
+            .await
+            .expect("failed to read index"),
+        head(source.join("index"))
+            .await
+            .expect("failed to read index")
+    );
+}
+
+#[tokio::test]
+async fn test_metalink() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // The second crate can not be downloaded so it is not stored in the cache.
+    resources.exe().run(&cache, &["sync"]).await;
+
+    let output = resources
+        .exe()
+        .output(
+            &cache,
+            &["metalink", "--mirror", "https://mirror.example/crates"],
+        )
+        .await;
+    let metalink = String::from_utf8(output).expect("metalink is not valid utf-8");
+
+    assert!(metalink.contains("<metalink xmlns=\"urn:ietf:params:xml:ns:metalink\">"));
+    assert!(metalink.contains("<file name=\"a-0.0.1.crate\">"));
+    assert!(!metalink.contains("b-0.0.1.crate"));
+    assert!(metalink.contains("<size>1</size>"));
+    assert!(metalink.contains(
+        "<hash type=\"sha-256\">5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9</hash>"
+    ));
+    assert!(metalink
+        .contains("<url priority=\"1\">https://mirror.example/crates/a/0.0.1/download</url>"));
+    assert!(metalink.contains(&format!(
+        "<url priority=\"2\">http://127.0.0.1:{}/a/0.0.1/download</url>",
+        socket.port()
+    )));
+}
+
+#[tokio::test]
+async fn test_sync_pins_to_ipfs() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and_then(|name: String, version: String| async move {
+                match (name.as_str(), version.as_str()) {
+                    ("a", "0.0.1") => Ok("0"),
+                    _ => Err(warp::reject::not_found()),
+                }
+            })
+            .or(warp::post()
+                .and(warp::path!("api" / "v0" / "add"))
+                .and(warp::query::<BTreeMap<String, String>>())
+                .map(|query: BTreeMap<String, String>| {
+                    assert_eq!(query.get("pin").map(String::as_str), Some("true"));
+                    r#"{"Name":"a-0.0.1.crate","Hash":"bafkreic2ldcc4qyzwknpvz6ybtbomnjlccmnxs2jhkrc3jlepqjazgkk7u","Size":"1"}"#
+                })),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--ipfs",
+                &format!("http://127.0.0.1:{}", socket.port()),
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    let pins = String::from_utf8(resources.exe().output(&cache, &["pins"]).await)
+        .expect("pins are not valid utf-8");
+    assert_eq!(
+        pins,
+        "a 0.0.1 bafkreic2ldcc4qyzwknpvz6ybtbomnjlccmnxs2jhkrc3jlepqjazgkk7u\n"
+    );
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn test_sync_pushes_to_oci_registry() {
+    let resources = Resources::new();
+    let blobs = Arc::new(Mutex::new(BTreeMap::new()));
+    let manifests = Arc::new(Mutex::new(BTreeMap::new()));
+
+    let (socket, _guard) = serve(
+        &warp::path!(String / String / "download")
+            .and_then(|name: String, version: String| async move {
+                match (name.as_str(), version.as_str()) {
+                    ("a", "0.0.1") => Ok("0"),
+                    _ => Err(warp::reject::not_found()),
+                }
+            })
+            .or(warp::path("v2")
+                .and(warp::method())
+                .and(warp::path::tail())
+                .and(warp::query::<BTreeMap<String, String>>())
+                .and(warp::body::bytes())
+                .map({
+                    let blobs = blobs.clone();
+                    let manifests = manifests.clone();
+                    move |method: Method,
+                          tail: warp::path::Tail,
+                          query: BTreeMap<String, String>,
+                          body: warp::hyper::body::Bytes| {
+                        let tail = tail.as_str().to_owned();
+                        let response = Response::builder();
+                        match (method, tail.as_str()) {
+                            (Method::GET, "") => response.status(StatusCode::OK).body(Vec::new()),
+                            (Method::HEAD, path) if path.starts_with("crates/a/blobs/sha256:") => {
+                                let digest = path.trim_start_matches("crates/a/blobs/");
+                                let status =
+                                    if blobs.lock().expect("lock is poisoned").contains_key(digest)
+                                    {
+                                        StatusCode::OK
+                                    } else {
+                                        StatusCode::NOT_FOUND
+                                    };
+
+                                response.status(status).body(Vec::new())
+                            }
+                            (Method::POST, "crates/a/blobs/uploads/") => response
+                                .status(StatusCode::ACCEPTED)
+                                .header("Location", "/v2/crates/a/blobs/uploads/upload?state=1")
+                                .body(Vec::new()),
+                            (Method::PUT, "crates/a/blobs/uploads/upload") => {
+                                assert_eq!(query.get("state").map(String::as_str), Some("1"));
+                                blobs.lock().expect("lock is poisoned").insert(
+                                    query.get("digest").expect("upload has no digest").clone(),
+                                    body.to_vec(),
+                                );
+
+                                response.status(StatusCode::CREATED).body(Vec::new())
+                            }
+                            (Method::PUT, path) if path.starts_with("crates/a/manifests/") => {
+                                manifests.lock().expect("lock is poisoned").insert(
+                                    path.trim_start_matches("crates/a/manifests/").to_owned(),
+                                    body.to_vec(),
+                                );
+
+                                response.status(StatusCode::CREATED).body(Vec::new())
+                            }
+                            _ => response.status(StatusCode::NOT_FOUND).body(Vec::new()),
+                        }
+                    }
+                })),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--oci",
+                &format!("http://127.0.0.1:{}/crates", socket.port()),
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    let blobs = blobs.lock().expect("lock is poisoned").clone();
+    assert_eq!(
+        blobs
+            .get("sha256:5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9")
+            .map(Vec::as_slice),
+        Some(b"0".as_slice())
+    );
+    assert_eq!(
+        blobs
+            .get("sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a")
+            .map(Vec::as_slice),
+        Some(b"{}".as_slice())
+    );
+
+    let manifests = manifests.lock().expect("lock is poisoned").clone();
+    let manifest: serde_json::Value =
+        serde_json::from_slice(manifests.get("0.0.1").expect("manifest was not pushed"))
+            .expect("manifest is not valid json");
+    assert_eq!(
+        manifest["artifactType"],
+        "application/vnd.crateful.crate.v1"
+    );
+    assert_eq!(
+        manifest["layers"][0]["annotations"]["org.opencontainers.image.title"],
+        "a-0.0.1.crate"
+    );
+}
+
+#[tokio::test]
+async fn test_migrate_layout() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "Con", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "3/c/con",
+                r#"{"name":"Con","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/Con/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+
+    // Crates are found through the index even if the metadata database does not record them (eg.
+    // in a cache that was created before there was a database).
+    for name in [
+        "metadata.sqlite",
+        "metadata.sqlite-wal",
+        "metadata.sqlite-shm",
+    ] {
+        if let Err(error) = fs::remove_file(cache.join(name)).await {
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::NotFound,
+                "failed to delete {name}"
+            );
+        }
+    }
+
+    let status = resources
+        .exe()
+        .run(&cache, &["migrate-layout", "portable"])
+        .await;
+    assert!(status.success(), "failed to migrate layout");
+    assert_exists(
+        [
+            cache.join("crates/1/a/0.0.1/download"),
+            cache.join("crates/3/c/!con/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists(
+        [cache.join("crates/a"), cache.join("crates/Con")].into_iter(),
+        false,
+    )
+    .await;
+
+    // Crates are found where the recorded layout locates them.
+    let bytes = resources
+        .exe()
+        .output(&cache, &["read", "--name", "Con", "--version", "0.0.1"])
+        .await;
+    assert_eq!(bytes, b"0");
+
+    let status = resources.exe().run(&cache, &["verify"]).await;
+    assert!(status.success(), "failed to verify cache");
+}
+
+#[tokio::test]
+async fn test_create_with_layout() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+    let url = Url::from_file_path(&registry_index)
+        .expect("failed to get url for registry index")
+        .to_string();
+
+    for (layout, path) in [
+        ("cargo", "crates/a-0.0.1.crate"),
+        ("sharded", "crates/1/a/0.0.1/download"),
+        (
+            "content-addressed",
+            "crates/sha256/5f/5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9.crate",
+        ),
+    ] {
+        let cache = resources.workspace().join(layout);
+        let status = resources
+            .exe()
+            .run(&cache, &["new", "--url", &url, "--layout", layout])
+            .await;
+        assert!(status.success(), "failed to create cache");
+
+        let status = resources.exe().sync(&cache).await;
+        assert!(status.success(), "failed to sync cache");
+        assert_exists([cache.join(path)].into_iter(), true).await;
+        assert_exists([cache.join("crates/a")].into_iter(), false).await;
+
+        let bytes = resources
+            .exe()
+            .output(&cache, &["read", "--name", "a", "--version", "0.0.1"])
+            .await;
+        assert_eq!(bytes, b"0");
+
+        let status = resources.exe().run(&cache, &["verify"]).await;
+        assert!(status.success(), "failed to verify cache");
+    }
+}
+
+#[tokio::test]
+async fn test_export_cargo() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Compressed crates are exported as plain crates.
+    let status = resources
+        .exe()
+        .run(&cache, &["--storage", "zstd", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    let output = resources.workspace().join("cargo");
+    let status = resources
+        .exe()
+        .run(&cache, &["export-cargo", &output.to_string_lossy()])
+        .await;
+    assert!(status.success(), "failed to export cache");
+    for name in ["a-0.0.1.crate", "b-0.0.1.crate"] {
+        let bytes = fs::read(output.join(name))
+            .await
+            .expect("failed to read exported crate");
+        assert_eq!(bytes, b"0");
+    }
+
+    // Crates that were already exported are not copied again.
+    fs::write(output.join("a-0.0.1.crate"), b"1")
+        .await
+        .expect("failed to write exported crate");
+    let status = resources
+        .exe()
+        .run(&cache, &["export-cargo", &output.to_string_lossy()])
+        .await;
+    assert!(status.success(), "failed to export cache");
+    let bytes = fs::read(output.join("a-0.0.1.crate"))
+        .await
+        .expect("failed to read exported crate");
+    assert_eq!(bytes, b"1");
+}
+
+/// Returns a gzipped tarball that holds files at paths within `root` like a published crate.
+fn crate_archive(root: &str, files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    ));
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{root}/{path}"), contents.as_bytes())
+            .expect("failed to append file to crate");
+    }
+
+    builder
+        .into_inner()
+        .expect("failed to write crate")
+        .finish()
+        .expect("failed to compress crate")
+}
+
+#[tokio::test]
+async fn test_export_cargo_source() {
+    use sha2::{Digest, Sha256};
+
+    let resources = Resources::new();
+    let a = crate_archive(
+        "a-0.0.1",
+        &[("Cargo.toml", "[package]"), ("src/lib.rs", "pub fn a() {}")],
+    );
+    let b = crate_archive("c-0.0.1", &[("src/lib.rs", "pub fn b() {}")]);
+    let c = crate_archive("c-0.0.1", &[("src/lib.rs", "pub fn c() {}")]);
+    let checksums = [&a, &b, &c].map(|bytes| hex::encode(Sha256::digest(bytes)));
+
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        move |name: String, version: String| {
+            let bytes = match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Some(a.clone()),
+                ("b", "0.0.1") => Some(b.clone()),
+                ("c", "0.0.1") => Some(c.clone()),
+                _ => None,
+            };
+            async move { bytes.ok_or_else(warp::reject::not_found) }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    let packages = [("a", false), ("b", false), ("c", true)]
+        .iter()
+        .zip(&checksums)
+        .map(|((name, yanked), checksum)| {
+            (
+                format!("1/{name}"),
+                format!(
+                    r#"{{"name":"{name}","vers":"0.0.1","deps":[],"cksum":"{checksum}","features":{{}},"yanked":{yanked}}}"#
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &packages
+            .iter()
+            .map(|(path, contents)| (path.as_str(), contents.as_str()))
+            .collect::<Vec<_>>(),
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // Yanked crates are not exported when the filters skip them and crates with entries outside of
+    // their directory are not unpacked.
+    let output = resources.workspace().join("cargo");
+    let source = resources.workspace().join("src");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--yanked",
+                "skip",
+                "export-cargo",
+                &output.to_string_lossy(),
+                "--source",
+                &source.to_string_lossy(),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to export cache");
+    assert_exists(
+        [
+            output.join("a-0.0.1.crate"),
+            output.join("b-0.0.1.crate"),
+            source.join("a-0.0.1/Cargo.toml"),
+            source.join("a-0.0.1/.cargo-ok"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists(
+        [
+            output.join("c-0.0.1.crate"),
+            source.join("b-0.0.1"),
+            source.join("c-0.0.1"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+
+    let contents = fs::read_to_string(source.join("a-0.0.1/src/lib.rs"))
+        .await
+        .expect("failed to read unpacked file");
+    assert_eq!(contents, "pub fn a() {}");
+}
+
+#[tokio::test]
+async fn test_verify_archives() {
+    use sha2::{Digest, Sha256};
+
+    let resources = Resources::new();
+    let a = crate_archive("a-0.0.1", &[("src/lib.rs", "pub fn a() {}")]);
+    let b = crate_archive("c-0.0.1", &[("src/lib.rs", "pub fn b() {}")]);
+    let c = b"0".to_vec();
+    let checksums = [&a, &b, &c].map(|bytes| hex::encode(Sha256::digest(bytes)));
+
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        move |name: String, version: String| {
+            let bytes = match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Some(a.clone()),
+                ("b", "0.0.1") => Some(b.clone()),
+                ("c", "0.0.1") => Some(c.clone()),
+                _ => None,
+            };
+            async move { bytes.ok_or_else(warp::reject::not_found) }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    let packages = ["a", "b", "c"]
+        .iter()
+        .zip(&checksums)
+        .map(|(name, checksum)| {
+            (
+                format!("1/{name}"),
+                format!(
+                    r#"{{"name":"{name}","vers":"0.0.1","deps":[],"cksum":"{checksum}","features":{{}},"yanked":false}}"#
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &packages
+            .iter()
+            .map(|(path, contents)| (path.as_str(), contents.as_str()))
+            .collect::<Vec<_>>(),
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // Checksums alone do not find suspicious archives.
+    let status = resources.exe().run(&cache, &["verify", "--all"]).await;
+    assert!(status.success(), "failed to verify cache");
+
+    let status = resources.exe().run(&cache, &["verify", "--archives"]).await;
+    assert!(!status.success(), "verified suspicious archives");
+
+    let output = resources
+        .exe()
+        .output(&cache, &["verify", "--archives", "--report"])
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("report is not utf-8"),
+        concat!(
+            r#"{"name":"b","version":"0.0.1","problem":"suspicious"}"#,
+            "\n",
+            r#"{"name":"c","version":"0.0.1","problem":"suspicious"}"#,
+            "\n",
+        )
+    );
+
+    // Suspicious crates are kept.
+    assert_exists(
+        [
+            cache.join("crates/b/0.0.1/download"),
+            cache.join("crates/c/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sync_with_permissions() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // A process can always give its files to a group that it is in.
+    let group = fs::metadata(&cache)
+        .await
+        .expect("failed to read cache metadata")
+        .gid()
+        .to_string();
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--file-mode",
+                "0640",
+                "--directory-mode",
+                "0750",
+                "--group",
+                &group,
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    let metadata = fs::metadata(cache.join("crates/a/0.0.1/download"))
+        .await
+        .expect("failed to read crate metadata");
+    assert_eq!(metadata.permissions().mode() & 0o7777, 0o640);
+    assert_eq!(metadata.gid().to_string(), group);
+
+    for directory in ["crates", "crates/a", "crates/a/0.0.1"] {
+        let metadata = fs::metadata(cache.join(directory))
+            .await
+            .expect("failed to read directory metadata");
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o750, "{directory}");
+    }
+}
+
+#[tokio::test]
+async fn test_discover_path() {
+    let resources = Resources::new();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(&registry_index, String::from("http://127.0.0.1:1"), &[]).await;
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+
+    let exe = resources.exe();
+    let discovered = |variable: &str, value: &Path, arguments: &[&str]| {
+        Command::new(&exe.location)
+            .env_remove("CRATEFUL_PATH")
+            .env_remove("XDG_DATA_HOME")
+            .env(variable, value)
+            .args(arguments)
+            .args(["new", "--url", url.as_str()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    };
+
+    // The path in the environment is used when no path is given.
+    let cache = resources.workspace().join("cache");
+    let status = discovered("CRATEFUL_PATH", &cache, &[])
+        .await
+        .expect("failed to run crateful");
+    assert!(status.success(), "failed to create cache");
+    assert_exists([cache.join("index")].into_iter(), true).await;
+
+    // Otherwise, the cache of the registry is in the data directory.
+    let data = resources.workspace().join("data");
+    let status = discovered("XDG_DATA_HOME", &data, &["--registry", "mirror"])
+        .await
+        .expect("failed to run crateful");
+    assert!(status.success(), "failed to create cache");
+    assert_exists([data.join("crateful/mirror/index")].into_iter(), true).await;
+}
+
+#[tokio::test]
+async fn test_sync_traces_http() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(
+        &warp::path!("a" / "0.0.1" / "download")
+            .map(|| warp::redirect::found(Uri::from_static("/files/a-0.0.1.crate")))
+            .or(warp::path!("files" / "a-0.0.1.crate").map(|| "0")),
+    );
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let log = resources.workspace().join("log");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                OsStr::new("--trace-http"),
+                OsStr::new("--log-file"),
+                log.as_os_str(),
+                OsStr::new("sync"),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+
+    let log = fs::read_to_string(&log)
+        .await
+        .expect("failed to read log file");
+    let origin = format!("http://127.0.0.1:{}", socket.port());
+    for line in [
+        format!("> GET {origin}/a/0.0.1/download"),
+        format!("following 302 Found redirect from {origin}/a/0.0.1/download to {origin}/files/a-0.0.1.crate"),
+        format!("< HTTP/1.1 200 OK from {origin}/files/a-0.0.1.crate"),
+    ] {
+        assert!(log.contains(&line), "{line} is not in {log}");
+    }
+}
+
+#[tokio::test]
+async fn test_sync_reports_transfers() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let log = resources.workspace().join("log");
+    let sync = || async {
+        let status = resources
+            .exe()
+            .run(
+                &cache,
+                &[
+                    OsStr::new("--log-file"),
+                    log.as_os_str(),
+                    OsStr::new("sync"),
+                ],
+            )
+            .await;
+        assert!(status.success(), "failed to sync cache");
+        fs::read_to_string(&log)
+            .await
+            .expect("failed to read log file")
+    };
+
+    let contents = sync().await;
+    assert!(
+        contents.contains("checked 2 crates, downloaded 2 (2 bytes), 0 failed, and removed 0 in "),
+        "the transfers are not reported in {contents}"
+    );
+
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo).remove(Path::new("1/b")).commit();
+        }
+    })
+    .await
+    .expect("failed to remove crate from registry index");
+
+    let contents = sync().await;
+    assert!(
+        contents.contains("checked 0 crates, downloaded 0 (0 bytes), 0 failed, and removed 1 in "),
+        "the removal is not reported in {contents}"
+    );
+}
+
+#[tokio::test]
+async fn test_sync_limits_connections() {
+    let resources = Resources::new();
+    let downloading = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then({
+        let downloading = downloading.clone();
+        let most = most.clone();
+        move |_: String, _: String| {
+            let downloading = downloading.clone();
+            let most = most.clone();
+            async move {
+                let current = downloading.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                downloading.fetch_sub(1, Ordering::SeqCst);
+                Ok::<_, Rejection>("0")
+            }
+        }
+    }));
+
+    let names = ["a", "b", "c", "d"];
+    let packages = names
+        .iter()
+        .map(|name| {
+            (
+                format!("1/{name}"),
+                format!(
+                    r#"{{"name":"{name}","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+                ),
+            )
+        })
+        .collect::<Vec<_>>();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &packages
+            .iter()
+            .map(|(path, package)| (path.as_str(), package.as_str()))
+            .collect::<Vec<_>>(),
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Crates are checked by several jobs but only one downloads at a time.
+    let status = resources
+        .exe()
+        .run(&cache, &["--jobs", "1", "--hash-jobs", "4", "sync"])
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_eq!(most.load(Ordering::SeqCst), 1);
+    assert_exists(
+        names
+            .iter()
+            .map(|name| cache.join(format!("crates/{name}/0.0.1/download"))),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_sync_limits_memory() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Crates that are compressed are held in memory until they are stored, each on its own when
+    // the limit is smaller than any crate.
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "--jobs",
+                "4",
+                "--in-flight-bytes",
+                "1",
+                "--storage",
+                "zstd",
+                "sync",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_disk_usage() {
+    let resources = Resources::new();
+    let (socket, _guard) =
+        serve(&warp::path!(String / String / "download").map(|_: String, _: String| "0"));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[
+            (
+                "1/a",
+                concat!(
+                    r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                    "\n",
+                    r#"{"name":"a","vers":"0.0.2","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                ),
+            ),
+            (
+                "1/b",
+                r#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+            ),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let usage = String::from_utf8(resources.exe().output(&cache, &["du"]).await)
+        .expect("du is not valid utf-8");
+    assert_eq!(usage, "a 2 2\nb 1 1\n");
+
+    let usage = String::from_utf8(resources.exe().output(&cache, &["du", "--top", "1"]).await)
+        .expect("du is not valid utf-8");
+    assert_eq!(usage, "a 2 2\n");
+}
+
+#[tokio::test]
+async fn test_doctor() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path::end().map(warp::reply));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let findings = String::from_utf8(resources.exe().output(&cache, &["doctor"]).await)
+        .expect("doctor is not valid utf-8");
+    let checks = findings
+        .lines()
+        .map(|line| line.split(':').next().unwrap_or_default())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        checks,
+        [
+            "ok index",
+            "ok configuration",
+            "ok writable",
+            "ok network",
+            "ok clock",
+            "ok lock"
+        ],
+        "{findings}"
+    );
+
+    // A cache without an index can not be synchronised.
+    fs::remove_dir_all(cache.join("index"))
+        .await
+        .expect("failed to remove index");
+    let status = resources.exe().run(&cache, &["doctor"]).await;
+    assert!(!status.success(), "doctor should fail");
+}
+
+#[tokio::test]
+async fn test_create_with_ssh_identity() {
+    let resources = Resources::new();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(&registry_index, String::from("http://127.0.0.1"), &[]).await;
+
+    let identity = resources.workspace().join("id_ed25519");
+    let cache = resources.workspace().join("cache");
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                OsStr::new("new"),
+                OsStr::new("--url"),
+                OsStr::new(url.as_str()),
+                OsStr::new("--ssh-identity"),
+                identity.as_os_str(),
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let recorded = || {
+        let index = cache.join("index");
+        spawn_blocking(move || {
+            Repository::open(index)
+                .expect("failed to open cache index")
+                .config()
+                .expect("failed to open index configuration")
+                .get_path("crateful.identity")
+                .expect("identity was not recorded")
+        })
+    };
+
+    // The identity is recorded so that updates are fetched with it and a repaired index is cloned
+    // with it again.
+    assert_eq!(recorded().await.expect("failed to read identity"), identity);
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let status = resources.exe().run(&cache, &["repair-index"]).await;
+    assert!(status.success(), "failed to repair index");
+    assert_eq!(recorded().await.expect("failed to read identity"), identity);
+}
+
+#[tokio::test]
+async fn test_create_with_git_token() {
+    let resources = Resources::new();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(&registry_index, String::from("http://127.0.0.1"), &[]).await;
+
+    let cache = resources.workspace().join("cache");
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &[
+                "new",
+                "--url",
+                url.as_str(),
+                "--git-username",
+                "mirror",
+                "--git-token-env",
+                "INDEX_TOKEN",
+            ],
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // Only the name of the environment variable is recorded and never the token itself.
+    let index = cache.join("index");
+    let (username, token_env) = spawn_blocking(move || {
+        let config = Repository::open(index)
+            .expect("failed to open cache index")
+            .config()
+            .expect("failed to open index configuration");
+        (
+            config
+                .get_string("crateful.username")
+                .expect("user name was not recorded"),
+            config
+                .get_string("crateful.tokenEnv")
+                .expect("token environment variable was not recorded"),
+        )
+    })
+    .await
+    .expect("failed to read authentication");
+    assert_eq!(username, "mirror");
+    assert_eq!(token_env, "INDEX_TOKEN");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+}
+
+#[tokio::test]
+async fn test_repair_index() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let url = Url::from_file_path(&registry_index).expect("failed to get url for registry index");
+    let status = resources.exe().create(&cache, &url).await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // The index can not be opened once HEAD is lost but its remote can still be read.
+    fs::remove_file(cache.join("index/.git/HEAD"))
+        .await
+        .expect("failed to corrupt index");
+    let status = resources.exe().sync(&cache).await;
+    assert!(!status.success(), "sync should fail with a corrupt index");
+
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"1/b".to_vec(),
+                    br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to update registry index");
+
+    let status = resources.exe().run(&cache, &["repair-index"]).await;
+    assert!(status.success(), "failed to repair index");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+    assert_exists([cache.join("index.repair")].into_iter(), false).await;
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync repaired cache");
+
+    // An index that is missing entirely is cloned from the URL that is given.
+    fs::remove_dir_all(cache.join("index"))
+        .await
+        .expect("failed to remove index");
+    let status = resources.exe().run(&cache, &["repair-index"]).await;
+    assert!(!status.success(), "repair-index should need a URL");
+
+    let status = resources
+        .exe()
+        .run(&cache, &["repair-index", "--url", url.as_str()])
+        .await;
+    assert!(status.success(), "failed to repair index from url");
+}
+
+#[tokio::test]
+async fn test_verify_index_integrity() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    let status = resources.exe().run(&cache, &["verify", "--fsck"]).await;
+    assert!(status.success(), "failed to verify intact index");
+
+    // The references of the index remain but the objects that they name are lost.
+    let pack = cache.join("index/.git/objects/pack");
+    fs::remove_dir_all(&pack)
+        .await
+        .expect("failed to remove objects");
+    fs::create_dir(&pack)
+        .await
+        .expect("failed to create pack directory");
+
+    let status = resources.exe().run(&cache, &["verify", "--fsck"]).await;
+    assert_eq!(
+        status.code(),
+        Some(3),
+        "verify should fail with an index error"
+    );
+
+    let status = resources.exe().run(&cache, &["repair-index"]).await;
+    assert!(status.success(), "failed to repair index");
+
+    let status = resources.exe().run(&cache, &["verify", "--fsck"]).await;
+    assert!(status.success(), "failed to verify repaired index");
+}
+
+#[tokio::test]
+async fn test_update_with_rewritten_history() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let download = format!("http://127.0.0.1:{}", socket.port());
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        download.clone(),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    // The history of the registry is squashed into a commit that does not descend from the commit
+    // that the cache holds.
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            let mut stager = Stager {
+                repository: &repo,
+                index: Index::new().expect("failed to create index"),
+            };
+            stager
+                .add(
+                    b"config.json".to_vec(),
+                    &serde_json::to_vec(&IndexFormat { download })
+                        .expect("failed to serialise index format"),
+                )
+                .add(
+                    b"1/b".to_vec(),
+                    br#"{"name":"b","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                );
+
+            let tree = repo
+                .find_tree(
+                    stager
+                        .index
+                        .write_tree_to(&repo)
+                        .expect("failed to write tree"),
+                )
+                .expect("failed to find tree");
+            let signature =
+                Signature::now("crateful", "crateful").expect("failed to create signature");
+            let squashed = repo
+                .commit(None, &signature, &signature, "squash", &tree, &[])
+                .expect("failed to commit");
+            repo.reference("refs/heads/master", squashed, true, "squash")
+                .expect("failed to reset branch");
+        }
+    })
+    .await
+    .expect("failed to rewrite registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), true).await;
+    assert_exists([cache.join("crates/a")].into_iter(), false).await;
+
+    let reflog = fs::read_to_string(cache.join("index/.git/logs/refs/heads/master"))
+        .await
+        .expect("failed to read reflog");
+    assert!(
+        reflog.contains("reset branch to rewritten history"),
+        "{reflog}"
+    );
+
+    // The next update continues from the rewritten history.
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache again");
+}
+
+#[tokio::test]
+async fn test_update_with_many_packages() {
+    // Enough packages are added that they are parsed in several batches and on several threads.
+    const PACKAGES: usize = 1500;
+
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |_: String, version: String| async move {
+            match version.as_str() {
+                "0.0.1" => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://127.0.0.1:{}", socket.port()),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            let mut stager = Stager::new(&repo);
+            stager.remove(Path::new("1/a"));
+            for index in 0..PACKAGES {
+                let name = format!("crate{index:04}");
+                let contents = format!(
+                    r#"{{"name":"{name}","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+                );
+                stager.add(format!("cr/at/{name}").into_bytes(), contents.as_bytes());
+            }
+
+            stager.commit();
+        }
+    })
+    .await
+    .expect("failed to add crates to registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists(
+        (0..PACKAGES).map(|index| cache.join(format!("crates/crate{index:04}/0.0.1/download"))),
+        true,
+    )
+    .await;
+    assert_exists([cache.join("crates/a")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_sync_with_empty_index() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    // The registry has no commits yet.
+    let registry_index = resources.workspace().join("index");
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || Repository::init(&registry_index).expect("failed to initialise registry index")
+    })
+    .await
+    .expect("failed to prepare registry index");
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync empty cache");
+
+    // The first commit of the registry is applied by the next update.
+    let download = format!("http://127.0.0.1:{}", socket.port());
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"config.json".to_vec(),
+                    &serde_json::to_vec(&IndexFormat { download })
+                        .expect("failed to serialise index format"),
+                )
+                .add(
+                    b"1/a".to_vec(),
+                    br#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to update registry index");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache again");
+}
+
+#[tokio::test]
+async fn test_read_with_check() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://{socket}"),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache");
+    assert_exists([cache.join("consistency.json")].into_iter(), true).await;
+
+    let arguments = ["read", "--name", "a", "--version", "0.0.1", "--check"];
+    let bytes = resources.exe().output(&cache, &arguments).await;
+    assert_eq!(bytes, b"0");
+
+    // A corrupt crate is never read and is moved to the quarantine directory.
+    fs::write(cache.join("crates/a/0.0.1/download"), "corrupt")
+        .await
+        .expect("failed to corrupt crate");
+    let status = resources.exe().run(&cache, &arguments).await;
+    assert!(!status.success(), "read a corrupt crate");
+    assert_exists(
+        [cache.join("quarantine/a/0.0.1/download")].into_iter(),
+        true,
+    )
+    .await;
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("consistency.json"),
+        ]
+        .into_iter(),
+        false,
+    )
+    .await;
+
+    // The next synchronisation downloads the crate again.
+    let status = resources.exe().sync(&cache).await;
+    assert!(status.success(), "failed to sync cache again");
+    let bytes = resources.exe().output(&cache, &arguments).await;
+    assert_eq!(bytes, b"0");
+}
+
+#[tokio::test]
+async fn test_sync_lenient() {
+    let resources = Resources::new();
+    let (socket, _guard) = serve(&warp::path!(String / String / "download").and_then(
+        |name: String, version: String| async move {
+            match (name.as_str(), version.as_str()) {
+                ("a" | "b", "0.0.1") => Ok("0"),
+                _ => Err(warp::reject::not_found()),
+            }
+        },
+    ));
+
+    let valid = |name: &str| {
+        format!(
+            r#"{{"name":"{name}","vers":"0.0.1","deps":[],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{{}},"yanked":false}}"#
+        )
+    };
+    let corrupt =
+        r#"{"name":"b","vers":"0.0.2","deps":[],"cksum":"5feceb66","features":{},"yanked":false}"#;
+
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        format!("http://{socket}"),
+        &[
+            ("1/a", &valid("a")),
+            ("1/b", &format!("{}\n{corrupt}", valid("b"))),
+        ],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    // A corrupt line fails the synchronisation unless the index is read leniently.
+    let status = resources.exe().sync(&cache).await;
+    assert!(!status.success(), "synchronised a corrupt index");
+
+    let status = resources.exe().run(&cache, &["--lenient", "sync"]).await;
+    assert!(status.success(), "failed to sync cache leniently");
+    assert_exists(
+        [
+            cache.join("crates/a/0.0.1/download"),
+            cache.join("crates/b/0.0.1/download"),
+        ]
+        .into_iter(),
+        true,
+    )
+    .await;
+
+    // A crate is not removed when its line becomes corrupt.
+    spawn_blocking({
+        let registry_index = registry_index.clone();
+        move || {
+            let repo = Repository::open(&registry_index).expect("failed to open registry index");
+            Stager::new(&repo)
+                .add(
+                    b"1/a".to_vec(),
+                    valid("a").replace("0.0.1", "0.1").as_bytes(),
+                )
+                .commit();
+        }
+    })
+    .await
+    .expect("failed to corrupt registry index");
+
+    let status = resources.exe().run(&cache, &["--lenient", "sync"]).await;
+    assert!(status.success(), "failed to sync cache leniently");
+    assert_exists([cache.join("crates/a/0.0.1/download")].into_iter(), true).await;
+
+    // Crates that are selected by a pattern are found by reading every package in the index.
+    let status = resources.exe().run(&cache, &["remove", "b*"]).await;
+    assert!(!status.success(), "removed crates from a corrupt index");
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), true).await;
+
+    let status = resources
+        .exe()
+        .run(&cache, &["--lenient", "remove", "b*"])
+        .await;
+    assert!(status.success(), "failed to remove crates leniently");
+    assert_exists([cache.join("crates/b/0.0.1/download")].into_iter(), false).await;
+}
+
+#[tokio::test]
+async fn test_dependencies() {
+    let resources = Resources::new();
+    let registry_index = resources.workspace().join("index");
+    create_registry_index(
+        &registry_index,
+        String::from("http://127.0.0.1"),
+        &[(
+            "1/a",
+            r#"{"name":"a","vers":"0.0.1","deps":[{"name":"b","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"},{"name":"c","req":"^0.2","features":[],"optional":true,"default_features":true,"target":null,"kind":"dev"}],"cksum":"5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9","features":{},"yanked":false}"#,
+        )],
+    )
+    .await;
+
+    let cache = resources.workspace().join("cache");
+    let status = resources
+        .exe()
+        .create(
+            &cache,
+            &Url::from_file_path(&registry_index).expect("failed to get url for registry index"),
+        )
+        .await;
+    assert!(status.success(), "failed to create cache");
+
+    let output = resources
+        .exe()
+        .output(
+            &cache,
+            &["dependencies", "--name", "a", "--version", "0.0.1"],
+        )
+        .await;
+    assert_eq!(
+        String::from_utf8(output).expect("output is not utf-8"),
+        "b ^1 normal\nc ^0.2 dev optional\n"
+    );
+
+    let status = resources
+        .exe()
+        .run(
+            &cache,
+            &["dependencies", "--name", "a", "--version", "0.0.2"],
+        )
+        .await;
+    assert!(!status.success(), "found dependencies of a missing crate");
 }